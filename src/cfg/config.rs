@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
 
 use crate::db_error::Result;
+use crate::errinput;
 
 pub fn get_config_path() -> PathBuf {
     // 1. 环境变量优先
@@ -55,6 +56,90 @@ pub struct Config {
 
     //LRU 旧文件句柄的缓存容量
     pub file_cache_capacity: usize,
+
+    // 后台 MVCC 垃圾回收间隔 单位：秒，0 表示关闭
+    #[serde(default)]
+    pub gc_interval_secs: u64,
+
+    // 存储引擎，缺省为 bitcask
+    #[serde(default)]
+    pub storage_engine: EngineKind,
+
+    // BitCask 日志条目值的压缩算法，缺省不压缩
+    #[serde(default)]
+    pub compression: Compression,
+
+    // 值的长度达到该字节数才压缩 单位：字节
+    #[serde(default = "default_compression_min_size")]
+    pub compression_min_size: usize,
+
+    // BitCask 静态加密的密钥文件，缺省不加密
+    #[serde(default)]
+    pub encryption_keyfile: Option<PathBuf>,
+}
+
+fn default_compression_min_size() -> usize {
+    DEFAULT_COMPRESSION_MIN_SIZE
+}
+
+/// 默认的压缩阈值：更短的值压缩收益很小
+pub const DEFAULT_COMPRESSION_MIN_SIZE: usize = 256;
+
+/// BitCask 日志条目值的压缩算法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// 不压缩
+    #[default]
+    None,
+    /// LZ4 块压缩，速度快，适合文本较多的行
+    Lz4,
+}
+
+/// 可选的存储引擎
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    /// 内存引擎，进程退出后数据丢失，适合临时实例与集成测试
+    Memory,
+    /// BitCask 日志结构哈希表
+    #[default]
+    Bitcask,
+    /// LSM 树
+    Lsm,
+    /// 页式 B+ 树
+    Btree,
+}
+
+impl EngineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineKind::Memory => "memory",
+            EngineKind::Bitcask => "bitcask",
+            EngineKind::Lsm => "lsm",
+            EngineKind::Btree => "btree",
+        }
+    }
+}
+
+impl std::fmt::Display for EngineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for EngineKind {
+    type Err = crate::db_error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(EngineKind::Memory),
+            "bitcask" => Ok(EngineKind::Bitcask),
+            "lsm" => Ok(EngineKind::Lsm),
+            "btree" => Ok(EngineKind::Btree),
+            _ => errinput!("unknown storage engine {s}, expected memory, bitcask, lsm or btree"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
@@ -99,6 +184,27 @@ impl ConfigBuilder {
         self
     }
 
+    fn gc_interval_secs(mut self, secs: u64) -> Self {
+        self.inner.gc_interval_secs = secs;
+        self
+    }
+
+    fn storage_engine(mut self, engine: EngineKind) -> Self {
+        self.inner.storage_engine = engine;
+        self
+    }
+
+    fn compression(mut self, compression: Compression, min_size: usize) -> Self {
+        self.inner.compression = compression;
+        self.inner.compression_min_size = min_size;
+        self
+    }
+
+    fn encryption_keyfile(mut self, path: PathBuf) -> Self {
+        self.inner.encryption_keyfile = Some(path);
+        self
+    }
+
     fn valiate(&self) -> Result<()> {
         // todo!("配置模块属性验证在这里添加");
         Ok(())
@@ -131,6 +237,11 @@ impl Config {
                 fsync_inteval_ms: 1000,
                 compaction_threshold: 0.6,
                 file_cache_capacity: 32,
+                gc_interval_secs: 0,
+                storage_engine: EngineKind::Bitcask,
+                compression: Compression::None,
+                compression_min_size: DEFAULT_COMPRESSION_MIN_SIZE,
+                encryption_keyfile: None,
             });
         }
         // 1、读取配置文件
@@ -144,10 +255,18 @@ impl Config {
 
 #[cfg(test)]
 mod test {
-    use crate::cfg::config::{Config, SyncStrategy};
+    use crate::cfg::config::{Compression, Config, EngineKind, SyncStrategy, DEFAULT_COMPRESSION_MIN_SIZE};
     use crate::db_error::Result;
     use std::path::PathBuf;
 
+    /// 只包含必填项的配置文件，extra 追加在末尾
+    fn minimal_config_toml(extra: &str) -> String {
+        format!(
+            "[config]\nstorage_path = \"./db\"\nsingle_file_limit = 1\nsync_strategy = \"Never\"\n\
+             fsync_inteval_ms = 1000\ncompaction_threshold = 0.6\nfile_cache_capacity = 32\n{extra}"
+        )
+    }
+
     /// 单元测试：
     /// 测试配置模块的构建方法
     #[test]
@@ -161,6 +280,50 @@ mod test {
         assert_eq!(config.sync_strategy, SyncStrategy::Never);
         assert_eq!(config.file_cache_capacity, 32);
         assert_eq!(config.compaction_threshold, 0.6);
+        assert_eq!(config.storage_engine, EngineKind::Bitcask);
+        Ok(())
+    }
+
+    /// 单元测试：
+    /// 测试存储引擎配置项的解析
+    #[test]
+    fn storage_engine_test() -> Result<()> {
+        let wrapper: crate::cfg::config::ConfigWrapper = toml::from_str(&minimal_config_toml("storage_engine = \"lsm\"\n"))?;
+        assert_eq!(wrapper.config.storage_engine, EngineKind::Lsm);
+        assert_eq!("Memory".parse::<EngineKind>()?, EngineKind::Memory);
+        assert!("rocksdb".parse::<EngineKind>().is_err());
+        let config = Config::builder("./db").storage_engine(EngineKind::Btree).build()?;
+        assert_eq!(config.storage_engine.to_string(), "btree");
+        Ok(())
+    }
+
+    /// 单元测试：
+    /// 测试压缩配置项的解析与默认值
+    #[test]
+    fn compression_test() -> Result<()> {
+        let wrapper: crate::cfg::config::ConfigWrapper = toml::from_str(&minimal_config_toml(""))?;
+        assert_eq!(wrapper.config.compression, Compression::None);
+        assert_eq!(wrapper.config.compression_min_size, DEFAULT_COMPRESSION_MIN_SIZE);
+        let wrapper: crate::cfg::config::ConfigWrapper =
+            toml::from_str(&minimal_config_toml("compression = \"lz4\"\ncompression_min_size = 64\n"))?;
+        assert_eq!(wrapper.config.compression, Compression::Lz4);
+        assert_eq!(wrapper.config.compression_min_size, 64);
+        let config = Config::builder("./db").compression(Compression::Lz4, 128).build()?;
+        assert_eq!((config.compression, config.compression_min_size), (Compression::Lz4, 128));
+        Ok(())
+    }
+
+    /// 单元测试：
+    /// 测试密钥文件配置项的解析
+    #[test]
+    fn encryption_keyfile_test() -> Result<()> {
+        let wrapper: crate::cfg::config::ConfigWrapper = toml::from_str(&minimal_config_toml(""))?;
+        assert_eq!(wrapper.config.encryption_keyfile, None);
+        let wrapper: crate::cfg::config::ConfigWrapper =
+            toml::from_str(&minimal_config_toml("encryption_keyfile = \"/etc/mini-db/keys\"\n"))?;
+        assert_eq!(wrapper.config.encryption_keyfile, Some(PathBuf::from("/etc/mini-db/keys")));
+        let config = Config::builder("./db").encryption_keyfile(PathBuf::from("keys")).build()?;
+        assert_eq!(config.encryption_keyfile, Some(PathBuf::from("keys")));
         Ok(())
     }
 
@@ -1,7 +1,7 @@
 mod config;
 mod watcher;
 
-pub use config::Config;
+pub use config::{Compression, Config, EngineKind};
 pub use watcher::watch_config;
 
 use lazy_static::lazy_static;
@@ -33,6 +33,11 @@ pub fn test_config_with_path(path: std::path::PathBuf) -> Config {
         fsync_inteval_ms: 1000,
         compaction_threshold: 0.6,
         file_cache_capacity: 32,
+        gc_interval_secs: 0,
+        storage_engine: config::EngineKind::Bitcask,
+        compression: config::Compression::None,
+        compression_min_size: config::DEFAULT_COMPRESSION_MIN_SIZE,
+        encryption_keyfile: None,
     }
 }
 
@@ -53,3 +58,25 @@ pub fn get_max_size() -> u64 {
     let config = CONFIG.lock().unwrap();
     config.single_file_limit * 1024 * 1024
 }
+
+pub fn get_gc_interval_secs() -> u64 {
+    let config = CONFIG.lock().unwrap();
+    config.gc_interval_secs
+}
+
+pub fn get_storage_engine() -> EngineKind {
+    let config = CONFIG.lock().unwrap();
+    config.storage_engine
+}
+
+/// BitCask 值压缩的算法与最小值长度
+pub fn get_compression() -> (Compression, usize) {
+    let config = CONFIG.lock().unwrap();
+    (config.compression, config.compression_min_size)
+}
+
+/// BitCask 静态加密的密钥文件，None 表示不加密
+pub fn get_encryption_keyfile() -> Option<std::path::PathBuf> {
+    let config = CONFIG.lock().unwrap();
+    config.encryption_keyfile.clone()
+}
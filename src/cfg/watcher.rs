@@ -47,6 +47,7 @@ pub async fn watch_config(mut shutdown: broadcast::Receiver<()>) {
                                     config.fsync_inteval_ms = new_config.fsync_inteval_ms;
                                     config.compaction_threshold = new_config.compaction_threshold;
                                     config.file_cache_capacity = new_config.file_cache_capacity;
+                                    config.gc_interval_secs = new_config.gc_interval_secs;
                                 }
                                 Err(e) => error!("重新加载配置失败: {}", e),
                             }
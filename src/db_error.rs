@@ -217,6 +217,12 @@ impl From<ParseFloatError> for Error {
     }
     
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::ParseError(value.to_string())
+    }
+}
 #[cfg(test)]
 mod tests {
     use crate::db_error;
@@ -2,18 +2,23 @@ pub mod cfg;
 pub mod db_error;
 pub mod storage;
 pub mod utils;
-pub use storage::BitCask;
+pub use storage::{AnyEngine, BTree, BitCask, Lsm, Memory};
 
 pub mod sql;
 pub mod types;
 
 use crate::db_error::Result;
-use crate::sql::execution::{execute, ResultSet};
+use crate::sql::execution::{execute, transfer, ChangeSet, Format, ResultSet, Session, TransferReport};
 use crate::sql::parser::Parser;
 use crate::sql::planner::planner::plan;
-use crate::storage::mvcc::MVCC;
+use crate::storage::engine::Engine;
+use crate::storage::mvcc::{GcReport, MVCC};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
 
 pub fn init_tracing() {
     tracing_subscriber::fmt()
@@ -25,27 +30,182 @@ pub fn init_tracing() {
         .init();
 }
 
-pub fn init_db() -> db_error::Result<BitCask> {
-    BitCask::init_db()
+/// 按配置中的 storage_engine 在存储路径下打开存储引擎
+pub fn init_db() -> db_error::Result<AnyEngine> {
+    open_engine(cfg::get_storage_engine())
+}
+
+/// 在配置的存储路径下打开指定类型的存储引擎
+pub fn open_engine(kind: cfg::EngineKind) -> db_error::Result<AnyEngine> {
+    AnyEngine::open(kind, std::path::Path::new(&cfg::get_db_base()))
+}
+
+/// 以只读方式打开配置的存储路径下的存储引擎，不获取目录锁
+pub fn open_engine_read_only(kind: cfg::EngineKind) -> db_error::Result<AnyEngine> {
+    AnyEngine::open_read_only(kind, std::path::Path::new(&cfg::get_db_base()))
 }
 
 /// 数据库会话，封装 MVCC 引擎与 SQL 执行
-pub struct Database {
-    mvcc: Arc<Mutex<MVCC<BitCask>>>,
+/// 不持有全局锁：并发由 MVCC 内部的读写锁控制，只读查询可以并行执行
+/// 存储引擎默认为 BitCask，也可以换成任何实现了 [`Engine`] 的引擎（如 [`Lsm`]）
+pub struct Database<E: Engine + 'static = BitCask> {
+    mvcc: Arc<MVCC<E>>,
 }
 
-impl Database {
-    pub fn new(engine: BitCask) -> Self {
+impl<E: Engine + 'static> Database<E> {
+    pub fn new(engine: E) -> Result<Self> {
+        Ok(Self {
+            mvcc: Arc::new(MVCC::new(engine)?),
+        })
+    }
+
+    /// 只读数据库：可以在另一个进程持有数据目录时执行查询，写入语句返回 [`db_error::Error::ReadOnly`]
+    /// 引擎应以只读方式打开（如 [`BitCask::open_read_only`]），见 [`MVCC::new_read_only`]
+    pub fn new_read_only(engine: E) -> Self {
         Self {
-            mvcc: Arc::new(Mutex::new(MVCC::new(engine))),
+            mvcc: Arc::new(MVCC::new_read_only(engine)),
         }
     }
 
+    /// 以自动提交方式执行一条语句
+    /// 执行过程是同步阻塞的（磁盘读写），放到阻塞线程池中，避免占用异步运行时的工作线程
     pub async fn execute(&self, sql: &str) -> Result<ResultSet> {
-        let statement = Parser::pasre(sql)?;
-        let mvcc = self.mvcc.lock().await;
-        let plan = plan(&mvcc, &statement)?;
-        let result = execute(&mvcc, &plan)?;
-        Ok(result)
+        self.execute_with(sql, true).await
+    }
+
+    /// 执行来自网络的语句：COPY、BACKUP TO 读写的是服务器上任意指定的路径，直接拒绝，
+    /// 只能在本地通过 `exec` / `cli` 执行
+    pub async fn execute_remote(&self, sql: &str) -> Result<ResultSet> {
+        self.execute_with(sql, false).await
+    }
+
+    async fn execute_with(&self, sql: &str, allow_files: bool) -> Result<ResultSet> {
+        let mvcc = self.mvcc.clone();
+        let sql = sql.to_string();
+        run_blocking(move || {
+            let statement = Parser::pasre(&sql)?;
+            if !allow_files && statement.accesses_files() {
+                return errinput!("COPY and BACKUP TO access server files and are not allowed over the network");
+            }
+            let plan = plan(&mvcc, &statement)?;
+            execute(&mvcc, &plan)
+        })
+        .await
+    }
+
+    /// 打开一个会话，支持 BEGIN / COMMIT / ROLLBACK 显式事务
+    pub fn connect(&self) -> Connection<E> {
+        Connection {
+            mvcc: self.mvcc.clone(),
+            session: Session::new(),
+        }
+    }
+
+    /// 立即执行一次 MVCC 垃圾回收
+    pub async fn vacuum(&self) -> Result<GcReport> {
+        let mvcc = self.mvcc.clone();
+        run_blocking(move || mvcc.gc()).await
+    }
+
+    /// 在线备份：在 dest 下生成数据库的一致副本，可以直接作为数据目录打开
+    /// 备份期间查询照常执行，写入等待备份完成
+    pub async fn backup(&self, dest: impl Into<std::path::PathBuf>) -> Result<()> {
+        let mvcc = self.mvcc.clone();
+        let dest = dest.into();
+        run_blocking(move || mvcc.checkpoint(&dest)).await
+    }
+
+    /// 逻辑导出所有表，与存储引擎无关，导出结果可以导入任何引擎
+    /// SQL 格式写入 dest 文件，CSV / JSON 写入 dest 目录
+    pub async fn export(&self, dest: impl Into<std::path::PathBuf>, format: Format) -> Result<TransferReport> {
+        let mvcc = self.mvcc.clone();
+        let dest = dest.into();
+        run_blocking(move || transfer::export(&mvcc, &dest, format)).await
+    }
+
+    /// 导入 [`Database::export`] 的结果，表不能已经存在
+    pub async fn import(&self, src: impl Into<std::path::PathBuf>, format: Format) -> Result<TransferReport> {
+        let mvcc = self.mvcc.clone();
+        let src = src.into();
+        run_blocking(move || transfer::import(&mvcc, &src, format)).await
+    }
+
+    /// 订阅之后提交的行变更（CDC），每个元素对应一次提交，按提交顺序到达
+    /// 消费过慢、落后超过 [`storage::cdc::CHANGE_FEED_CAPACITY`] 个提交时产生一个错误，
+    /// 之后从最新的提交继续；需要补齐时用 [`Database::changes_since`] 按序号重新拉取
+    pub fn change_stream(&self) -> impl Stream<Item = Result<ChangeSet>> + Send + 'static {
+        BroadcastStream::new(self.mvcc.changes().subscribe()).map(|event| match event {
+            Ok(event) => ChangeSet::decode(&event),
+            Err(BroadcastStreamRecvError::Lagged(n)) => errdata!("change feed lagged, {n} commits dropped"),
+        })
+    }
+
+    /// 长轮询：返回提交序号大于 seq 的行变更，暂时没有时最多等待 timeout
+    /// 只保留最近的提交，序号过旧时只能拿到仍保留的部分
+    pub async fn changes_since(&self, seq: u64, timeout: Duration) -> Result<Vec<ChangeSet>> {
+        let events = self.mvcc.changes().wait_since(seq, timeout).await?;
+        events.iter().map(|event| ChangeSet::decode(event)).collect()
+    }
+
+    /// 启动后台垃圾回收任务，每隔 interval() 执行一次 VACUUM
+    /// 每轮都重新读取间隔，配置热加载后立即生效；间隔为 0 时暂停回收，每秒检查一次是否重新开启
+    pub fn spawn_gc<F>(&self, interval: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Duration + Send + 'static,
+    {
+        let mvcc = self.mvcc.clone();
+        tokio::spawn(async move {
+            loop {
+                // 先等待一个间隔，以免启动时就做一次全量扫描
+                let wait = interval();
+                if wait.is_zero() {
+                    tokio::time::sleep(GC_PAUSED_POLL).await;
+                    continue;
+                }
+                tokio::time::sleep(wait).await;
+                if interval().is_zero() {
+                    continue;
+                }
+                let mvcc = mvcc.clone();
+                match run_blocking(move || mvcc.gc()).await {
+                    Ok(report) => tracing::debug!("后台垃圾回收完成: {:?}", report),
+                    Err(e) => tracing::error!("后台垃圾回收失败: {}", e),
+                }
+            }
+        })
+    }
+}
+
+/// 后台垃圾回收关闭时检查间隔是否重新开启的周期
+const GC_PAUSED_POLL: Duration = Duration::from_secs(1);
+
+/// 数据库连接，持有一个 SQL 会话
+/// 与 [`Database::execute`] 的自动提交不同，连接上的语句可以组成显式事务
+pub struct Connection<E: Engine + 'static = BitCask> {
+    mvcc: Arc<MVCC<E>>,
+    session: Session<E>,
+}
+
+impl<E: Engine + 'static> Connection<E> {
+    /// 连接上的语句按顺序执行，直接在当前任务中运行
+    pub async fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        self.session.execute(&self.mvcc, sql)
+    }
+
+    /// 当前是否处于显式事务中
+    pub fn in_transaction(&self) -> bool {
+        self.session.in_transaction()
+    }
+}
+
+/// 在阻塞线程池中执行同步的数据库操作
+async fn run_blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => errdata!("database task failed: {e}"),
     }
 }
@@ -1,17 +1,24 @@
-use axum::routing::post;
+use axum::extract::Query;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
 use axum::{extract::State, Json, Router};
 use clap::{Parser, Subcommand};
-use mini_db::cfg::watch_config;
+use mini_db::cfg::{get_db_base, get_gc_interval_secs, get_storage_engine, watch_config, EngineKind};
 use mini_db::init_tracing;
-use mini_db::sql::execution::ResultSet;
+use mini_db::sql::execution::{ChangeSet, Format, ResultSet};
+use mini_db::storage::{DirLock, Keyring};
 use mini_db::types::Value;
-use mini_db::Database;
+use mini_db::utils::{Formatter, MVCC};
+use mini_db::{AnyEngine, BitCask, Database};
 use serde::{Deserialize, Serialize};
 use std::io::{self, BufRead, Write};
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt};
 
 #[derive(Deserialize)]
 struct SqlRequest {
@@ -26,14 +33,41 @@ struct SqlResponse {
     error: Option<String>,
 }
 
+/// 长轮询变更的参数
+#[derive(Deserialize)]
+struct ChangesQuery {
+    /// 只返回提交序号大于它的变更
+    #[serde(default)]
+    since: u64,
+    /// 没有新变更时最多等待的毫秒数
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ChangesResponse {
+    success: bool,
+    changes: Vec<serde_json::Value>,
+    error: Option<String>,
+}
+
+/// 长轮询默认和最长的等待时间
+const CHANGES_DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const CHANGES_MAX_TIMEOUT_MS: u64 = 60_000;
+
 #[derive(Parser)]
 #[command(name = "mini-db")]
 #[command(about = "A mini SQL database with Bitcask storage engine")]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Storage engine: memory, bitcask, lsm or btree (defaults to `storage_engine` in the config)
+    #[arg(long, global = true)]
+    engine: Option<EngineKind>,
 }
 
+/// HTTP 服务使用的数据库类型，引擎在启动时选择
+type Db = Database<AnyEngine>;
+
 #[derive(Subcommand)]
 enum Commands {
     /// Start the HTTP server (default if no subcommand is given)
@@ -42,66 +76,295 @@ enum Commands {
     Exec {
         /// SQL statement to execute
         sql: String,
+        /// Open the database read-only, allowed while another process holds it (bitcask only)
+        #[arg(long)]
+        read_only: bool,
     },
     /// Start an interactive SQL REPL
-    Cli,
+    Cli {
+        /// Open the database read-only, allowed while another process holds it (bitcask only)
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Print every log entry in a data directory
+    Dump {
+        /// Data directory (defaults to the configured storage path)
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Inspect a database that another process is using instead of locking the directory
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Verify checksums and KeyDir consistency of a data directory
+    Fsck {
+        /// Data directory (defaults to the configured storage path)
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Inspect a database that another process is using instead of locking the directory
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Rebuild a clean data directory from salvageable entries
+    Repair {
+        /// Destination directory, must be empty or not exist
+        dest: PathBuf,
+        /// Data directory to repair (defaults to the configured storage path)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Re-encrypt all live data with the current key of the encryption keyfile
+    RotateKey {
+        /// Data directory (defaults to the configured storage path)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Restore a backup made with `BACKUP TO` into an empty data directory
+    Restore {
+        /// Backup directory
+        backup: PathBuf,
+        /// Data directory to restore into, must be empty or not exist (defaults to the configured storage path)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Export all tables as a SQL dump, or as CSV / JSON lines files with a schema.sql
+    Export {
+        /// Destination file (sql) or empty directory (csv, json)
+        dest: PathBuf,
+        /// Output format: sql, csv or json
+        #[arg(long, default_value = "sql")]
+        format: Format,
+    },
+    /// Import the output of `export` into the database
+    Import {
+        /// Source file (sql) or directory (csv, json)
+        src: PathBuf,
+        /// Input format: sql, csv or json
+        #[arg(long, default_value = "sql")]
+        format: Format,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    let engine = cli.engine.unwrap_or_else(get_storage_engine);
 
     match cli.command.unwrap_or(Commands::Server) {
         Commands::Server => {
-            if let Err(e) = run_server().await {
+            if let Err(e) = run_server(engine).await {
                 eprintln!("Server error: {e}");
                 std::process::exit(1);
             }
         }
-        Commands::Exec { sql } => {
-            if let Err(e) = run_exec(&sql).await {
+        Commands::Exec { sql, read_only } => {
+            if let Err(e) = run_exec(engine, &sql, read_only).await {
                 eprintln!("Error: {e}");
                 std::process::exit(1);
             }
         }
-        Commands::Cli => {
-            if let Err(e) = run_cli().await {
+        Commands::Cli { read_only } => {
+            if let Err(e) = run_cli(engine, read_only).await {
                 eprintln!("CLI error: {e}");
                 std::process::exit(1);
             }
         }
+        Commands::Dump { path, read_only } => {
+            if let Err(e) = require_bitcask(engine, "dump").and_then(|_| run_dump(&data_dir(path), read_only)) {
+                eprintln!("Dump error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::Fsck { path, read_only } => {
+            match require_bitcask(engine, "fsck").and_then(|_| run_fsck(&data_dir(path), read_only)) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(2),
+                Err(e) => {
+                    eprintln!("Fsck error: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Repair { dest, path } => {
+            if let Err(e) = require_bitcask(engine, "repair").and_then(|_| run_repair(&data_dir(path), &dest)) {
+                eprintln!("Repair error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::RotateKey { path } => {
+            if let Err(e) = require_bitcask(engine, "rotate-key").and_then(|_| run_rotate_key(&data_dir(path))) {
+                eprintln!("Rotate key error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::Restore { backup, path } => {
+            if let Err(e) = require_bitcask(engine, "restore").and_then(|_| run_restore(&backup, &data_dir(path))) {
+                eprintln!("Restore error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::Export { dest, format } => {
+            if let Err(e) = run_export(engine, &dest, format).await {
+                eprintln!("Export error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Commands::Import { src, format } => {
+            if let Err(e) = run_import(engine, &src, format).await {
+                eprintln!("Import error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// 维护命令未指定目录时使用配置中的存储路径
+fn data_dir(path: Option<PathBuf>) -> PathBuf {
+    path.unwrap_or_else(|| PathBuf::from(get_db_base()))
+}
+
+/// 离线维护命令直接读取 BitCask 的日志文件，其他引擎没有对应的实现
+fn require_bitcask(engine: EngineKind, command: &str) -> mini_db::db_error::Result<()> {
+    if engine != EngineKind::Bitcask {
+        return mini_db::errinput!("`{command}` only supports the bitcask engine, got {engine}");
+    }
+    Ok(())
+}
+
+/// 离线检查默认持有目录锁，避免其他进程在检查过程中写入；只读模式不加锁，直接检查运行中的数据库，
+/// 此时活跃文件末尾正在写入的条目可能显示为截断
+fn lock_for_inspection(dir: &Path, read_only: bool) -> mini_db::db_error::Result<Option<DirLock>> {
+    if read_only {
+        return Ok(None);
+    }
+    BitCask::lock_dir(dir).map(Some)
+}
+
+fn run_dump(dir: &Path, read_only: bool) -> mini_db::db_error::Result<()> {
+    let _lock = lock_for_inspection(dir, read_only)?;
+    BitCask::dump(dir, |record| {
+        match record {
+            Ok(r) => println!(
+                "{}@{} tstamp={}{}{}{} {}",
+                r.file_id,
+                r.offset,
+                r.tstamp,
+                r.expires_at.map(|t| format!(" expires={t}")).unwrap_or_default(),
+                if r.value.is_none() { " tombstone" } else { "" },
+                if r.crc_ok { "" } else { " CRC-MISMATCH" },
+                MVCC::key_maybe_value(&r.key, r.value.as_deref()),
+            ),
+            Err(e) => println!("!! {e}"),
+        }
+        Ok(())
+    })
+}
+
+fn run_fsck(dir: &Path, read_only: bool) -> mini_db::db_error::Result<bool> {
+    let _lock = lock_for_inspection(dir, read_only)?;
+    let report = BitCask::fsck(dir)?;
+    for problem in &report.problems {
+        println!("{problem}");
     }
+    println!(
+        "{} files, {} entries, {} live keys, {} problems",
+        report.files,
+        report.entries,
+        report.live_keys,
+        report.problems.len()
+    );
+    Ok(report.is_clean())
+}
+
+fn run_repair(src: &Path, dest: &Path) -> mini_db::db_error::Result<()> {
+    let report = BitCask::repair(src, dest)?;
+    println!(
+        "salvaged {} entries ({} dropped, {} truncated files), {} live keys written to {}",
+        report.salvaged,
+        report.dropped,
+        report.truncated_files,
+        report.live_keys,
+        dest.display()
+    );
+    Ok(())
+}
+
+fn run_rotate_key(dir: &Path) -> mini_db::db_error::Result<()> {
+    let Some(keyring) = Keyring::from_config()? else {
+        return mini_db::errinput!("`rotate-key` requires encryption_keyfile in the config");
+    };
+    let key_id = keyring.current_id();
+    let mut db = BitCask::init_db_with_keyring(dir, Some(keyring))?;
+    db.rotate_keys()?;
+    println!("re-encrypted {} with key {key_id}", dir.display());
+    Ok(())
+}
+
+fn run_restore(backup: &Path, dest: &Path) -> mini_db::db_error::Result<()> {
+    let report = BitCask::restore(backup, dest)?;
+    println!(
+        "restored {} files ({} entries, {} live keys) to {}",
+        report.files,
+        report.entries,
+        report.live_keys,
+        dest.display()
+    );
+    Ok(())
 }
 
-async fn run_server() -> mini_db::db_error::Result<()> {
+async fn run_export(engine: EngineKind, dest: &Path, format: Format) -> mini_db::db_error::Result<()> {
+    let db = Database::new(mini_db::open_engine(engine)?)?;
+    let report = db.export(dest, format).await?;
+    println!("exported {} tables, {} rows to {}", report.tables, report.rows, dest.display());
+    Ok(())
+}
+
+async fn run_import(engine: EngineKind, src: &Path, format: Format) -> mini_db::db_error::Result<()> {
+    let db = Database::new(mini_db::open_engine(engine)?)?;
+    let report = db.import(src, format).await?;
+    println!("imported {} tables, {} rows from {}", report.tables, report.rows, src.display());
+    Ok(())
+}
+
+async fn run_server(engine: EngineKind) -> mini_db::db_error::Result<()> {
     init_tracing();
     watch_config(broadcast::channel(10).1).await;
 
-    let engine = mini_db::init_db()?;
-    let db = Arc::new(Database::new(engine));
+    let engine = mini_db::open_engine(engine)?;
+    let db = Arc::new(Database::new(engine)?);
+    // 间隔支持热加载，为 0 时后台任务暂停
+    db.spawn_gc(|| Duration::from_secs(get_gc_interval_secs()));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 6666));
     let listener = TcpListener::bind(addr).await?;
 
     let app = Router::new()
         .route("/", post(execute_sql))
+        .route("/changes", get(poll_changes))
+        .route("/changes/stream", get(stream_changes))
         .with_state(db);
 
     axum::serve(listener, app).await?;
     Ok(())
 }
 
-async fn run_exec(sql: &str) -> mini_db::db_error::Result<()> {
-    let engine = mini_db::init_db()?;
-    let db = Database::new(engine);
+/// 打开数据库；只读模式不获取目录锁，可以在服务运行时查询
+fn open_database(engine: EngineKind, read_only: bool) -> mini_db::db_error::Result<Db> {
+    if read_only {
+        return Ok(Database::new_read_only(mini_db::open_engine_read_only(engine)?));
+    }
+    Database::new(mini_db::open_engine(engine)?)
+}
+
+async fn run_exec(engine: EngineKind, sql: &str, read_only: bool) -> mini_db::db_error::Result<()> {
+    let db = open_database(engine, read_only)?;
     let result = db.execute(sql).await?;
     println!("{}", format_result(&result));
     Ok(())
 }
 
-async fn run_cli() -> mini_db::db_error::Result<()> {
-    let engine = mini_db::init_db()?;
-    let db = Database::new(engine);
+async fn run_cli(engine: EngineKind, read_only: bool) -> mini_db::db_error::Result<()> {
+    let db = open_database(engine, read_only)?;
+    let mut conn = db.connect();
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
@@ -137,7 +400,7 @@ async fn run_cli() -> mini_db::db_error::Result<()> {
             break;
         }
 
-        match db.execute(input).await {
+        match conn.execute(input).await {
             Ok(result) => println!("{}", format_result(&result)),
             Err(e) => eprintln!("Error: {e}"),
         }
@@ -217,10 +480,10 @@ fn value_to_string(v: &Value) -> String {
 }
 
 async fn execute_sql(
-    State(db): State<Arc<Database>>,
+    State(db): State<Arc<Db>>,
     Json(req): Json<SqlRequest>,
 ) -> Json<SqlResponse> {
-    match db.execute(&req.sql).await {
+    match db.execute_remote(&req.sql).await {
         Ok(result_set) => {
             let labels: Vec<String> = result_set.labels.iter().map(|l| l.as_header()).collect();
             let rows: Vec<Vec<serde_json::Value>> = result_set
@@ -244,6 +507,61 @@ async fn execute_sql(
     }
 }
 
+/// 长轮询已提交的行变更：GET /changes?since=<seq>&timeout_ms=<ms>
+async fn poll_changes(
+    State(db): State<Arc<Db>>,
+    Query(query): Query<ChangesQuery>,
+) -> Json<ChangesResponse> {
+    let timeout = query.timeout_ms.unwrap_or(CHANGES_DEFAULT_TIMEOUT_MS).min(CHANGES_MAX_TIMEOUT_MS);
+    match db.changes_since(query.since, Duration::from_millis(timeout)).await {
+        Ok(changes) => Json(ChangesResponse {
+            success: true,
+            changes: changes.iter().map(change_set_to_json).collect(),
+            error: None,
+        }),
+        Err(e) => Json(ChangesResponse {
+            success: false,
+            changes: vec![],
+            error: Some(e.to_string()),
+        }),
+    }
+}
+
+/// 以 server-sent events 推送之后提交的行变更：GET /changes/stream
+/// 每个事件对应一次提交，事件 id 为提交序号；落后过多时发送 error 事件
+async fn stream_changes(
+    State(db): State<Arc<Db>>,
+) -> Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>> {
+    let stream = db.change_stream().map(|changes| {
+        Ok(match changes {
+            Ok(changes) => Event::default()
+                .id(changes.seq.to_string())
+                .json_data(change_set_to_json(&changes))
+                .unwrap_or_else(|e| Event::default().event("error").data(e.to_string())),
+            Err(e) => Event::default().event("error").data(e.to_string()),
+        })
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn change_set_to_json(changes: &ChangeSet) -> serde_json::Value {
+    let row_to_json = |row: &Option<Vec<Value>>| match row {
+        Some(row) => serde_json::Value::Array(row.iter().map(value_to_json).collect()),
+        None => serde_json::Value::Null,
+    };
+    serde_json::json!({
+        "seq": changes.seq,
+        "version": changes.version,
+        "commit_time": changes.commit_time,
+        "changes": changes.changes.iter().map(|change| serde_json::json!({
+            "table": change.table,
+            "primary_key": value_to_json(&change.primary_key),
+            "before": row_to_json(&change.before),
+            "after": row_to_json(&change.after),
+        })).collect::<Vec<_>>(),
+    })
+}
+
 fn value_to_json(v: &mini_db::types::Value) -> serde_json::Value {
     use mini_db::types::Value;
     match v {
@@ -1,20 +1,39 @@
 use crate::db_error::Result;
 use crate::storage::engine::Engine;
 use crate::storage::mvcc::MVCC;
-use crate::types::Table;
+use crate::types::{Column, Table};
 use crate::utils::bin_coder;
+use serde::Deserialize;
 
 fn catalog_key(table_name: &str) -> Vec<u8> {
     format!("__catalog__\x00{}", table_name).into_bytes()
 }
 
+const CATALOG_PREFIX: &[u8] = b"__catalog__\x00";
+
+/// 增加 `expire_after` 之前写入的表结构
+#[derive(Deserialize)]
+struct TableV1 {
+    name: String,
+    primary_key: usize,
+    columns: Vec<Column>,
+}
+
+/// 解码表结构，兼容旧版本写入的表结构
+fn decode_table(bytes: &[u8]) -> Result<Table> {
+    bin_coder::decode(bytes).or_else(|e| match bin_coder::decode::<TableV1>(bytes) {
+        Ok(TableV1 { name, primary_key, columns }) => Ok(Table { name, primary_key, columns, expire_after: None }),
+        Err(_) => Err(e),
+    })
+}
+
 /// 目录管理：使用 MVCC 引擎直接存储表结构（无版本键）
 pub struct Catalog;
 
 impl Catalog {
     pub fn get_table<E: Engine>(mvcc: &MVCC<E>, name: &str) -> Result<Option<Table>> {
         match mvcc.get_unversioned(&catalog_key(name))? {
-            Some(bytes) if !bytes.is_empty() => Ok(Some(bin_coder::decode(&bytes)?)),
+            Some(bytes) if !bytes.is_empty() => Ok(Some(decode_table(&bytes)?)),
             _ => Ok(None),
         }
     }
@@ -24,6 +43,15 @@ impl Catalog {
         mvcc.set_unversioned(&catalog_key(&table.name).to_vec(), &bytes)
     }
 
+    /// 按表名排序列出所有表，已删除的表（空值）被跳过
+    pub fn list_tables<E: Engine>(mvcc: &MVCC<E>) -> Result<Vec<Table>> {
+        mvcc.scan_unversioned(CATALOG_PREFIX)?
+            .into_iter()
+            .filter(|(_, bytes)| !bytes.is_empty())
+            .map(|(_, bytes)| decode_table(&bytes))
+            .collect()
+    }
+
     pub fn drop_table<E: Engine>(mvcc: &MVCC<E>, name: &str) -> Result<()> {
         mvcc.set_unversioned(&catalog_key(name).to_vec(), &[])
     }
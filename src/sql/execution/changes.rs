@@ -0,0 +1,95 @@
+use crate::db_error::Result;
+use crate::sql::execution::executor::decode_row_key;
+use crate::storage::cdc::CommitEvent;
+use crate::storage::mvcc::Version;
+use crate::types::{Row, Value};
+use crate::utils::bin_coder;
+
+/// 一行数据的变更
+/// - 插入：before 为 None
+/// - 删除：after 为 None
+/// - 更新：两者都有
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowChange {
+    pub table: String,
+    pub primary_key: Value,
+    pub before: Option<Row>,
+    pub after: Option<Row>,
+}
+
+/// 一次提交中的所有行变更，按表名、主键编码排序
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeSet {
+    /// 提交序号，按提交顺序递增
+    pub seq: u64,
+    /// 事务版本号
+    pub version: Version,
+    /// 提交时间（毫秒级 Unix 时间戳）
+    pub commit_time: u64,
+    pub changes: Vec<RowChange>,
+}
+
+impl ChangeSet {
+    /// 把存储层的键值变更解码为行变更
+    /// 表结构通过无版本键存储，不经过事务提交，因此变更中只有行数据
+    pub fn decode(event: &CommitEvent) -> Result<Self> {
+        let mut changes = Vec::with_capacity(event.changes.len());
+        for change in &event.changes {
+            let (table, primary_key) = decode_row_key(&change.key)?;
+            let decode_row = |value: &Option<Vec<u8>>| value.as_deref().map(bin_coder::decode::<Row>).transpose();
+            changes.push(RowChange {
+                table,
+                primary_key,
+                before: decode_row(&change.before)?,
+                after: decode_row(&change.after)?,
+            });
+        }
+        Ok(Self { seq: event.seq, version: event.version, commit_time: event.commit_time, changes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::execution::Session;
+    use crate::storage::memory::Memory;
+    use crate::storage::mvcc::MVCC;
+
+    #[test]
+    fn test_row_changes() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        let mut receiver = mvcc.changes().subscribe();
+        let mut session = Session::new();
+        for sql in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)",
+            "INSERT INTO users VALUES (1, 'alice'), (2, 'bob')",
+            "BEGIN",
+            "UPDATE users SET name = 'alex' WHERE id = 1",
+            "DELETE FROM users WHERE id = 2",
+            "COMMIT",
+            "BEGIN",
+            "INSERT INTO users VALUES (3, 'carol')",
+            "ROLLBACK",
+        ] {
+            session.execute(&mvcc, sql)?;
+        }
+        let row = |id: i64, name: &str| Some(vec![Value::Integer(id), Value::String(name.into())]);
+        let change = |id: i64, before, after| RowChange {
+            table: "users".into(),
+            primary_key: Value::Integer(id),
+            before,
+            after,
+        };
+
+        // 建表只写入表结构，不产生变更事件
+        let insert = ChangeSet::decode(&receiver.try_recv().unwrap())?;
+        assert_eq!(insert.changes, vec![change(1, None, row(1, "alice")), change(2, None, row(2, "bob"))]);
+        let update = ChangeSet::decode(&receiver.try_recv().unwrap())?;
+        assert!(update.seq > insert.seq);
+        assert_eq!(update.changes, vec![change(1, row(1, "alice"), row(1, "alex")), change(2, row(2, "bob"), None)]);
+        // 回滚的事务不发布
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(mvcc.changes().since(insert.seq)?.len(), 1);
+        Ok(())
+    }
+}
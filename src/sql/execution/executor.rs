@@ -1,13 +1,17 @@
 use crate::db_error::{Error, Result};
 use crate::sql::execution::catalog::Catalog;
 use crate::sql::execution::expr::{evaluate, Scope};
+use crate::sql::execution::transfer;
 use crate::sql::parser::ast::{Direction, Expression, JoinType, Literal, Operator};
 use crate::sql::planner::plan::{Aggregate, Node, Plan};
 use crate::storage::engine::Engine;
-use crate::storage::mvcc::MVCC;
-use crate::types::{Label, Row, Table, Value};
-use crate::utils::bin_coder;
+use crate::storage::mvcc::{Transaction, MVCC};
+use crate::storage::util::KeyRange;
+use crate::types::{DataType, Label, Row, Table, Value};
+use crate::utils::{bin_coder, get_timestamp_millis};
 use std::collections::HashMap;
+use std::ops::Bound;
+use std::path::Path;
 
 /// 执行结果
 #[derive(Debug)]
@@ -24,6 +28,32 @@ impl ResultSet {
 
 /// 执行计划
 pub fn execute<E: Engine>(mvcc: &MVCC<E>, plan: &Plan) -> Result<ResultSet> {
+    // 自动提交：每条语句在独立的事务中执行，只读语句不占用版本号
+    // COPY FROM 自行分批提交，避免一个事务的写集过大
+    if let Plan::CopyFrom { table, path, format } = plan {
+        let rows = transfer::copy_from_file(mvcc, None, table, Path::new(path), (*format).into())?;
+        return Ok(copy_result(rows));
+    }
+    let txn = match plan {
+        Plan::Select { .. } | Plan::CreateTable { .. } | Plan::Vacuum | Plan::Backup { .. } | Plan::CopyTo { .. } => {
+            mvcc.begin_readonly()?
+        }
+        _ => mvcc.begin()?,
+    };
+    match execute_in(mvcc, &txn, plan) {
+        Ok(result) => {
+            txn.commit()?;
+            Ok(result)
+        }
+        Err(err) => {
+            txn.rollback()?;
+            Err(err)
+        }
+    }
+}
+
+/// 在给定事务中执行计划，不提交事务
+pub fn execute_in<E: Engine>(mvcc: &MVCC<E>, txn: &Transaction<E>, plan: &Plan) -> Result<ResultSet> {
     match plan {
         Plan::CreateTable { schema } => {
             Catalog::set_table(mvcc, schema)?;
@@ -33,7 +63,7 @@ pub fn execute<E: Engine>(mvcc: &MVCC<E>, plan: &Plan) -> Result<ResultSet> {
             match Catalog::get_table(mvcc, name)? {
                 Some(_) => {
                     // 删除表的所有数据
-                    delete_all_rows(mvcc, name)?;
+                    delete_all_rows(txn, name)?;
                     Catalog::drop_table(mvcc, name)?;
                 }
                 None if !if_exists => {
@@ -44,8 +74,7 @@ pub fn execute<E: Engine>(mvcc: &MVCC<E>, plan: &Plan) -> Result<ResultSet> {
             Ok(ResultSet::empty())
         }
         Plan::Insert { table, column_map, source } => {
-            let txn = mvcc.begin()?;
-            let values = execute_node(mvcc, source, &[])?;
+            let values = execute_node(mvcc, txn, source, &[])?;
             for row in values.rows {
                 // 如果有 column_map，需要重排/补全列
                 let final_row = if let Some(ref map) = column_map {
@@ -57,29 +86,25 @@ pub fn execute<E: Engine>(mvcc: &MVCC<E>, plan: &Plan) -> Result<ResultSet> {
                 } else {
                     row
                 };
-                insert_row(&txn, table, &final_row)?;
+                insert_row(txn, table, &final_row)?;
             }
-            txn.commit()?;
             Ok(ResultSet::empty())
         }
         Plan::Delete { table, source, .. } => {
-            let txn = mvcc.begin()?;
             let schema = Catalog::get_table(mvcc, table)?
                 .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
             let scope = table_scope(&schema);
-            let result = execute_node(mvcc, source, &scope.labels)?;
+            let result = execute_node(mvcc, txn, source, &scope.labels)?;
             for row in result.rows {
                 let pk = row.get(schema.primary_key).cloned().unwrap_or(Value::Null);
                 let key = row_key(table, &pk);
                 txn.delete(&key)?;
             }
-            txn.commit()?;
             Ok(ResultSet::empty())
         }
         Plan::Update { table, expressions, source, .. } => {
-            let txn = mvcc.begin()?;
             let scope = table_scope(table);
-            let result = execute_node(mvcc, source, &scope.labels)?;
+            let result = execute_node(mvcc, txn, source, &scope.labels)?;
             for mut row in result.rows {
                 for (col_idx, expr) in expressions {
                     let val = evaluate(expr, &row, &scope)?;
@@ -87,19 +112,55 @@ pub fn execute<E: Engine>(mvcc: &MVCC<E>, plan: &Plan) -> Result<ResultSet> {
                 }
                 let pk = row.get(table.primary_key).cloned().unwrap_or(Value::Null);
                 let key = row_key(&table.name, &pk);
-                let val = bin_coder::encode(&row)?;
+                // 更新会刷新行的过期时间
+                let val = encode_row(table, &row)?;
                 txn.set(&key, Some(&val))?;
             }
-            txn.commit()?;
             Ok(ResultSet::empty())
         }
         Plan::Select { root, labels } => {
-            execute_node(mvcc, root, labels)
+            execute_node(mvcc, txn, root, labels)
+        }
+        Plan::Vacuum => {
+            // 先在独立的写事务中删除已过期的行，再回收旧版本
+            let expired = purge_expired_rows(mvcc)?;
+            let report = mvcc.gc()?;
+            let labels =
+                ["watermark", "versions_removed", "tombstones_removed", "snapshots_removed", "expired_rows_removed"]
+                    .iter()
+                    .map(|l| Label::Unqualified(l.to_string()))
+                    .collect();
+            let row =
+                [report.watermark, report.versions_removed, report.tombstones_removed, report.snapshots_removed, expired]
+                    .iter()
+                .map(|n| Value::Integer(*n as i64))
+                .collect();
+            Ok(ResultSet { labels, rows: vec![row] })
+        }
+        Plan::Backup { path } => {
+            if Path::new(path).exists() {
+                return Err(Error::UnExpectedInput(format!("backup destination {path} already exists")));
+            }
+            mvcc.checkpoint(Path::new(path))?;
+            Ok(ResultSet::empty())
+        }
+        Plan::CopyFrom { table, path, format } => {
+            let rows = transfer::copy_from_file(mvcc, Some(txn), table, Path::new(path), (*format).into())?;
+            Ok(copy_result(rows))
+        }
+        Plan::CopyTo { table, path, format } => {
+            let rows = transfer::copy_to_file(txn, table, Path::new(path), (*format).into())?;
+            Ok(copy_result(rows))
         }
     }
 }
 
-fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label]) -> Result<ResultSet> {
+/// COPY 的结果：一行，只有导入或导出的行数
+fn copy_result(rows: u64) -> ResultSet {
+    ResultSet { labels: vec![Label::Unqualified("rows".to_string())], rows: vec![vec![Value::Integer(rows as i64)]] }
+}
+
+fn execute_node<E: Engine>(mvcc: &MVCC<E>, txn: &Transaction<E>, node: &Node, parent_labels: &[Label]) -> Result<ResultSet> {
     match node {
         Node::Empty => Ok(ResultSet::empty()),
         Node::Values { rows } => {
@@ -115,20 +176,32 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             // VALUES 节点的 label 由 parent 提供
             Ok(ResultSet { labels: parent_labels.to_vec(), rows: result_rows })
         }
-        Node::Scan { table } => {
+        Node::Scan { table, order: Some(direction), limit } => {
+            let schema = Catalog::get_table(mvcc, table)?
+                .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
+            let rows = scan_primary_key_order(txn, &schema, direction, *limit)?;
+            let labels = schema
+                .columns
+                .iter()
+                .map(|c| Label::Qualified(table.clone(), c.name.clone()))
+                .collect();
+            Ok(ResultSet { labels, rows })
+        }
+        Node::Scan { table, order: None, .. } => {
             let schema = Catalog::get_table(mvcc, table)?
                 .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
-            let txn = mvcc.begin_readonly()?;
             let prefix = table.as_bytes();
+            let now = get_timestamp_millis();
             let mut rows = Vec::new();
-            let mut scan = txn.scan_prefix(prefix);
+            let mut scan = txn.scan_prefix(prefix)?;
             while let Some((key, value)) = scan.next().transpose()? {
                 // 跳过目录键
                 if key.starts_with(b"__catalog__") {
                     continue;
                 }
-                let row: Row = bin_coder::decode(&value)?;
-                rows.push(row);
+                if let Some(row) = decode_row(&value, now)? {
+                    rows.push(row);
+                }
             }
             let labels = schema
                 .columns
@@ -137,8 +210,30 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
                 .collect();
             Ok(ResultSet { labels, rows })
         }
+        Node::History { table, primary_key } => {
+            let schema = Catalog::get_table(mvcc, table)?
+                .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
+            let pk = evaluate(primary_key, &Vec::new(), &Scope::new(vec![]))?;
+            let mut rows = Vec::new();
+            for (version, value) in txn.history(&row_key(table, &pk))? {
+                let mut row = vec![Value::Integer(version as i64), Value::Boolean(value.is_none())];
+                match value {
+                    Some(value) => row.extend(bin_coder::decode::<Row>(&value)?),
+                    // 删除的版本只保留主键，其余列为 NULL
+                    None => row.extend((0..schema.columns.len()).map(|i| {
+                        if i == schema.primary_key { pk.clone() } else { Value::Null }
+                    })),
+                }
+                rows.push(row);
+            }
+            let labels = [Label::Unqualified("version".into()), Label::Unqualified("deleted".into())]
+                .into_iter()
+                .chain(schema.columns.iter().map(|c| Label::Qualified(table.clone(), c.name.clone())))
+                .collect();
+            Ok(ResultSet { labels, rows })
+        }
         Node::Filter { predicate, source } => {
-            let mut result = execute_node(mvcc, source, parent_labels)?;
+            let mut result = execute_node(mvcc, txn, source, parent_labels)?;
             let scope = Scope::new(result.labels.clone());
             result.rows.retain(|row| {
                 match evaluate(predicate, row, &scope) {
@@ -149,7 +244,7 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             Ok(result)
         }
         Node::Projection { expressions, source } => {
-            let source_result = execute_node(mvcc, source, parent_labels)?;
+            let source_result = execute_node(mvcc, txn, source, parent_labels)?;
             let scope = Scope::new(source_result.labels.clone());
             let mut new_labels = Vec::new();
             let mut rows = Vec::with_capacity(source_result.rows.len());
@@ -195,8 +290,8 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             Ok(ResultSet { labels: new_labels, rows })
         }
         Node::NestedLoopJoin { left, right, r#type, predicate } => {
-            let left_result = execute_node(mvcc, left, parent_labels)?;
-            let right_result = execute_node(mvcc, right, parent_labels)?;
+            let left_result = execute_node(mvcc, txn, left, parent_labels)?;
+            let right_result = execute_node(mvcc, txn, right, parent_labels)?;
             let left_scope = Scope::new(left_result.labels.clone());
             let right_scope = Scope::new(right_result.labels.clone());
             let joined_scope = Scope::join(&left_scope, &right_scope);
@@ -238,7 +333,7 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             Ok(ResultSet { labels, rows })
         }
         Node::Order { expressions, source } => {
-            let mut result = execute_node(mvcc, source, parent_labels)?;
+            let mut result = execute_node(mvcc, txn, source, parent_labels)?;
             let scope = Scope::new(result.labels.clone());
             let order_keys: Result<Vec<_>> = expressions
                 .iter()
@@ -273,7 +368,7 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             Ok(result)
         }
         Node::Limit { offset, limit, source } => {
-            let mut result = execute_node(mvcc, source, parent_labels)?;
+            let mut result = execute_node(mvcc, txn, source, parent_labels)?;
             let scope = Scope::new(result.labels.clone());
             let off = if let Some(expr) = offset {
                 match evaluate(expr, &Vec::new(), &scope)? {
@@ -304,7 +399,7 @@ fn execute_node<E: Engine>(mvcc: &MVCC<E>, node: &Node, parent_labels: &[Label])
             Ok(result)
         }
         Node::Aggregate { group_by, aggregates, source } => {
-            let source_result = execute_node(mvcc, source, parent_labels)?;
+            let source_result = execute_node(mvcc, txn, source, parent_labels)?;
             let source_scope = Scope::new(source_result.labels.clone());
 
             // 分组
@@ -486,23 +581,180 @@ fn encode_pk(v: &Value) -> Vec<u8> {
     }
 }
 
-fn insert_row<E: Engine>(txn: &crate::storage::mvcc::Transaction<E>, table: &Table, row: &Row) -> Result<()> {
+/// 按主键顺序扫描表，最多读取 limit 行
+/// 降序时反向扫描，`ORDER BY pk DESC LIMIT n` 只需要读取表尾的 n 行
+fn scan_primary_key_order<E: Engine>(
+    txn: &Transaction<E>,
+    table: &Table,
+    direction: &Direction,
+    limit: Option<usize>,
+) -> Result<Vec<Row>> {
+    let data_type = table.columns[table.primary_key].data_type;
+    let mut ranges = primary_key_ranges(&table.name, data_type);
+    if *direction == Direction::Desc {
+        ranges.reverse();
+    }
+    let now = get_timestamp_millis();
+    let mut rows = Vec::new();
+    for range in ranges {
+        let remaining = limit.map(|limit| limit - rows.len());
+        if remaining == Some(0) {
+            break;
+        }
+        let scan = txn.scan(range)?;
+        // 过期的行会被跳过，此时无法预知需要读取多少行，只能边读边数
+        let scan = match remaining {
+            Some(remaining) if table.expire_after.is_none() => scan.with_limit(remaining),
+            _ => scan,
+        };
+        let items: Box<dyn Iterator<Item = _>> = match direction {
+            Direction::Asc => Box::new(scan),
+            Direction::Desc => Box::new(scan.rev()),
+        };
+        for item in items {
+            let (_, value) = item?;
+            if let Some(row) = decode_row(&value, now)? {
+                rows.push(row);
+            }
+            if limit == Some(rows.len()) {
+                break;
+            }
+        }
+    }
+    Ok(rows)
+}
+
+
+/// 表中行键的范围，按主键值升序排列
+/// 整数主键以大端补码编码，负数的字节序排在非负数之后，需要拆成两段并交换顺序
+fn primary_key_ranges(table: &str, data_type: DataType) -> Vec<KeyRange> {
+    let key = |suffix: &[u8]| [table.as_bytes(), b"\x00", suffix].concat();
+    // 表名之后的分隔符为 0x00，0x01 之前的键都属于这张表
+    let end = Bound::Excluded([table.as_bytes(), b"\x01"].concat());
+    match data_type {
+        DataType::Integer => vec![
+            (Bound::Included(key(&[])), Bound::Excluded(key(&[0x02]))),
+            (Bound::Included(key(&[0x02, 0x80])), Bound::Excluded(key(&[0x03]))),
+            (Bound::Included(key(&[0x02])), Bound::Excluded(key(&[0x02, 0x80]))),
+            (Bound::Included(key(&[0x03])), end),
+        ],
+        _ => vec![(Bound::Included(key(&[])), end)],
+    }
+}
+
+/// 解析行键，返回表名和主键，是 `row_key` 的逆操作
+pub(crate) fn decode_row_key(key: &[u8]) -> Result<(String, Value)> {
+    let Some(separator) = key.iter().position(|b| *b == 0x00) else {
+        return Err(Error::InvalidData(format!("invalid row key {key:?}")));
+    };
+    let table = String::from_utf8(key[..separator].to_vec())
+        .map_err(|_| Error::InvalidData(format!("invalid table name in row key {key:?}")))?;
+    let pk = decode_pk(&key[separator + 1..])
+        .ok_or_else(|| Error::InvalidData(format!("invalid primary key in row key {key:?}")))?;
+    Ok((table, pk))
+}
+
+fn decode_pk(bytes: &[u8]) -> Option<Value> {
+    let (tag, rest) = bytes.split_first()?;
+    match (tag, rest) {
+        (0x00, []) => Some(Value::Null),
+        (0x01, [b]) => Some(Value::Boolean(*b != 0)),
+        (0x02, rest) => Some(Value::Integer(i64::from_be_bytes(rest.try_into().ok()?))),
+        (0x03, rest) => Some(Value::Float(f64::from_be_bytes(rest.try_into().ok()?))),
+        (0x04, rest) => Some(Value::String(String::from_utf8(rest.to_vec()).ok()?)),
+        _ => None,
+    }
+}
+
+/// 按主键升序逐行读取整张表，返回读取的行数
+pub(crate) fn for_each_row<E: Engine>(
+    txn: &Transaction<E>,
+    table: &Table,
+    mut f: impl FnMut(Row) -> Result<()>,
+) -> Result<u64> {
+    let data_type = table.columns[table.primary_key].data_type;
+    let now = get_timestamp_millis();
+    let mut count = 0;
+    for range in primary_key_ranges(&table.name, data_type) {
+        for item in txn.scan(range)? {
+            let (_, value) = item?;
+            if let Some(row) = decode_row(&value, now)? {
+                f(row)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+pub(crate) fn insert_row<E: Engine>(txn: &Transaction<E>, table: &Table, row: &Row) -> Result<()> {
     let pk = row.get(table.primary_key).cloned().unwrap_or(Value::Null);
     let key = row_key(&table.name, &pk);
-    let val = bin_coder::encode(row)?;
+    let val = encode_row(table, row)?;
     txn.set(&key, Some(&val))
 }
 
-fn delete_all_rows<E: Engine>(mvcc: &MVCC<E>, table: &str) -> Result<()> {
+/// 编码行的值，设置了 `EXPIRE AFTER` 的表在行之后追加过期时间（Unix 毫秒）
+fn encode_row(table: &Table, row: &Row) -> Result<Vec<u8>> {
+    let mut value = bin_coder::encode(row)?;
+    if let Some(secs) = table.expire_after {
+        let expires_at = get_timestamp_millis().saturating_add(secs.saturating_mul(1000));
+        bin_coder::encode_into(&mut value, &expires_at)?;
+    }
+    Ok(value)
+}
+
+/// 解码行的值，已过期的行返回 None
+fn decode_row(value: &[u8], now: u64) -> Result<Option<Row>> {
+    let (row, len) = bin_coder::decode_prefix(value)?;
+    if len < value.len() {
+        let expires_at: u64 = bin_coder::decode(&value[len..])?;
+        if expires_at <= now {
+            return Ok(None);
+        }
+    }
+    Ok(Some(row))
+}
+
+/// 删除所有表中已过期的行，返回删除的行数
+/// 过期的行对查询已不可见，这里写入删除标记，之后由 GC 回收它们的旧版本
+fn purge_expired_rows<E: Engine>(mvcc: &MVCC<E>) -> Result<u64> {
+    let tables: Vec<Table> = Catalog::list_tables(mvcc)?.into_iter().filter(|t| t.expire_after.is_some()).collect();
+    if tables.is_empty() {
+        return Ok(0);
+    }
     let txn = mvcc.begin()?;
-    let mut scan = txn.scan_prefix(table.as_bytes());
+    let now = get_timestamp_millis();
+    let mut removed = 0;
+    for table in &tables {
+        let data_type = table.columns[table.primary_key].data_type;
+        let mut expired = Vec::new();
+        for range in primary_key_ranges(&table.name, data_type) {
+            for item in txn.scan(range)? {
+                let (key, value) = item?;
+                if decode_row(&value, now)?.is_none() {
+                    expired.push(key);
+                }
+            }
+        }
+        for key in expired {
+            txn.delete(&key)?;
+            removed += 1;
+        }
+    }
+    txn.commit()?;
+    Ok(removed)
+}
+
+fn delete_all_rows<E: Engine>(txn: &Transaction<E>, table: &str) -> Result<()> {
+    let mut scan = txn.scan_prefix(table.as_bytes())?;
     while let Some((key, _)) = scan.next().transpose()? {
         if key.starts_with(b"__catalog__") {
             continue;
         }
         txn.delete(&key)?;
     }
-    txn.commit()
+    Ok(())
 }
 
 #[cfg(test)]
@@ -512,7 +764,7 @@ mod tests {
     use crate::types::{DataType, Value};
 
     fn create_test_mvcc() -> MVCC<Memory> {
-        MVCC::new(Memory::default())
+        MVCC::new(Memory::default()).unwrap()
     }
 
     fn exec(mvcc: &MVCC<Memory>, sql: &str) -> ResultSet {
@@ -552,6 +804,58 @@ mod tests {
         assert_eq!(result.rows[2][0], Value::Integer(3));
     }
 
+    #[test]
+    fn test_order_by_primary_key_scan() {
+        let mvcc = create_test_mvcc();
+        exec(&mvcc, "CREATE TABLE events (id INTEGER PRIMARY KEY, name STRING)");
+        exec(&mvcc, "CREATE TABLE events2 (id INTEGER PRIMARY KEY, name STRING)");
+        exec(&mvcc, "INSERT INTO events VALUES (3, 'c'), (-5, 'neg'), (1, 'a'), (200, 'big'), (0, 'zero')");
+        exec(&mvcc, "INSERT INTO events2 VALUES (1000, 'other')");
+        let ids = |sql: &str| -> Vec<Value> { exec(&mvcc, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+
+        // 主键排序下推到扫描，不再生成 Order 节点
+        let stmt = crate::sql::parser::Parser::pasre("SELECT * FROM events ORDER BY id DESC LIMIT 2").unwrap();
+        let Plan::Select { root, .. } = crate::sql::planner::planner::plan(&mvcc, &stmt).unwrap() else { panic!() };
+        let Node::Limit { source, .. } = root else { panic!("unexpected plan {root:?}") };
+        let Node::Projection { source, .. } = *source else { panic!() };
+        assert_eq!(*source, Node::Scan { table: "events".into(), order: Some(Direction::Desc), limit: Some(2) });
+
+        let int = |v: &[i64]| v.iter().map(|i| Value::Integer(*i)).collect::<Vec<_>>();
+        assert_eq!(ids("SELECT * FROM events ORDER BY id DESC LIMIT 2"), int(&[200, 3]));
+        assert_eq!(ids("SELECT * FROM events ORDER BY id"), int(&[-5, 0, 1, 3, 200]));
+        assert_eq!(ids("SELECT * FROM events ORDER BY events.id DESC"), int(&[200, 3, 1, 0, -5]));
+        assert_eq!(ids("SELECT * FROM events ORDER BY id OFFSET 1 LIMIT 2"), int(&[0, 1]));
+        assert_eq!(ids("SELECT * FROM events WHERE id < 3 ORDER BY id DESC LIMIT 2"), int(&[1, 0]));
+        assert_eq!(ids("SELECT id FROM events ORDER BY id DESC LIMIT 10"), int(&[200, 3, 1, 0, -5]));
+
+        exec(&mvcc, "CREATE TABLE names (name STRING PRIMARY KEY)");
+        exec(&mvcc, "INSERT INTO names VALUES ('bob'), ('alice'), ('carol')");
+        let names = ids("SELECT * FROM names ORDER BY name DESC LIMIT 2");
+        assert_eq!(names, vec![Value::String("carol".into()), Value::String("bob".into())]);
+    }
+
+    #[test]
+    fn test_history() {
+        let mvcc = create_test_mvcc();
+        exec(&mvcc, "CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)");
+        exec(&mvcc, "INSERT INTO users VALUES (1, 'alice'), (2, 'bob')");
+        exec(&mvcc, "UPDATE users SET name = 'alex' WHERE id = 1");
+        exec(&mvcc, "DELETE FROM users WHERE id = 1");
+
+        let result = exec(&mvcc, "SELECT deleted, name FROM HISTORY(users, 1)");
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Boolean(false), Value::String("alice".into())],
+                vec![Value::Boolean(false), Value::String("alex".into())],
+                vec![Value::Boolean(true), Value::Null],
+            ]
+        );
+        let result = exec(&mvcc, "SELECT version FROM HISTORY(users, 1) WHERE deleted = TRUE");
+        assert_eq!(result.rows.len(), 1);
+        assert!(exec(&mvcc, "SELECT * FROM HISTORY(users, 3)").rows.is_empty());
+    }
+
     #[test]
     fn test_update() {
         let mvcc = create_test_mvcc();
@@ -581,4 +885,63 @@ mod tests {
         let result = exec(&mvcc, "SELECT category, COUNT(*) FROM orders GROUP BY category");
         assert_eq!(result.rows.len(), 2);
     }
+
+    #[test]
+    fn test_vacuum() {
+        let mvcc = create_test_mvcc();
+        exec(&mvcc, "CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)");
+        exec(&mvcc, "INSERT INTO users VALUES (1, 'alice')");
+        exec(&mvcc, "UPDATE users SET name = 'alex' WHERE id = 1");
+        exec(&mvcc, "UPDATE users SET name = 'bob' WHERE id = 1");
+        let result = exec(&mvcc, "VACUUM");
+        assert_eq!(result.labels.len(), 5);
+        assert_eq!(result.rows.len(), 1);
+        assert!(matches!(result.rows[0][1], Value::Integer(n) if n >= 2));
+
+        let result = exec(&mvcc, "SELECT * FROM users");
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0][1], Value::String("bob".into()));
+    }
+
+    #[test]
+    fn test_expire_after() -> Result<()> {
+        let mvcc = create_test_mvcc();
+        exec(&mvcc, "CREATE TABLE sessions (id INTEGER PRIMARY KEY, user STRING) EXPIRE AFTER 3600");
+        exec(&mvcc, "INSERT INTO sessions VALUES (1, 'alice'), (2, 'bob'), (3, 'carol')");
+        // 手动写入一个已经过期的行
+        let txn = mvcc.begin()?;
+        let mut value = bin_coder::encode(vec![Value::Integer(2), Value::String("bob".into())])?;
+        bin_coder::encode_into(&mut value, &1u64)?;
+        txn.set(&row_key("sessions", &Value::Integer(2)), Some(&value))?;
+        txn.commit()?;
+
+        let ids = |sql: &str| -> Vec<Value> { exec(&mvcc, sql).rows.into_iter().map(|row| row[0].clone()).collect() };
+        assert_eq!(ids("SELECT * FROM sessions"), vec![Value::Integer(1), Value::Integer(3)]);
+        assert_eq!(ids("SELECT * FROM sessions ORDER BY id DESC LIMIT 2"), vec![Value::Integer(3), Value::Integer(1)]);
+        assert_eq!(ids("SELECT * FROM sessions ORDER BY id LIMIT 1"), vec![Value::Integer(1)]);
+        assert!(ids("SELECT * FROM sessions WHERE user = 'bob'").is_empty());
+
+        // 过期的行可以重新插入
+        exec(&mvcc, "INSERT INTO sessions VALUES (2, 'bob')");
+        assert_eq!(ids("SELECT * FROM sessions WHERE user = 'bob'"), vec![Value::Integer(2)]);
+
+        // VACUUM 删除过期的行，最后一列是删除的行数
+        let txn = mvcc.begin()?;
+        txn.set(&row_key("sessions", &Value::Integer(3)), Some(&value))?;
+        txn.commit()?;
+        let result = exec(&mvcc, "VACUUM");
+        assert_eq!(result.rows[0][4], Value::Integer(1));
+        assert_eq!(exec(&mvcc, "VACUUM").rows[0][4], Value::Integer(0));
+        assert_eq!(ids("SELECT * FROM sessions"), vec![Value::Integer(1), Value::Integer(2)]);
+        assert_eq!(Catalog::get_table(&mvcc, "sessions")?.unwrap().expire_after, Some(3600));
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_unsupported_engine() {
+        let mvcc = create_test_mvcc();
+        let stmt = crate::sql::parser::Parser::pasre("BACKUP TO '/tmp/never-created'").unwrap();
+        let plan = crate::sql::planner::planner::plan(&mvcc, &stmt).unwrap();
+        assert!(execute(&mvcc, &plan).is_err());
+    }
 }
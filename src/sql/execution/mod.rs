@@ -1,5 +1,11 @@
 pub mod catalog;
+pub mod changes;
 pub mod expr;
 pub mod executor;
+pub mod session;
+pub mod transfer;
 
-pub use executor::{execute, ResultSet};
+pub use changes::{ChangeSet, RowChange};
+pub use executor::{execute, execute_in, ResultSet};
+pub use session::Session;
+pub use transfer::{Format, TransferReport};
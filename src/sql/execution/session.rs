@@ -0,0 +1,191 @@
+use crate::db_error::Result;
+use crate::errinput;
+use crate::sql::execution::executor::{execute, execute_in, ResultSet};
+use crate::sql::parser::ast::{AsOf, Isolation, Statement};
+use crate::sql::parser::Parser;
+use crate::sql::planner::planner::plan;
+use crate::storage::engine::Engine;
+use crate::storage::mvcc::{IsolationLevel, Transaction, MVCC};
+
+/// SQL 会话：维护一个连接上的显式事务
+/// - 没有显式事务时，每条语句自动提交
+/// - `BEGIN` 之后的语句都在同一个事务中执行，直到 `COMMIT` / `ROLLBACK`
+pub struct Session<E: Engine> {
+    // 当前显式事务
+    txn: Option<Transaction<E>>,
+}
+
+impl<E: Engine> Default for Session<E> {
+    fn default() -> Self {
+        Self { txn: None }
+    }
+}
+
+impl<E: Engine> Session<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 当前是否处于显式事务中
+    pub fn in_transaction(&self) -> bool {
+        self.txn.is_some()
+    }
+
+    /// 解析并执行一条语句
+    pub fn execute(&mut self, mvcc: &MVCC<E>, sql: &str) -> Result<ResultSet> {
+        let statement = Parser::pasre(sql)?;
+        self.execute_statement(mvcc, &statement)
+    }
+
+    /// 执行一条已解析的语句
+    pub fn execute_statement(&mut self, mvcc: &MVCC<E>, statement: &Statement) -> Result<ResultSet> {
+        match statement {
+            Statement::Begin { read_only, as_of, isolation } => {
+                if self.txn.is_some() {
+                    return errinput!("already in a transaction");
+                }
+                let txn = match (read_only, as_of) {
+                    (_, Some(AsOf::Version(version))) => mvcc.begin_readonly_version(*version)?,
+                    (_, Some(AsOf::Timestamp(timestamp))) => mvcc.begin_readonly_at(*timestamp)?,
+                    (true, None) => mvcc.begin_readonly()?,
+                    (false, None) => mvcc.begin_with_isolation(match isolation {
+                        Isolation::Snapshot => IsolationLevel::Snapshot,
+                        Isolation::Serializable => IsolationLevel::Serializable,
+                    })?,
+                };
+                self.txn = Some(txn);
+                Ok(ResultSet::empty())
+            }
+            Statement::Commit => match self.txn.take() {
+                Some(txn) => txn.commit().map(|_| ResultSet::empty()),
+                None => errinput!("not in a transaction"),
+            },
+            Statement::Rollback => match self.txn.take() {
+                Some(txn) => txn.rollback().map(|_| ResultSet::empty()),
+                None => errinput!("not in a transaction"),
+            },
+            Statement::Savepoint(name) => match &self.txn {
+                Some(txn) => txn.savepoint(name).map(|_| ResultSet::empty()),
+                None => errinput!("not in a transaction"),
+            },
+            Statement::RollbackToSavepoint(name) => match &self.txn {
+                Some(txn) => txn.rollback_to_savepoint(name).map(|_| ResultSet::empty()),
+                None => errinput!("not in a transaction"),
+            },
+            Statement::ReleaseSavepoint(name) => match &self.txn {
+                Some(txn) => txn.release_savepoint(name).map(|_| ResultSet::empty()),
+                None => errinput!("not in a transaction"),
+            },
+            statement => {
+                let plan = plan(mvcc, statement)?;
+                match &self.txn {
+                    Some(txn) => execute_in(mvcc, txn, &plan),
+                    None => execute(mvcc, &plan),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::Memory;
+    use crate::types::Value;
+
+    fn exec(session: &mut Session<Memory>, mvcc: &MVCC<Memory>, sql: &str) -> Result<ResultSet> {
+        session.execute(mvcc, sql)
+    }
+
+    #[test]
+    fn test_explicit_transaction() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        let mut s1 = Session::new();
+        let mut s2 = Session::new();
+        exec(&mut s1, &mvcc, "CREATE TABLE t (id INTEGER PRIMARY KEY, v INTEGER)")?;
+
+        exec(&mut s1, &mvcc, "BEGIN")?;
+        assert!(s1.in_transaction());
+        exec(&mut s1, &mvcc, "INSERT INTO t VALUES (1, 10)")?;
+        // 未提交的写入只对本会话可见
+        assert_eq!(exec(&mut s1, &mvcc, "SELECT * FROM t")?.rows.len(), 1);
+        assert_eq!(exec(&mut s2, &mvcc, "SELECT * FROM t")?.rows.len(), 0);
+        exec(&mut s1, &mvcc, "ROLLBACK")?;
+        assert_eq!(exec(&mut s2, &mvcc, "SELECT * FROM t")?.rows.len(), 0);
+
+        exec(&mut s1, &mvcc, "BEGIN")?;
+        exec(&mut s1, &mvcc, "INSERT INTO t VALUES (1, 10)")?;
+        exec(&mut s1, &mvcc, "COMMIT")?;
+        assert!(!s1.in_transaction());
+        assert_eq!(exec(&mut s2, &mvcc, "SELECT * FROM t")?.rows[0][1], Value::Integer(10));
+
+        assert!(exec(&mut s1, &mvcc, "COMMIT").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoints() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        let mut s = Session::new();
+        exec(&mut s, &mvcc, "CREATE TABLE t (id INTEGER PRIMARY KEY, v INTEGER)")?;
+        assert!(exec(&mut s, &mvcc, "SAVEPOINT sp1").is_err());
+
+        exec(&mut s, &mvcc, "BEGIN")?;
+        exec(&mut s, &mvcc, "INSERT INTO t VALUES (1, 10)")?;
+        exec(&mut s, &mvcc, "SAVEPOINT sp1")?;
+        exec(&mut s, &mvcc, "UPDATE t SET v = 11 WHERE id = 1")?;
+        exec(&mut s, &mvcc, "INSERT INTO t VALUES (2, 20)")?;
+        exec(&mut s, &mvcc, "ROLLBACK TO SAVEPOINT sp1")?;
+        let rows = exec(&mut s, &mvcc, "SELECT * FROM t")?.rows;
+        assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(10)]]);
+
+        exec(&mut s, &mvcc, "RELEASE SAVEPOINT sp1")?;
+        assert!(exec(&mut s, &mvcc, "ROLLBACK TO sp1").is_err());
+        exec(&mut s, &mvcc, "COMMIT")?;
+        assert_eq!(exec(&mut s, &mvcc, "SELECT * FROM t")?.rows.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of_system_time() -> Result<()> {
+        use crate::utils::{format_timestamp_millis, get_timestamp_millis};
+        let mvcc = MVCC::new(Memory::default())?;
+        let mut s = Session::new();
+        exec(&mut s, &mvcc, "CREATE TABLE t (id INTEGER PRIMARY KEY, v INTEGER)")?;
+        exec(&mut s, &mvcc, "INSERT INTO t VALUES (1, 10)")?;
+        let timestamp = format_timestamp_millis(get_timestamp_millis());
+        std::thread::sleep(std::time::Duration::from_millis(3));
+        exec(&mut s, &mvcc, "UPDATE t SET v = 11 WHERE id = 1")?;
+
+        exec(&mut s, &mvcc, &format!("BEGIN READ ONLY AS OF SYSTEM TIME '{timestamp}'"))?;
+        assert_eq!(exec(&mut s, &mvcc, "SELECT v FROM t")?.rows, vec![vec![Value::Integer(10)]]);
+        assert!(exec(&mut s, &mvcc, "UPDATE t SET v = 12 WHERE id = 1").is_err());
+        exec(&mut s, &mvcc, "COMMIT")?;
+        assert_eq!(exec(&mut s, &mvcc, "SELECT v FROM t")?.rows, vec![vec![Value::Integer(11)]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_write_skew() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        let mut s1 = Session::new();
+        let mut s2 = Session::new();
+        exec(&mut s1, &mvcc, "CREATE TABLE doctors (id INTEGER PRIMARY KEY, on_call BOOLEAN)")?;
+        exec(&mut s1, &mvcc, "INSERT INTO doctors VALUES (1, TRUE), (2, TRUE)")?;
+
+        // 两个会话都确认还有两人值班，然后各自让一人下班
+        exec(&mut s1, &mvcc, "BEGIN ISOLATION LEVEL SERIALIZABLE")?;
+        exec(&mut s2, &mvcc, "BEGIN ISOLATION LEVEL SERIALIZABLE")?;
+        assert_eq!(exec(&mut s1, &mvcc, "SELECT * FROM doctors WHERE on_call = TRUE")?.rows.len(), 2);
+        assert_eq!(exec(&mut s2, &mvcc, "SELECT * FROM doctors WHERE on_call = TRUE")?.rows.len(), 2);
+        exec(&mut s1, &mvcc, "UPDATE doctors SET on_call = FALSE WHERE id = 1")?;
+        exec(&mut s2, &mvcc, "UPDATE doctors SET on_call = FALSE WHERE id = 2")?;
+        exec(&mut s1, &mvcc, "COMMIT")?;
+        assert!(exec(&mut s2, &mvcc, "COMMIT").is_err());
+        assert!(!s2.in_transaction());
+
+        let result = exec(&mut s1, &mvcc, "SELECT * FROM doctors WHERE on_call = TRUE")?;
+        assert_eq!(result.rows.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,805 @@
+//! 逻辑导入导出：SQL 转储、CSV 与 JSON lines
+//!
+//! - SQL 转储：所有表的 `CREATE TABLE`，以及每 [`DUMP_INSERT_ROWS`] 行一条的 `INSERT`，
+//!   每 [`COPY_BATCH_ROWS`] 行包在一对 `BEGIN` / `COMMIT` 中，可以交给任何客户端重放
+//! - CSV：第一行为列名；没有引号的空字段表示 NULL，`""` 表示空字符串
+//! - JSON lines：每行一个以列名为键的对象；非有限浮点数写成字符串 `"NaN"` / `"Infinity"` / `"-Infinity"`
+//!
+//! 导出在一个只读事务中完成，所有表对应同一个快照。导入逐行经过 `insert_row` 写入，
+//! 自动提交模式下每 [`COPY_BATCH_ROWS`] 行提交一次。已过期的行不会导出，
+//! 导入到 `EXPIRE AFTER` 表中的行从导入时重新开始计时。
+
+use crate::db_error::Result;
+use crate::sql::execution::catalog::Catalog;
+use crate::sql::execution::executor::{for_each_row, insert_row};
+use crate::sql::parser::ast::{CopyFormat, Statement};
+use crate::sql::parser::Parser;
+use crate::sql::execution::session::Session;
+use crate::storage::engine::Engine;
+use crate::storage::mvcc::{Transaction, MVCC};
+use crate::types::{DataType, Row, Table, Value};
+use crate::{errdata, errinput};
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// 导入时每个事务写入的行数
+pub const COPY_BATCH_ROWS: usize = 10_000;
+/// SQL 转储中每条 INSERT 语句包含的行数
+pub const DUMP_INSERT_ROWS: usize = 500;
+/// CSV / JSON 导出目录中保存表结构的文件
+pub const SCHEMA_FILE: &str = "schema.sql";
+
+/// 导入导出的数据格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Sql,
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Sql => "sql",
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+
+    /// 导出文件的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Sql => "sql",
+            Format::Csv => "csv",
+            Format::Json => "jsonl",
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = crate::db_error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sql" => Ok(Format::Sql),
+            "csv" => Ok(Format::Csv),
+            "json" | "jsonl" => Ok(Format::Json),
+            _ => errinput!("unknown format {s}, expected sql, csv or json"),
+        }
+    }
+}
+
+impl From<CopyFormat> for Format {
+    fn from(format: CopyFormat) -> Self {
+        match format {
+            CopyFormat::Csv => Format::Csv,
+            CopyFormat::Json => Format::Json,
+        }
+    }
+}
+
+/// 导入导出涉及的表数和行数
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TransferReport {
+    pub tables: u64,
+    pub rows: u64,
+}
+
+/// 导出所有表：SQL 格式写入 dest 文件；CSV / JSON 写入 dest 目录，
+/// 每张表一个文件，表结构保存在 [`SCHEMA_FILE`] 中
+pub fn export<E: Engine>(mvcc: &MVCC<E>, dest: &Path, format: Format) -> Result<TransferReport> {
+    let txn = mvcc.begin_readonly()?;
+    let tables = Catalog::list_tables(mvcc)?;
+    let mut report = TransferReport { tables: tables.len() as u64, rows: 0 };
+    match format {
+        Format::Sql => {
+            let mut out = BufWriter::new(create_new(dest)?);
+            report.rows = write_sql_dump(&txn, &tables, &mut out)?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        Format::Csv | Format::Json => {
+            if dest.is_dir() && fs::read_dir(dest)?.next().is_some() {
+                return errdata!("export destination {} is not empty", dest.display());
+            }
+            fs::create_dir_all(dest)?;
+            let mut schema = String::new();
+            for table in &tables {
+                schema.push_str(&create_table_sql(table));
+                schema.push_str(";\n");
+            }
+            fs::write(dest.join(SCHEMA_FILE), schema)?;
+            for table in &tables {
+                let path = dest.join(format!("{}.{}", table.name, format.extension()));
+                report.rows += copy_to_file(&txn, table, &path, format)?;
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(report)
+}
+
+/// 导入 [`export`] 的结果：SQL 格式逐条重放 src 文件；CSV / JSON 先执行 src 目录中的
+/// [`SCHEMA_FILE`] 建表，再导入每张表对应的数据文件
+pub fn import<E: Engine>(mvcc: &MVCC<E>, src: &Path, format: Format) -> Result<TransferReport> {
+    match format {
+        Format::Sql => run_script(mvcc, BufReader::new(File::open(src)?)),
+        Format::Csv | Format::Json => {
+            let schema = BufReader::new(File::open(src.join(SCHEMA_FILE))?);
+            let mut report = run_script(mvcc, schema)?;
+            for table in Catalog::list_tables(mvcc)? {
+                let path = src.join(format!("{}.{}", table.name, format.extension()));
+                if path.is_file() {
+                    report.rows += copy_from_file(mvcc, None, &table, &path, format)?;
+                }
+            }
+            Ok(report)
+        }
+    }
+}
+
+/// 依次执行脚本中以分号结尾的语句，脚本中可以包含 BEGIN / COMMIT
+fn run_script<E: Engine>(mvcc: &MVCC<E>, reader: impl BufRead) -> Result<TransferReport> {
+    let mut session = Session::new();
+    let mut report = TransferReport::default();
+    for sql in ScriptReader::new(reader) {
+        let sql = sql?;
+        let statement = Parser::pasre(&sql)?;
+        match &statement {
+            Statement::CreateTable { .. } => report.tables += 1,
+            Statement::Insert { values, .. } => report.rows += values.len() as u64,
+            _ => {}
+        }
+        session.execute_statement(mvcc, &statement)?;
+    }
+    if session.in_transaction() {
+        return errinput!("script ended inside a transaction without COMMIT");
+    }
+    Ok(report)
+}
+
+/// 把一张表导出到文件，返回导出的行数
+pub(crate) fn copy_to_file<E: Engine>(txn: &Transaction<E>, table: &Table, path: &Path, format: Format) -> Result<u64> {
+    let mut out = BufWriter::new(create_new(path)?);
+    let rows = copy_to(txn, table, format, &mut out)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(rows)
+}
+
+/// 创建导出文件，不覆盖已存在的文件
+fn create_new(path: &Path) -> Result<File> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => Ok(file),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            errinput!("export destination {} already exists", path.display())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 从文件导入一张表，返回导入的行数
+/// txn 为 None 时（自动提交）每 [`COPY_BATCH_ROWS`] 行提交一次，出错时已提交的批次保留；
+/// 否则所有行写入给定的事务
+pub(crate) fn copy_from_file<E: Engine>(
+    mvcc: &MVCC<E>,
+    txn: Option<&Transaction<E>>,
+    table: &Table,
+    path: &Path,
+    format: Format,
+) -> Result<u64> {
+    let reader = BufReader::new(File::open(path)?);
+    copy_from(mvcc, txn, table, format, reader, COPY_BATCH_ROWS)
+}
+
+fn copy_to<E: Engine>(txn: &Transaction<E>, table: &Table, format: Format, out: &mut impl Write) -> Result<u64> {
+    match format {
+        Format::Csv => {
+            let header: Vec<_> = table.columns.iter().map(|c| csv_quote(&c.name)).collect();
+            writeln!(out, "{}", header.join(","))?;
+            for_each_row(txn, table, |row| {
+                let fields: Vec<_> = row.iter().map(csv_field).collect();
+                writeln!(out, "{}", fields.join(","))?;
+                Ok(())
+            })
+        }
+        Format::Json => for_each_row(txn, table, |row| {
+            let mut line = String::from("{");
+            for (i, (column, value)) in table.columns.iter().zip(&row).enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                line.push_str(&serde_json::to_string(&column.name)?);
+                line.push(':');
+                line.push_str(&json_value(value)?);
+            }
+            line.push('}');
+            writeln!(out, "{line}")?;
+            Ok(())
+        }),
+        Format::Sql => errinput!("COPY supports csv or json, not sql"),
+    }
+}
+
+fn copy_from<E: Engine>(
+    mvcc: &MVCC<E>,
+    txn: Option<&Transaction<E>>,
+    table: &Table,
+    format: Format,
+    reader: impl BufRead,
+    batch_rows: usize,
+) -> Result<u64> {
+    let mut batch: Option<Transaction<E>> = None;
+    let mut count = 0;
+    let mut insert = |row: Row| -> Result<()> {
+        match txn {
+            Some(txn) => insert_row(txn, table, &row)?,
+            None => {
+                if batch.is_none() {
+                    batch = Some(mvcc.begin()?);
+                }
+                insert_row(batch.as_ref().unwrap(), table, &row)?;
+                if (count + 1) % batch_rows as u64 == 0 {
+                    batch.take().unwrap().commit()?;
+                }
+            }
+        }
+        count += 1;
+        Ok(())
+    };
+    let result = match format {
+        Format::Csv => read_csv(table, reader, &mut insert),
+        Format::Json => read_json(table, reader, &mut insert),
+        Format::Sql => errinput!("COPY supports csv or json, not sql"),
+    };
+    match (result, batch) {
+        (Ok(()), Some(batch)) => batch.commit()?,
+        (Ok(()), None) => {}
+        (Err(err), Some(batch)) => {
+            batch.rollback()?;
+            return Err(err);
+        }
+        (Err(err), None) => return Err(err),
+    }
+    Ok(count)
+}
+
+/// 生成可以重建该表的 `CREATE TABLE` 语句（不含分号）
+pub fn create_table_sql(table: &Table) -> String {
+    let columns: Vec<_> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let mut sql = format!("  {} {}", quote_ident(&column.name), column.data_type);
+            if i == table.primary_key {
+                sql.push_str(" PRIMARY KEY");
+            }
+            if !column.nullable {
+                sql.push_str(" NOT NULL");
+            }
+            if let Some(default) = &column.default {
+                sql.push_str(&format!(" DEFAULT {}", sql_literal(default)));
+            }
+            if column.unique && i != table.primary_key {
+                sql.push_str(" UNIQUE");
+            }
+            if column.index {
+                sql.push_str(" INDEX");
+            }
+            if let Some(references) = &column.references {
+                sql.push_str(&format!(" REFERENCES {}", quote_ident(references)));
+            }
+            sql
+        })
+        .collect();
+    let mut sql = format!("CREATE TABLE {} (\n{}\n)", quote_ident(&table.name), columns.join(",\n"));
+    if let Some(secs) = table.expire_after {
+        sql.push_str(&format!(" EXPIRE AFTER {secs}"));
+    }
+    sql
+}
+
+/// 写出 SQL 转储，返回写出的行数
+fn write_sql_dump<E: Engine>(txn: &Transaction<E>, tables: &[Table], out: &mut impl Write) -> Result<u64> {
+    writeln!(out, "-- mini-db dump: {} tables", tables.len())?;
+    for table in tables {
+        writeln!(out, "{};", create_table_sql(table))?;
+    }
+    let mut total = 0;
+    for table in tables {
+        let insert = format!("INSERT INTO {} VALUES", quote_ident(&table.name));
+        let mut rows = 0;
+        let mut pending = Vec::with_capacity(DUMP_INSERT_ROWS);
+        let flush = |pending: &mut Vec<String>, out: &mut dyn Write| -> Result<()> {
+            if !pending.is_empty() {
+                writeln!(out, "{insert}\n{};", pending.join(",\n"))?;
+                pending.clear();
+            }
+            Ok(())
+        };
+        for_each_row(txn, table, |row| {
+            if rows % COPY_BATCH_ROWS == 0 {
+                flush(&mut pending, out)?;
+                if rows > 0 {
+                    writeln!(out, "COMMIT;")?;
+                }
+                writeln!(out, "BEGIN;")?;
+            }
+            let values: Vec<_> = row.iter().map(sql_literal).collect();
+            pending.push(format!("({})", values.join(", ")));
+            if pending.len() == DUMP_INSERT_ROWS {
+                flush(&mut pending, out)?;
+            }
+            rows += 1;
+            Ok(())
+        })?;
+        flush(&mut pending, out)?;
+        if rows > 0 {
+            writeln!(out, "COMMIT;")?;
+        }
+        total += rows as u64;
+    }
+    Ok(total)
+}
+
+/// 标识符统一加双引号，保留原始大小写
+fn quote_ident(name: &str) -> String {
+    format!("\"{name}\"")
+}
+
+/// 值在 SQL 中的字面量写法，解析后得到相同的值
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Boolean(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        // 字面量不带符号，最小值的绝对值无法表示为正整数
+        Value::Integer(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "NAN".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { "INFINITY" } else { "-INFINITY" }.to_string(),
+        // Debug 格式总是带小数点或指数，确保解析为浮点数
+        Value::Float(f) => format!("{f:?}"),
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+    }
+}
+
+fn csv_quote(text: &str) -> String {
+    if text.is_empty() || text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::String(s) => csv_quote(s),
+    }
+}
+
+fn json_value(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Null => "null".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "\"NaN\"".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { "\"Infinity\"" } else { "\"-Infinity\"" }.to_string(),
+        Value::Float(f) => serde_json::to_string(f)?,
+        Value::String(s) => serde_json::to_string(s)?,
+    })
+}
+
+/// 按列定义补全一行：缺失的列取默认值，没有默认值的可空列取 NULL
+fn complete_row(table: &Table, values: Vec<Option<Value>>, line: u64) -> Result<Row> {
+    let mut row = Vec::with_capacity(values.len());
+    for (i, (column, value)) in table.columns.iter().zip(values).enumerate() {
+        let value = match (value, &column.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => default.clone(),
+            (None, None) if column.nullable && i != table.primary_key => Value::Null,
+            (None, None) => return errinput!("line {line}: missing value for column {}", column.name),
+        };
+        if value.is_null() && (!column.nullable || i == table.primary_key) {
+            return errinput!("line {line}: column {} cannot be NULL", column.name);
+        }
+        row.push(value);
+    }
+    Ok(row)
+}
+
+/// 把表头映射到表的列序号
+fn column_positions<'a>(table: &Table, names: impl Iterator<Item = &'a str>) -> Result<Vec<usize>> {
+    let mut positions: Vec<usize> = Vec::new();
+    for name in names {
+        let Some(i) = table.columns.iter().position(|c| c.name == name) else {
+            return errinput!("table {} has no column {name}", table.name);
+        };
+        if positions.contains(&i) {
+            return errinput!("column {name} appears more than once");
+        }
+        positions.push(i);
+    }
+    Ok(positions)
+}
+
+fn read_csv(table: &Table, reader: impl BufRead, insert: &mut impl FnMut(Row) -> Result<()>) -> Result<()> {
+    let mut csv = CsvReader::new(reader);
+    let Some(header) = csv.next_record()? else {
+        return Ok(());
+    };
+    let positions = column_positions(table, header.iter().map(|f| f.text.as_str()))?;
+    while let Some(record) = csv.next_record()? {
+        let line = csv.line;
+        if record.len() != positions.len() {
+            return errinput!("line {line}: expected {} fields, got {}", positions.len(), record.len());
+        }
+        let mut values = vec![None; table.columns.len()];
+        for (field, &i) in record.into_iter().zip(&positions) {
+            values[i] = Some(parse_csv_field(table.columns[i].data_type, field, line, &table.columns[i].name)?);
+        }
+        insert(complete_row(table, values, line)?)?;
+    }
+    Ok(())
+}
+
+fn parse_csv_field(data_type: DataType, field: CsvField, line: u64, column: &str) -> Result<Value> {
+    if field.text.is_empty() && !field.quoted {
+        return Ok(Value::Null);
+    }
+    let text = field.text;
+    let invalid = || errinput!("line {line}: invalid {data_type} value {text:?} for column {column}");
+    Ok(match data_type {
+        DataType::Boolean => match text.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Value::Boolean(true),
+            "false" | "f" | "0" => Value::Boolean(false),
+            _ => return invalid(),
+        },
+        DataType::Integer => match text.trim().parse() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => return invalid(),
+        },
+        DataType::Float => match text.trim().parse() {
+            Ok(f) => Value::Float(f),
+            Err(_) => return invalid(),
+        },
+        DataType::String => Value::String(text),
+    })
+}
+
+fn read_json(table: &Table, reader: impl BufRead, insert: &mut impl FnMut(Row) -> Result<()>) -> Result<()> {
+    for (n, line) in reader.lines().enumerate() {
+        let line_no = n as u64 + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let json = serde_json::from_str(&line).or_else(|e| errinput!("line {line_no}: {e}"))?;
+        let serde_json::Value::Object(object) = json else {
+            return errinput!("line {line_no}: expected a JSON object");
+        };
+        let positions = column_positions(table, object.keys().map(String::as_str))?;
+        let mut values = vec![None; table.columns.len()];
+        for (json, &i) in object.values().zip(&positions) {
+            let column = &table.columns[i];
+            let Some(value) = parse_json_value(column.data_type, json) else {
+                return errinput!("line {line_no}: invalid {} value {json} for column {}", column.data_type, column.name);
+            };
+            values[i] = Some(value);
+        }
+        insert(complete_row(table, values, line_no)?)?;
+    }
+    Ok(())
+}
+
+fn parse_json_value(data_type: DataType, json: &serde_json::Value) -> Option<Value> {
+    use serde_json::Value as Json;
+    Some(match (data_type, json) {
+        (_, Json::Null) => Value::Null,
+        (DataType::Boolean, Json::Bool(b)) => Value::Boolean(*b),
+        (DataType::Integer, Json::Number(n)) => Value::Integer(n.as_i64()?),
+        (DataType::Float, Json::Number(n)) => Value::Float(n.as_f64()?),
+        (DataType::Float, Json::String(s)) => match s.as_str() {
+            "NaN" => Value::Float(f64::NAN),
+            "Infinity" => Value::Float(f64::INFINITY),
+            "-Infinity" => Value::Float(f64::NEG_INFINITY),
+            _ => return None,
+        },
+        (DataType::String, Json::String(s)) => Value::String(s.clone()),
+        _ => return None,
+    })
+}
+
+/// CSV 字段，quoted 用于区分 NULL（空字段）和空字符串（`""`）
+#[derive(Debug, PartialEq)]
+struct CsvField {
+    text: String,
+    quoted: bool,
+}
+
+/// 按行读取 CSV 记录，引号内的字段可以跨行；空行被跳过
+struct CsvReader<R> {
+    reader: R,
+    /// 当前记录结束时所在的行号
+    line: u64,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<CsvField>>> {
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            if self.reader.read_line(&mut buf)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if !buf.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+        let mut fields = Vec::new();
+        let mut field = CsvField { text: String::new(), quoted: false };
+        let mut in_quotes = false;
+        loop {
+            let mut chars = buf.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    match c {
+                        '"' if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.text.push('"');
+                        }
+                        '"' => in_quotes = false,
+                        c => field.text.push(c),
+                    }
+                    continue;
+                }
+                match c {
+                    ',' => fields.push(std::mem::replace(&mut field, CsvField { text: String::new(), quoted: false })),
+                    '"' if field.text.is_empty() && !field.quoted => {
+                        in_quotes = true;
+                        field.quoted = true;
+                    }
+                    '\r' if chars.peek() == Some(&'\n') => {}
+                    '\n' => {}
+                    c => field.text.push(c),
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            // 引号内的换行属于字段内容，继续读下一行
+            buf.clear();
+            if self.reader.read_line(&mut buf)? == 0 {
+                return errinput!("line {}: unterminated quoted field", self.line);
+            }
+            self.line += 1;
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+}
+
+/// 把 SQL 脚本切分为单条语句：以引号外的分号结尾，跳过 `--` 开头的行注释
+struct ScriptReader<R> {
+    reader: R,
+    /// 当前行中尚未处理的部分
+    line: String,
+    done: bool,
+}
+
+impl<R: BufRead> ScriptReader<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, line: String::new(), done: false }
+    }
+
+    fn next_statement(&mut self) -> Result<Option<String>> {
+        let mut statement = String::new();
+        // 当前所在的引号：' 为字符串，" 为标识符
+        let mut quote: Option<char> = None;
+        loop {
+            if self.line.is_empty() && self.reader.read_line(&mut self.line)? == 0 {
+                if quote.is_some() {
+                    return errinput!("unterminated quote at end of script");
+                }
+                let rest = statement.trim();
+                return Ok((!rest.is_empty()).then(|| rest.to_string()));
+            }
+            let line = std::mem::take(&mut self.line);
+            let mut chars = line.char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
+                match quote {
+                    Some(q) => {
+                        statement.push(c);
+                        if c == q {
+                            quote = None;
+                        }
+                    }
+                    None => match c {
+                        '\'' | '"' => {
+                            quote = Some(c);
+                            statement.push(c);
+                        }
+                        '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                            statement.push('\n');
+                            break;
+                        }
+                        ';' => {
+                            // 同一行中剩余的内容留给下一条语句
+                            self.line = line[i + 1..].to_string();
+                            let sql = statement.trim();
+                            if !sql.is_empty() {
+                                return Ok(Some(sql.to_string()));
+                            }
+                            statement.clear();
+                            break;
+                        }
+                        c => statement.push(c),
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ScriptReader<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_statement().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::execution::execute;
+    use crate::sql::planner::planner::plan;
+    use crate::storage::memory::Memory;
+    use tempfile::TempDir;
+
+    fn exec(mvcc: &MVCC<Memory>, sql: &str) -> Result<crate::sql::execution::ResultSet> {
+        let statement = Parser::pasre(sql)?;
+        execute(mvcc, &plan(mvcc, &statement)?)
+    }
+
+    fn rows(mvcc: &MVCC<Memory>, table: &str) -> Vec<Row> {
+        exec(mvcc, &format!("SELECT * FROM {table} ORDER BY id")).unwrap().rows
+    }
+
+    /// 覆盖所有类型和各种需要转义的值
+    fn sample() -> MVCC<Memory> {
+        let mvcc = MVCC::new(Memory::default()).unwrap();
+        exec(&mvcc, "CREATE TABLE items (id INTEGER PRIMARY KEY, name STRING NOT NULL DEFAULT 'x', price FLOAT, ok BOOLEAN, note TEXT)").unwrap();
+        exec(&mvcc, "CREATE TABLE tags (id STRING PRIMARY KEY, item INTEGER REFERENCES items INDEX)").unwrap();
+        let sql = "INSERT INTO items VALUES \
+            (1, 'plain', 1.5, TRUE, NULL), \
+            (2, 'it''s, \"quoted\"', -0.25, FALSE, ''), \
+            (3, 'multi\nline', NAN, NULL, ' padded '), \
+            (-4, '', INFINITY, TRUE, '--;'), \
+            (9223372036854775807, 'max', 1e300, FALSE, '中文')";
+        exec(&mvcc, sql).unwrap();
+        exec(&mvcc, "INSERT INTO tags VALUES ('a', 1), ('b;c', NULL)").unwrap();
+        mvcc
+    }
+
+    /// NaN 不等于自身，比较时转换成字符串
+    fn normalize(rows: Vec<Row>) -> Vec<String> {
+        rows.into_iter().map(|row| format!("{row:?}")).collect()
+    }
+
+    #[test]
+    fn test_roundtrip_each_format() -> Result<()> {
+        let source = sample();
+        for format in [Format::Sql, Format::Csv, Format::Json] {
+            let dir = TempDir::new()?;
+            let path = dir.path().join("export");
+            let report = export(&source, &path, format)?;
+            assert_eq!(report, TransferReport { tables: 2, rows: 7 }, "{format}");
+
+            let target = MVCC::new(Memory::default())?;
+            let report = import(&target, &path, format)?;
+            assert_eq!(report, TransferReport { tables: 2, rows: 7 }, "{format}");
+            assert_eq!(Catalog::list_tables(&target)?, Catalog::list_tables(&source)?, "{format}");
+            assert_eq!(normalize(rows(&target, "items")), normalize(rows(&source, "items")), "{format}");
+            assert_eq!(normalize(rows(&target, "tags")), normalize(rows(&source, "tags")), "{format}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_table_sql_expire_after() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        exec(&mvcc, "CREATE TABLE sessions (id INTEGER PRIMARY KEY) EXPIRE AFTER 60")?;
+        let table = Catalog::get_table(&mvcc, "sessions")?.unwrap();
+        let sql = create_table_sql(&table);
+        assert!(sql.ends_with(") EXPIRE AFTER 60"), "{sql}");
+
+        let target = MVCC::new(Memory::default())?;
+        exec(&target, &sql)?;
+        assert_eq!(Catalog::get_table(&target, "sessions")?, Some(table));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_dump_batches() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        exec(&mvcc, "CREATE TABLE t (id INTEGER PRIMARY KEY)")?;
+        let values: Vec<_> = (0..1200).map(|i| format!("({i})")).collect();
+        exec(&mvcc, &format!("INSERT INTO t VALUES {}", values.join(",")))?;
+        let mut out = Vec::new();
+        let txn = mvcc.begin_readonly()?;
+        assert_eq!(write_sql_dump(&txn, &Catalog::list_tables(&mvcc)?, &mut out)?, 1200);
+        let dump = String::from_utf8(out).unwrap();
+        assert_eq!(dump.matches("INSERT INTO").count(), 3);
+        assert_eq!(dump.matches("BEGIN;").count(), 1);
+        assert_eq!(dump.matches("COMMIT;").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_from_batches_and_errors() -> Result<()> {
+        let mvcc = MVCC::new(Memory::default())?;
+        exec(&mvcc, "CREATE TABLE t (id INTEGER PRIMARY KEY, name STRING NOT NULL, n INTEGER DEFAULT 7)")?;
+        let table = Catalog::get_table(&mvcc, "t")?.unwrap();
+
+        // 表头可以调整列顺序、省略有默认值的列；每 2 行提交一次
+        let csv = "name,id\na,1\n\"b\nb\",2\n\n\"\",3\n";
+        assert_eq!(copy_from(&mvcc, None, &table, Format::Csv, csv.as_bytes(), 2)?, 3);
+        let result = rows(&mvcc, "t");
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[1], vec![Value::Integer(2), Value::String("b\nb".into()), Value::Integer(7)]);
+        assert_eq!(result[2][1], Value::String(String::new()));
+
+        // 出错时当前批次回滚，之前提交的批次保留
+        let csv = "id,name\n10,a\n11,b\n12,c\n13,\n";
+        let err = copy_from(&mvcc, None, &table, Format::Csv, csv.as_bytes(), 2).unwrap_err();
+        assert!(err.to_string().contains("line 5"), "{err}");
+        assert_eq!(rows(&mvcc, "t").len(), 5);
+
+        let bad = [
+            (Format::Csv, "id,name,extra\n1,a,b\n"),
+            (Format::Csv, "id,name\nx,a\n"),
+            (Format::Csv, "id,name\n1\n"),
+            (Format::Csv, "id,name\n1,\"open\n"),
+            (Format::Json, "{\"id\": 1}\n"),
+            (Format::Json, "{\"id\": 1.5, \"name\": \"a\"}\n"),
+            (Format::Json, "[1, \"a\"]\n"),
+        ];
+        for (format, input) in bad {
+            assert!(copy_from(&mvcc, None, &table, format, input.as_bytes(), 100).is_err(), "{input:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_reader() -> Result<()> {
+        let script = "-- header\nBEGIN; INSERT INTO t VALUES ('a;b', \"c;d\"); -- trailing\n\n  COMMIT  ;;\nSELECT 1--c\nFROM t";
+        let statements = ScriptReader::new(script.as_bytes()).collect::<Result<Vec<_>>>()?;
+        assert_eq!(statements, vec!["BEGIN", "INSERT INTO t VALUES ('a;b', \"c;d\")", "COMMIT", "SELECT 1\nFROM t"]);
+        assert!(ScriptReader::new("SELECT 'open;\n".as_bytes()).collect::<Result<Vec<_>>>().is_err());
+        Ok(())
+    }
+}
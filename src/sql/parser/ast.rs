@@ -6,24 +6,46 @@ use std::hash::Hash;
 pub enum Statement {
     /// BEGIN: 开启一个新事务
     /// - read_only: 只读标记
-    /// - target_version: 事务版本号
+    /// - as_of: 回溯的版本号或时间
+    /// - isolation: 隔离级别
     Begin {
         read_only: bool,
-        target_version: Option<u64>,
+        as_of: Option<AsOf>,
+        isolation: Isolation,
     },
     /// COMMIT: 事务提交
     Commit,
     /// ROLLBACK: 事务回滚
     Rollback,
+    /// SAVEPOINT name: 在当前事务中创建保存点
+    Savepoint(String),
+    /// ROLLBACK TO [SAVEPOINT] name: 撤销保存点之后的写入
+    RollbackToSavepoint(String),
+    /// RELEASE [SAVEPOINT] name: 释放保存点，保留其后的写入
+    ReleaseSavepoint(String),
+    /// VACUUM: 清理所有活跃事务都不可见的旧版本
+    Vacuum,
+    /// BACKUP TO 'dir': 在线备份，在目录 dir 下生成数据库的一致副本
+    Backup(String),
+    /// COPY table FROM|TO 'file' [(FORMAT csv|json)]: 从文件导入或导出到文件
+    /// - from: true 为导入，false 为导出
+    Copy {
+        table: String,
+        from: bool,
+        path: String,
+        format: CopyFormat,
+    },
     /// EXPLAIN: 展示sql执行计划
     /// 由于不确认sql语言的大小，所以存储在堆里
     Explain(Box<Statement>),
     /// 建表语句
     /// - name: 表名
     /// - columns：列信息
+    /// - expire_after: `EXPIRE AFTER <秒数>`，行在最后一次写入之后多少秒过期
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        expire_after: Option<u64>,
     },
     /// 删除表语句
     /// - name: 表名
@@ -81,6 +103,39 @@ pub enum Statement {
     },
 }
 
+impl Statement {
+    /// 是否读写服务器本地的文件（COPY、BACKUP TO），路径由语句任意指定
+    pub fn accesses_files(&self) -> bool {
+        matches!(self, Statement::Copy { .. } | Statement::Backup(_))
+    }
+}
+
+/// AS OF SYSTEM TIME 的回溯目标
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsOf {
+    /// MVCC 版本号
+    Version(u64),
+    /// 毫秒级 Unix 时间戳，由 ISO 8601 字符串解析而来
+    Timestamp(u64),
+}
+
+/// BEGIN ... ISOLATION LEVEL 指定的隔离级别，执行时映射为 MVCC 的隔离级别
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Isolation {
+    /// SNAPSHOT 或 REPEATABLE READ
+    #[default]
+    Snapshot,
+    /// SERIALIZABLE
+    Serializable,
+}
+
+/// COPY 的文件格式，执行时映射为导入导出的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    Csv,
+    Json,
+}
+
 /// From语句
 #[derive(Debug)]
 pub enum From {
@@ -90,6 +145,13 @@ pub enum From {
         alias: Option<String>,
     },
 
+    /// 行的版本历史：HISTORY(table, primary_key)
+    History {
+        table: String,
+        primary_key: Expression,
+        alias: Option<String>,
+    },
+
     /// 连接方式和连接条件
     Join {
         left: Box<From>,
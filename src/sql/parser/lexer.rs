@@ -72,14 +72,17 @@ impl From<Keyword> for Token {
 /// 词法关键字
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Keyword {
+    After,
     And,
     As,
     Asc,
+    Backup,
     Begin,
     Bool,
     Boolean,
     By,
     Commit,
+    Copy,
     Create,
     Cross,
     Default,
@@ -88,6 +91,7 @@ pub enum Keyword {
     Double,
     Drop,
     Exists,
+    Expire,
     Explain,
     False,
     Float,
@@ -103,9 +107,11 @@ pub enum Keyword {
     Integer,
     Into,
     Is,
+    Isolation,
     Join,
     Key,
     Left,
+    Level,
     Like,
     Limit,
     NaN,
@@ -121,20 +127,27 @@ pub enum Keyword {
     Primary,
     Read,
     References,
+    Release,
+    Repeatable,
     Right,
     Rollback,
+    Savepoint,
     Select,
+    Serializable,
     Set,
+    Snapshot,
     String,
     System,
     Table,
     Text,
     Time,
+    To,
     Transaction,
     True,
     Unique,
     Update,
     Union,
+    Vacuum,
     Values,
     Varchar,
     Where,
@@ -149,14 +162,17 @@ impl TryFrom<&str> for Keyword {
         // allocating a string to change the case. Assert this.
         debug_assert!(value.chars().all(|c| !c.is_uppercase()), "keyword must be lowercase");
         Ok(match value {
+            "after" => Self::After,
             "as" => Self::As,
             "asc" => Self::Asc,
+            "backup" => Self::Backup,
             "and" => Self::And,
             "begin" => Self::Begin,
             "bool" => Self::Bool,
             "boolean" => Self::Boolean,
             "by" => Self::By,
             "commit" => Self::Commit,
+            "copy" => Self::Copy,
             "create" => Self::Create,
             "cross" => Self::Cross,
             "default" => Self::Default,
@@ -165,6 +181,7 @@ impl TryFrom<&str> for Keyword {
             "double" => Self::Double,
             "drop" => Self::Drop,
             "exists" => Self::Exists,
+            "expire" => Self::Expire,
             "explain" => Self::Explain,
             "false" => Self::False,
             "float" => Self::Float,
@@ -180,9 +197,11 @@ impl TryFrom<&str> for Keyword {
             "integer" => Self::Integer,
             "into" => Self::Into,
             "is" => Self::Is,
+            "isolation" => Self::Isolation,
             "join" => Self::Join,
             "key" => Self::Key,
             "left" => Self::Left,
+            "level" => Self::Level,
             "like" => Self::Like,
             "limit" => Self::Limit,
             "nan" => Self::NaN,
@@ -198,20 +217,27 @@ impl TryFrom<&str> for Keyword {
             "primary" => Self::Primary,
             "read" => Self::Read,
             "references" => Self::References,
+            "release" => Self::Release,
+            "repeatable" => Self::Repeatable,
             "right" => Self::Right,
             "rollback" => Self::Rollback,
+            "savepoint" => Self::Savepoint,
             "select" => Self::Select,
+            "serializable" => Self::Serializable,
             "set" => Self::Set,
+            "snapshot" => Self::Snapshot,
             "string" => Self::String,
             "system" => Self::System,
             "table" => Self::Table,
             "text" => Self::Text,
             "time" => Self::Time,
+            "to" => Self::To,
             "transaction" => Self::Transaction,
             "true" => Self::True,
             "unique" => Self::Unique,
             "update" => Self::Update,
             "union" => Self::Union,
+            "vacuum" => Self::Vacuum,
             "values" => Self::Values,
             "varchar" => Self::Varchar,
             "where" => Self::Where,
@@ -223,14 +249,17 @@ impl TryFrom<&str> for Keyword {
 impl Display for Keyword {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
+            Self::After => "AFTER",
             Self::As => "AS",
             Self::Asc => "ASC",
+            Self::Backup => "BACKUP",
             Self::And => "AND",
             Self::Begin => "BEGIN",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
             Self::Commit => "COMMIT",
+            Self::Copy => "COPY",
             Self::Create => "CREATE",
             Self::Cross => "CROSS",
             Self::Default => "DEFAULT",
@@ -239,6 +268,7 @@ impl Display for Keyword {
             Self::Double => "DOUBLE",
             Self::Drop => "DROP",
             Self::Exists => "EXISTS",
+            Self::Expire => "EXPIRE",
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
@@ -254,9 +284,11 @@ impl Display for Keyword {
             Self::Integer => "INTEGER",
             Self::Into => "INTO",
             Self::Is => "IS",
+            Self::Isolation => "ISOLATION",
             Self::Join => "JOIN",
             Self::Key => "KEY",
             Self::Left => "LEFT",
+            Self::Level => "LEVEL",
             Self::Like => "LIKE",
             Self::Limit => "LIMIT",
             Self::NaN => "NAN",
@@ -272,20 +304,27 @@ impl Display for Keyword {
             Self::Primary => "PRIMARY",
             Self::Read => "READ",
             Self::References => "REFERENCES",
+            Self::Release => "RELEASE",
+            Self::Repeatable => "REPEATABLE",
             Self::Right => "RIGHT",
             Self::Rollback => "ROLLBACK",
+            Self::Savepoint => "SAVEPOINT",
             Self::Select => "SELECT",
+            Self::Serializable => "SERIALIZABLE",
             Self::Set => "SET",
+            Self::Snapshot => "SNAPSHOT",
             Self::String => "STRING",
             Self::System => "SYSTEM",
             Self::Table => "TABLE",
             Self::Text => "TEXT",
             Self::Time => "TIME",
+            Self::To => "TO",
             Self::Transaction => "TRANSACTION",
             Self::True => "TRUE",
             Self::Unique => "UNIQUE",
             Self::Update => "UPDATE",
             Self::Union => "UNION",
+            Self::Vacuum => "VACUUM",
             Self::Values => "VALUES",
             Self::Varchar => "VARCHAR",
             Self::Where => "WHERE",
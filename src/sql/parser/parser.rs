@@ -1,4 +1,4 @@
-use super::ast::{Column, Direction, Expression, JoinType, Literal, Statement};
+use super::ast::{AsOf, Column, CopyFormat, Direction, Expression, Isolation, JoinType, Literal, Statement};
 use crate::db_error::Result;
 use crate::errinput;
 use crate::sql::parser::ast;
@@ -6,6 +6,7 @@ use crate::sql::parser::ast::Literal::Null;
 use crate::sql::parser::ast::Statement::{Delete, Insert, Select};
 use crate::sql::parser::lexer::{Keyword, Lexer, Token};
 use crate::types::DataType;
+use crate::utils::parse_timestamp_millis;
 use std::cmp::PartialEq;
 use std::collections::BTreeMap;
 use std::iter::Peekable;
@@ -161,7 +162,12 @@ impl<'a> Parser<'a> {
             Token::Keyword(Keyword::Begin) => self.parse_begin(),
             Token::Keyword(Keyword::Commit) => self.parse_commit(),
             Token::Keyword(Keyword::Rollback) => self.parse_rollback(),
+            Token::Keyword(Keyword::Savepoint) => self.parse_savepoint(),
+            Token::Keyword(Keyword::Release) => self.parse_release(),
             Token::Keyword(Keyword::Explain) => self.parse_explain(),
+            Token::Keyword(Keyword::Vacuum) => self.parse_vacuum(),
+            Token::Keyword(Keyword::Backup) => self.parse_backup(),
+            Token::Keyword(Keyword::Copy) => self.parse_copy(),
             // 表操作
             Token::Keyword(Keyword::Create) => self.parse_create_table(),
             Token::Keyword(Keyword::Drop) => self.parse_drop_table(),
@@ -180,6 +186,7 @@ impl<'a> Parser<'a> {
     /// - `BEGIN`
     /// - `BEGIN TRANSACTION`
     /// - `BEGIN READ ONLY` / `BEGIN READ WRITE`
+    /// - `BEGIN ISOLATION LEVEL SERIALIZABLE` / `SNAPSHOT` / `REPEATABLE READ`
     /// - `BEGIN AS OF SYSTEM TIME <number>`
     /// - 上述选项的组合（顺序受 SQL 规范约束）
     ///
@@ -190,12 +197,16 @@ impl<'a> Parser<'a> {
     /// 2. **可选的 `TRANSACTION` 关键字**：
     ///    若存在则跳过（不影响解析结果）。
     ///
-    /// 3. **可选的访问模式**：
+    /// 3. **可选的访问模式与隔离级别**（顺序任意，各出现一次）：
     ///    如果下一个 token 是 `READ`：
     ///    - `READ ONLY` → 设置 `read_only = true`；
     ///    - `READ WRITE` → 保持 `read_only = false`；
     ///    - 其他 token → 报错。
     ///
+    ///    如果下一个 token 是 `ISOLATION`，则期望 `ISOLATION LEVEL <级别>`：
+    ///    - `SERIALIZABLE` → 可串行化；
+    ///    - `SNAPSHOT` / `REPEATABLE READ` → 快照隔离（默认）。
+    ///
     /// 4. **可选的时间戳限定**：
     ///    如果下一个 token 是 `AS`，则期望依次匹配：
     ///    `AS OF SYSTEM TIME <number>`
@@ -224,30 +235,47 @@ impl<'a> Parser<'a> {
         //2、跳过事务关键字
         self.skip(Keyword::Transaction.into());
 
-        let mut read_only = false;
-        if self.next_is(Keyword::Read.into()) {
-            match self.next()? {
-                Token::Keyword(Keyword::Only) => {
-                    read_only = true;
+        let mut read_only = None;
+        let mut isolation = None;
+        loop {
+            if read_only.is_none() && self.next_is(Keyword::Read.into()) {
+                match self.next()? {
+                    Token::Keyword(Keyword::Only) => read_only = Some(true),
+                    Token::Keyword(Keyword::Write) => read_only = Some(false),
+                    token => return errinput!("Unexpected token{:?}",token),
                 }
-                Token::Keyword(Keyword::Write) => {}
-                token => return errinput!("Unexpected token{:?}",token),
+            } else if isolation.is_none() && self.next_is(Keyword::Isolation.into()) {
+                self.expect(Keyword::Level.into())?;
+                isolation = Some(match self.next()? {
+                    Token::Keyword(Keyword::Serializable) => Isolation::Serializable,
+                    Token::Keyword(Keyword::Snapshot) => Isolation::Snapshot,
+                    Token::Keyword(Keyword::Repeatable) => {
+                        self.expect(Keyword::Read.into())?;
+                        Isolation::Snapshot
+                    }
+                    token => return errinput!("Unexpected token{:?}, wanted isolation level",token),
+                });
+            } else {
+                break;
             }
         }
 
-        let mut version = None;
+        // AS OF SYSTEM TIME <version> | '<ISO 8601 时间>'
+        let mut as_of = None;
         if self.next_is(Keyword::As.into()) {
             self.expect(Keyword::Of.into())?;
             self.expect(Keyword::System.into())?;
             self.expect(Keyword::Time.into())?;
             match self.next()? {
-                Token::Number(number) => version = Some(number.parse()?),
-                token => return errinput!("Unexpected token{:?}, wanted number",token),
+                Token::Number(number) => as_of = Some(AsOf::Version(number.parse()?)),
+                Token::String(timestamp) => as_of = Some(AsOf::Timestamp(parse_timestamp_millis(&timestamp)?)),
+                token => return errinput!("Unexpected token{:?}, wanted version or timestamp",token),
             }
         }
         Ok(Statement::Begin {
-            read_only,
-            target_version: version,
+            read_only: read_only.unwrap_or(false),
+            as_of,
+            isolation: isolation.unwrap_or_default(),
         })
     }
 
@@ -259,9 +287,75 @@ impl<'a> Parser<'a> {
     }
 
     /// 将词法单元RollBack转化为语法单元
+    /// - ROLLBACK
+    /// - ROLLBACK TO [SAVEPOINT] name
     fn parse_rollback(&mut self) -> Result<Statement> {
         self.expect(Keyword::Rollback.into())?;
-        Ok(Statement::Rollback)
+        if !self.next_is(Keyword::To.into()) {
+            return Ok(Statement::Rollback);
+        }
+        self.next_is(Keyword::Savepoint.into());
+        Ok(Statement::RollbackToSavepoint(self.next_ident()?))
+    }
+
+    /// 将词法单元Savepoint转化为语法单元
+    fn parse_savepoint(&mut self) -> Result<Statement> {
+        self.expect(Keyword::Savepoint.into())?;
+        Ok(Statement::Savepoint(self.next_ident()?))
+    }
+
+    /// 将词法单元Release转化为语法单元：RELEASE [SAVEPOINT] name
+    fn parse_release(&mut self) -> Result<Statement> {
+        self.expect(Keyword::Release.into())?;
+        self.next_is(Keyword::Savepoint.into());
+        Ok(Statement::ReleaseSavepoint(self.next_ident()?))
+    }
+
+    /// 将词法单元Vacuum转化为语法单元
+    fn parse_vacuum(&mut self) -> Result<Statement> {
+        self.expect(Keyword::Vacuum.into())?;
+        Ok(Statement::Vacuum)
+    }
+
+    /// 将词法单元Backup转化为语法单元：BACKUP TO 'dir'
+    fn parse_backup(&mut self) -> Result<Statement> {
+        self.expect(Keyword::Backup.into())?;
+        self.expect(Keyword::To.into())?;
+        match self.next()? {
+            Token::String(path) => Ok(Statement::Backup(path)),
+            token => errinput!("expected backup directory string, got {token:?}"),
+        }
+    }
+
+    /// 将词法单元Copy转化为语法单元：COPY table FROM|TO 'file' [(FORMAT csv|json)]
+    fn parse_copy(&mut self) -> Result<Statement> {
+        self.expect(Keyword::Copy.into())?;
+        let table = self.next_ident()?;
+        let from = match self.next()? {
+            Token::Keyword(Keyword::From) => true,
+            Token::Keyword(Keyword::To) => false,
+            token => return errinput!("expected FROM or TO, got {token:?}"),
+        };
+        let path = match self.next()? {
+            Token::String(path) => path,
+            token => return errinput!("expected file path string, got {token:?}"),
+        };
+        let mut format = CopyFormat::Csv;
+        if self.next_is(Token::OpenParen) {
+            let option = self.next_ident()?;
+            if !option.eq_ignore_ascii_case("format") {
+                return errinput!("unknown COPY option {option}");
+            }
+            let name = self.next_ident()?;
+            format = match name.to_ascii_lowercase().as_str() {
+                "csv" => CopyFormat::Csv,
+                "json" | "jsonl" => CopyFormat::Json,
+                "sql" => return errinput!("COPY supports csv or json, not sql"),
+                _ => return errinput!("unknown format {name}, expected csv or json"),
+            };
+            self.expect(Token::CloseParen)?;
+        }
+        Ok(Statement::Copy { table, from, path, format })
     }
 
     /// 将词法单元Explain转化为语法单元
@@ -293,7 +387,19 @@ impl<'a> Parser<'a> {
         }
         // 闭合判断
         self.expect(Token::CloseParen.into())?;
-        Ok(Statement::CreateTable { name, columns })
+        // 表选项：EXPIRE AFTER <秒数>
+        let mut expire_after = None;
+        if self.next_is(Keyword::Expire.into()) {
+            self.expect(Keyword::After.into())?;
+            expire_after = match self.next()? {
+                Token::Number(n) => match n.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Some(secs),
+                    _ => return errinput!("EXPIRE AFTER requires a positive number of seconds, got {n}"),
+                },
+                token => return errinput!("unexpected token {:?}, wanted number of seconds", token),
+            };
+        }
+        Ok(Statement::CreateTable { name, columns, expire_after })
     }
 
 
@@ -480,17 +586,29 @@ impl<'a> Parser<'a> {
     /// from table t/ as t
     fn parse_from_table(&mut self) -> Result<ast::From> {
         let name = self.next_ident()?;
-        let mut alias = None;
-        if self.next_is(Keyword::As.into())
-            || matches!(self.peek()?,Some(Token::Identifier(_))) {
-            alias = Some(self.next_ident()?);
+        // 表函数 HISTORY(table, primary_key)
+        if name == "history" && self.next_is(Token::OpenParen) {
+            let table = self.next_ident()?;
+            self.expect(Token::Comma)?;
+            let primary_key = self.parse_expression()?;
+            self.expect(Token::CloseParen)?;
+            return Ok(ast::From::History { table, primary_key, alias: self.parse_from_alias()? });
         }
         Ok(ast::From::Table {
             name,
-            alias,
+            alias: self.parse_from_alias()?,
         })
     }
 
+    /// 表别名：[AS] alias
+    fn parse_from_alias(&mut self) -> Result<Option<String>> {
+        if self.next_is(Keyword::As.into())
+            || matches!(self.peek()?,Some(Token::Identifier(_))) {
+            return Ok(Some(self.next_ident()?));
+        }
+        Ok(None)
+    }
+
     /// 表链接类型
     fn parse_from_join(&mut self) -> Result<Option<JoinType>> {
         let keywords = vec![
@@ -1441,6 +1559,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parser_begin_as_of() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::{AsOf, Statement};
+        let parse = |sql: &str| Parser::new(sql).parse_begin();
+        assert!(matches!(
+            parse("BEGIN READ ONLY AS OF SYSTEM TIME 7")?,
+            Statement::Begin { as_of: Some(AsOf::Version(7)), .. }
+        ));
+        assert!(matches!(
+            parse("BEGIN READ ONLY AS OF SYSTEM TIME '1970-01-01T00:00:01Z'")?,
+            Statement::Begin { as_of: Some(AsOf::Timestamp(1000)), .. }
+        ));
+        assert!(parse("BEGIN AS OF SYSTEM TIME 'yesterday'").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parser_begin_isolation_level() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::{Isolation, Statement};
+        let begin = "BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE READ WRITE";
+        let mut parser = Parser::new(begin);
+        assert!(matches!(
+            parser.parse_begin()?,
+            Statement::Begin { read_only: false, isolation: Isolation::Serializable, .. }
+        ));
+        let begin = "BEGIN READ ONLY ISOLATION LEVEL REPEATABLE READ";
+        let mut parser = Parser::new(begin);
+        assert!(matches!(
+            parser.parse_begin()?,
+            Statement::Begin { read_only: true, isolation: Isolation::Snapshot, .. }
+        ));
+        assert!(Parser::new("BEGIN ISOLATION LEVEL READ").parse_begin().is_err());
+        Ok(())
+    }
+
     #[test]
     fn parser_commit() -> crate::db_error::Result<()> {
         let commit = "COMMIT";
@@ -1456,4 +1609,77 @@ mod tests {
         println!("{:?}", parser.parse_rollback()?);
         Ok(())
     }
+
+    #[test]
+    fn parser_history() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::{From, Statement};
+        let statement = Parser::new("SELECT * FROM HISTORY(users, 1) AS h WHERE deleted = FALSE").parse_statement()?;
+        let Statement::Select { from, .. } = statement else { panic!("expected select") };
+        assert!(matches!(
+            &from[..],
+            [From::History { table, alias: Some(alias), .. }] if table == "users" && alias == "h"
+        ));
+        assert!(Parser::new("SELECT * FROM HISTORY(users)").parse_statement().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parser_savepoint() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::Statement;
+        let parse = |sql: &str| Parser::new(sql).parse_statement();
+        assert!(matches!(parse("SAVEPOINT sp1")?, Statement::Savepoint(name) if name == "sp1"));
+        assert!(matches!(parse("ROLLBACK TO SAVEPOINT sp1")?, Statement::RollbackToSavepoint(name) if name == "sp1"));
+        assert!(matches!(parse("ROLLBACK TO sp1")?, Statement::RollbackToSavepoint(name) if name == "sp1"));
+        assert!(matches!(parse("ROLLBACK")?, Statement::Rollback));
+        assert!(matches!(parse("RELEASE SAVEPOINT sp1")?, Statement::ReleaseSavepoint(name) if name == "sp1"));
+        assert!(matches!(parse("RELEASE sp1")?, Statement::ReleaseSavepoint(name) if name == "sp1"));
+        assert!(parse("SAVEPOINT").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parser_expire_after() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::Statement;
+        let parse = |sql: &str| Parser::pasre(sql);
+        let statement = parse("CREATE TABLE sessions (id STRING PRIMARY KEY) EXPIRE AFTER 3600")?;
+        assert!(matches!(statement, Statement::CreateTable { expire_after: Some(3600), .. }));
+        let statement = parse("CREATE TABLE sessions (id STRING PRIMARY KEY)")?;
+        assert!(matches!(statement, Statement::CreateTable { expire_after: None, .. }));
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY) EXPIRE AFTER 0").is_err());
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY) EXPIRE AFTER -1").is_err());
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY) EXPIRE 10").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parser_vacuum() -> crate::db_error::Result<()> {
+        let vacuum = "VACUUM";
+        let mut parser = Parser::new(vacuum);
+        println!("{:?}", parser.parse_vacuum()?);
+        Ok(())
+    }
+
+    #[test]
+    fn parser_backup() -> crate::db_error::Result<()> {
+        let statement = Parser::pasre("BACKUP TO '/tmp/mini-db backup'")?;
+        assert!(matches!(statement, crate::sql::parser::ast::Statement::Backup(ref path) if path == "/tmp/mini-db backup"));
+        assert!(Parser::pasre("BACKUP '/tmp/x'").is_err());
+        assert!(Parser::pasre("BACKUP TO backups").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parser_copy() -> crate::db_error::Result<()> {
+        use crate::sql::parser::ast::{CopyFormat, Statement};
+        let statement = Parser::pasre("COPY users FROM '/tmp/users.jsonl' (FORMAT json)")?;
+        assert!(matches!(statement, Statement::Copy { ref table, from: true, ref path, format: CopyFormat::Json }
+            if table == "users" && path == "/tmp/users.jsonl"));
+        let statement = Parser::pasre("COPY users TO '/tmp/users.csv';")?;
+        assert!(matches!(statement, Statement::Copy { from: false, format: CopyFormat::Csv, .. }));
+        assert!(Parser::pasre("COPY users TO '/tmp/users.sql' (FORMAT sql)").is_err());
+        assert!(Parser::pasre("COPY users TO '/tmp/users.xml' (FORMAT xml)").is_err());
+        assert!(Parser::pasre("COPY users TO '/tmp/users' (HEADER csv)").is_err());
+        assert!(Parser::pasre("COPY users INTO '/tmp/users.csv'").is_err());
+        Ok(())
+    }
 }
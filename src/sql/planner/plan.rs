@@ -27,7 +27,7 @@ use std::collections::HashMap;
 /// 4. Projection 从行中挑选出请求的列值。
 /// 5. Order 根据发行日期对行进行排序。
 /// 6. Select 将最终的行返回给客户端。
-use crate::sql::parser::ast::{Expression, Direction, JoinType};
+use crate::sql::parser::ast::{CopyFormat, Expression, Direction, JoinType};
 use crate::types::*;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -38,12 +38,24 @@ pub enum Plan {
     Insert { table: Table, column_map: Option<HashMap<usize, usize>>, source: Node },
     Update { table: Table, primary_key: usize, source: Node, expressions: Vec<(usize, Expression)> },
     Select { root: Node, labels: Vec<Label> },
+    /// 回收旧版本
+    Vacuum,
+    /// 在线备份到指定目录
+    Backup { path: String },
+    /// 从文件导入一张表
+    CopyFrom { table: Table, path: String, format: CopyFormat },
+    /// 把一张表导出到文件
+    CopyTo { table: Table, path: String, format: CopyFormat },
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Node {
     /// 扫描表
-    Scan { table: String },
+    /// - order: 按主键顺序输出，None 表示不保证顺序
+    /// - limit: 最多读取的行数，只在扫描结果不再被过滤时下推
+    Scan { table: String, order: Option<Direction>, limit: Option<usize> },
+    /// 行的版本历史，输出 version、deleted 两列和表的所有列
+    History { table: String, primary_key: Expression },
     /// 过滤
     Filter { predicate: Expression, source: Box<Node> },
     /// 投影
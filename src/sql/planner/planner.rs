@@ -11,7 +11,7 @@ use std::collections::HashMap;
 /// 将 AST 语句转换为执行计划
 pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
     match stmt {
-        Statement::CreateTable { name, columns } => {
+        Statement::CreateTable { name, columns, expire_after } => {
             // 确定主键索引
             let pk_idx = columns
                 .iter()
@@ -23,6 +23,7 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
                 name: name.clone(),
                 primary_key: pk_idx,
                 columns: schema_cols,
+                expire_after: *expire_after,
             };
             Ok(Plan::CreateTable { schema })
         }
@@ -76,7 +77,7 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
         Statement::Delete { table, r#where } => {
             let schema = Catalog::get_table(mvcc, table)?
                 .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
-            let scan = Node::Scan { table: table.clone() };
+            let scan = Node::Scan { table: table.clone(), order: None, limit: None };
             let source = if let Some(ref cond) = r#where {
                 Node::Filter {
                     predicate: cond.clone(),
@@ -94,7 +95,7 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
         Statement::Update { table, set, r#where } => {
             let schema = Catalog::get_table(mvcc, table)?
                 .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
-            let scan = Node::Scan { table: table.clone() };
+            let scan = Node::Scan { table: table.clone(), order: None, limit: None };
             let source = if let Some(ref cond) = r#where {
                 Node::Filter {
                     predicate: cond.clone(),
@@ -134,6 +135,26 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
             // 构建 FROM 节点
             let mut node = build_from(mvcc, from)?;
 
+            // 只按主键排序时，按主键顺序扫描代替排序；结果不再被过滤时把 OFFSET + LIMIT 也下推到扫描
+            let has_aggregates = select.iter().any(|(expr, _)| contains_aggregate(expr))
+                || having.as_ref().map_or(false, |h| contains_aggregate(h));
+            let has_group_by = !group_by.is_empty();
+            let pk_order = if has_aggregates || has_group_by {
+                None
+            } else {
+                primary_key_order(mvcc, from, select, order_by)?
+            };
+            if let (Some(direction), Node::Scan { order, limit: scan_limit, .. }) = (&pk_order, &mut node) {
+                *order = Some(direction.clone());
+                if r#where.is_none() {
+                    let offset = offset.as_ref().map_or(Some(0), literal_usize);
+                    *scan_limit = match (offset, limit.as_ref().and_then(literal_usize)) {
+                        (Some(offset), Some(limit)) => Some(offset + limit),
+                        _ => None,
+                    };
+                }
+            }
+
             // WHERE
             if let Some(ref cond) = r#where {
                 node = Node::Filter {
@@ -143,10 +164,6 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
             }
 
             // GROUP BY + 聚合
-            let has_aggregates = select.iter().any(|(expr, _)| contains_aggregate(expr))
-                || having.as_ref().map_or(false, |h| contains_aggregate(h));
-            let has_group_by = !group_by.is_empty();
-
             let rewritten_select = if has_aggregates || has_group_by {
                 let aggregates = extract_aggregates(select)?;
                 let mut rewritten = Vec::new();
@@ -182,7 +199,7 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
             };
 
             // ORDER BY
-            if !order_by.is_empty() {
+            if !order_by.is_empty() && pk_order.is_none() {
                 node = Node::Order {
                     expressions: order_by.clone(),
                     source: Box::new(node),
@@ -215,12 +232,60 @@ pub fn plan<E: Engine>(mvcc: &MVCC<E>, stmt: &Statement) -> Result<Plan> {
 
             Ok(Plan::Select { root: node, labels })
         }
-        Statement::Begin { .. } | Statement::Commit | Statement::Rollback | Statement::Explain(_) => {
+        Statement::Vacuum => Ok(Plan::Vacuum),
+        Statement::Backup(path) => Ok(Plan::Backup { path: path.clone() }),
+        Statement::Copy { table, from, path, format } => {
+            let table = Catalog::get_table(mvcc, table)?
+                .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
+            let (path, format) = (path.clone(), *format);
+            Ok(if *from { Plan::CopyFrom { table, path, format } } else { Plan::CopyTo { table, path, format } })
+        }
+        Statement::Begin { .. }
+        | Statement::Commit
+        | Statement::Rollback
+        | Statement::Savepoint(_)
+        | Statement::RollbackToSavepoint(_)
+        | Statement::ReleaseSavepoint(_)
+        | Statement::Explain(_) => {
             Err(Error::InvalidData("unsupported statement for planning".into()))
         }
     }
 }
 
+/// 判断 ORDER BY 是否只按单表的主键排序，是则返回排序方向
+/// 行键中主键的字节序需要与值的顺序一致，浮点数主键不满足，不做下推
+fn primary_key_order<E: Engine>(
+    mvcc: &MVCC<E>,
+    from: &[From],
+    select: &[(Expression, Option<String>)],
+    order_by: &[(Expression, ast::Direction)],
+) -> Result<Option<ast::Direction>> {
+    let ([From::Table { name, alias }], [(Expression::Column(table, column), direction)]) = (from, order_by) else {
+        return Ok(None);
+    };
+    if table.as_ref().is_some_and(|t| t != name && Some(t) != alias.as_ref()) {
+        return Ok(None);
+    }
+    let Some(schema) = Catalog::get_table(mvcc, name)? else { return Ok(None) };
+    let pk = &schema.columns[schema.primary_key];
+    if pk.name != *column || pk.data_type == DataType::Float {
+        return Ok(None);
+    }
+    // 投影中的别名与主键列同名时，ORDER BY 引用的是别名
+    let shadowed = select.iter().any(|(expr, alias)| {
+        alias.as_ref() == Some(column) && !matches!(expr, Expression::Column(_, c) if c == column)
+    });
+    Ok((!shadowed).then(|| direction.clone()))
+}
+
+/// 非负整数字面量
+fn literal_usize(expr: &Expression) -> Option<usize> {
+    match expr {
+        Expression::Literal(ast::Literal::Integer(i)) => usize::try_from(*i).ok(),
+        _ => None,
+    }
+}
+
 fn build_from<E: Engine>(mvcc: &MVCC<E>, from: &[From]) -> Result<Node> {
     if from.is_empty() {
         return Ok(Node::Empty);
@@ -244,7 +309,12 @@ fn build_from_item<E: Engine>(mvcc: &MVCC<E>, item: &From) -> Result<Node> {
             // 校验表存在
             let _ = Catalog::get_table(mvcc, name)?
                 .ok_or_else(|| Error::InvalidData(format!("table {} not found", name)))?;
-            Ok(Node::Scan { table: name.clone() })
+            Ok(Node::Scan { table: name.clone(), order: None, limit: None })
+        }
+        From::History { table, primary_key, .. } => {
+            let _ = Catalog::get_table(mvcc, table)?
+                .ok_or_else(|| Error::InvalidData(format!("table {} not found", table)))?;
+            Ok(Node::History { table: table.clone(), primary_key: primary_key.clone() })
         }
         From::Join { left, right, r#type, predicate } => {
             let left_node = build_from_item(mvcc, left)?;
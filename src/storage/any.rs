@@ -0,0 +1,164 @@
+use crate::cfg::EngineKind;
+use crate::db_error::Result;
+use crate::errinput;
+use crate::storage::engine::{Engine, EngineStatus, WriteBatch};
+use crate::storage::{BTree, BitCask, Lsm, Memory};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::time::Duration;
+
+/// 运行时选择的存储引擎
+///
+/// `Engine` 的扫描迭代器是关联类型，不能做成 trait 对象；这里用枚举包装所有引擎，
+/// 让按配置或命令行参数选择引擎的程序只需要一个具体类型 `Database<AnyEngine>`
+pub enum AnyEngine {
+    Memory(Memory),
+    BitCask(BitCask),
+    Lsm(Lsm),
+    BTree(BTree),
+}
+
+impl AnyEngine {
+    /// 在 path 下打开指定类型的引擎，内存引擎忽略 path
+    pub fn open(kind: EngineKind, path: &Path) -> Result<Self> {
+        Ok(match kind {
+            EngineKind::Memory => AnyEngine::Memory(Memory::default()),
+            EngineKind::Bitcask => AnyEngine::BitCask(BitCask::init_db_at(path)?),
+            EngineKind::Lsm => AnyEngine::Lsm(Lsm::init_db_at(path)?),
+            EngineKind::Btree => AnyEngine::BTree(BTree::init_db_at(path)?),
+        })
+    }
+
+    /// 以只读方式打开 path 下的引擎，可以在另一个进程读写数据库时查询；目前只有 BitCask 支持
+    pub fn open_read_only(kind: EngineKind, path: &Path) -> Result<Self> {
+        match kind {
+            EngineKind::Bitcask => Ok(AnyEngine::BitCask(BitCask::open_read_only(path)?)),
+            kind => errinput!("read-only mode only supports the bitcask engine, got {kind}"),
+        }
+    }
+
+    pub fn kind(&self) -> EngineKind {
+        match self {
+            AnyEngine::Memory(_) => EngineKind::Memory,
+            AnyEngine::BitCask(_) => EngineKind::Bitcask,
+            AnyEngine::Lsm(_) => EngineKind::Lsm,
+            AnyEngine::BTree(_) => EngineKind::Btree,
+        }
+    }
+}
+
+/// 把调用分发到具体的引擎
+macro_rules! dispatch {
+    ($engine:expr, $inner:ident => $body:expr) => {
+        match $engine {
+            AnyEngine::Memory($inner) => $body,
+            AnyEngine::BitCask($inner) => $body,
+            AnyEngine::Lsm($inner) => $body,
+            AnyEngine::BTree($inner) => $body,
+        }
+    };
+}
+
+impl Engine for AnyEngine {
+    type ScanIter<'a> = ScanIterator<'a>;
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        dispatch!(self, engine => engine.set(key, value))
+    }
+
+    fn set_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        dispatch!(self, engine => engine.set_with_ttl(key, value, ttl))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        dispatch!(self, engine => engine.get(key))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        dispatch!(self, engine => engine.delete(key))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.flush())
+    }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        dispatch!(self, engine => engine.write_batch(batch))
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+        match self {
+            AnyEngine::Memory(engine) => ScanIterator::Memory(engine.scan(range)),
+            AnyEngine::BitCask(engine) => ScanIterator::BitCask(engine.scan(range)),
+            AnyEngine::Lsm(engine) => ScanIterator::Lsm(engine.scan(range)),
+            AnyEngine::BTree(engine) => ScanIterator::BTree(engine.scan(range)),
+        }
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.clear())
+    }
+
+    fn status(&self) -> Result<EngineStatus> {
+        dispatch!(self, engine => engine.status())
+    }
+
+    fn checkpoint(&self, dest: &Path) -> Result<()> {
+        dispatch!(self, engine => engine.checkpoint(dest))
+    }
+}
+
+/// 包装各引擎的扫描迭代器
+pub enum ScanIterator<'a> {
+    Memory(<Memory as Engine>::ScanIter<'a>),
+    BitCask(<BitCask as Engine>::ScanIter<'a>),
+    Lsm(<Lsm as Engine>::ScanIter<'a>),
+    BTree(<BTree as Engine>::ScanIter<'a>),
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ScanIterator::Memory(iter) => iter.next(),
+            ScanIterator::BitCask(iter) => iter.next(),
+            ScanIterator::Lsm(iter) => iter.next(),
+            ScanIterator::BTree(iter) => iter.next(),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for ScanIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            ScanIterator::Memory(iter) => iter.next_back(),
+            ScanIterator::BitCask(iter) => iter.next_back(),
+            ScanIterator::Lsm(iter) => iter.next_back(),
+            ScanIterator::BTree(iter) => iter.next_back(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    crate::storage::conformance::test_engine!(|path: &Path| AnyEngine::open(EngineKind::Lsm, path), persistent);
+
+    #[test]
+    fn test_open_each_kind() -> Result<()> {
+        for kind in [EngineKind::Memory, EngineKind::Bitcask, EngineKind::Lsm, EngineKind::Btree] {
+            let dir = TempDir::new().unwrap();
+            let mut engine = AnyEngine::open(kind, dir.path())?;
+            assert_eq!(engine.kind(), kind);
+            engine.set(b"b", b"2")?;
+            engine.set(b"a", b"1")?;
+            engine.delete(b"b")?;
+            assert_eq!(engine.get(b"a")?, Some(b"1".to_vec()));
+            assert_eq!(engine.scan(..).rev().collect::<Result<Vec<_>>>()?, vec![(b"a".to_vec(), b"1".to_vec())]);
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,20 @@
-use crate::cfg::{get_db_base, get_max_size};
-use crate::db_error::Result;
-use crate::storage::engine::{Engine, EngineStatus};
+use crate::cfg::{get_compression, get_db_base, get_max_size, Compression};
+use crate::db_error::{Error, Result};
+use crate::{errdata, errinput};
+use crate::storage::engine::{Engine, EngineStatus, FileStatus, WriteBatch, WriteOp};
+use crate::storage::keyring::{Keyring, SEAL_OVERHEAD};
+use crate::utils::Raw;
 use std::collections::btree_map::Range;
+use std::collections::VecDeque;
+use std::ops::Bound;
 
 use fs4::fs_std::FileExt;
 use sha3::{Digest, Sha3_256};
 use std::fs::{self, read_dir};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use std::vec;
 use tracing::info;
 use tsid::create_tsid;
@@ -17,86 +23,132 @@ use tsid::create_tsid;
 /// struct - BitCask
 /// 成员：
 /// 日志文件集合 - Log
+/// 数据文件表 - FileTable
 /// 全局的映射表 - KeyDir
 #[derive(Debug)]
 pub struct BitCask {
     log: Option<Log>,
+    /// 活跃文件在文件表中的编号
+    active: FileId,
+    files: FileTable,
     keydir: KeyDir,
     db_base: String,
+    /// 新写入条目的值压缩设置，打开时取自配置
+    compression: ValueCompression,
+    /// 静态加密的密钥环，None 表示新写入的条目不加密
+    keyring: Option<Arc<Keyring>>,
+    /// 数据目录的写锁，只读打开时为 None，此时不允许任何写入
+    lock: Option<DirLock>,
 }
 
+/// compact 每批从 KeyDir 中取出的键数量，避免一次性克隆整个 KeyDir
+const COMPACT_BATCH: usize = 1024;
+
 impl BitCask {
     /// 1、扫描数据库所有的存储文件
     /// 2、构建全局KeyDir——索引
     /// 3、打开活跃的存储文件
     pub fn init_db() -> Result<Self> {
-        Self::init_db_with_base(get_db_base())
+        Self::init_db_with_base(get_db_base(), Keyring::from_config()?)
+    }
+
+    fn init_db_with_base(db_base: String, keyring: Option<Keyring>) -> Result<Self> {
+        // 先获取目录锁，另一个进程正在使用时直接报错，不读取任何数据文件
+        let lock = DirLock::acquire(Path::new(db_base.as_str()))?;
+        let (mut db, active) = Self::load(db_base.clone(), keyring, Some(lock))?;
+        match active {
+            Some(file_id) => {
+                let name = db.files.name(file_id)?.to_string();
+                db.log = Some(Log::new_with_base(name, db_base)?);
+                db.active = file_id;
+            }
+            None => db.open_new_active()?,
+        }
+        Ok(db)
     }
 
-    fn init_db_with_base(db_base: String) -> Result<Self> {
+    /// 扫描数据目录中的所有文件，构建 KeyDir，返回实例与活跃文件的编号
+    fn load(db_base: String, keyring: Option<Keyring>, lock: Option<DirLock>) -> Result<(Self, Option<FileId>)> {
         let path = Path::new(db_base.as_str());
-        let mut log_file_id = create_tsid().number().to_string() + "_active";
+        let mut active = None;
         let mut db = Self {
             log: None,
+            active: 0,
+            files: FileTable::default(),
             keydir: KeyDir::new(),
             db_base: db_base.clone(),
+            compression: ValueCompression::from_config(),
+            keyring: keyring.map(Arc::new),
+            lock,
         };
         if path.is_dir() {
-            //1 、遍历目录下的所有文件收集所有文件路径
-            let mut paths = read_dir(path)?
-                .into_iter()
-                .map(|e| e.unwrap().path())
-                .collect::<Vec<_>>();
-            //2、根据时间大小排序 创建时间越晚文件名的数值越大
-            //活跃文件的文件名永远是最大的
-            if !paths.is_empty() {
-                paths.sort_by(|file_a, file_b| {
-                    let mut file_a_name = file_a.file_name().and_then(|n| n.to_str()).unwrap();
-                    file_a_name = file_a_name.strip_suffix("_active").unwrap_or(file_a_name);
-                    let mut file_b_name = file_b.file_name().and_then(|n| n.to_str()).unwrap();
-                    file_b_name = file_b_name.strip_suffix("_active").unwrap_or(file_b_name);
-                    file_a_name
-                        .to_string()
-                        .parse::<u64>()
-                        .unwrap()
-                        .cmp(&file_b_name.to_string().parse::<u64>().unwrap())
-                });
-                // 3、遍历文件集合，构建索引
-                paths.iter().for_each(|file_path| {
-                    if file_path
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap()
-                        .ends_with("active")
-                    {
-                        log_file_id = file_path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap()
-                            .to_string();
-                    }
-                    // 由于for_each闭包不能使用?操作符，这里改用for循环
-                    if let Err(e) = db.build_key_dir(file_path) {
-                        // 可以根据需要选择panic、返回Err或打印错误，这里选择panic
-                        panic!("构建KeyDir失败: {:?}", e);
-                    }
-                });
+            // 遍历文件集合，构建索引
+            for file_path in log_files(path)? {
+                let file_name = file_path.file_name().and_then(|n| n.to_str()).unwrap();
+                let is_active = file_name.ends_with("active");
+                let file_id = db.build_key_dir(&file_path)?;
+                if is_active {
+                    active = Some(file_id);
+                }
             }
         }
-        db.log = Some(Log::new_with_base(log_file_id, db_base)?);
-        Ok(db)
+        Ok((db, active))
     }
 
     pub fn init_db_at(path: &Path) -> Result<Self> {
-        let mut base = path.to_string_lossy().to_string();
-        if !base.ends_with('/') {
-            base.push('/');
+        Self::init_db_with_keyring(path, Keyring::from_config()?)
+    }
+
+    /// 使用指定的密钥环打开数据目录，不读取配置中的密钥文件
+    /// keyring 为 None 时新写入的条目不加密，遇到加密的条目时报错
+    pub fn init_db_with_keyring(path: &Path, keyring: Option<Keyring>) -> Result<Self> {
+        Self::init_db_with_base(db_base(path), keyring)
+    }
+
+    /// 以只读方式打开数据目录，可以在另一个进程读写数据库的同时检查数据
+    ///
+    /// 不获取目录锁，数据文件只加共享锁，不创建活跃文件；所有写入返回 [`Error::ReadOnly`]。
+    /// 读到的是打开时刻的快照：扫描时打开的文件句柄一直保留，之后的写入不可见，
+    /// 写入方压缩时删除的文件仍然可以通过这些句柄读取。
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        if !path.is_dir() {
+            return errinput!("data directory {} does not exist", path.display());
         }
-        Self::init_db_with_base(base)
+        let (mut db, active) = Self::load(db_base(path), Keyring::from_config()?, None)?;
+        db.active = active.unwrap_or_default();
+        Ok(db)
+    }
+
+    /// 只获取数据目录锁，不打开数据库：离线检查期间阻止其他进程打开并写入
+    /// 目录已被占用时返回 "database ... is in use by pid X"
+    pub fn lock_dir(path: &Path) -> Result<DirLock> {
+        if !path.is_dir() {
+            return errinput!("data directory {} does not exist", path.display());
+        }
+        DirLock::acquire(path)
+    }
+
+    /// 是否以只读方式打开
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
     }
-    /// 构建索引
-    fn build_key_dir(&mut self, file_path: &PathBuf) -> Result<String> {
-        let file_id = file_path
+
+    fn check_writable(&self) -> Result<()> {
+        match self.lock {
+            Some(_) => Ok(()),
+            None => Err(Error::ReadOnly),
+        }
+    }
+
+    /// 设置之后写入（包括压缩时重写）的条目使用的值压缩算法，长度小于 `min_size` 的值不压缩
+    /// 已写入的条目保持原样，读取时按条目的标志位解压
+    pub fn set_compression(&mut self, algorithm: Compression, min_size: usize) {
+        self.compression = ValueCompression { algorithm, min_size };
+    }
+
+    /// 构建索引，返回该文件在文件表中的编号
+    fn build_key_dir(&mut self, file_path: &PathBuf) -> Result<FileId> {
+        let file_name = file_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap()
@@ -107,50 +159,114 @@ impl BitCask {
             .create(false)
             .truncate(false)
             .open(file_path)?;
-        file.try_lock_exclusive()?;
+        // 独占由目录锁保证，数据文件只加共享锁，只读打开的进程可以同时读取
+        if !FileExt::try_lock_shared(&file)? {
+            return errdata!("{file_name} is locked by another process");
+        }
         let file_len = file.metadata()?.len();
-        // key、value的长度读取缓冲区
-        let mut len_buf = [0u8; 4];
         let mut reader = BufReader::new(&mut file);
-        let mut pos = reader.seek(SeekFrom::Start(0))?;
+        // 识别文件格式：带文件头的新格式或无文件头的旧格式
+        let format = FileFormat::detect(&mut reader, file_len)?;
+        let file_id = self.files.add(file_name.clone(), format, file_len);
+        let header_len = format.entry_header_len() as u64;
+        // 条目头部读取缓冲区
+        let mut header_buf = vec![0u8; header_len as usize];
+        let mut pos = format.data_start();
+        let now = crate::utils::get_timestamp_millis();
         // 从头开始扫描文件
         while pos < file_len {
             let result =
-                || -> std::result::Result<(Vec<u8>, String, Option<u32>), std::io::Error> {
-                    let crc_pos = pos as u32;
-                    let ksz_pos = (crc_pos + 12) as u64;
-                    reader.seek(SeekFrom::Start(ksz_pos))?;
-                    reader.read_exact(&mut len_buf)?;
-                    let ksz = u32::from_be_bytes(len_buf);
-                    let key_pos = 20 + crc_pos as u64;
+                || -> Result<Vec<ScannedKey>> {
+                    let crc_pos = pos;
+                    reader.seek(SeekFrom::Start(crc_pos))?;
+                    reader.read_exact(&mut header_buf)?;
+                    let (ksz, value_sz) = format.decode_sizes(&header_buf)?;
+                    let tstamp = format.decode_tstamp(&header_buf)?;
                     let mut key = vec![0u8; ksz as usize];
-                    reader.seek(SeekFrom::Start(key_pos))?;
                     reader.read_exact(&mut key)?;
-                    let value_sz_pos = (crc_pos + 16) as u64;
-                    reader.seek(SeekFrom::Start(value_sz_pos))?;
-                    reader.read_exact(&mut len_buf)?;
-                    let value_sz = i32::from_be_bytes(len_buf);
+                    if format.supports_batch() && value_sz == BATCH_VALUE_SZ {
+                        // 批量写入帧：校验通过才整批生效，残缺的帧整批丢弃
+                        let frame = [header_buf.as_slice(), &key].concat();
+                        let ops = decode_batch(format, crc_pos, frame)?;
+                        pos = pos + header_len + ksz as u64;
+                        return Ok(ops
+                            .into_iter()
+                            .map(|(crc_pos, entry)| {
+                                let live = (entry.value_sz > 0).then_some(KeyDirEntry {
+                                    file_id,
+                                    crc_pos,
+                                    value_sz: entry.value_sz as u32,
+                                    tstamp: entry.tstamp,
+                                    expires_at: entry.expires_at.unwrap_or(0),
+                                });
+                                let aad = (entry.flags & FLAG_ENCRYPTED != 0).then(|| entry.header_aad());
+                                (entry.key, aad, live)
+                            })
+                            .collect());
+                    }
+                    let flags = format.decode_flags(&header_buf);
+                    // 与 LogEntry::header_aad 相同：crc 之后的头部字段加上过期时间
+                    let mut aad = header_buf[format.checksum.len()..].to_vec();
                     if value_sz > 0 {
-                        pos = pos + 20 + ksz as u64 + value_sz as u64;
-                        Ok((key, file_id.clone(), Some(crc_pos)))
+                        // 过期时间存放在值的开头，只需要多读 8 个字节
+                        let mut expires_at = 0;
+                        if flags & FLAG_EXPIRES != 0 {
+                            let mut expiry = [0u8; EXPIRY_LEN];
+                            reader.read_exact(&mut expiry)?;
+                            expires_at = u64::from_be_bytes(expiry);
+                            aad.extend_from_slice(&expiry);
+                        }
+                        pos = pos + header_len + ksz as u64 + value_sz as u64;
+                        let value_sz = value_sz as u32;
+                        let aad = (flags & FLAG_ENCRYPTED != 0).then_some(aad);
+                        Ok(vec![(key, aad, Some(KeyDirEntry { file_id, crc_pos, value_sz, tstamp, expires_at }))])
                     } else {
-                        pos = pos + 20 + ksz as u64;
-                        Ok((key, file_id.clone(), None))
+                        pos = pos + header_len + ksz as u64;
+                        Ok(vec![(key, (flags & FLAG_ENCRYPTED != 0).then_some(aad), None)])
                     }
                 }();
             match result {
-                Ok((key, file_id, Some(crc_pos))) => {
-                    self.keydir.insert(key, (file_id, crc_pos));
-                }
-                Ok((key, _, None)) => {
-                    self.keydir.remove(&key);
+                Ok(entries) => {
+                    for (key, aad, entry) in entries {
+                        // 加密条目的键需要解密后才能放入 KeyDir；解密失败说明密钥不对或头部被篡改，直接报错，
+                        // 不能当作截断跳过，否则之后的写入会建立在不完整的数据之上
+                        let (key, entry) = match aad {
+                            None => (key, entry),
+                            Some(aad) => {
+                                let plain = open_key(self.keyring.as_deref(), &key, &aad)
+                                    .or_else(|e| errdata!("{file_name}@{pos}: {e}"))?;
+                                // 键的加密开销计入 value_sz，文件统计与实际占用保持一致
+                                let overhead = (key.len() - plain.len()) as u32;
+                                (plain, entry.map(|entry| KeyDirEntry { value_sz: entry.value_sz + overhead, ..entry }))
+                            }
+                        };
+                        // 已经过期的条目与墓碑相同
+                        match entry.filter(|entry| !entry.is_expired(now)) {
+                            Some(entry) => self.index_insert(&key, entry),
+                            None => self.index_remove(&key),
+                        }
+                    }
                 }
-                Err(_) => {
-                    println!("ERROR");
+                Err(e) => {
+                    // 文件末尾存在截断的条目，之后的数据无法解析
+                    tracing::warn!("{file_name}@{pos}: 无法解析的条目，停止扫描: {e}");
+                    break;
                 }
             }
         }
-        Ok("构建完成".to_string())
+        // 扫描用的句柄留作该文件的只读句柄，文件之后被重命名或删除也不影响读取
+        drop(reader);
+        let _ = self.files.get(file_id)?.reader.set(file);
+        Ok(file_id)
+    }
+
+    /// 创建新的活跃文件并登记到文件表
+    fn open_new_active(&mut self) -> Result<()> {
+        let name = create_tsid().number().to_string() + "_active";
+        let log = Log::new_with_base(name.clone(), self.db_base.clone())?;
+        self.active = self.files.add(name, log.format, log.format.data_start());
+        self.log = Some(log);
+        Ok(())
     }
 
     /// 更新存储文件
@@ -158,21 +274,20 @@ impl BitCask {
         let db_base = self.db_base.clone();
         //1、将当前活跃文件设置为非活跃文件
         let file_id = self.log.as_mut().unwrap().file_id.clone();
+        let sealed = file_id
+            .as_str()
+            .strip_suffix("_active")
+            .unwrap_or(file_id.as_str())
+            .to_string();
         fs::rename(
             Path::new(&(db_base.clone() + file_id.as_str())),
-            Path::new(
-                &(db_base.clone()
-                    + file_id
-                    .as_str()
-                    .strip_suffix("_active")
-                    .unwrap_or(file_id.as_str())),
-            ),
+            Path::new(&(db_base.clone() + sealed.as_str())),
         )
             .expect("重命名失败");
+        // 文件编号不变，KeyDir 中指向它的条目无需更新
+        self.files.rename(self.active, sealed);
         //2、创建新的活跃文件
-        let new_file_id = create_tsid().number().to_string() + "_active";
-        let new_log = Log::new_with_base(new_file_id, self.db_base.clone()).unwrap();
-        self.log = Some(new_log);
+        self.open_new_active().unwrap();
     }
 
     /// 判断活跃文件是否超过了限制大小
@@ -180,64 +295,282 @@ impl BitCask {
         file.metadata().unwrap().len() >= get_max_size()
     }
 
-    // 判断Key是在活跃文件还是在非活跃文件
+    // 根据文件编号获取日志文件
     // 如果为活跃文件直接通过self.log 去读取数据
     // 如果不是，则需要初始化一个old非活跃文件实体old_log 去读取数据
-    fn get_log_by_key(&self, key: Vec<u8>) -> Result<Option<Log>> {
-        let file_id = self
-            .keydir
-            .get(&key)
-            .map(|(file_id, _)| file_id.clone())
-            .unwrap_or("".to_owned());
-        if file_id.eq("") {
+    fn get_log(&self, file_id: FileId) -> Result<Log> {
+        if file_id == self.active {
+            if let Some(log) = &self.log {
+                return Ok(log.to_owned()); // 返回 Log 的克隆
+            }
+        }
+        let name = self.files.name(file_id)?;
+        Log::new_with_base(name.to_string(), self.db_base.clone()) // 创建新的 Log 实例
+    }
+
+    /// 根据 KeyDir 条目读取值，crc 校验失败时返回 None
+    /// 只需要 `&self`：通过文件表中共享的只读句柄做定位读，多个读者可以并行
+    fn read_value(&self, entry: &KeyDirEntry) -> Result<Option<Vec<u8>>> {
+        let file = self.files.get(entry.file_id)?;
+        let reader = file.reader(&self.db_base)?;
+        let Some(mut log_entry) = read_entry_at(reader, file.format, entry.crc_pos)? else {
             return Ok(None);
+        };
+        log_entry.decrypt(self.keyring.as_deref())?;
+        log_entry.take_value().map(Some)
+    }
+
+    /// 估算内存索引（KeyDir 与文件表）占用的字节数
+    fn index_memory(&self) -> u64 {
+        let per_key = (std::mem::size_of::<Box<[u8]>>() + std::mem::size_of::<KeyDirEntry>()) as u64;
+        let key_bytes: u64 = self.files.iter().map(|file| file.stats.key_bytes).sum();
+        self.keydir.len() as u64 * per_key + key_bytes + self.files.memory_usage() as u64
+    }
+
+    /// 更新 KeyDir，被覆盖的旧条目在其所在文件中转为垃圾数据
+    fn index_insert(&mut self, key: &[u8], entry: KeyDirEntry) {
+        self.files.add_live(key.len(), &entry);
+        if let Some(old) = self.keydir.insert(key.into(), entry) {
+            self.files.remove_live(key.len(), &old);
         }
-        let active = &self.log;
-        match active {
-            None => Ok(None),
-            _ => {
-                let active_file_id = active.as_ref().unwrap().file_id.clone();
-                if file_id.eq(&active_file_id) {
-                    return Ok(Some(active.as_ref().unwrap().to_owned())); // 返回 Log 的克隆
-                }
-                let log = Log::new_with_base(file_id.to_string(), self.db_base.clone())?; // 创建新的 Log 实例
-                Ok(Some(log)) // 返回新的 Log 实例
+    }
+
+    /// 从 KeyDir 中移除键，旧条目在其所在文件中转为垃圾数据
+    fn index_remove(&mut self, key: &[u8]) {
+        if let Some(old) = self.keydir.remove(key) {
+            self.files.remove_live(key.len(), &old);
+        }
+    }
+
+    /// 各数据文件的存活/垃圾数据统计，供压缩调度使用
+    pub fn file_status(&self) -> Vec<FileStatus> {
+        self.files.iter().map(DataFile::status).collect()
+    }
+
+    /// 活跃文件超过大小限制时先压缩，压缩后仍超过限制则切换到新的活跃文件
+    fn rotate_if_full(&mut self) -> Result<()> {
+        {
+            let log = self.log.as_ref().unwrap();
+            let file = log.file.lock()?;
+            let need_compact = BitCask::check_size_limit(&file);
+            info!("需要压缩:{:?}", need_compact);
+            if need_compact {
+                drop(file);
+                self.compact()?;
+            }
+        }
+        {
+            let log = self.log.as_ref().unwrap();
+            let file = log.file.lock()?;
+            let need_refresh = BitCask::check_size_limit(&file);
+            info!("需要写入到新的活跃文件:{:?}", need_refresh);
+            if need_refresh {
+                drop(file);
+                self.refresh_active();
             }
         }
+        Ok(())
     }
 
     /// compact方法
-    /// 压缩活跃日志文件：
+    /// 压缩日志文件：
     /// 1、创建新的活跃日志文件，将所有活跃的键写入新的日志文件
-    /// 2、删除旧的活跃日志文件
+    /// 2、删除其余所有日志文件，它们只剩垃圾数据
+    ///
+    /// 存活的值按当前的压缩与加密设置重新编码，因此压缩之后所有数据都以当前密钥加密，
+    /// 旧密钥可以从密钥文件中删除（见 [`BitCask::rotate_keys`]）；已经过期的键不再写入
     fn compact(&mut self) -> Result<()> {
-        let db_base = self.db_base.clone();
-        // 3、根据旧文件名删除旧的活跃日志文件
-        let old_file_id = self.log.as_ref().unwrap().file_id.clone();
+        self.check_writable()?;
         // 1、创建新的活跃日志文件
-        let new_log = Log::new_with_base(create_tsid().number().to_string() + "_active", self.db_base.clone())?;
-        self.log = Some(new_log);
-        fn write(log: &mut Log, key: Vec<u8>, value: Vec<u8>) -> Result<u64> {
-            let tstamp = crate::utils::get_timestamp_to_vec();
-            let mut log_entry = LogEntry::new(tstamp, key, value);
-            log_entry.build_crc(); // 构建crc校验字段
-            log.write_entry(log_entry)
-        }
-        // 2、将所有活跃的键写入新的日志文件
-        let keydir = self.keydir.clone();
-        for (key, _) in keydir.iter() {
-            let value = self.get(key)?;
-            let crc_pos = write(self.log.as_mut().unwrap(), key.clone(), value.unwrap())?;
-            self.keydir.insert(
-                key.clone(),
-                (self.log.as_ref().unwrap().file_id.clone(), crc_pos as u32),
-            );
+        self.open_new_active()?;
+        let now = crate::utils::get_timestamp_millis();
+        // 2、将所有活跃的键分批写入新的日志文件
+        let mut last: Option<Box<[u8]>> = None;
+        loop {
+            let start = match &last {
+                Some(key) => Bound::Excluded(&**key),
+                None => Bound::Unbounded,
+            };
+            let batch: Vec<(Box<[u8]>, KeyDirEntry)> = self
+                .keydir
+                .range::<[u8], _>((start, Bound::Unbounded))
+                .take(COMPACT_BATCH)
+                .map(|(key, entry)| (key.clone(), *entry))
+                .collect();
+            let Some((last_key, _)) = batch.last() else {
+                break;
+            };
+            last = Some(last_key.clone());
+            for (key, entry) in batch {
+                if entry.is_expired(now) {
+                    self.index_remove(&key);
+                    continue;
+                }
+                let Some(value) = self.read_value(&entry)? else {
+                    tracing::warn!("compact: 键 {} 的条目校验失败，已丢弃", Raw::bytes(&key));
+                    self.index_remove(&key);
+                    continue;
+                };
+                // 保留原始写入时间与过期时间；值按当前的压缩与加密设置重新编码
+                let mut log_entry = LogEntry::new(FileFormat::CURRENT, entry.tstamp, key.to_vec(), value)
+                    .with_expiry(entry.expiry());
+                let value_sz = log_entry.encode(self.compression, self.keyring.as_deref())?;
+                let crc_pos = self.log.as_mut().unwrap().write_entry(log_entry)?;
+                self.files.append(self.active, key.len(), value_sz as usize);
+                let entry = KeyDirEntry { file_id: self.active, crc_pos, value_sz, ..entry };
+                self.index_insert(&key, entry);
+            }
         }
         self.flush()?;
-        fs::remove_file(Path::new(&(db_base.clone() + old_file_id.as_str())))?;
+        // 3、按创建顺序删除旧文件：先删较早的文件，中途崩溃时不会出现旧值越过较新的墓碑复活
+        let stale: Vec<FileId> = self.files.ids().filter(|&file_id| file_id != self.active).collect();
+        for file_id in stale {
+            let name = self.files.name(file_id)?;
+            fs::remove_file(Path::new(&(self.db_base.clone() + name)))?;
+            self.files.remove(file_id);
+        }
         Ok(())
     }
+
+    /// 立即压缩，以当前密钥（密钥文件的最后一行）重新加密所有存活数据，并删除包含旧密钥密文的文件
+    /// 完成后旧密钥不再被任何条目使用，可以从密钥文件中删除
+    pub fn rotate_keys(&mut self) -> Result<()> {
+        self.compact()
+    }
 }
+/// 离线维护工具：dump / fsck / repair
+/// 只读取数据目录中的文件，不依赖已打开的 BitCask 实例；加密的条目使用配置中的密钥文件解密
+impl BitCask {
+    /// 逐条读取数据目录中的所有日志条目（按文件创建顺序），并交给 `visit` 处理。
+    /// 无法解析的条目以 `Err` 传入，随后跳过该文件的剩余部分。
+    pub fn dump(dir: &Path, mut visit: impl FnMut(Result<EntryRecord>) -> Result<()>) -> Result<()> {
+        let keyring = Keyring::from_config()?.map(Arc::new);
+        for file_path in log_files(dir)? {
+            for record in LogScanner::open(&file_path, keyring.clone())? {
+                visit(record)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验数据目录：
+    /// 1、逐条校验所有条目的 crc，并检查文件末尾是否存在截断的条目
+    /// 2、按 `build_key_dir` 的规则重建 KeyDir，确认每个存活键指向的条目可以正常读取
+    pub fn fsck(dir: &Path) -> Result<FsckReport> {
+        Self::fsck_with_keyring(dir, Keyring::from_config()?.map(Arc::new))
+    }
+
+    fn fsck_with_keyring(dir: &Path, keyring: Option<Arc<Keyring>>) -> Result<FsckReport> {
+        let mut report = FsckReport::default();
+        let mut keydir: std::collections::BTreeMap<Vec<u8>, EntryRecord> = Default::default();
+        let now = crate::utils::get_timestamp_millis();
+        for file_path in log_files(dir)? {
+            report.files += 1;
+            for record in LogScanner::open(&file_path, keyring.clone())? {
+                match record {
+                    Ok(record) => {
+                        report.entries += 1;
+                        if !record.crc_ok {
+                            report.problems.push(format!(
+                                "{}@{}: crc mismatch for key {}",
+                                record.file_id,
+                                record.offset,
+                                Raw::bytes(&record.key)
+                            ));
+                        }
+                        // 与打开时重建 KeyDir 一致，过期的条目视为墓碑
+                        match record.value {
+                            Some(_) if !record.is_expired(now) => keydir.insert(record.key.clone(), record),
+                            _ => keydir.remove(&record.key),
+                        };
+                    }
+                    Err(e) => report.problems.push(e.to_string()),
+                }
+            }
+        }
+        for (key, record) in &keydir {
+            if !record.crc_ok {
+                report.problems.push(format!(
+                    "keydir: key {} resolves to corrupt entry {}@{}",
+                    Raw::bytes(key),
+                    record.file_id,
+                    record.offset
+                ));
+            }
+        }
+        report.live_keys = keydir.len();
+        Ok(report)
+    }
+
+    /// 从 `src` 中抢救所有 crc 校验通过的条目，写入一个全新的数据目录 `dest`。
+    /// 每个文件遇到无法解析的条目时停止读取该文件；`dest` 必须不存在或为空目录。
+    pub fn repair(src: &Path, dest: &Path) -> Result<RepairReport> {
+        if dest.is_dir() && read_dir(dest)?.next().is_some() {
+            return errdata!("repair destination {} is not empty", dest.display());
+        }
+        let mut report = RepairReport::default();
+        let mut live: std::collections::BTreeMap<Vec<u8>, (Vec<u8>, Option<u64>)> = Default::default();
+        let keyring = Keyring::from_config()?.map(Arc::new);
+        let now = crate::utils::get_timestamp_millis();
+        for file_path in log_files(src)? {
+            for record in LogScanner::open(&file_path, keyring.clone())? {
+                let Ok(record) = record else {
+                    report.truncated_files += 1;
+                    break;
+                };
+                if !record.crc_ok {
+                    report.dropped += 1;
+                    continue;
+                }
+                report.salvaged += 1;
+                let expired = record.is_expired(now);
+                match record.value {
+                    Some(value) if !expired => live.insert(record.key, (value, record.expires_at)),
+                    _ => live.remove(&record.key),
+                };
+            }
+        }
+        let mut db = Self::init_db_at(dest)?;
+        // 未过期的键保留原来的过期时间
+        for (key, (value, expires_at)) in &live {
+            db.put(key, value, *expires_at)?;
+        }
+        db.flush()?;
+        report.live_keys = live.len();
+        Ok(report)
+    }
+}
+
+impl BitCask {
+    /// 把 `checkpoint` 生成的备份恢复到 `dest`：先用 fsck 校验备份，再复制全部数据文件。
+    /// `dest` 必须不存在或为空目录，恢复期间不能有其他进程打开它。
+    pub fn restore(backup: &Path, dest: &Path) -> Result<FsckReport> {
+        let report = Self::fsck(backup)?;
+        if report.files == 0 {
+            return errdata!("backup {} contains no data files", backup.display());
+        }
+        if !report.is_clean() {
+            return errdata!(
+                "backup {} failed verification with {} problems, first: {}",
+                backup.display(),
+                report.problems.len(),
+                report.problems[0]
+            );
+        }
+        if dest.is_dir() && read_dir(dest)?.next().is_some() {
+            return errdata!("restore destination {} is not empty", dest.display());
+        }
+        fs::create_dir_all(dest)?;
+        for path in log_files(backup)? {
+            let target = dest.join(path.file_name().unwrap());
+            fs::copy(&path, &target)?;
+            fs::File::open(&target)?.sync_all()?;
+        }
+        fs::File::open(dest)?.sync_all()?;
+        Ok(report)
+    }
+}
+
 impl Drop for BitCask {
     fn drop(&mut self) {
         self.flush().expect("缓冲数据无法刷入磁盘");
@@ -245,86 +578,135 @@ impl Drop for BitCask {
     }
 }
 
-impl Engine for BitCask {
-    type ScanIter<'a> = ScanIterator<'a>;
-    /// 写入条目数据
-    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+impl BitCask {
+    /// 写入条目数据，expires_at 为过期时间（Unix 毫秒），None 表示永不过期
+    fn put(&mut self, key: &[u8], value: &[u8], expires_at: Option<u64>) -> Result<()> {
+        self.check_writable()?;
+        u32::try_from(value.len()).or_else(|_| errinput!("value too large: {} bytes", value.len()))?;
+        // 空值在重新打开时视为墓碑，不需要过期时间
+        let expires_at = expires_at.filter(|_| !value.is_empty());
+        let keyring = self.keyring.clone();
+        // 返回条目位置与计入文件统计的值长度
+        let write = |log: &mut Log, compression: ValueCompression, tstamp: u64, key: &[u8], value: &[u8]| -> Result<(u64, u32)> {
+            let mut log_entry = LogEntry::new(log.format, tstamp, key.to_vec(), value.to_vec()).with_expiry(expires_at);
+            let value_sz = log_entry.encode(compression, keyring.as_deref())?;
+            Ok((log.write_entry(log_entry)?, value_sz))
+        };
+        let tstamp = crate::utils::get_timestamp_millis();
+        // 加密和过期时间都需要标志位，不能写入没有标志位的旧格式文件
+        let needs_flags = self.keyring.is_some() || expires_at.is_some();
         // 0、获取当前key所在的文件
-        let mut belong_log = self.get_log_by_key(key.to_vec())?;
-        let current_file_id = self.log.clone().unwrap().file_id;
-        fn write(log: &mut Log, key: &[u8], value: &[u8]) -> Result<u64> {
-            let tstamp = crate::utils::get_timestamp_to_vec();
-            let mut log_entry = LogEntry::new(tstamp, key.to_vec(), value.to_vec());
-            log_entry.build_crc(); // 构建crc校验字段
-            log.write_entry(log_entry)
-        }
-        if let Some(log) = &mut belong_log {
+        let existing = self.keydir.get(key).map(|entry| entry.file_id).filter(|&file_id| {
+            !needs_flags || self.files.get(file_id).is_ok_and(|file| file.format.has_flags())
+        });
+        let expires_at = expires_at.unwrap_or(0);
+        if let Some(file_id) = existing {
             // 键值已经存在,写入数据
-            let crc_pos = write(log, key, value)?;
+            let mut log = self.get_log(file_id)?;
+            let (crc_pos, value_sz) = write(&mut log, self.compression, tstamp, key, value)?;
             info!("写入文件位置:{:?}", crc_pos);
-            // 4、更新索引
-            self.keydir
-                .insert(key.to_vec(), (log.file_id.clone(), crc_pos as u32));
+            // 4、更新索引与文件统计
+            self.files.append(file_id, key.len(), value_sz as usize);
+            self.index_insert(key, KeyDirEntry { file_id, crc_pos, value_sz, tstamp, expires_at });
         } else {
-            {
-                let log = self.log.as_ref().unwrap();
-                let file = log.file.lock()?;
-                let need_compact =
-                    log.file_id == current_file_id && BitCask::check_size_limit(&file);
-                info!("需要压缩:{:?}", need_compact);
-                if need_compact {
-                    drop(file);
-                    self.compact()?;
-                }
-            }
-            {
-                let log = self.log.as_ref().unwrap();
-                let file = log.file.lock()?;
-                let need_refresh =
-                    log.file_id == current_file_id && BitCask::check_size_limit(&file);
-                info!("需要写入到新的活跃文件:{:?}", need_refresh);
-                if need_refresh {
-                    drop(file);
-                    self.refresh_active();
-                }
+            self.rotate_if_full()?;
+            if needs_flags && !self.log.as_ref().unwrap().format.has_flags() {
+                self.refresh_active();
             }
-
             let log = self.log.as_mut().unwrap();
-            let crc_pos = write(log, key, value)?;
+            let (crc_pos, value_sz) = write(log, self.compression, tstamp, key, value)?;
             info!("写入文件位置:{:?}", crc_pos);
-            // 4、更新索引
-            self.keydir
-                .insert(key.to_vec(), (log.file_id.clone(), crc_pos as u32));
+            // 4、更新索引与文件统计
+            let file_id = self.active;
+            self.files.append(file_id, key.len(), value_sz as usize);
+            self.index_insert(key, KeyDirEntry { file_id, crc_pos, value_sz, tstamp, expires_at });
         }
         Ok(())
     }
+}
+
+impl Engine for BitCask {
+    type ScanIter<'a> = ScanIterator<'a>;
+    /// 写入条目数据
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.put(key, value, None)
+    }
+
+    /// 写入带过期时间的条目，过期时间与值一起写入日志
+    fn set_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        let expires_at = crate::utils::get_timestamp_millis().saturating_add(ttl.as_millis() as u64);
+        self.put(key, value, Some(expires_at))
+    }
+
     /// 读取条目数据
     /// 根据keyDir取获取
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         // 1、根据key,从keydir中读相关的存储信息
-        // KeyDir：key ——— (fileId、crc_pos）
-        if let Some((_, crc_pos)) = self.keydir.get(key) {
-            let log = self.get_log_by_key(key.to_vec())?;
-            match log {
-                Some(mut log) => {
-                    println!("读取文件位置:{:?}", *crc_pos);
-                    let entry = log.read_entry(*crc_pos)?;
-                    match entry {
-                        Some(e) => Ok(Some(e.value)),
-                        None => Ok(None),
-                    }
+        // KeyDir：key ——— (fileId、crc_pos、value_sz、tstamp、expires_at）
+        match self.keydir.get(key) {
+            Some(entry) if !entry.is_expired(crate::utils::get_timestamp_millis()) => self.read_value(entry),
+            _ => Ok(None),
+        }
+    }
+
+    /// 整批操作编码为一个带提交标记的帧，一次追加写入活跃文件
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.check_writable()?;
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.rotate_if_full()?;
+        // 旧格式的文件无法识别批量写入帧，也无法标记加密的条目，先切换到新格式的活跃文件
+        let format = self.log.as_ref().unwrap().format;
+        if !format.supports_batch() || (self.keyring.is_some() && !format.has_flags()) {
+            self.refresh_active();
+        }
+        let format = self.log.as_ref().unwrap().format;
+        let tstamp = crate::utils::get_timestamp_millis();
+        let count = batch.len() as u32;
+        // 1、每个操作编码为一个普通条目，记录它在帧内的偏移
+        let mut payload = vec![];
+        let mut ops = Vec::with_capacity(batch.len());
+        for op in batch {
+            let (key, value, is_put) = match op {
+                WriteOp::Put(key, value) => (key, value, true),
+                WriteOp::Delete(key) => (key, vec![], false),
+            };
+            u32::try_from(value.len()).or_else(|_| errinput!("value too large: {} bytes", value.len()))?;
+            let mut entry = LogEntry::new(format, tstamp, key.clone(), value);
+            let value_sz = entry.encode(self.compression, self.keyring.as_deref())?;
+            ops.push((key, payload.len() as u64, is_put.then_some(value_sz)));
+            payload.extend(entry.get_entry());
+        }
+        // 2、追加提交标记，整个帧由外层 crc 保护
+        payload.extend_from_slice(BATCH_COMMIT);
+        payload.extend_from_slice(&count.to_be_bytes());
+        let mut frame = LogEntry::batch(format, tstamp, payload);
+        frame.build_crc();
+        let frame_len = frame.get_entry().len() as u64;
+        let frame_pos = self.log.as_mut().unwrap().write_entry(frame)?;
+        info!("批量写入文件位置:{:?}", frame_pos);
+        // 3、更新索引与文件统计，条目位置指向帧内的普通条目
+        let file_id = self.active;
+        self.files.grow(file_id, frame_len);
+        let entries_start = frame_pos + format.entry_header_len() as u64;
+        // 与 set 一致：写入空值的键保留在 KeyDir 中，删除的键从 KeyDir 移除
+        for (key, offset, value_sz) in ops {
+            match value_sz {
+                Some(value_sz) => {
+                    let crc_pos = entries_start + offset;
+                    self.index_insert(&key, KeyDirEntry { file_id, crc_pos, value_sz, tstamp, expires_at: 0 });
                 }
-                None => Ok(None),
+                None => self.index_remove(&key),
             }
-        } else {
-            Ok(None)
         }
+        Ok(())
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<()> {
         self.set(&key, &[])?;
         self.flush()?;
-        self.keydir.remove(key);
+        self.index_remove(key);
         Ok(())
     }
 
@@ -336,10 +718,15 @@ impl Engine for BitCask {
         }
         Ok(())
     }
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+        let range = (
+            range.start_bound().map(|key| key.as_slice()),
+            range.end_bound().map(|key| key.as_slice()),
+        );
         Self::ScanIter {
-            inner: self.keydir.range(range),
-            log: &mut self.log,
+            inner: self.keydir.range::<[u8], _>(range),
+            db: self,
+            now: crate::utils::get_timestamp_millis(),
         }
     }
 
@@ -353,103 +740,66 @@ impl Engine for BitCask {
         Ok(())
     }
 
-    fn status(&mut self) -> Result<EngineStatus> {
-        let db_base = self.db_base.clone();
-        //1、获取所有key
-        let keys = self.keydir.keys().cloned().collect::<Vec<_>>();
-        // 获取所有存活的key
-        let live_keys = keys
-            .iter()
-            .filter(|&key| {
-                println!("获取key:{:?}", String::from_utf8_lossy(key));
-                match self.keydir.contains_key(key) {
-                    true => true,
-                    false => false, // 处理 Err 或 None 的情况
-                }
-            })
-            .collect::<Vec<_>>();
-        //2、计算所有键值的数量
-        let total_count = self.keydir.len();
-        let logical_size: u64 = self
-            .keydir
-            .iter()
-            .map(|(key, value)| {
-                let mut log = match self.get_log_by_key(key.clone()) {
-                    Ok(Some(log)) => log,
-                    _ => return 0, // 处理错误情况
-                };
-                match log.read_entry(value.1) {
-                    Ok(Some(entry)) => entry.get_entry().len(),
-                    _ => 0, // 处理错误情况
-                }
-            })
-            .sum::<usize>() as u64;
-        //3、计算所有数据的磁盘总占用空间
-        let path = Path::new(db_base.as_str());
-        let mut total_disk_size = 0;
-        let mut live_disk_size = 0;
-        if path.is_dir() {
-            // 获取所有目录条目，并处理可能的错误
-            let entries: Vec<_> = match read_dir(path) {
-                Ok(dir) => dir.filter_map(|entry| entry.ok()).collect(),
-                Err(e) => {
-                    eprintln!("无法读取目录: {}", e);
-                    vec![] // 返回空数组，继续执行
-                }
-            };
-            // 遍历所有目录条目，计算总大小
-            total_disk_size = entries
-                .iter()
-                .filter_map(|entry| entry.path().metadata().ok())
-                .map(|metadata| metadata.len())
-                .sum();
-            if total_disk_size > 0 {
-                //4、计算活跃数据的磁盘存储空间
-                live_disk_size = live_keys
-                    .iter()
-                    .map(|&key| {
-                        let log = self.get_log_by_key(key.clone()).unwrap();
-                        match log {
-                            Some(mut log) => {
-                                let value = self.keydir.get(&key.clone()).unwrap();
-                                log.read_entry(value.1).unwrap().unwrap().get_entry().len()
-                            }
-                            None => 0,
-                        }
-                    })
-                    .sum::<usize>() as u64;
-            }
-        }
-        let garbage_disk_size = total_disk_size - live_disk_size;
-
+    fn status(&self) -> Result<EngineStatus> {
+        // 由各文件增量维护的计数汇总得出，无需读取数据文件
+        let files = self.file_status();
+        let logical_size = self.files.iter().map(|file| file.stats.logical_bytes()).sum();
+        let total_size: u64 = files.iter().map(|file| file.total_size).sum();
+        let live_size: u64 = files.iter().map(|file| file.live_size).sum();
         Ok(EngineStatus {
             name: "bitcask".to_string(),
             logical_size,
-            total_count: total_count as u64,
-            total_size: total_disk_size,
-            live_size: live_disk_size,
-            garbage_size: garbage_disk_size,
+            total_count: self.keydir.len() as u64,
+            total_size,
+            live_size,
+            garbage_size: total_size.saturating_sub(live_size),
+            index_memory: self.index_memory(),
+            files,
         })
     }
+
+    /// 在线备份：把每个数据文件复制到 dest，只复制到文件统计中记录的长度
+    ///
+    /// 旧文件并不是只读的：覆盖或删除一个键时条目追加到该键所在的文件（见 set），
+    /// 因此不能用硬链接共享文件内容，否则之后的写入会出现在副本中。
+    /// 记录的长度就是最后一个已写入条目的结束位置，截取到这里即为此刻的一致快照；
+    /// 副本中未提交的事务在打开时由 MVCC 回滚。
+    fn checkpoint(&self, dest: &Path) -> Result<()> {
+        if dest.is_dir() && read_dir(dest)?.next().is_some() {
+            return errdata!("checkpoint destination {} is not empty", dest.display());
+        }
+        fs::create_dir_all(dest)?;
+        for file in self.files.iter() {
+            let len = file.stats.total_bytes;
+            let src = fs::File::open(PathBuf::from(self.db_base.clone() + &file.name))?;
+            let mut out = fs::File::create(dest.join(&file.name))?;
+            let copied = std::io::copy(&mut src.take(len), &mut out)?;
+            if copied != len {
+                return errdata!("{}: expected {len} bytes, copied {copied}", file.name);
+            }
+            out.sync_all()?;
+        }
+        // 文件名写入目录项后同样需要落盘
+        fs::File::open(dest)?.sync_all()?;
+        info!("checkpoint: {} 个文件写入 {}", self.files.iter().count(), dest.display());
+        Ok(())
+    }
 }
 
 /// 迭代器结构体
 /// ScanIterator
 pub struct ScanIterator<'a> {
     /// 迭代器
-    inner: Range<'a, Vec<u8>, ValTuple>,
-    /// 所属日志
-    log: &'a mut Option<Log>,
+    inner: Range<'a, Box<[u8]>, KeyDirEntry>,
+    /// 所属数据库，按条目的文件编号读取值
+    db: &'a BitCask,
+    /// 扫描开始的时间，在此之前过期的键被跳过
+    now: u64,
 }
 impl<'a> ScanIterator<'a> {
-    fn map(&mut self, item: (&Vec<u8>, &ValTuple)) -> <Self as Iterator>::Item {
-        let (key, value) = item;
-        let log = self.log.as_mut().unwrap();
-        let val = match log.read_entry(value.1)? {
-            Some(entry) => entry.value,
-            None => vec![],
-        };
-        Ok((key.clone(), val))
+    fn map(&mut self, key: &[u8], entry: &KeyDirEntry) -> <Self as Iterator>::Item {
+        let val = self.db.read_value(entry)?.unwrap_or_default();
+        Ok((key.to_vec(), val))
     }
 }
 /// 实现由前向后迭代功能
@@ -457,21 +807,212 @@ impl<'a> Iterator for ScanIterator<'a> {
     type Item = Result<(Vec<u8>, Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|item| self.map(item))
+        let now = self.now;
+        self.inner.find(|(_, entry)| !entry.is_expired(now)).map(|(key, entry)| self.map(key, entry))
     }
 }
 /// 实现由后向前迭代功能
 impl<'a> DoubleEndedIterator for ScanIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.inner.next_back().map(|item| self.map(item))
+        let now = self.now;
+        self.inner.rfind(|(_, entry)| !entry.is_expired(now)).map(|(key, entry)| self.map(key, entry))
     }
 }
 
 /// KeyDir
-/// 维护key和（fileId、crc_pos）的映射关系
+/// 维护key和（fileId、crc_pos、value_sz、tstamp）的映射关系
 /// 因为基于当前设计crc、tstamp、ksz、value_sz均为定长数组
-type KeyDir = std::collections::BTreeMap<Vec<u8>, ValTuple>;
-type ValTuple = (String, u32);
+/// crc_pos 使用 64 位偏移，单个文件可以超过 4 GiB
+/// 键使用 `Box<[u8]>` 存储，省去 `Vec` 的容量字段
+type KeyDir = std::collections::BTreeMap<Box<[u8]>, KeyDirEntry>;
+
+/// 数据文件在文件表中的编号，KeyDir 中以它代替文件名
+type FileId = u32;
+
+/// 启动扫描时读出的一条记录：键、加密条目的附加认证数据（未加密时为 None），以及墓碑之外的 KeyDir 条目
+type ScannedKey = (Vec<u8>, Option<Vec<u8>>, Option<KeyDirEntry>);
+
+/// KeyDir 中每个键对应的定长条目
+#[derive(Debug, Clone, Copy)]
+struct KeyDirEntry {
+    /// 所在数据文件的编号
+    file_id: FileId,
+    /// 条目在文件中的起始位置
+    crc_pos: u64,
+    /// 值在文件中的长度（压缩后）
+    value_sz: u32,
+    /// 写入时间（毫秒）
+    tstamp: u64,
+    /// 过期时间（Unix 毫秒），0 表示永不过期
+    expires_at: u64,
+}
+
+impl KeyDirEntry {
+    /// 在 now 时刻是否已经过期
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+
+    fn expiry(&self) -> Option<u64> {
+        (self.expires_at != 0).then_some(self.expires_at)
+    }
+}
+
+/// 数据文件表：文件编号 → 文件名与存储格式
+/// 被压缩删除的文件保留空位，编号不会复用
+#[derive(Debug, Default)]
+struct FileTable {
+    files: Vec<Option<DataFile>>,
+}
+
+#[derive(Debug)]
+struct DataFile {
+    name: String,
+    format: FileFormat,
+    stats: FileStats,
+    /// 只读句柄，首次读取时打开；读者之间通过定位读共享，不移动文件指针
+    reader: OnceLock<fs::File>,
+}
+
+/// 单个数据文件的增量统计，在 set / delete / compact / build_key_dir 中维护
+/// 存活条目指 KeyDir 中仍然指向该文件的条目，文件中其余字节（文件头、被覆盖的条目、墓碑、截断的尾部）均为垃圾数据
+#[derive(Debug, Default)]
+struct FileStats {
+    /// 文件总字节数
+    total_bytes: u64,
+    /// 存活条目数
+    live_keys: u64,
+    /// 存活条目的字节数（含条目头部）
+    live_bytes: u64,
+    /// 存活条目中键的字节数
+    key_bytes: u64,
+    /// 存活条目中值的字节数（压缩后）
+    value_bytes: u64,
+}
+
+impl FileStats {
+    fn logical_bytes(&self) -> u64 {
+        self.key_bytes + self.value_bytes
+    }
+}
+
+impl DataFile {
+    /// 获取只读句柄。文件轮转重命名后句柄仍指向同一个文件
+    fn reader(&self, db_base: &str) -> Result<&fs::File> {
+        if let Some(file) = self.reader.get() {
+            return Ok(file);
+        }
+        let file = fs::File::open(PathBuf::from(db_base.to_string() + &self.name))?;
+        // 并发打开时只保留先完成的句柄
+        let _ = self.reader.set(file);
+        Ok(self.reader.get().expect("reader initialized"))
+    }
+
+    fn entry_len(&self, key_len: usize, value_len: usize) -> u64 {
+        (self.format.entry_header_len() + key_len + value_len) as u64
+    }
+
+    fn status(&self) -> FileStatus {
+        FileStatus {
+            name: self.name.clone(),
+            total_size: self.stats.total_bytes,
+            live_size: self.stats.live_bytes,
+            live_count: self.stats.live_keys,
+        }
+    }
+}
+
+impl FileTable {
+    /// 登记一个数据文件，返回它的编号；`total_bytes` 为文件当前长度
+    fn add(&mut self, name: String, format: FileFormat, total_bytes: u64) -> FileId {
+        let stats = FileStats { total_bytes, ..Default::default() };
+        self.files.push(Some(DataFile { name, format, stats, reader: OnceLock::new() }));
+        (self.files.len() - 1) as FileId
+    }
+
+    /// 所有仍然存在的数据文件
+    fn iter(&self) -> impl Iterator<Item = &DataFile> {
+        self.files.iter().flatten()
+    }
+
+    /// 所有仍然存在的数据文件的编号，按创建顺序
+    fn ids(&self) -> impl Iterator<Item = FileId> + '_ {
+        self.files.iter().enumerate().filter(|(_, file)| file.is_some()).map(|(id, _)| id as FileId)
+    }
+
+    fn get(&self, file_id: FileId) -> Result<&DataFile> {
+        match self.files.get(file_id as usize) {
+            Some(Some(file)) => Ok(file),
+            _ => errdata!("unknown data file id {file_id}"),
+        }
+    }
+
+    fn name(&self, file_id: FileId) -> Result<&str> {
+        Ok(self.get(file_id)?.name.as_str())
+    }
+
+    fn get_mut(&mut self, file_id: FileId) -> Option<&mut DataFile> {
+        self.files.get_mut(file_id as usize).and_then(|file| file.as_mut())
+    }
+
+    /// 记录向文件追加了一个条目
+    fn append(&mut self, file_id: FileId, key_len: usize, value_len: usize) {
+        if let Some(file) = self.get_mut(file_id) {
+            file.stats.total_bytes += file.entry_len(key_len, value_len);
+        }
+    }
+
+    /// 记录向文件追加了 `bytes` 字节（如批量写入帧）
+    fn grow(&mut self, file_id: FileId, bytes: u64) {
+        if let Some(file) = self.get_mut(file_id) {
+            file.stats.total_bytes += bytes;
+        }
+    }
+
+    /// 条目成为 KeyDir 中的存活条目
+    fn add_live(&mut self, key_len: usize, entry: &KeyDirEntry) {
+        if let Some(file) = self.get_mut(entry.file_id) {
+            let entry_len = file.entry_len(key_len, entry.value_sz as usize);
+            let stats = &mut file.stats;
+            stats.live_keys += 1;
+            stats.live_bytes += entry_len;
+            stats.key_bytes += key_len as u64;
+            stats.value_bytes += entry.value_sz as u64;
+        }
+    }
+
+    /// 条目被覆盖或删除，转为垃圾数据
+    fn remove_live(&mut self, key_len: usize, entry: &KeyDirEntry) {
+        if let Some(file) = self.get_mut(entry.file_id) {
+            let entry_len = file.entry_len(key_len, entry.value_sz as usize);
+            let stats = &mut file.stats;
+            stats.live_keys -= 1;
+            stats.live_bytes -= entry_len;
+            stats.key_bytes -= key_len as u64;
+            stats.value_bytes -= entry.value_sz as u64;
+        }
+    }
+
+    /// 文件轮转后更新文件名
+    fn rename(&mut self, file_id: FileId, name: String) {
+        if let Some(Some(file)) = self.files.get_mut(file_id as usize) {
+            file.name = name;
+        }
+    }
+
+    fn remove(&mut self, file_id: FileId) {
+        if let Some(file) = self.files.get_mut(file_id as usize) {
+            *file = None;
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        let slots = self.files.capacity() * std::mem::size_of::<Option<DataFile>>();
+        let names: usize = self.iter().map(|file| file.name.capacity()).sum();
+        slots + names
+    }
+}
+
 /// 实现一个日志文件结构体
 /// file_id 文件的索引
 /// file_path 文件路径
@@ -484,145 +1025,802 @@ struct Log {
     file_path: PathBuf,
     file: Arc<Mutex<fs::File>>, // 使用 Arc 和 Mutex 包装 File
     #[allow(dead_code)]
-    current_offset: u32,
+    current_offset: u64,
+    /// 文件的存储格式，由文件头决定
+    format: FileFormat,
+}
+
+/// 数据文件头部的魔数
+const MAGIC: &[u8; 4] = b"MDBK";
+/// 数据文件头部：
+/// ```text
+/// ------|--------|---------|---------|
+/// magic |version |checksum |reserved |
+/// ------|--------|---------|---------|
+///  4    | 1      | 1       | 2       |
+/// ------|--------|---------|---------|
+/// ```
+const FILE_HEADER_LEN: u64 = 8;
+
+/// 条目完整性校验算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// 旧格式：SHA3-256 摘要截取的 8 个字节
+    Sha3,
+    /// CRC32C（Castagnoli），4 个字节
+    Crc32c,
+}
+
+impl Checksum {
+    fn len(&self) -> usize {
+        match self {
+            Checksum::Sha3 => 8,
+            Checksum::Crc32c => 4,
+        }
+    }
+
+    fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Checksum::Sha3 => {
+                let mut hasher = Sha3_256::new();
+                hasher.update(data);
+                hasher.finalize()[15..23].to_vec()
+            }
+            Checksum::Crc32c => crc32c::crc32c(data).to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<u8> for Checksum {
+    type Error = crate::db_error::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Checksum::Sha3),
+            1 => Ok(Checksum::Crc32c),
+            v => errdata!("unknown checksum algorithm {v}"),
+        }
+    }
+}
+
+impl From<Checksum> for u8 {
+    fn from(value: Checksum) -> Self {
+        match value {
+            Checksum::Sha3 => 0,
+            Checksum::Crc32c => 1,
+        }
+    }
+}
+
+/// 数据文件的存储格式
+/// - 版本 1：无文件头，条目使用 SHA3 校验，时间戳为 32 位秒（旧格式，只读兼容）
+/// - 版本 2：带文件头，条目使用 CRC32C 校验，时间戳为 32 位秒
+/// - 版本 3：带文件头，条目使用 CRC32C 校验，时间戳为 64 位毫秒，value_sz 字段为 64 位（值仍不超过 `u32::MAX` 字节）
+/// - 版本 4：与版本 3 的条目布局相同，额外支持批量写入帧（value_sz 为 `BATCH_VALUE_SZ`）
+/// - 版本 5：条目头部末尾增加 1 字节标志位，标记值是否压缩（见 [`FLAG_LZ4`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub version: u8,
+    pub checksum: Checksum,
+}
+
+impl FileFormat {
+    /// 没有文件头的旧格式
+    pub const LEGACY: FileFormat = FileFormat { version: 1, checksum: Checksum::Sha3 };
+    /// 带文件头、32 位时间戳的格式
+    pub const V2: FileFormat = FileFormat { version: 2, checksum: Checksum::Crc32c };
+    /// 64 位时间戳、不支持批量写入帧的格式
+    pub const V3: FileFormat = FileFormat { version: 3, checksum: Checksum::Crc32c };
+    /// 支持批量写入帧、条目没有标志位的格式
+    pub const V4: FileFormat = FileFormat { version: 4, checksum: Checksum::Crc32c };
+    /// 新建文件使用的格式
+    pub const CURRENT: FileFormat = FileFormat { version: 5, checksum: Checksum::Crc32c };
+
+    /// 读取文件头识别文件格式，文件开头不是魔数时视为旧格式
+    fn detect<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<Self> {
+        if file_len < FILE_HEADER_LEN {
+            return Ok(Self::LEGACY);
+        }
+        let mut header = [0u8; FILE_HEADER_LEN as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Ok(Self::LEGACY);
+        }
+        let format = FileFormat { version: header[4], checksum: header[5].try_into()? };
+        if ![Self::V2, Self::V3, Self::V4, Self::CURRENT].contains(&format) {
+            return errdata!("unsupported data file format {format:?}");
+        }
+        Ok(format)
+    }
+
+    /// 序列化文件头
+    fn header(&self) -> Vec<u8> {
+        [MAGIC.as_slice(), &[self.version, self.checksum.into()], &[0u8; 2]].concat()
+    }
+
+    /// 第一个条目在文件中的位置
+    fn data_start(&self) -> u64 {
+        match self.version {
+            1 => 0,
+            _ => FILE_HEADER_LEN,
+        }
+    }
+
+    /// 时间戳和 value_sz 是否为 64 位
+    fn is_wide(&self) -> bool {
+        self.version >= 3
+    }
+
+    /// 是否支持批量写入帧
+    fn supports_batch(&self) -> bool {
+        self.version >= 4
+    }
+
+    /// 条目头部是否带标志位
+    fn has_flags(&self) -> bool {
+        self.version >= 5
+    }
+
+    /// tstamp 字段的长度
+    fn tstamp_len(&self) -> usize {
+        if self.is_wide() { 8 } else { 4 }
+    }
+
+    /// value_sz 字段的长度
+    fn value_sz_len(&self) -> usize {
+        if self.is_wide() { 8 } else { 4 }
+    }
+
+    /// 条目头部（crc、tstamp、ksz、value_sz、flags）的长度
+    fn entry_header_len(&self) -> usize {
+        self.flags_offset() + self.has_flags() as usize
+    }
+
+    /// flags 字段相对条目起始位置的偏移，位于 value_sz 之后
+    fn flags_offset(&self) -> usize {
+        self.ksz_offset() + 4 + self.value_sz_len()
+    }
+
+    /// ksz 字段相对条目起始位置的偏移
+    fn ksz_offset(&self) -> usize {
+        self.checksum.len() + self.tstamp_len()
+    }
+
+    /// 从条目头部中解析写入时间（毫秒）
+    fn decode_tstamp(&self, header: &[u8]) -> Result<u64> {
+        let crc_len = self.checksum.len();
+        let tstamp = &header[crc_len..crc_len + self.tstamp_len()];
+        Ok(match self.is_wide() {
+            true => u64::from_be_bytes(tstamp.try_into()?),
+            false => u32::from_be_bytes(tstamp.try_into()?) as u64 * 1000,
+        })
+    }
+
+    /// 从条目头部中解析 ksz 和 value_sz
+    fn decode_sizes(&self, header: &[u8]) -> Result<(u32, i64)> {
+        let ksz_offset = self.ksz_offset();
+        let ksz = u32::from_be_bytes(header[ksz_offset..ksz_offset + 4].try_into()?);
+        let value_sz = &header[ksz_offset + 4..ksz_offset + 4 + self.value_sz_len()];
+        let value_sz = match self.is_wide() {
+            true => i64::from_be_bytes(value_sz.try_into()?),
+            false => i32::from_be_bytes(value_sz.try_into()?) as i64,
+        };
+        Ok((ksz, value_sz))
+    }
+
+    /// 从条目头部中解析标志位，没有标志位的格式返回 0
+    fn decode_flags(&self, header: &[u8]) -> u8 {
+        match self.has_flags() {
+            true => header[self.flags_offset()],
+            false => 0,
+        }
+    }
+}
+
+/// 条目标志位：值经过 LZ4 块压缩，前 4 个字节为原始长度
+const FLAG_LZ4: u8 = 0b0000_0001;
+/// 条目标志位：键和值分别经过认证加密，格式见 [`Keyring`]
+const FLAG_ENCRYPTED: u8 = 0b0000_0010;
+/// 条目标志位：值的开头是 8 字节的过期时间（Unix 毫秒），不参与压缩和加密
+const FLAG_EXPIRES: u8 = 0b0000_0100;
+/// 过期时间的长度
+const EXPIRY_LEN: usize = 8;
+
+/// 解密加密条目的键，aad 见 [`LogEntry::header_aad`]
+fn open_key(keyring: Option<&Keyring>, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    match keyring {
+        Some(keyring) => keyring.open(sealed, aad),
+        None => errdata!("entry is encrypted but no encryption keyfile is configured"),
+    }
+}
+
+/// 值压缩设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ValueCompression {
+    algorithm: Compression,
+    /// 值的长度达到该字节数才压缩
+    min_size: usize,
+}
+
+impl ValueCompression {
+    fn from_config() -> Self {
+        let (algorithm, min_size) = get_compression();
+        Self { algorithm, min_size }
+    }
 }
 
 /// 实现一个日志文件条目结构体
-/// - crc 完整性验证字段 256位的hash编码 => Vec<u8> 长度 32
-/// - tstamp 时间戳 32位的时间戳 => Vec<u8> 长度 4
+/// - crc 完整性验证字段，长度由文件格式的校验算法决定（CRC32C 4 字节，旧格式 SHA3 8 字节）
+/// - tstamp 写入时间（毫秒），新格式存储 64 位毫秒，旧格式存储 32 位秒
 /// - ksz key的长度 根据键值定
-/// - value_sz value的长度 根据value值定
+/// - value_sz value的长度 根据value值定，-1 表示墓碑；新格式的字段宽 64 位，旧格式 32 位。
+///   KeyDir 用 u32 记录长度，写入时拒绝超过 `u32::MAX` 字节的值，64 位字段只是为以后放宽上限预留
+/// - flags 标志位，版本 5 起才有，记录值的编码方式（如 [`FLAG_LZ4`]）
 /// - key 键 Vec<u8>
-/// - value 值 Vec<u8>
+/// - value 值 Vec<u8>，压缩时为压缩后的字节，value_sz 为压缩后的长度
+/// - expires_at 过期时间，存放在 value 之前（见 [`FLAG_EXPIRES`]），计入 value_sz
 /// 拼接方式：
 /// ```text
-/// ------|------|------|---------|------|------|
-///  crc  |tstamp|ksz   |value_sz |key   |value |
-/// ------|------|------|---------|------|------|
+/// ------|------|------|---------|------|------|------|
+///  crc  |tstamp|ksz   |value_sz |flags |key   |value |
+/// ------|------|------|---------|------|------|------|
 /// ```
 #[derive(Debug)]
 pub(super) struct LogEntry {
+    format: FileFormat,
     crc: Vec<u8>,
-    tstamp: Vec<u8>,
+    tstamp: u64,
     ksz: u32,
-    value_sz: i32,
+    value_sz: i64,
+    flags: u8,
     key: Vec<u8>,
     value: Vec<u8>,
+    expires_at: Option<u64>,
 }
 
 #[allow(dead_code)]
 impl LogEntry {
-    /// 初始化日志条目
+    /// 初始化日志条目，按所属文件的格式编码
     /// ```ignore
-    /// let tstamp = mini_db::utils::get_timestamp_to_vec();
+    /// let tstamp = mini_db::utils::get_timestamp_millis();
     /// let key = "key".as_bytes().to_vec();
     /// let value = "value".as_bytes().to_vec();
-    /// let log = mini_db::storage::LogEntry::new(tstamp, key, value);
+    /// let log = mini_db::storage::LogEntry::new(FileFormat::CURRENT, tstamp, key, value);
     /// ```
-    pub fn new(tstamp: Vec<u8>, key: Vec<u8>, value: Vec<u8>) -> Self {
+    pub fn new(format: FileFormat, tstamp: u64, key: Vec<u8>, value: Vec<u8>) -> Self {
         let ksz = key.len() as u32;
         let value_sz = match value.len() {
             0 => -1,
-            _ => value.len() as i32,
+            _ => value.len() as i64,
         };
         Self {
+            format,
             crc: vec![],
             tstamp,
             ksz,
             value_sz,
+            flags: 0,
             key,
             value,
+            expires_at: None,
+        }
+    }
+
+    /// 设置过期时间（Unix 毫秒），由 encode 写入条目
+    fn with_expiry(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// 按压缩与加密设置编码条目并构建 crc，返回计入文件统计的值长度：
+    /// 文件中值的长度（压缩、加密后，含过期时间）加上键的加密开销
+    fn encode(&mut self, compression: ValueCompression, keyring: Option<&Keyring>) -> Result<u32> {
+        let key_len = self.key.len();
+        self.compress(compression);
+        if self.expires_at.is_some() {
+            if !self.format.has_flags() || self.value_sz <= 0 {
+                return errdata!("cannot set an expiry on this entry");
+            }
+            self.flags |= FLAG_EXPIRES;
+        }
+        if let Some(keyring) = keyring {
+            self.encrypt(keyring)?;
+        }
+        if self.expires_at.is_some() {
+            self.value_sz = (self.value.len() + EXPIRY_LEN) as i64;
+        }
+        self.build_crc();
+        Ok((self.value.len() + self.expiry_bytes().len() + self.key.len() - key_len) as u32)
+    }
+
+    /// 加密键和值，需在压缩和设置过期标志之后、build_crc 之前调用
+    /// 密文长度是确定的，先写好头部再把它作为两段密文的附加认证数据，标志位、过期时间和写入时间都不能被篡改；
+    /// 值另外以明文键作为附加认证数据，不能被挪到其他键下；墓碑只加密键
+    fn encrypt(&mut self, keyring: &Keyring) -> Result<()> {
+        if !self.format.has_flags() {
+            return errdata!("cannot encrypt entries in a version {} data file", self.format.version);
+        }
+        let key = std::mem::take(&mut self.key);
+        let value = std::mem::take(&mut self.value);
+        self.ksz = (key.len() + SEAL_OVERHEAD) as u32;
+        if self.value_sz > 0 {
+            self.value_sz = (value.len() + SEAL_OVERHEAD + self.expiry_bytes().len()) as i64;
+        }
+        self.flags |= FLAG_ENCRYPTED;
+        let aad = self.header_aad();
+        if self.value_sz > 0 {
+            self.value = keyring.seal(&value, &[aad.as_slice(), &key].concat())?;
+        }
+        self.key = keyring.seal(&key, &aad)?;
+        Ok(())
+    }
+
+    /// 解密键和值，需在校验 crc 之后调用；未加密的条目保持不变
+    fn decrypt(&mut self, keyring: Option<&Keyring>) -> Result<()> {
+        if self.flags & FLAG_ENCRYPTED == 0 {
+            return Ok(());
+        }
+        let aad = self.header_aad();
+        self.key = open_key(keyring, &self.key, &aad)?;
+        if self.value_sz > 0 {
+            // open_key 已经确认密钥环存在
+            self.value = keyring.unwrap().open(&self.value, &[aad.as_slice(), &self.key].concat())?;
+        }
+        self.flags &= !FLAG_ENCRYPTED;
+        Ok(())
+    }
+
+    /// 加密条目的附加认证数据：crc 之后的头部字段（tstamp、ksz、value_sz、flags）与过期时间
+    fn header_aad(&self) -> Vec<u8> {
+        [
+            self.tstamp_bytes(),
+            self.ksz.to_be_bytes().to_vec(),
+            self.value_sz_bytes(),
+            self.flags_bytes(),
+            self.expiry_bytes(),
+        ]
+            .concat()
+    }
+
+    /// 按压缩设置压缩值，需在 build_crc 之前调用
+    /// 墓碑、过短的值、不支持标志位的旧格式文件以及压缩后没有变小的值保持原样
+    fn compress(&mut self, compression: ValueCompression) {
+        if self.value_sz <= 0 || !self.format.has_flags() || self.value.len() < compression.min_size {
+            return;
+        }
+        match compression.algorithm {
+            Compression::None => {}
+            Compression::Lz4 => {
+                let compressed = lz4_flex::compress_prepend_size(&self.value);
+                if compressed.len() < self.value.len() {
+                    self.value_sz = compressed.len() as i64;
+                    self.value = compressed;
+                    self.flags |= FLAG_LZ4;
+                }
+            }
+        }
+    }
+
+    /// 取出解压后的值
+    fn take_value(&mut self) -> Result<Vec<u8>> {
+        let value = std::mem::take(&mut self.value);
+        if self.flags & FLAG_LZ4 == 0 {
+            return Ok(value);
+        }
+        lz4_flex::decompress_size_prepended(&value).or_else(|e| errdata!("corrupt compressed value: {e}"))
+    }
+
+    /// 初始化批量写入帧：`payload` 存放在 key 字段中，value_sz 固定为 `BATCH_VALUE_SZ`
+    fn batch(format: FileFormat, tstamp: u64, payload: Vec<u8>) -> Self {
+        Self {
+            format,
+            crc: vec![],
+            tstamp,
+            ksz: payload.len() as u32,
+            value_sz: BATCH_VALUE_SZ,
+            flags: 0,
+            key: payload,
+            value: vec![],
+            expires_at: None,
         }
     }
 
     /// 构建完整性校验字段
     /// ```ignore
-    /// let tstamp = mini_db::utils::get_timestamp_to_vec();
+    /// let tstamp = mini_db::utils::get_timestamp_millis();
     /// let key = "key".as_bytes().to_vec();
     /// let value = "value".as_bytes().to_vec();
-    /// let log = mini_db::storage::LogEntry::new(tstamp, key, value);
-    /// //log.build_src();
+    /// let log = mini_db::storage::LogEntry::new(FileFormat::CURRENT, tstamp, key, value);
+    /// //log.build_crc();
     /// ```
     fn build_crc(&mut self) {
-        let mut hasher = Sha3_256::new();
-        let input = [
-            self.tstamp.clone(),
+        self.crc = self.format.checksum.compute(&self.check_parts());
+    }
+
+    /// 根据条目内容重新计算 crc，并与存储的 crc 比较
+    fn verify_crc(&self) -> bool {
+        self.format.checksum.compute(&self.check_parts()) == self.crc
+    }
+
+    /// 按文件格式编码 tstamp 字段
+    fn tstamp_bytes(&self) -> Vec<u8> {
+        match self.format.is_wide() {
+            true => self.tstamp.to_be_bytes().to_vec(),
+            false => ((self.tstamp / 1000) as u32).to_be_bytes().to_vec(),
+        }
+    }
+
+    /// 按文件格式编码 value_sz 字段
+    fn value_sz_bytes(&self) -> Vec<u8> {
+        match self.format.is_wide() {
+            true => self.value_sz.to_be_bytes().to_vec(),
+            false => (self.value_sz as i32).to_be_bytes().to_vec(),
+        }
+    }
+
+    /// 按文件格式编码 flags 字段，旧格式没有该字段
+    fn flags_bytes(&self) -> Vec<u8> {
+        match self.format.has_flags() {
+            true => vec![self.flags],
+            false => vec![],
+        }
+    }
+
+    /// 编码过期时间，只有设置了 [`FLAG_EXPIRES`] 的条目才有
+    fn expiry_bytes(&self) -> Vec<u8> {
+        match self.expires_at {
+            Some(expires_at) if self.flags & FLAG_EXPIRES != 0 => expires_at.to_be_bytes().to_vec(),
+            _ => vec![],
+        }
+    }
+
+    /// 参与校验的字段：除 crc 以外的全部内容
+    fn check_parts(&self) -> Vec<u8> {
+        [
+            self.tstamp_bytes(),
             self.ksz.to_be_bytes().to_vec(),
-            self.value_sz.to_be_bytes().to_vec(),
+            self.value_sz_bytes(),
+            self.flags_bytes(),
             self.key.clone(),
+            self.expiry_bytes(),
             self.value.clone(),
         ]
             .concat()
-            .to_vec();
-        hasher.update(input);
-        self.crc = hasher.finalize()[15..23].to_vec();
     }
 
     /// 获取条目的存储格式
     /// 数据拼接方式：
-    /// ------|------|------|---------|------|------|
-    ///  crc  |tstamp|ksz   |value_sz |key   |value |
-    /// ------|------|------|---------|------|------|
-    /// ------|------|------|---------|------|------|
-    ///  8    | 4    | 4    | 4       | ...  |...   |
-    /// ------|------|------|---------|------|------|
+    /// ------|------|------|---------|------|------|------|
+    ///  crc  |tstamp|ksz   |value_sz |flags |key   |value |
+    /// ------|------|------|---------|------|------|------|
+    /// ------|------|------|---------|------|------|------|
+    ///  4(8) | 8(4) | 4    | 8(4)    | 1(0) | ...  |...   |
+    /// ------|------|------|---------|------|------|------|
     fn get_entry_str(&self) -> String {
-        let parts = [
-            self.crc.clone(),
-            self.tstamp.clone(),
-            self.ksz.to_be_bytes().to_vec(),
-            self.value_sz.to_be_bytes().to_vec(),
-            self.key.clone(),
-            self.value.clone(),
-        ]
-            .concat()
-            .to_vec();
-        hex::encode(parts)
+        hex::encode(self.get_entry())
     }
 
     fn get_entry(&self) -> Vec<u8> {
-        [
-            self.crc.clone(),
-            self.tstamp.clone(),
-            self.ksz.to_be_bytes().to_vec(),
-            self.value_sz.to_be_bytes().to_vec(),
-            self.key.clone(),
-            self.value.clone(),
-        ]
-            .concat()
+        [self.crc.clone(), self.check_parts()].concat()
+    }
+
+    /// 根据数组恢复成结构体
+    fn from_bytes(bytes: Vec<u8>, format: FileFormat) -> Result<Self> {
+        let crc_len = format.checksum.len();
+        let header_len = format.entry_header_len();
+        let (ksz, value_sz) = format.decode_sizes(&bytes)?;
+        let tstamp = format.decode_tstamp(&bytes)?;
+        let flags = format.decode_flags(&bytes);
+        let key_end = header_len + ksz as usize;
+        let key = &bytes[header_len..key_end];
+        let mut value = match value_sz {
+            x if x > 0 => &bytes[key_end..key_end + value_sz as usize],
+            _ => &[0u8; 0],
+        };
+        let mut expires_at = None;
+        if flags & FLAG_EXPIRES != 0 {
+            let Some((expiry, rest)) = value.split_first_chunk::<EXPIRY_LEN>() else {
+                return errdata!("entry is marked as expiring but has no expiry");
+            };
+            expires_at = Some(u64::from_be_bytes(*expiry));
+            value = rest;
+        }
+        Ok(Self {
+            format,
+            crc: bytes[0..crc_len].to_vec(),
+            tstamp,
+            ksz,
+            value_sz,
+            flags,
+            key: key.to_vec(),
+            value: value.to_vec(),
+            expires_at,
+        })
+    }
+    /// 初始化值的位置
+    fn init_value_pos(&self, belong_log: Log) -> u64 {
+        let current_offset = belong_log.current_offset;
+        current_offset + (self.crc.len() + self.format.tstamp_len() + 2) as u64
+    }
+    /// 初始化校验字段的位置
+    fn init_crc_pos(&self, belong_log: Log) -> u64 {
+        belong_log.current_offset
+    }
+}
+
+/// 批量写入帧的 value_sz 标记
+const BATCH_VALUE_SZ: i64 = -2;
+/// 批量写入帧的提交标记，后跟 4 字节的操作数量
+const BATCH_COMMIT: &[u8; 4] = b"CMIT";
+
+/// 批量写入帧：
+/// ```text
+/// ------|------|-------------|---------|------------------------------------|
+///  crc  |tstamp|ksz=len(帧体) |value_sz |entry … entry | "CMIT" | count(u32) |
+///       |      |             |  = -2   |                                    |
+/// ------|------|-------------|---------|------------------------------------|
+/// ```
+/// 帧体中的每个 entry 都是完整的普通条目（删除为墓碑），外层 crc 覆盖整个帧体。
+/// 解析帧并返回其中每个条目及其在文件中的位置；crc 或提交标记校验失败时返回错误，整批丢弃。
+fn decode_batch(format: FileFormat, frame_pos: u64, bytes: Vec<u8>) -> Result<Vec<(u64, LogEntry)>> {
+    let header_len = format.entry_header_len();
+    let frame = LogEntry::from_bytes(bytes, format)?;
+    if !frame.verify_crc() {
+        return errdata!("batch frame at {frame_pos}: crc mismatch");
+    }
+    let payload = frame.key.as_slice();
+    let Some(body_len) = payload.len().checked_sub(BATCH_COMMIT.len() + 4) else {
+        return errdata!("batch frame at {frame_pos}: missing commit marker");
+    };
+    let (body, trailer) = payload.split_at(body_len);
+    if &trailer[..BATCH_COMMIT.len()] != BATCH_COMMIT {
+        return errdata!("batch frame at {frame_pos}: missing commit marker");
+    }
+    let count = u32::from_be_bytes(trailer[BATCH_COMMIT.len()..].try_into()?);
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset < body.len() {
+        if body.len() - offset < header_len {
+            return errdata!("batch frame at {frame_pos}: truncated entry");
+        }
+        let (ksz, value_sz) = format.decode_sizes(&body[offset..])?;
+        let entry_len = header_len + ksz as usize + value_sz.max(0) as usize;
+        if body.len() - offset < entry_len {
+            return errdata!("batch frame at {frame_pos}: truncated entry");
+        }
+        let entry = LogEntry::from_bytes(body[offset..offset + entry_len].to_vec(), format)?;
+        entries.push((frame_pos + (header_len + offset) as u64, entry));
+        offset += entry_len;
+    }
+    if entries.len() != count as usize {
+        return errdata!("batch frame at {frame_pos}: expected {count} entries, found {}", entries.len());
+    }
+    Ok(entries)
+}
+
+/// 数据目录中的锁文件，内容为持有写锁的进程号
+const LOCK_FILE: &str = "LOCK";
+
+/// 数据目录的写锁：同一时间只有一个进程可以读写数据目录
+/// 锁随文件句柄一起释放，进程崩溃后留下的锁文件不会妨碍下次打开
+#[derive(Debug)]
+pub struct DirLock {
+    _file: fs::File,
+}
+
+impl DirLock {
+    pub(crate) fn acquire(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_FILE))?;
+        if !FileExt::try_lock_exclusive(&file)? {
+            // 有的平台上锁住的文件无法读取，读不到进程号时不影响报错
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            let pid = match pid.trim() {
+                "" => "unknown",
+                pid => pid,
+            };
+            return errdata!("database {} is in use by pid {pid}", dir.display());
+        }
+        file.set_len(0)?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.sync_all()?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// 数据目录路径转换为以 `/` 结尾的文件名前缀
+fn db_base(path: &Path) -> String {
+    let mut base = path.to_string_lossy().to_string();
+    if !base.ends_with('/') {
+        base.push('/');
+    }
+    base
+}
+
+/// 按文件创建顺序列出数据目录中的日志文件
+/// 创建时间越晚文件名的数值越大，活跃文件的文件名永远是最大的
+fn log_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file() && p.file_name() != Some(LOCK_FILE.as_ref()));
+    paths.sort_by_key(|p| {
+        let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        name.strip_suffix("_active").unwrap_or(name).parse::<u64>().unwrap_or(u64::MAX)
+    });
+    Ok(paths)
+}
+
+/// 离线工具读取到的单条日志条目
+#[derive(Debug, Clone)]
+pub struct EntryRecord {
+    /// 所属文件
+    pub file_id: String,
+    /// 条目在文件中的起始位置
+    pub offset: u64,
+    /// 写入时间戳（毫秒）
+    pub tstamp: u64,
+    pub key: Vec<u8>,
+    /// 值，墓碑条目为 None
+    pub value: Option<Vec<u8>>,
+    /// 过期时间（Unix 毫秒），None 表示永不过期
+    pub expires_at: Option<u64>,
+    /// crc 校验是否通过
+    pub crc_ok: bool,
+}
+
+impl EntryRecord {
+    /// 在 now 时刻是否已经过期，过期的条目与墓碑相同
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// fsck 检查结果
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub files: usize,
+    pub entries: usize,
+    pub live_keys: usize,
+    /// 发现的问题描述，为空表示数据目录完好
+    pub problems: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// repair 修复结果
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// 成功抢救的条目数
+    pub salvaged: usize,
+    /// 因 crc 校验失败丢弃的条目数
+    pub dropped: usize,
+    /// 末尾存在无法解析数据的文件数
+    pub truncated_files: usize,
+    /// 写入新目录的存活键数量
+    pub live_keys: usize,
+}
+
+/// 顺序读取单个日志文件的条目，批量写入帧展开为其中的每个条目
+/// 遇到截断或无法解析的条目时返回一个 Err，然后结束迭代
+struct LogScanner {
+    file_id: String,
+    format: FileFormat,
+    reader: BufReader<fs::File>,
+    pos: u64,
+    len: u64,
+    /// 已解析、尚未返回的条目
+    pending: VecDeque<EntryRecord>,
+    /// 解密加密条目使用的密钥环
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl LogScanner {
+    fn open(file_path: &Path, keyring: Option<Arc<Keyring>>) -> Result<Self> {
+        let file = fs::File::open(file_path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let format = FileFormat::detect(&mut reader, len)?;
+        let pos = reader.seek(SeekFrom::Start(format.data_start()))?;
+        Ok(Self {
+            file_id: file_path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string(),
+            format,
+            reader,
+            pos,
+            len,
+            pending: VecDeque::new(),
+            keyring,
+        })
     }
-    /// 根据数组恢复成结构体
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        let ksz = u32::from_be_bytes(bytes[12..16].try_into().unwrap());
-        let value_sz = i32::from_be_bytes(bytes[16..20].try_into().unwrap());
-        let key = &bytes[20..(20 + ksz) as usize];
-        let value = match value_sz {
-            x if x > 0 => &bytes[(20 + ksz) as usize..(20 + ksz + value_sz as u32) as usize],
-            _ => &[0u8; 0],
+
+    fn read_next(&mut self) -> Result<()> {
+        let offset = self.pos;
+        let header_len = self.format.entry_header_len();
+        if self.len - offset < header_len as u64 {
+            return errdata!("{}@{}: truncated entry header", self.file_id, offset);
+        }
+        let mut bytes = vec![0u8; header_len];
+        self.reader.read_exact(&mut bytes)?;
+        let (ksz, value_sz) = self.format.decode_sizes(&bytes)?;
+        let body_len = ksz as u64 + value_sz.max(0) as u64;
+        if self.len - offset - (header_len as u64) < body_len {
+            return errdata!("{}@{}: truncated entry body", self.file_id, offset);
+        }
+        bytes.resize(header_len + body_len as usize, 0);
+        self.reader.read_exact(&mut bytes[header_len..])?;
+        self.pos += bytes.len() as u64;
+
+        let entries = match self.format.supports_batch() && value_sz == BATCH_VALUE_SZ {
+            true => match decode_batch(self.format, offset, bytes) {
+                Ok(entries) => entries,
+                Err(e) => return errdata!("{}@{}: {e}", self.file_id, offset),
+            },
+            false => vec![(offset, LogEntry::from_bytes(bytes, self.format)?)],
         };
-        Self {
-            crc: bytes[0..8].to_vec(),
-            tstamp: bytes[8..12].to_vec(),
-            ksz,
-            value_sz,
-            key: key.to_vec(),
-            value: value.to_vec(),
+        for (offset, mut entry) in entries {
+            let crc_ok = entry.verify_crc();
+            // 校验失败的条目内容不可信，不做解密和解压
+            if crc_ok {
+                if let Err(e) = entry.decrypt(self.keyring.as_deref()) {
+                    return errdata!("{}@{}: {e}", self.file_id, offset);
+                }
+            }
+            let value = match entry.value_sz >= 0 {
+                true if crc_ok => match entry.take_value() {
+                    Ok(value) => Some(value),
+                    Err(e) => return errdata!("{}@{}: {e}", self.file_id, offset),
+                },
+                true => Some(entry.value),
+                false => None,
+            };
+            self.pending.push_back(EntryRecord {
+                file_id: self.file_id.clone(),
+                offset,
+                tstamp: entry.tstamp,
+                crc_ok,
+                value,
+                expires_at: entry.expires_at,
+                key: entry.key,
+            });
         }
+        Ok(())
     }
-    /// 初始化值的位置
-    fn init_value_pos(&self, belong_log: Log) -> u32 {
-        let current_offset = belong_log.current_offset;
-        current_offset + (self.crc.len() + self.tstamp.len() + 2) as u32
-    }
-    /// 初始化校验字段的位置
-    fn init_crc_pos(&self, belong_log: Log) -> u32 {
-        belong_log.current_offset
+}
+
+impl Iterator for LogScanner {
+    type Item = Result<EntryRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.pos >= self.len {
+                return None;
+            }
+            if let Err(e) = self.read_next() {
+                self.pos = self.len;
+                return Some(Err(e));
+            }
+        }
     }
 }
 
 impl Log {
     /// 创建一个新的日志存储文件
     /// 或者打开一个活跃存储文件
+    #[cfg(test)]
     fn new(file_id: String) -> Result<Self> {
         Self::new_with_base(file_id, get_db_base())
     }
@@ -638,23 +1836,26 @@ impl Log {
             .create(true)
             .truncate(false)
             .open(&path)?;
-        file.try_lock_exclusive()?;
+        if !FileExt::try_lock_shared(&file)? {
+            return errdata!("{file_id} is locked by another process");
+        }
+        // 新文件写入文件头，已有文件根据文件头识别格式
+        let file_len = file.metadata()?.len();
+        let format = if file_len == 0 {
+            (&file).write_all(&FileFormat::CURRENT.header())?;
+            FileFormat::CURRENT
+        } else {
+            FileFormat::detect(&mut &file, file_len)?
+        };
         Ok(Self {
             file_path: path,
             file: Arc::new(Mutex::new(file)),
             file_id,
             current_offset: 0,
+            format,
         })
     }
 
-    /// 存储数据完整性验证
-    fn check_crc(&self, current_crc: Vec<u8>, check_parts: Vec<u8>) -> bool {
-        let mut hasher = Sha3_256::new();
-        hasher.update(check_parts);
-        let crc = hasher.finalize()[15..23].to_vec();
-        current_crc.eq(&crc)
-    }
-
     /// 将条目写入文件
     fn write_entry(&mut self, log_entry: LogEntry) -> Result<u64> {
         // 1、计算存储条目的总大小
@@ -679,53 +1880,50 @@ impl Log {
     }
 
     /// value位置、value大小、crc位置读取值
-    fn read_entry(&mut self, crc_pos: u32) -> Result<Option<LogEntry>> {
-        let mut len_buf = [0u8; 4];
-        // 1、计算ksz
-        let ksz_pos = (crc_pos + 12) as u64;
-        let mut file = self.file.lock()?;
-
-        if let Err(e) = file.seek(SeekFrom::Start(ksz_pos)) {
-            eprintln!("定位文件指针时出错: {}", e);
-            return Err(e.into());
-        }
-
-        if let Err(e) = file.read_exact(&mut len_buf) {
-            eprintln!("读取 ksz 时出错: {}", e);
-            return Err(e.into());
-        }
-
-        let ksz = u32::from_be_bytes(len_buf);
-        // 2、计算value_sz
-        let value_sz_pos = (crc_pos + 16) as u64;
-        file.seek(SeekFrom::Start(value_sz_pos))?;
-        file.read_exact(&mut len_buf)?;
-        let value_sz = i32::from_be_bytes(len_buf);
-        if value_sz < 0 {
-            return Ok(None);
-        }
+    #[cfg(test)]
+    fn read_entry(&self, crc_pos: u64) -> Result<Option<LogEntry>> {
+        read_entry_at(&*self.file.lock()?, self.format, crc_pos)
+    }
+}
 
-        // 3、计算条目总长度，并构建结构体
-        let entry_len: usize = (20u32 + ksz + value_sz as u32) as usize;
+/// 定位读取 crc_pos 处的条目，墓碑或 crc 校验失败时返回 None
+/// 不移动文件指针，同一个句柄可以被多个线程同时读取
+fn read_entry_at(file: &fs::File, format: FileFormat, crc_pos: u64) -> Result<Option<LogEntry>> {
+    let header_len = format.entry_header_len();
+    // 1、读取条目头部，解析ksz、value_sz
+    let mut entry = vec![0u8; header_len];
+    read_exact_at(file, &mut entry, crc_pos)?;
+    let (ksz, value_sz) = format.decode_sizes(&entry)?;
+    if value_sz < 0 {
+        return Ok(None);
+    }
+    // 2、读取key、value，并构建结构体
+    entry.resize(header_len + ksz as usize + value_sz as usize, 0);
+    read_exact_at(file, &mut entry[header_len..], crc_pos + header_len as u64)?;
+    let log_entry = LogEntry::from_bytes(entry, format)?;
+    // 3、检验完整性
+    Ok(log_entry.verify_crc().then_some(log_entry))
+}
 
-        let mut entry = vec![0u8; entry_len];
-        if let Err(e) = file.seek(SeekFrom::Start(crc_pos as u64)) {
-            eprintln!("定位文件指针时出错: {}", e);
-            return Err(e.into());
-        }
+#[cfg(unix)]
+pub(super) fn read_exact_at(file: &fs::File, buf: &mut [u8], pos: u64) -> Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, pos)?;
+    Ok(())
+}
 
-        if let Err(e) = file.read_exact(&mut entry) {
-            eprintln!("读取条目时出错: {}", e);
-            return Err(e.into());
-        }
-        let log_entry = LogEntry::from_bytes(entry);
-        // 4、检验完整性
-        if self.check_crc(log_entry.crc.clone(), log_entry.get_entry()[8..].to_vec()) {
-            return Ok(Some(log_entry));
+#[cfg(windows)]
+pub(super) fn read_exact_at(file: &fs::File, mut buf: &mut [u8], mut pos: u64) -> Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, pos)? {
+            0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            n => {
+                buf = &mut buf[n..];
+                pos += n as u64;
+            }
         }
-        file.rewind()?;
-        Ok(None)
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -739,12 +1937,14 @@ mod tests {
         override_config_for_test(config);
     }
 
+    crate::storage::conformance::test_engine!(BitCask::init_db_at, persistent);
+
     #[test]
     fn test_log_entry() {
-        let tstamp = crate::utils::get_timestamp_to_vec();
+        let tstamp = crate::utils::get_timestamp_millis();
         let key = "key".as_bytes().to_vec();
         let value = "value".as_bytes().to_vec();
-        let mut log = LogEntry::new(tstamp, key, value);
+        let mut log = LogEntry::new(FileFormat::CURRENT, tstamp, key, value);
         log.build_crc();
         assert_eq!(log.ksz, 3);
         assert_eq!(log.value_sz, 5);
@@ -754,17 +1954,29 @@ mod tests {
 
     #[test]
     fn test_get_entry() {
-        let tstamp = crate::utils::get_timestamp_to_vec();
+        let tstamp = crate::utils::get_timestamp_millis();
         let key = "key".as_bytes().to_vec();
         let value = "value".as_bytes().to_vec();
         let ksz = key.len() as u32;
         let value_sz = value.len() as u32;
         assert_eq!(ksz.to_be_bytes().to_vec(), [0, 0, 0, 3]);
         assert_eq!(value_sz.to_be_bytes().to_vec(), [0, 0, 0, 5]);
-        let mut log = LogEntry::new(tstamp, key, value);
+        let mut log = LogEntry::new(FileFormat::CURRENT, tstamp, key.clone(), value.clone());
+        log.build_crc();
+        let entry = log.get_entry();
+        assert_eq!(entry.len(), 4 + 8 + 4 + 8 + 1 + 3 + 5);
+        let decoded = LogEntry::from_bytes(entry, FileFormat::CURRENT).unwrap();
+        assert_eq!(decoded.tstamp, tstamp);
+        assert!(decoded.verify_crc());
+
+        // 旧格式仍是 32 位秒级时间戳和 32 位 value_sz
+        let mut log = LogEntry::new(FileFormat::V2, tstamp, key, value);
         log.build_crc();
         let entry = log.get_entry();
-        assert_eq!(entry.len(), 8 + 4 + 4 + 4 + 3 + 5);
+        assert_eq!(entry.len(), 4 + 4 + 4 + 4 + 3 + 5);
+        let decoded = LogEntry::from_bytes(entry, FileFormat::V2).unwrap();
+        assert_eq!(decoded.tstamp, tstamp / 1000 * 1000);
+        assert!(decoded.verify_crc());
     }
 
     #[test]
@@ -773,13 +1985,14 @@ mod tests {
         setup(&dir);
         let file_id = create_tsid().number().to_string() + "_active";
         let mut log_db = Log::new(file_id).unwrap();
-        let tstamp = crate::utils::get_timestamp_to_vec();
+        let tstamp = crate::utils::get_timestamp_millis();
         let key = "test_1".as_bytes().to_vec();
         let value = "test-3333".as_bytes().to_vec();
-        let mut log = LogEntry::new(tstamp, key, value);
+        let mut log = LogEntry::new(log_db.format, tstamp, key, value);
         log.build_crc();
-        let _ = log_db.write_entry(log);
-        let log_entry = log_db.read_entry(0u32).unwrap().unwrap();
+        let pos = log_db.write_entry(log).unwrap();
+        assert_eq!(pos, FILE_HEADER_LEN);
+        let log_entry = log_db.read_entry(pos).unwrap().unwrap();
         assert_eq!("test-3333", String::from_utf8_lossy(&log_entry.value));
     }
 
@@ -908,6 +2121,59 @@ mod tests {
         assert_eq!(status.garbage_size, status.total_size - status.live_size);
     }
 
+    #[test]
+    fn test_status_counters() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let disk_size = || -> u64 {
+            log_files(dir.path()).unwrap().iter().map(|p| p.metadata().unwrap().len()).sum()
+        };
+        let before = {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set(b"a", b"1").unwrap();
+            db.set(b"b", b"22").unwrap();
+            db.refresh_active();
+            db.set(b"a", b"333").unwrap();
+            db.set(b"c", b"4").unwrap();
+            db.delete(b"c").unwrap();
+
+            let status = db.status().unwrap();
+            let entry = |key: &[u8], value: &[u8]| {
+                (FileFormat::CURRENT.entry_header_len() + key.len() + value.len()) as u64
+            };
+            assert_eq!(status.total_count, 2);
+            assert_eq!(status.logical_size, 1 + 3 + 1 + 2);
+            assert_eq!(status.live_size, entry(b"a", b"333") + entry(b"b", b"22"));
+            assert_eq!(status.total_size, disk_size());
+            assert_eq!(status.files.len(), 2);
+            // 旧文件中 a 的第一个版本被覆盖
+            let sealed = &status.files[0];
+            assert_eq!(sealed.live_count, 2);
+            assert_eq!(sealed.garbage_size(), FILE_HEADER_LEN + entry(b"a", b"1"));
+            // 活跃文件中只有 c 的写入和墓碑
+            let active = &status.files[1];
+            assert_eq!(active.live_count, 0);
+            assert_eq!(active.garbage_size(), active.total_size);
+            assert!(active.garbage_rate() > sealed.garbage_rate());
+            status
+        };
+
+        // 重新打开后由 build_key_dir 重建的计数保持一致
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        let after = db.status().unwrap();
+        assert_eq!(after.total_count, before.total_count);
+        assert_eq!(after.logical_size, before.logical_size);
+        assert_eq!(after.live_size, before.live_size);
+        assert_eq!(after.total_size, before.total_size);
+
+        // 压缩后旧的活跃文件被删除，存活数据全部迁移到新文件
+        db.compact().unwrap();
+        let status = db.status().unwrap();
+        assert_eq!(status.total_size, disk_size());
+        assert_eq!(status.live_size, before.live_size);
+        assert_eq!(status.files.iter().map(|f| f.live_count).sum::<u64>(), 2);
+    }
+
     #[test]
     fn test_clear() {
         let dir = TempDir::new().unwrap();
@@ -967,6 +2233,57 @@ mod tests {
         assert!(db.get(b"remove").unwrap().is_none());
     }
 
+    #[test]
+    fn test_compact_in_batches() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        let count = COMPACT_BATCH * 2 + 10;
+        for i in 0..count {
+            db.set(format!("key_{i:05}").as_bytes(), format!("v{i}").as_bytes()).unwrap();
+        }
+        db.delete(b"key_00000").unwrap();
+        db.compact().unwrap();
+
+        assert_eq!(db.keydir.len(), count - 1);
+        assert!(db.keydir.values().all(|entry| entry.file_id == db.active));
+        assert!(db.get(b"key_00000").unwrap().is_none());
+        assert_eq!(db.get(b"key_01500").unwrap().unwrap(), b"v1500");
+
+        let status = db.status().unwrap();
+        assert_eq!(status.total_count, (count - 1) as u64);
+        assert!(status.index_memory >= status.total_count * 9);
+    }
+
+    #[test]
+    fn test_scan_after_rotation() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set(b"a", b"1").unwrap();
+            db.set(b"b", b"2").unwrap();
+            db.refresh_active();
+            db.set(b"c", b"3").unwrap();
+            // 轮转后的旧文件通过文件表中更新后的文件名读取
+            assert_eq!(db.get(b"a").unwrap().unwrap(), b"1");
+            let items = db.scan(..).collect::<Result<Vec<_>>>().unwrap();
+            assert_eq!(
+                items,
+                vec![
+                    (b"a".to_vec(), b"1".to_vec()),
+                    (b"b".to_vec(), b"2".to_vec()),
+                    (b"c".to_vec(), b"3".to_vec()),
+                ]
+            );
+        }
+        let db = BitCask::init_db_at(dir.path()).unwrap();
+        let items = db.scan(b"b".to_vec()..).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(items, vec![(b"b".to_vec(), b"2".to_vec()), (b"c".to_vec(), b"3".to_vec())]);
+        let status = db.status().unwrap();
+        assert_eq!(status.logical_size, 6);
+    }
+
     #[test]
     fn test_overwrite_and_reopen() {
         let dir = TempDir::new().unwrap();
@@ -1045,4 +2362,519 @@ mod tests {
         db.set(b"k", b"v2").unwrap();
         assert_eq!(db.get(b"k").unwrap().unwrap(), b"v2");
     }
+
+    #[test]
+    fn test_fsck_and_repair() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let active_path = {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set(b"a", b"1").unwrap();
+            db.set(b"b", b"2").unwrap();
+            db.delete(b"a").unwrap();
+            db.set(b"c", b"3").unwrap();
+            dir.path().join(&db.log.as_ref().unwrap().file_id)
+        };
+        let report = BitCask::fsck(dir.path()).unwrap();
+        assert!(report.is_clean(), "{:?}", report.problems);
+        assert_eq!(report.entries, 4);
+        assert_eq!(report.live_keys, 2);
+
+        // 破坏最后一个条目的值，并在末尾追加半个条目
+        let mut bytes = fs::read(&active_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        bytes.extend_from_slice(&[0u8; 7]);
+        fs::write(&active_path, bytes).unwrap();
+
+        let report = BitCask::fsck(dir.path()).unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.problems.len(), 3);
+
+        let dest = TempDir::new().unwrap();
+        let report = BitCask::repair(dir.path(), dest.path()).unwrap();
+        assert_eq!(report.dropped, 1);
+        assert_eq!(report.truncated_files, 1);
+        assert_eq!(report.live_keys, 1);
+
+        let db = BitCask::init_db_at(dest.path()).unwrap();
+        assert_eq!(db.get(b"b").unwrap().unwrap(), b"2");
+        assert!(db.get(b"a").unwrap().is_none());
+        assert!(db.get(b"c").unwrap().is_none());
+        assert!(BitCask::fsck(dest.path()).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_checkpoint_and_restore() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let backup = TempDir::new().unwrap();
+        let backup_path = backup.path().join("snapshot");
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        db.set(b"sealed", b"old").unwrap();
+        db.set(b"gone", b"1").unwrap();
+        db.refresh_active();
+        db.set(b"active", b"a1").unwrap();
+        db.checkpoint(&backup_path).unwrap();
+        assert_eq!(fs::read_dir(&backup_path).unwrap().count(), 2);
+
+        // 备份之后的写入：覆盖和删除会追加到已封存的旧文件，不能影响备份
+        db.set(b"sealed", b"new").unwrap();
+        db.delete(b"gone").unwrap();
+        db.set(b"active", b"a2").unwrap();
+        db.set(b"later", b"x").unwrap();
+
+        let snapshot = BitCask::init_db_at(&backup_path).unwrap();
+        let expected = vec![
+            (b"active".to_vec(), b"a1".to_vec()),
+            (b"gone".to_vec(), b"1".to_vec()),
+            (b"sealed".to_vec(), b"old".to_vec()),
+        ];
+        assert_eq!(snapshot.scan(..).collect::<Result<Vec<_>>>().unwrap(), expected);
+        drop(snapshot);
+
+        // 目标目录非空时拒绝覆盖
+        assert!(db.checkpoint(&backup_path).is_err());
+        assert!(BitCask::restore(&backup_path, dir.path()).is_err());
+
+        let restored = TempDir::new().unwrap();
+        let report = BitCask::restore(&backup_path, restored.path()).unwrap();
+        assert_eq!((report.files, report.live_keys), (2, 3));
+        let db = BitCask::init_db_at(restored.path()).unwrap();
+        assert_eq!(db.scan(..).collect::<Result<Vec<_>>>().unwrap(), expected);
+
+        // 没有数据文件的目录不是有效的备份
+        let empty = TempDir::new().unwrap();
+        assert!(BitCask::restore(empty.path(), TempDir::new().unwrap().path()).is_err());
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set(b"old", b"value").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"a", b"1").put(b"b", b"2").delete(b"old").put(b"a", b"3");
+            db.write_batch(batch).unwrap();
+            assert_eq!(db.get(b"a").unwrap().unwrap(), b"3");
+            assert_eq!(db.get(b"b").unwrap().unwrap(), b"2");
+            assert!(db.get(b"old").unwrap().is_none());
+            let active = dir.path().join(&db.log.as_ref().unwrap().file_id);
+            assert_eq!(db.status().unwrap().total_size, fs::metadata(active).unwrap().len());
+        }
+        let report = BitCask::fsck(dir.path()).unwrap();
+        assert!(report.is_clean(), "{:?}", report.problems);
+        assert_eq!(report.entries, 5);
+
+        let db = BitCask::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"a").unwrap().unwrap(), b"3");
+        assert_eq!(db.get(b"b").unwrap().unwrap(), b"2");
+        assert!(db.get(b"old").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_torn_batch_is_discarded() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let file_id = {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set(b"a", b"before").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"a", b"after").put(b"b", b"new");
+            db.write_batch(batch).unwrap();
+            db.log.as_ref().unwrap().file_id.clone()
+        };
+        let path = dir.path().join(file_id);
+        let full = fs::read(&path).unwrap();
+
+        // 批量写入帧写到一半时崩溃：整批丢弃，之前的数据保持不变
+        fs::write(&path, &full[..full.len() - 3]).unwrap();
+        {
+            let db = BitCask::init_db_at(dir.path()).unwrap();
+            assert_eq!(db.get(b"a").unwrap().unwrap(), b"before");
+            assert!(db.get(b"b").unwrap().is_none());
+        }
+        assert!(!BitCask::fsck(dir.path()).unwrap().is_clean());
+
+        // 帧内数据损坏同样整批丢弃
+        let mut corrupt = full.clone();
+        let last = corrupt.len() - 12;
+        corrupt[last] ^= 0xff;
+        fs::write(&path, &corrupt).unwrap();
+        let db = BitCask::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"a").unwrap().unwrap(), b"before");
+        assert!(db.get(b"b").unwrap().is_none());
+    }
+
+    /// 手工构造一个指定旧格式的活跃文件，内容为 k1 被删除、k2 = v2
+    fn write_old_format_file(dir: &Path, format: FileFormat) {
+        let file_id = create_tsid().number().to_string() + "_active";
+        let mut bytes = match format.version {
+            1 => vec![],
+            _ => format.header(),
+        };
+        for (key, value) in [(b"k1", b"v1".as_slice()), (b"k2", b"v2"), (b"k1", b"")] {
+            let tstamp = crate::utils::get_timestamp_millis();
+            let mut entry = LogEntry::new(format, tstamp, key.to_vec(), value.to_vec());
+            entry.build_crc();
+            bytes.extend(entry.get_entry());
+        }
+        fs::write(dir.join(&file_id), bytes).unwrap();
+    }
+
+    /// 打开旧格式文件、继续追加、再压缩迁移到新格式
+    fn check_old_format(format: FileFormat) {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        write_old_format_file(dir.path(), format);
+
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            assert_eq!(db.log.as_ref().unwrap().format, format);
+            assert!(db.get(b"k1").unwrap().is_none());
+            assert_eq!(db.get(b"k2").unwrap().unwrap(), b"v2");
+            // 旧格式的活跃文件继续以旧格式追加
+            db.set(b"k3", b"v3").unwrap();
+        }
+        assert!(BitCask::fsck(dir.path()).unwrap().is_clean());
+
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"k3").unwrap().unwrap(), b"v3");
+        // 压缩后数据迁移到新格式
+        db.compact().unwrap();
+        assert_eq!(db.log.as_ref().unwrap().format, FileFormat::CURRENT);
+        assert_eq!(db.get(b"k2").unwrap().unwrap(), b"v2");
+        assert_eq!(db.get(b"k3").unwrap().unwrap(), b"v3");
+    }
+
+    #[test]
+    fn test_read_legacy_format() {
+        // 没有文件头、使用 SHA3 校验的旧格式
+        check_old_format(FileFormat::LEGACY);
+    }
+
+    #[test]
+    fn test_read_v2_format() {
+        // 带文件头、32 位时间戳的格式
+        check_old_format(FileFormat::V2);
+    }
+
+    #[test]
+    fn test_read_v3_format() {
+        // 64 位时间戳、不支持批量写入帧的格式
+        check_old_format(FileFormat::V3);
+    }
+
+    #[test]
+    fn test_read_v4_format() {
+        // 条目没有标志位的格式
+        check_old_format(FileFormat::V4);
+    }
+
+    #[test]
+    fn test_value_compression() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let text = "mini-db stores rows as bincode encoded values. ".repeat(40).into_bytes();
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set_compression(Compression::Lz4, 64);
+            db.set(b"text", &text).unwrap();
+            db.set(b"short", b"short value").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"batched", &text);
+            db.write_batch(batch).unwrap();
+            assert_eq!(db.get(b"text").unwrap().unwrap(), text);
+            assert_eq!(db.get(b"batched").unwrap().unwrap(), text);
+            // 统计按压缩后的长度计算
+            let status = db.status().unwrap();
+            assert!(status.logical_size < 2 * text.len() as u64 / 4);
+            assert_eq!(status.total_size, fs::metadata(dir.path().join(&db.log.as_ref().unwrap().file_id)).unwrap().len());
+        }
+        assert!(BitCask::fsck(dir.path()).unwrap().is_clean());
+        let mut values = vec![];
+        BitCask::dump(dir.path(), |record| {
+            values.push(record?.value);
+            Ok(())
+        })
+        .unwrap();
+        assert!(values.contains(&Some(text.clone())));
+
+        // 重新打开后按标志位解压，与当前的压缩设置无关
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        let scanned: Vec<_> = db.scan(..).collect::<Result<_>>().unwrap();
+        assert_eq!(scanned.len(), 3);
+        assert_eq!(db.get(b"short").unwrap().unwrap(), b"short value");
+        // 不压缩时压缩会把值还原为原始长度
+        db.compact().unwrap();
+        assert_eq!(db.get(b"text").unwrap().unwrap(), text);
+        assert!(db.status().unwrap().logical_size > 2 * text.len() as u64);
+    }
+
+    const KEY_1: &str = "1 0101010101010101010101010101010101010101010101010101010101010101\n";
+    const KEY_2: &str = "2 0202020202020202020202020202020202020202020202020202020202020202\n";
+
+    /// 数据目录中所有文件的内容
+    fn read_all_files(dir: &Path) -> Vec<u8> {
+        log_files(dir).unwrap().iter().flat_map(|path| fs::read(path).unwrap()).collect()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_encryption() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let keyring = || Some(Keyring::parse(KEY_1).unwrap());
+        {
+            let mut db = BitCask::init_db_with_keyring(dir.path(), keyring()).unwrap();
+            db.set(b"secret-key", b"secret-value").unwrap();
+            db.set(b"other", b"other-value").unwrap();
+            db.delete(b"other").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"batched-key", b"batched-value");
+            db.write_batch(batch).unwrap();
+            assert_eq!(db.get(b"secret-key").unwrap().unwrap(), b"secret-value");
+            // 统计中的长度与文件大小一致
+            let status = db.status().unwrap();
+            assert_eq!(status.total_size, fs::metadata(dir.path().join(&db.log.as_ref().unwrap().file_id)).unwrap().len());
+        }
+        // 键和值都不以明文出现在文件中
+        let bytes = read_all_files(dir.path());
+        for plain in [&b"secret-key"[..], b"secret-value", b"other", b"batched-key", b"batched-value"] {
+            assert!(!contains(&bytes, plain));
+        }
+        let report = BitCask::fsck_with_keyring(dir.path(), keyring().map(Arc::new)).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.live_keys, 2);
+
+        // 重新打开后可以读取，没有密钥时无法打开
+        let db = BitCask::init_db_with_keyring(dir.path(), keyring()).unwrap();
+        let scanned: Vec<_> = db.scan(..).collect::<Result<_>>().unwrap();
+        assert_eq!(
+            scanned,
+            vec![(b"batched-key".to_vec(), b"batched-value".to_vec()), (b"secret-key".to_vec(), b"secret-value".to_vec())]
+        );
+        drop(db);
+        assert!(BitCask::init_db_with_keyring(dir.path(), None).is_err());
+        let report = BitCask::fsck_with_keyring(dir.path(), None).unwrap();
+        assert!(report.problems[0].contains("no encryption keyfile"));
+        assert!(BitCask::init_db_with_keyring(dir.path(), Some(Keyring::parse(KEY_2).unwrap())).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_header_is_authenticated() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let keyring = || Some(Keyring::parse(KEY_1).unwrap());
+        let (path, format, crc_pos) = {
+            let mut db = BitCask::init_db_with_keyring(dir.path(), keyring()).unwrap();
+            db.set_with_ttl(b"k", b"v", Duration::from_secs(60)).unwrap();
+            let entry = db.keydir[&b"k"[..]];
+            let file = db.files.get(entry.file_id).unwrap();
+            (dir.path().join(&file.name), file.format, entry.crc_pos)
+        };
+        // 改写过期时间或写入时间并重新计算 crc，解密时认证失败
+        let tamper = |change: &dyn Fn(&mut LogEntry)| {
+            let original = fs::read(&path).unwrap();
+            let file = fs::File::open(&path).unwrap();
+            let mut entry = read_entry_at(&file, format, crc_pos).unwrap().unwrap();
+            change(&mut entry);
+            entry.build_crc();
+            let mut bytes = original.clone();
+            let encoded = entry.get_entry();
+            bytes[crc_pos as usize..crc_pos as usize + encoded.len()].copy_from_slice(&encoded);
+            fs::write(&path, bytes).unwrap();
+            let err = BitCask::init_db_with_keyring(dir.path(), keyring()).err().unwrap().to_string();
+            assert!(err.contains("decryption with key 1 failed"), "{err}");
+            fs::write(&path, original).unwrap();
+        };
+        tamper(&|entry| entry.expires_at = Some(u64::MAX));
+        tamper(&|entry| entry.tstamp += 1);
+        let db = BitCask::init_db_with_keyring(dir.path(), keyring()).unwrap();
+        assert_eq!(db.get(b"k").unwrap().unwrap(), b"v");
+    }
+
+    #[test]
+    fn test_rotate_keys() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        {
+            // 先以明文写入，随后开启加密：旧数据仍可读取，新数据加密
+            let mut db = BitCask::init_db_with_keyring(dir.path(), None).unwrap();
+            db.set(b"plain", b"plain-value").unwrap();
+        }
+        {
+            let mut db = BitCask::init_db_with_keyring(dir.path(), Some(Keyring::parse(KEY_1).unwrap())).unwrap();
+            assert_eq!(db.get(b"plain").unwrap().unwrap(), b"plain-value");
+            db.set(b"k1", b"value-1").unwrap();
+            db.set(b"plain", b"updated-value").unwrap();
+            assert_eq!(db.get(b"plain").unwrap().unwrap(), b"updated-value");
+            assert!(!contains(&read_all_files(dir.path()), b"updated-value"));
+        }
+        {
+            // 追加新密钥后轮换，所有数据以新密钥重写
+            let keyring = Keyring::parse(&[KEY_1, KEY_2].concat()).unwrap();
+            let mut db = BitCask::init_db_with_keyring(dir.path(), Some(keyring)).unwrap();
+            db.rotate_keys().unwrap();
+            assert_eq!(db.get(b"k1").unwrap().unwrap(), b"value-1");
+        }
+        assert!(!contains(&read_all_files(dir.path()), b"plain-value"));
+        // 只保留新密钥即可打开
+        let db = BitCask::init_db_with_keyring(dir.path(), Some(Keyring::parse(KEY_2).unwrap())).unwrap();
+        assert_eq!(db.get(b"plain").unwrap().unwrap(), b"updated-value");
+        assert_eq!(db.get(b"k1").unwrap().unwrap(), b"value-1");
+    }
+
+    #[test]
+    fn test_ttl() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let hour = Duration::from_secs(3600);
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            db.set_compression(Compression::Lz4, 64);
+            db.set_with_ttl(b"short", b"gone soon", Duration::from_millis(100)).unwrap();
+            db.set_with_ttl(b"long", &b"cached ".repeat(20), hour).unwrap();
+            db.set_with_ttl(b"cleared", b"v1", Duration::from_millis(100)).unwrap();
+            // 重新写入清除过期时间
+            db.set(b"cleared", b"v2").unwrap();
+            db.set(b"plain", b"value").unwrap();
+            assert_eq!(db.get(b"short").unwrap().unwrap(), b"gone soon");
+            let status = db.status().unwrap();
+            assert_eq!(status.total_size, fs::metadata(dir.path().join(&db.log.as_ref().unwrap().file_id)).unwrap().len());
+
+            std::thread::sleep(Duration::from_millis(150));
+            // 过期的键读不到，也不出现在扫描结果中
+            assert!(db.get(b"short").unwrap().is_none());
+            assert!(!db.exists(b"short").unwrap());
+            let keys: Vec<_> = db.scan(..).map(|item| item.unwrap().0).collect();
+            assert_eq!(keys, vec![b"cleared".to_vec(), b"long".to_vec(), b"plain".to_vec()]);
+            let keys: Vec<_> = db.scan(..).rev().map(|item| item.unwrap().0).collect();
+            assert_eq!(keys, vec![b"plain".to_vec(), b"long".to_vec(), b"cleared".to_vec()]);
+        }
+        let report = BitCask::fsck(dir.path()).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.live_keys, 3);
+        let mut expiring = vec![];
+        BitCask::dump(dir.path(), |record| {
+            let record = record?;
+            if record.expires_at.is_some() {
+                expiring.push(record.key);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(expiring, vec![b"short".to_vec(), b"long".to_vec(), b"cleared".to_vec()]);
+
+        // 重新打开时过期的条目视为墓碑，压缩时不再写入，未过期的键保留过期时间
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        assert!(!db.keydir.contains_key(&b"short"[..]));
+        db.compact().unwrap();
+        assert_eq!(db.keydir.len(), 3);
+        assert_ne!(db.keydir[&b"long"[..]].expires_at, 0);
+        assert_eq!(db.keydir[&b"cleared"[..]].expires_at, 0);
+        assert_eq!(db.get(b"long").unwrap().unwrap(), b"cached ".repeat(20));
+        assert_eq!(db.get(b"cleared").unwrap().unwrap(), b"v2");
+    }
+
+    #[test]
+    fn test_dir_lock() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        db.set(b"k", b"v").unwrap();
+        // 同一个目录不能被第二个实例以读写方式打开，错误中带有持有锁的进程号
+        let err = BitCask::init_db_at(dir.path()).unwrap_err().to_string();
+        assert!(err.contains(&format!("in use by pid {}", std::process::id())), "{err}");
+        // 锁文件不是数据文件
+        assert!(BitCask::fsck(dir.path()).unwrap().is_clean());
+        drop(db);
+        let db = BitCask::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"k").unwrap().unwrap(), b"v");
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        assert!(BitCask::open_read_only(&dir.path().join("missing")).is_err());
+        assert!(!dir.path().join("missing").exists());
+
+        let mut db = BitCask::init_db_at(dir.path()).unwrap();
+        db.set(b"a", b"1").unwrap();
+        db.set(b"b", b"2").unwrap();
+        db.refresh_active();
+        db.set(b"c", b"3").unwrap();
+
+        // 写入方仍然打开时可以只读打开，读到打开时刻的快照
+        let mut reader = BitCask::open_read_only(dir.path()).unwrap();
+        assert!(reader.is_read_only());
+        assert!(!db.is_read_only());
+        db.set(b"a", b"updated").unwrap();
+        db.set(b"d", b"4").unwrap();
+        db.compact().unwrap();
+        let items: Vec<_> = reader.scan(..).map(|item| item.unwrap()).collect();
+        assert_eq!(
+            items,
+            vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec()), (b"c".to_vec(), b"3".to_vec())]
+        );
+        assert_eq!(reader.status().unwrap().total_count, 3);
+
+        // 只读实例拒绝所有写入，也不会在目录中创建文件
+        let files = log_files(dir.path()).unwrap();
+        assert!(matches!(reader.set(b"x", b"y"), Err(Error::ReadOnly)));
+        assert!(matches!(reader.delete(b"a"), Err(Error::ReadOnly)));
+        let mut batch = WriteBatch::new();
+        batch.put(b"x", b"y");
+        assert!(matches!(reader.write_batch(batch), Err(Error::ReadOnly)));
+        assert!(matches!(reader.compact(), Err(Error::ReadOnly)));
+        drop(reader);
+        assert_eq!(log_files(dir.path()).unwrap(), files);
+
+        // 新的只读实例可以看到之前的写入
+        let reader = BitCask::open_read_only(dir.path()).unwrap();
+        assert_eq!(reader.get(b"a").unwrap().unwrap(), b"updated");
+        assert_eq!(reader.get(b"d").unwrap().unwrap(), b"4");
+    }
+
+    #[test]
+    fn test_batch_on_old_active_file() {
+        // 不支持批量写入帧的活跃文件先切换到新格式的活跃文件
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        write_old_format_file(dir.path(), FileFormat::V3);
+        {
+            let mut db = BitCask::init_db_at(dir.path()).unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"k3", b"v3").delete(b"k2");
+            db.write_batch(batch).unwrap();
+            assert_eq!(db.log.as_ref().unwrap().format, FileFormat::CURRENT);
+        }
+        assert!(BitCask::fsck(dir.path()).unwrap().is_clean());
+        let db = BitCask::init_db_at(dir.path()).unwrap();
+        assert!(db.get(b"k2").unwrap().is_none());
+        assert_eq!(db.get(b"k3").unwrap().unwrap(), b"v3");
+    }
+
+    #[test]
+    fn test_wide_offsets() {
+        // crc_pos 超过 u32 范围时依然可以正确寻址（稀疏文件，不实际占用磁盘）
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let file_id = create_tsid().number().to_string() + "_active";
+        let mut log_db = Log::new(file_id).unwrap();
+        let offset = u32::MAX as u64 + 16;
+        log_db.file.lock().unwrap().set_len(offset).unwrap();
+        let mut entry = LogEntry::new(log_db.format, 0, b"big".to_vec(), b"file".to_vec());
+        entry.build_crc();
+        let pos = log_db.write_entry(entry).unwrap();
+        assert_eq!(pos, offset);
+        let entry = log_db.read_entry(pos).unwrap().unwrap();
+        assert_eq!(entry.value, b"file");
+    }
 }
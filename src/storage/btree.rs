@@ -0,0 +1,1126 @@
+use crate::cfg::get_db_base;
+use crate::db_error::Result;
+use crate::storage::bitcask::read_exact_at;
+use crate::storage::engine::{Engine, EngineStatus, FileStatus, WriteBatch, WriteOp};
+use crate::storage::util::{after_end, before_start, KeyRange, Reader};
+use crate::{errdata, errinput};
+
+use fs4::fs_std::FileExt;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// 数据文件名
+const DATA_FILE: &str = "btree.db";
+/// 预写日志文件名，记录上次检查点之后提交的页面
+const WAL_FILE: &str = "btree.wal";
+/// 页面大小
+const PAGE_SIZE: usize = 4096;
+/// 元数据页的魔数
+const MAGIC: u64 = 0x6d69_6e69_6274_7231;
+/// 默认缓存的页面数量（4 MiB）
+const DEFAULT_CACHE_PAGES: usize = 1024;
+/// 预写日志超过这个大小后做一次检查点
+const WAL_CHECKPOINT_SIZE: u64 = 4 * 1024 * 1024;
+/// 键值超过这个大小时值写入溢出页，保证任意叶子拆分后两半都能放进一页
+const MAX_INLINE: usize = PAGE_SIZE / 4;
+/// 键的最大长度，键总是内联存储
+const MAX_KEY_SIZE: usize = MAX_INLINE - 24;
+/// 页头：crc(4) 类型(1)
+const PAGE_HEADER: usize = 5;
+/// 溢出页中数据的容量：页头 + 下一页(4) + 长度(2)
+const OVERFLOW_CAPACITY: usize = PAGE_SIZE - PAGE_HEADER - 6;
+
+/// 页面编号。0 号页是元数据页，不会被节点引用，因此也用 0 表示“没有”
+type PageId = u32;
+
+/// 叶子中的值：较小的值内联，较大的值放在溢出页链中
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Inline(Vec<u8>),
+    Overflow { first: PageId, len: u32 },
+}
+
+/// 解码后的页面
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// 叶子节点，按键排序，通过 prev / next 串成双向链表
+    Leaf { prev: PageId, next: PageId, entries: Vec<(Vec<u8>, Cell)> },
+    /// 内部节点：children[i] 中的键都小于 keys[i]，children[i + 1] 中的键都不小于 keys[i]
+    Internal { keys: Vec<Vec<u8>>, children: Vec<PageId> },
+    /// 溢出页，保存大值的一段
+    Overflow { next: PageId, data: Vec<u8> },
+    /// 空闲页，串成空闲链表等待复用
+    Free { next: PageId },
+}
+
+impl Node {
+    fn empty_leaf() -> Self {
+        Node::Leaf { prev: 0, next: 0, entries: vec![] }
+    }
+
+    /// 编码后占用的字节数（含页头）
+    fn encoded_len(&self) -> usize {
+        PAGE_HEADER
+            + match self {
+                Node::Leaf { entries, .. } => 10 + entries.iter().map(|(k, c)| cell_len(k, c)).sum::<usize>(),
+                Node::Internal { keys, .. } => 6 + keys.iter().map(|k| 6 + k.len()).sum::<usize>(),
+                Node::Overflow { data, .. } => 6 + data.len(),
+                Node::Free { .. } => 4,
+            }
+    }
+
+    /// 编码为一个完整的页面，首 4 字节为其余部分的 crc
+    fn encode(&self) -> Vec<u8> {
+        let mut page = vec![0u8; 4];
+        match self {
+            Node::Leaf { prev, next, entries } => {
+                page.push(1);
+                page.extend_from_slice(&prev.to_be_bytes());
+                page.extend_from_slice(&next.to_be_bytes());
+                page.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+                for (key, cell) in entries {
+                    page.extend_from_slice(&(key.len() as u16).to_be_bytes());
+                    page.extend_from_slice(key);
+                    match cell {
+                        Cell::Inline(value) => {
+                            page.push(0);
+                            page.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                            page.extend_from_slice(value);
+                        }
+                        Cell::Overflow { first, len } => {
+                            page.push(1);
+                            page.extend_from_slice(&first.to_be_bytes());
+                            page.extend_from_slice(&len.to_be_bytes());
+                        }
+                    }
+                }
+            }
+            Node::Internal { keys, children } => {
+                page.push(2);
+                page.extend_from_slice(&(keys.len() as u16).to_be_bytes());
+                page.extend_from_slice(&children[0].to_be_bytes());
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    page.extend_from_slice(&(key.len() as u16).to_be_bytes());
+                    page.extend_from_slice(key);
+                    page.extend_from_slice(&child.to_be_bytes());
+                }
+            }
+            Node::Overflow { next, data } => {
+                page.push(3);
+                page.extend_from_slice(&next.to_be_bytes());
+                page.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                page.extend_from_slice(data);
+            }
+            Node::Free { next } => {
+                page.push(4);
+                page.extend_from_slice(&next.to_be_bytes());
+            }
+        }
+        seal_page(page)
+    }
+
+    fn decode(id: PageId, page: &[u8]) -> Result<Self> {
+        let mut reader = open_page(id, page)?;
+        let node = match reader.u8()? {
+            1 => {
+                let (prev, next) = (reader.u32()?, reader.u32()?);
+                let mut entries = vec![];
+                for _ in 0..reader.u16()? {
+                    let len = reader.u16()? as usize;
+                    let key = reader.take(len)?.to_vec();
+                    let cell = match reader.u8()? {
+                        0 => {
+                            let len = reader.u32()? as usize;
+                            Cell::Inline(reader.take(len)?.to_vec())
+                        }
+                        _ => Cell::Overflow { first: reader.u32()?, len: reader.u32()? },
+                    };
+                    entries.push((key, cell));
+                }
+                Node::Leaf { prev, next, entries }
+            }
+            2 => {
+                let count = reader.u16()?;
+                let mut children = vec![reader.u32()?];
+                let mut keys = vec![];
+                for _ in 0..count {
+                    let len = reader.u16()? as usize;
+                    keys.push(reader.take(len)?.to_vec());
+                    children.push(reader.u32()?);
+                }
+                Node::Internal { keys, children }
+            }
+            3 => {
+                let next = reader.u32()?;
+                let len = reader.u16()? as usize;
+                Node::Overflow { next, data: reader.take(len)?.to_vec() }
+            }
+            4 => Node::Free { next: reader.u32()? },
+            kind => return errdata!("page {id} has unknown type {kind}"),
+        };
+        Ok(node)
+    }
+}
+
+/// 叶子中一个条目编码后的长度
+fn cell_len(key: &[u8], cell: &Cell) -> usize {
+    2 + key.len()
+        + 1
+        + match cell {
+            Cell::Inline(value) => 4 + value.len(),
+            Cell::Overflow { .. } => 8,
+        }
+}
+
+/// 元数据页：根节点、页面总数与空闲链表头
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Meta {
+    root: PageId,
+    page_count: u32,
+    free_head: PageId,
+}
+
+impl Meta {
+    fn encode(&self) -> Vec<u8> {
+        let mut page = vec![0u8; 4];
+        page.push(0);
+        page.extend_from_slice(&MAGIC.to_be_bytes());
+        page.extend_from_slice(&self.root.to_be_bytes());
+        page.extend_from_slice(&self.page_count.to_be_bytes());
+        page.extend_from_slice(&self.free_head.to_be_bytes());
+        seal_page(page)
+    }
+
+    fn decode(page: &[u8]) -> Result<Self> {
+        let mut reader = open_page(0, page)?;
+        if reader.u8()? != 0 || reader.u64()? != MAGIC {
+            return errdata!("{DATA_FILE} is not a b+tree data file");
+        }
+        Ok(Self { root: reader.u32()?, page_count: reader.u32()?, free_head: reader.u32()? })
+    }
+}
+
+/// 补齐到页面大小并写入 crc
+fn seal_page(mut page: Vec<u8>) -> Vec<u8> {
+    page.resize(PAGE_SIZE, 0);
+    let crc = crc32c::crc32c(&page[4..]);
+    page[..4].copy_from_slice(&crc.to_be_bytes());
+    page
+}
+
+/// 校验页面 crc，返回跳过 crc 的读取器
+fn open_page(id: PageId, page: &[u8]) -> Result<Reader<'_>> {
+    let mut reader = Reader(page);
+    let crc = reader.u32()?;
+    if crc32c::crc32c(reader.0) != crc {
+        return errdata!("page {id} crc mismatch");
+    }
+    Ok(reader)
+}
+
+/// 缓存中的页面
+struct CachedPage {
+    node: Arc<Node>,
+    /// 已写入预写日志但还没有写回数据文件
+    dirty: bool,
+    /// 最近一次访问的时钟，用于 LRU 淘汰
+    used: u64,
+}
+
+/// 页面管理：页面缓存、预写日志与空间分配
+///
+/// 一次写操作修改的页面先留在缓存中（pending）；操作结束时所有修改过的页面和元数据页
+/// 以完整页面镜像写成一条预写日志记录，之后这些页面才可能被写回数据文件。
+/// 崩溃后重放日志即可恢复到最后一次完整提交的状态，写到一半的记录整条丢弃。
+struct Pager {
+    file: fs::File,
+    wal: fs::File,
+    wal_size: u64,
+    /// 预写日志自上次同步后没有新写入的记录
+    wal_synced: bool,
+    meta: Meta,
+    cache: HashMap<PageId, CachedPage>,
+    capacity: usize,
+    clock: u64,
+    /// 当前操作修改过、还没有写入日志的页面
+    pending: BTreeSet<PageId>,
+    /// 当前操作开始前的页面与元数据，操作失败时用来撤销
+    undo: HashMap<PageId, Option<CachedPage>>,
+    undo_meta: Option<Meta>,
+}
+
+impl Pager {
+    /// 读取页面，优先命中缓存
+    fn read(&mut self, id: PageId) -> Result<Arc<Node>> {
+        self.clock += 1;
+        if let Some(page) = self.cache.get_mut(&id) {
+            page.used = self.clock;
+            return Ok(page.node.clone());
+        }
+        if id == 0 || id >= self.meta.page_count {
+            return errdata!("page {id} out of bounds");
+        }
+        let mut bytes = vec![0u8; PAGE_SIZE];
+        read_exact_at(&self.file, &mut bytes, id as u64 * PAGE_SIZE as u64)?;
+        let node = Arc::new(Node::decode(id, &bytes)?);
+        self.cache.insert(id, CachedPage { node: node.clone(), dirty: false, used: self.clock });
+        self.evict()?;
+        Ok(node)
+    }
+
+    /// 修改页面，记入当前操作
+    fn write(&mut self, id: PageId, node: Node) {
+        if !self.pending.contains(&id) {
+            let before = self.cache.remove(&id);
+            self.undo.entry(id).or_insert(before);
+            self.pending.insert(id);
+        }
+        self.clock += 1;
+        self.cache.insert(id, CachedPage { node: Arc::new(node), dirty: false, used: self.clock });
+    }
+
+    fn set_meta(&mut self, meta: Meta) {
+        self.undo_meta.get_or_insert(self.meta);
+        self.meta = meta;
+    }
+
+    /// 分配一个页面：优先复用空闲链表
+    fn allocate(&mut self) -> Result<PageId> {
+        let mut meta = self.meta;
+        let id = match meta.free_head {
+            0 => {
+                meta.page_count += 1;
+                meta.page_count - 1
+            }
+            id => {
+                let Node::Free { next } = *self.read(id)? else {
+                    return errdata!("free list page {id} is not free");
+                };
+                meta.free_head = next;
+                id
+            }
+        };
+        self.set_meta(meta);
+        Ok(id)
+    }
+
+    /// 释放页面，放到空闲链表头部
+    fn free(&mut self, id: PageId) {
+        let mut meta = self.meta;
+        self.write(id, Node::Free { next: meta.free_head });
+        meta.free_head = id;
+        self.set_meta(meta);
+    }
+
+    /// 把当前操作修改的页面作为一条记录写入预写日志
+    /// 记录：crc(4) 页数(4) 然后每页 编号(4) 页面(PAGE_SIZE)，元数据页总在最后
+    fn commit(&mut self) -> Result<()> {
+        if self.pending.is_empty() && self.undo_meta.is_none() {
+            return Ok(());
+        }
+        let mut payload = ((self.pending.len() + 1) as u32).to_be_bytes().to_vec();
+        for &id in &self.pending {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend(self.cache[&id].node.encode());
+        }
+        payload.extend_from_slice(&0u32.to_be_bytes());
+        payload.extend(self.meta.encode());
+        let mut record = crc32c::crc32c(&payload).to_be_bytes().to_vec();
+        record.extend(payload);
+        if let Err(e) = self.wal.write_all(&record) {
+            self.abort();
+            return Err(e.into());
+        }
+        self.wal_size += record.len() as u64;
+        self.wal_synced = false;
+        for id in std::mem::take(&mut self.pending) {
+            if let Some(page) = self.cache.get_mut(&id) {
+                page.dirty = true;
+            }
+        }
+        self.undo.clear();
+        self.undo_meta = None;
+        if self.wal_size >= WAL_CHECKPOINT_SIZE {
+            self.checkpoint()?;
+        }
+        self.evict()
+    }
+
+    /// 撤销当前操作的所有修改
+    fn abort(&mut self) {
+        for (id, before) in self.undo.drain() {
+            match before {
+                Some(page) => self.cache.insert(id, page),
+                None => self.cache.remove(&id),
+            };
+        }
+        self.pending.clear();
+        if let Some(meta) = self.undo_meta.take() {
+            self.meta = meta;
+        }
+    }
+
+    /// 原地覆盖数据文件中的页面之前先同步预写日志：否则崩溃后页面已经是新内容，
+    /// 对应的日志记录却没有落盘，同一次操作修改的其它页面无法重放
+    fn sync_wal(&mut self) -> Result<()> {
+        if !self.wal_synced {
+            self.wal.sync_data()?;
+            self.wal_synced = true;
+        }
+        Ok(())
+    }
+
+    /// 检查点：把已提交的脏页和元数据页写回数据文件并同步，然后清空预写日志
+    fn checkpoint(&mut self) -> Result<()> {
+        self.sync_wal()?;
+        let mut dirty: Vec<PageId> = self.cache.iter().filter(|(_, p)| p.dirty).map(|(&id, _)| id).collect();
+        dirty.sort_unstable();
+        for id in dirty {
+            write_page(&self.file, id, &self.cache[&id].node.encode())?;
+            self.cache.get_mut(&id).unwrap().dirty = false;
+        }
+        write_page(&self.file, 0, &self.meta.encode())?;
+        self.file.sync_data()?;
+        self.wal.set_len(0)?;
+        self.wal_size = 0;
+        Ok(())
+    }
+
+    /// 缓存超过容量时淘汰最久未用的八分之一，脏页先写回数据文件
+    /// 当前操作修改的页面还没有写入日志，不能淘汰
+    fn evict(&mut self) -> Result<()> {
+        if self.cache.len() <= self.capacity {
+            return Ok(());
+        }
+        let mut candidates: Vec<(u64, PageId)> = self
+            .cache
+            .iter()
+            .filter(|(id, _)| !self.pending.contains(id))
+            .map(|(&id, page)| (page.used, id))
+            .collect();
+        candidates.sort_unstable();
+        let count = (self.cache.len() - self.capacity + self.capacity / 8).min(candidates.len());
+        for &(_, id) in &candidates[..count] {
+            let page = self.cache.remove(&id).unwrap();
+            if page.dirty {
+                self.sync_wal()?;
+                write_page(&self.file, id, &page.node.encode())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 重放预写日志中完整的记录，写入数据文件；返回是否有记录被重放
+    fn recover(file: &fs::File, bytes: &[u8]) -> Result<bool> {
+        let mut reader = Reader(bytes);
+        let mut replayed = false;
+        while let Some(pages) = decode_wal_record(&mut reader) {
+            for (id, page) in pages {
+                write_page(file, id, page)?;
+            }
+            replayed = true;
+        }
+        Ok(replayed)
+    }
+}
+
+/// 解码一条预写日志记录，记录不完整或校验失败时返回 None
+fn decode_wal_record<'a>(reader: &mut Reader<'a>) -> Option<Vec<(PageId, &'a [u8])>> {
+    let crc = reader.u32().ok()?;
+    let count = Reader(reader.0).u32().ok()? as usize;
+    let len = 4 + count.checked_mul(4 + PAGE_SIZE)?;
+    let payload = reader.take(len).ok()?;
+    if crc32c::crc32c(payload) != crc {
+        return None;
+    }
+    let mut payload = Reader(&payload[4..]);
+    (0..count).map(|_| Some((payload.u32().ok()?, payload.take(PAGE_SIZE).ok()?))).collect()
+}
+
+fn write_page(file: &fs::File, id: PageId, page: &[u8]) -> Result<()> {
+    write_all_at(file, page, id as u64 * PAGE_SIZE as u64)
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &fs::File, buf: &[u8], pos: u64) -> Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, pos)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &fs::File, mut buf: &[u8], mut pos: u64) -> Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, pos)?;
+        buf = &buf[n..];
+        pos += n as u64;
+    }
+    Ok(())
+}
+
+/// 页式 B+ 树存储引擎
+///
+/// 所有数据保存在一个按 4 KiB 分页的文件中：内部节点只存分隔键，键值都在叶子中，
+/// 叶子串成双向链表，范围扫描按叶子顺序读取，不需要像 BitCask 那样逐个键随机读。
+/// 页面经过 LRU 缓存访问，修改通过预写日志保证崩溃安全。
+/// 删除不做节点合并，只回收变空的叶子，空出的页面进入空闲链表复用。
+pub struct BTree {
+    pager: Mutex<Pager>,
+}
+
+impl BTree {
+    /// 在配置的存储路径下打开数据库
+    pub fn init_db() -> Result<Self> {
+        Self::init_db_at(Path::new(&get_db_base()))
+    }
+
+    pub fn init_db_at(path: &Path) -> Result<Self> {
+        Self::open_with_cache(path, DEFAULT_CACHE_PAGES)
+    }
+
+    /// 打开数据库，最多缓存 cache_pages 个页面
+    /// 1、重放预写日志中完整的记录，做一次检查点
+    /// 2、读取元数据页；新建的数据库写入元数据页和一个空的根叶子
+    pub fn open_with_cache(path: &Path, cache_pages: usize) -> Result<Self> {
+        fs::create_dir_all(path)?;
+        let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path.join(DATA_FILE))?;
+        file.try_lock_exclusive()?;
+        let mut wal = fs::OpenOptions::new().read(true).append(true).create(true).open(path.join(WAL_FILE))?;
+        let mut bytes = vec![];
+        wal.read_to_end(&mut bytes)?;
+        if Pager::recover(&file, &bytes)? {
+            file.sync_data()?;
+        }
+        wal.set_len(0)?;
+
+        let meta = if file.metadata()?.len() == 0 {
+            let meta = Meta { root: 1, page_count: 2, free_head: 0 };
+            write_page(&file, 1, &Node::empty_leaf().encode())?;
+            write_page(&file, 0, &meta.encode())?;
+            file.sync_data()?;
+            meta
+        } else {
+            let mut page = vec![0u8; PAGE_SIZE];
+            read_exact_at(&file, &mut page, 0)?;
+            Meta::decode(&page)?
+        };
+        let pager = Pager {
+            file,
+            wal,
+            wal_size: 0,
+            wal_synced: true,
+            meta,
+            cache: HashMap::new(),
+            capacity: cache_pages.max(16),
+            clock: 0,
+            pending: BTreeSet::new(),
+            undo: HashMap::new(),
+            undo_meta: None,
+        };
+        Ok(Self { pager: Mutex::new(pager) })
+    }
+
+    /// 把一批操作作为一次提交执行，失败时撤销已做的修改
+    fn apply(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        let pager = self.pager.get_mut()?;
+        let result = ops.into_iter().try_for_each(|op| match op {
+            WriteOp::Put(key, value) => insert(pager, key, &value),
+            WriteOp::Delete(key) => remove(pager, &key),
+        });
+        match result {
+            Ok(()) => pager.commit(),
+            Err(e) => {
+                pager.abort();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// 从根节点向下找到 key 所在的叶子，返回叶子编号
+fn find_leaf(pager: &mut Pager, key: Option<&[u8]>, rightmost: bool) -> Result<PageId> {
+    let mut id = pager.meta.root;
+    loop {
+        match &*pager.read(id)? {
+            Node::Internal { keys, children } => {
+                id = match key {
+                    Some(key) => children[keys.partition_point(|k| k.as_slice() <= key)],
+                    None if rightmost => *children.last().unwrap(),
+                    None => children[0],
+                };
+            }
+            Node::Leaf { .. } => return Ok(id),
+            _ => return errdata!("page {id} is not a tree node"),
+        }
+    }
+}
+
+/// 读取一个条目的值，溢出的值沿溢出页链拼接
+fn read_cell(pager: &mut Pager, cell: &Cell) -> Result<Vec<u8>> {
+    match cell {
+        Cell::Inline(value) => Ok(value.clone()),
+        Cell::Overflow { first, len } => {
+            let mut value = Vec::with_capacity(*len as usize);
+            let mut id = *first;
+            while id != 0 {
+                let node = pager.read(id)?;
+                let Node::Overflow { next, data } = &*node else {
+                    return errdata!("page {id} is not an overflow page");
+                };
+                value.extend_from_slice(data);
+                id = *next;
+            }
+            if value.len() != *len as usize {
+                return errdata!("overflow chain at page {first} has {} bytes, expected {len}", value.len());
+            }
+            Ok(value)
+        }
+    }
+}
+
+/// 构造条目：大值写入新分配的溢出页链
+fn write_cell(pager: &mut Pager, key: &[u8], value: &[u8]) -> Result<Cell> {
+    if key.len() + value.len() <= MAX_INLINE {
+        return Ok(Cell::Inline(value.to_vec()));
+    }
+    let len = u32::try_from(value.len()).or_else(|_| errinput!("value too large: {} bytes", value.len()))?;
+    // 从尾部开始写，每页都能直接指向已分配的下一页
+    let mut next = 0;
+    for chunk in value.chunks(OVERFLOW_CAPACITY).rev() {
+        let id = pager.allocate()?;
+        pager.write(id, Node::Overflow { next, data: chunk.to_vec() });
+        next = id;
+    }
+    Ok(Cell::Overflow { first: next, len })
+}
+
+/// 释放条目占用的溢出页
+fn free_cell(pager: &mut Pager, cell: &Cell) -> Result<()> {
+    if let Cell::Overflow { first, .. } = cell {
+        let mut id = *first;
+        while id != 0 {
+            let Node::Overflow { next, .. } = *pager.read(id)? else {
+                return errdata!("page {id} is not an overflow page");
+            };
+            pager.free(id);
+            id = next;
+        }
+    }
+    Ok(())
+}
+
+/// 按编码长度找到拆分点，使两半都不超过一页
+fn split_point(lens: impl Iterator<Item = usize>) -> usize {
+    let lens: Vec<usize> = lens.collect();
+    let half = lens.iter().sum::<usize>() / 2;
+    let mut acc = 0;
+    for (i, len) in lens.iter().enumerate() {
+        acc += len;
+        if acc >= half {
+            return (i + 1).clamp(1, lens.len() - 1);
+        }
+    }
+    lens.len() / 2
+}
+
+fn insert(pager: &mut Pager, key: Vec<u8>, value: &[u8]) -> Result<()> {
+    if key.len() > MAX_KEY_SIZE {
+        return errinput!("key too large: {} bytes, at most {MAX_KEY_SIZE}", key.len());
+    }
+    let cell = write_cell(pager, &key, value)?;
+    let root = pager.meta.root;
+    if let Some((separator, right)) = insert_into(pager, root, key, cell)? {
+        // 根节点拆分，树长高一层
+        let new_root = pager.allocate()?;
+        pager.write(new_root, Node::Internal { keys: vec![separator], children: vec![root, right] });
+        pager.set_meta(Meta { root: new_root, ..pager.meta });
+    }
+    Ok(())
+}
+
+/// 插入到以 id 为根的子树，节点拆分时返回 (分隔键, 新的右兄弟)
+fn insert_into(pager: &mut Pager, id: PageId, key: Vec<u8>, cell: Cell) -> Result<Option<(Vec<u8>, PageId)>> {
+    let mut node = (*pager.read(id)?).clone();
+    match &mut node {
+        Node::Leaf { entries, .. } => {
+            match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                Ok(i) => {
+                    let old = std::mem::replace(&mut entries[i].1, cell);
+                    free_cell(pager, &old)?;
+                }
+                Err(i) => entries.insert(i, (key, cell)),
+            }
+            if node.encoded_len() <= PAGE_SIZE {
+                pager.write(id, node);
+                return Ok(None);
+            }
+            let Node::Leaf { prev, next, mut entries } = node else { unreachable!() };
+            let mid = split_point(entries.iter().map(|(k, c)| cell_len(k, c)));
+            let right_entries = entries.split_off(mid);
+            let separator = right_entries[0].0.clone();
+            let right = pager.allocate()?;
+            if next != 0 {
+                let Node::Leaf { next: after, entries: after_entries, .. } = (*pager.read(next)?).clone() else {
+                    return errdata!("page {next} is not a leaf");
+                };
+                pager.write(next, Node::Leaf { prev: right, next: after, entries: after_entries });
+            }
+            pager.write(id, Node::Leaf { prev, next: right, entries });
+            pager.write(right, Node::Leaf { prev: id, next, entries: right_entries });
+            Ok(Some((separator, right)))
+        }
+        Node::Internal { keys, children } => {
+            let index = keys.partition_point(|k| *k <= key);
+            let Some((separator, child)) = insert_into(pager, children[index], key, cell)? else {
+                return Ok(None);
+            };
+            keys.insert(index, separator);
+            children.insert(index + 1, child);
+            if node.encoded_len() <= PAGE_SIZE {
+                pager.write(id, node);
+                return Ok(None);
+            }
+            let Node::Internal { mut keys, mut children } = node else { unreachable!() };
+            // 中间的键上移到父节点，不留在任何一半中
+            let mid = split_point(keys.iter().map(|k| 6 + k.len())).min(keys.len() - 1);
+            let right_keys = keys.split_off(mid + 1);
+            let separator = keys.pop().unwrap();
+            let right_children = children.split_off(mid + 1);
+            let right = pager.allocate()?;
+            pager.write(id, Node::Internal { keys, children });
+            pager.write(right, Node::Internal { keys: right_keys, children: right_children });
+            Ok(Some((separator, right)))
+        }
+        _ => errdata!("page {id} is not a tree node"),
+    }
+}
+
+fn remove(pager: &mut Pager, key: &[u8]) -> Result<()> {
+    let root = pager.meta.root;
+    remove_from(pager, root, key)?;
+    // 根节点只剩一个孩子时树降低一层
+    loop {
+        let root = pager.meta.root;
+        match &*pager.read(root)? {
+            Node::Internal { children, .. } if children.len() == 1 => {
+                let child = children[0];
+                pager.free(root);
+                pager.set_meta(Meta { root: child, ..pager.meta });
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// 从以 id 为根的子树中删除键，返回该子树是否已经变空
+/// 变空的叶子从链表中摘除并释放；根节点即使变空也保留
+fn remove_from(pager: &mut Pager, id: PageId, key: &[u8]) -> Result<bool> {
+    let mut node = (*pager.read(id)?).clone();
+    match &mut node {
+        Node::Leaf { prev, next, entries } => {
+            let Ok(i) = entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) else {
+                return Ok(false);
+            };
+            let (_, cell) = entries.remove(i);
+            free_cell(pager, &cell)?;
+            if !entries.is_empty() || id == pager.meta.root {
+                pager.write(id, node);
+                return Ok(false);
+            }
+            let (prev, next) = (*prev, *next);
+            if prev != 0 {
+                let Node::Leaf { prev: before, entries, .. } = (*pager.read(prev)?).clone() else {
+                    return errdata!("page {prev} is not a leaf");
+                };
+                pager.write(prev, Node::Leaf { prev: before, next, entries });
+            }
+            if next != 0 {
+                let Node::Leaf { next: after, entries, .. } = (*pager.read(next)?).clone() else {
+                    return errdata!("page {next} is not a leaf");
+                };
+                pager.write(next, Node::Leaf { prev, next: after, entries });
+            }
+            pager.free(id);
+            Ok(true)
+        }
+        Node::Internal { keys, children } => {
+            let index = keys.partition_point(|k| k.as_slice() <= key);
+            if !remove_from(pager, children[index], key)? {
+                return Ok(false);
+            }
+            // 去掉空孩子和它一侧的分隔键
+            children.remove(index);
+            if !keys.is_empty() {
+                keys.remove(index.saturating_sub(1));
+            }
+            if children.is_empty() && id != pager.meta.root {
+                pager.free(id);
+                return Ok(true);
+            }
+            if children.is_empty() {
+                // 根节点的所有叶子都被删除，换成一个空叶子
+                pager.write(id, Node::empty_leaf());
+            } else {
+                pager.write(id, node);
+            }
+            Ok(false)
+        }
+        _ => errdata!("page {id} is not a tree node"),
+    }
+}
+
+impl Engine for BTree {
+    type ScanIter<'a> = ScanIterator<'a>;
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.apply(vec![WriteOp::Put(key.to_vec(), value.to_vec())])
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut pager = self.pager.lock()?;
+        let leaf = find_leaf(&mut pager, Some(key), false)?;
+        let node = pager.read(leaf)?;
+        let Node::Leaf { entries, .. } = &*node else {
+            return errdata!("page {leaf} is not a leaf");
+        };
+        match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+            Ok(i) => Ok(Some(read_cell(&mut pager, &entries[i].1)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.apply(vec![WriteOp::Delete(key.to_vec())])
+    }
+
+    /// 整批操作修改的页面写成一条预写日志记录，重放时要么全部生效，要么整条被丢弃
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.apply(batch.into_iter().collect())
+    }
+
+    /// 做一次检查点，把已提交的页面写回数据文件
+    fn flush(&mut self) -> Result<()> {
+        self.pager.get_mut()?.checkpoint()
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+        ScanIterator {
+            tree: self,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            front_cursor: Cursor::Start,
+            back_cursor: Cursor::Start,
+            front_last: None,
+            back_last: None,
+        }
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        let pager = self.pager.get_mut()?;
+        pager.cache.clear();
+        pager.meta = Meta { root: 1, page_count: 2, free_head: 0 };
+        pager.file.set_len(0)?;
+        write_page(&pager.file, 1, &Node::empty_leaf().encode())?;
+        write_page(&pager.file, 0, &pager.meta.encode())?;
+        pager.file.sync_data()?;
+        pager.wal.set_len(0)?;
+        pager.wal_size = 0;
+        pager.wal_synced = true;
+        Ok(())
+    }
+
+    /// 沿叶子链表统计存活数据；未被键值占用的页面空间都计为垃圾
+    fn status(&self) -> Result<EngineStatus> {
+        let mut pager = self.pager.lock()?;
+        let (mut logical_size, mut total_count, mut live_size) = (0, 0, 0);
+        let mut leaf = find_leaf(&mut pager, None, false)?;
+        while leaf != 0 {
+            let node = pager.read(leaf)?;
+            let Node::Leaf { next, entries, .. } = &*node else {
+                return errdata!("page {leaf} is not a leaf");
+            };
+            for (key, cell) in entries {
+                // 内联值已经计入 cell_len，溢出值另外占用溢出页
+                let (value_len, overflow_len) = match cell {
+                    Cell::Inline(value) => (value.len(), 0),
+                    Cell::Overflow { len, .. } => (*len as usize, *len as usize),
+                };
+                logical_size += (key.len() + value_len) as u64;
+                live_size += (cell_len(key, cell) + overflow_len) as u64;
+                total_count += 1;
+            }
+            leaf = *next;
+        }
+        let data_size = pager.meta.page_count as u64 * PAGE_SIZE as u64;
+        let files = vec![
+            FileStatus { name: DATA_FILE.to_string(), total_size: data_size, live_size, live_count: total_count },
+            FileStatus { name: WAL_FILE.to_string(), total_size: pager.wal_size, live_size: 0, live_count: 0 },
+        ];
+        let total_size = data_size + pager.wal_size;
+        Ok(EngineStatus {
+            name: "btree".to_string(),
+            logical_size,
+            total_count,
+            total_size,
+            live_size,
+            garbage_size: total_size.saturating_sub(live_size),
+            index_memory: (pager.cache.len() * PAGE_SIZE) as u64,
+            files,
+        })
+    }
+}
+
+/// 扫描游标的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cursor {
+    /// 还没有定位，第一次读取时从根节点向下查找
+    Start,
+    /// 下一个要读取的叶子
+    Leaf(PageId),
+    Done,
+}
+
+/// B+ 树的双向扫描迭代器：两端各自沿叶子链表读取，按键比较在中间相遇
+pub struct ScanIterator<'a> {
+    tree: &'a BTree,
+    range: KeyRange,
+    front: VecDeque<(Vec<u8>, Vec<u8>)>,
+    back: VecDeque<(Vec<u8>, Vec<u8>)>,
+    front_cursor: Cursor,
+    back_cursor: Cursor,
+    /// 两端最近返回的键
+    front_last: Option<Vec<u8>>,
+    back_last: Option<Vec<u8>>,
+}
+
+impl<'a> ScanIterator<'a> {
+    /// 读取下一个叶子中位于范围内的条目，reverse 表示从后端读取
+    fn load(&mut self, reverse: bool) -> Result<()> {
+        let cursor = if reverse { self.back_cursor } else { self.front_cursor };
+        let mut pager = self.tree.pager.lock()?;
+        let leaf = match cursor {
+            Cursor::Done => return Ok(()),
+            Cursor::Leaf(id) => id,
+            Cursor::Start if reverse => match &self.range.1 {
+                Bound::Included(key) | Bound::Excluded(key) => find_leaf(&mut pager, Some(key), true)?,
+                Bound::Unbounded => find_leaf(&mut pager, None, true)?,
+            },
+            Cursor::Start => match &self.range.0 {
+                Bound::Included(key) | Bound::Excluded(key) => find_leaf(&mut pager, Some(key), false)?,
+                Bound::Unbounded => find_leaf(&mut pager, None, false)?,
+            },
+        };
+        let node = pager.read(leaf)?;
+        let Node::Leaf { prev, next, entries } = &*node else {
+            return errdata!("page {leaf} is not a leaf");
+        };
+        let mut items = VecDeque::new();
+        // 越过范围边界之后不再继续读取
+        let mut beyond = false;
+        for (key, cell) in entries {
+            if before_start(key, &self.range.0) {
+                beyond |= reverse;
+                continue;
+            }
+            if after_end(key, &self.range.1) {
+                beyond |= !reverse;
+                continue;
+            }
+            items.push_back((key.clone(), read_cell(&mut pager, cell)?));
+        }
+        let cursor = match if reverse { *prev } else { *next } {
+            0 => Cursor::Done,
+            _ if beyond => Cursor::Done,
+            id => Cursor::Leaf(id),
+        };
+        if reverse {
+            self.back_cursor = cursor;
+            self.back = items;
+        } else {
+            self.front_cursor = cursor;
+            self.front = items;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        self.front_cursor = Cursor::Done;
+        self.back_cursor = Cursor::Done;
+        self.front.clear();
+        self.back.clear();
+    }
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front.is_empty() && self.front_cursor != Cursor::Done {
+            if let Err(e) = self.load(false) {
+                self.finish();
+                return Some(Err(e));
+            }
+        }
+        let (key, value) = self.front.pop_front()?;
+        // 与后端已经返回的键相遇，扫描结束
+        if self.back_last.as_ref().is_some_and(|last| key >= *last) {
+            self.finish();
+            return None;
+        }
+        self.front_last = Some(key.clone());
+        Some(Ok((key, value)))
+    }
+}
+
+impl<'a> DoubleEndedIterator for ScanIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back.is_empty() && self.back_cursor != Cursor::Done {
+            if let Err(e) = self.load(true) {
+                self.finish();
+                return Some(Err(e));
+            }
+        }
+        let (key, value) = self.back.pop_back()?;
+        if self.front_last.as_ref().is_some_and(|last| key <= *last) {
+            self.finish();
+            return None;
+        }
+        self.back_last = Some(key.clone());
+        Some(Ok((key, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn collect(iter: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        iter.collect::<Result<Vec<_>>>().unwrap()
+    }
+
+    // 很小的页面缓存，让随机测试频繁触发换出
+    crate::storage::conformance::test_engine!(|path: &Path| BTree::open_with_cache(path, 8), persistent);
+
+    #[test]
+    fn test_page_roundtrip() {
+        let leaf = Node::Leaf {
+            prev: 3,
+            next: 7,
+            entries: vec![
+                (b"a".to_vec(), Cell::Inline(b"1".to_vec())),
+                (b"b".to_vec(), Cell::Overflow { first: 9, len: 5000 }),
+            ],
+        };
+        let internal = Node::Internal { keys: vec![b"m".to_vec()], children: vec![4, 5] };
+        for node in [leaf, internal, Node::Overflow { next: 0, data: vec![1; 100] }, Node::Free { next: 2 }] {
+            let page = node.encode();
+            assert_eq!(page.len(), PAGE_SIZE);
+            assert_eq!(Node::decode(1, &page).unwrap(), node);
+        }
+        let mut page = Node::Free { next: 2 }.encode();
+        page[10] ^= 0xff;
+        assert!(Node::decode(1, &page).is_err());
+    }
+
+    #[test]
+    fn test_splits_scans_and_deletes() {
+        let dir = TempDir::new().unwrap();
+        let mut model = BTreeMap::new();
+        {
+            // 很小的缓存，覆盖脏页淘汰后写回数据文件的路径
+            let mut db = BTree::open_with_cache(dir.path(), 16).unwrap();
+            for i in 0..3000u32 {
+                let key = format!("key_{:05}", (i * 7919) % 3000).into_bytes();
+                let value = format!("value_{i}_{}", "x".repeat((i % 50) as usize)).into_bytes();
+                db.set(&key, &value).unwrap();
+                model.insert(key, value);
+            }
+            for i in (0..3000u32).filter(|i| i % 3 != 0 && i < &2500) {
+                let key = format!("key_{i:05}").into_bytes();
+                db.delete(&key).unwrap();
+                model.remove(&key);
+            }
+            let expected: Vec<_> = model.clone().into_iter().collect();
+            assert_eq!(collect(db.scan(..)), expected);
+            assert_eq!(collect(db.scan(..).rev()), expected.iter().rev().cloned().collect::<Vec<_>>());
+        }
+
+        // 不做检查点直接重新打开，从预写日志恢复
+        let db = BTree::open_with_cache(dir.path(), 16).unwrap();
+        for i in 0..3000u32 {
+            let key = format!("key_{i:05}").into_bytes();
+            assert_eq!(db.get(&key).unwrap(), model.get(&key).cloned(), "{i}");
+        }
+        let (start, end) = (b"key_00100".to_vec(), b"key_02700".to_vec());
+        let expected: Vec<_> = model.range(start.clone()..end.clone()).map(|(k, v)| (k.clone(), v.clone())).collect();
+        assert_eq!(collect(db.scan(start.clone()..end.clone())), expected);
+        assert_eq!(collect(db.scan(start..end).rev()), expected.iter().rev().cloned().collect::<Vec<_>>());
+
+        // 两端交替读取在中间相遇，不重复也不遗漏
+        let mut iter = db.scan(..);
+        let mut seen = vec![];
+        loop {
+            let Some(front) = iter.next() else { break };
+            seen.push(front.unwrap().0);
+            let Some(back) = iter.next_back() else { break };
+            seen.push(back.unwrap().0);
+        }
+        seen.sort();
+        assert_eq!(seen, model.keys().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_delete_all_shrinks_tree() {
+        let dir = TempDir::new().unwrap();
+        let mut db = BTree::open_with_cache(dir.path(), 16).unwrap();
+        let keys: Vec<Vec<u8>> = (0..2000u32).map(|i| format!("{i:08}").into_bytes()).collect();
+        for key in &keys {
+            db.set(key, &[7; 100]).unwrap();
+        }
+        let pages = db.pager.get_mut().unwrap().meta.page_count;
+        // 变空的叶子被摘除，只剩一个孩子的根节点被降级
+        for key in &keys {
+            db.delete(key).unwrap();
+        }
+        let pager = db.pager.get_mut().unwrap();
+        let root = pager.meta.root;
+        assert_eq!(*pager.read(root).unwrap(), Node::empty_leaf());
+        assert!(collect(db.scan(..)).is_empty());
+
+        // 释放的页面被重新使用
+        for key in &keys {
+            db.set(key, &[7; 100]).unwrap();
+        }
+        assert_eq!(db.pager.get_mut().unwrap().meta.page_count, pages);
+        assert_eq!(collect(db.scan(..).rev()).len(), keys.len());
+    }
+
+    #[test]
+    fn test_overflow_values_and_page_reuse() {
+        let dir = TempDir::new().unwrap();
+        let mut db = BTree::init_db_at(dir.path()).unwrap();
+        let big: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        db.set(b"big", &big).unwrap();
+        db.set(b"small", b"v").unwrap();
+        assert_eq!(db.get(b"big").unwrap().unwrap(), big);
+        assert_eq!(collect(db.scan(..))[0].1, big);
+
+        // 覆盖大值释放旧的溢出页，之后的写入复用空闲页，文件不再增长
+        db.set(b"big", &big[..10_000]).unwrap();
+        let pages = db.pager.get_mut().unwrap().meta.page_count;
+        db.set(b"big", &big).unwrap();
+        db.set(b"big", &big[..10_000]).unwrap();
+        assert_eq!(db.pager.get_mut().unwrap().meta.page_count, pages);
+        assert_eq!(db.get(b"big").unwrap().unwrap(), &big[..10_000]);
+        db.delete(b"big").unwrap();
+        assert!(db.get(b"big").unwrap().is_none());
+        assert_ne!(db.pager.get_mut().unwrap().meta.free_head, 0);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::db_error::Result;
+use crate::storage::mvcc::Version;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// 变更订阅保留的最近提交数量，也是广播通道的容量
+/// 订阅者落后超过这个数量时会丢失事件，需要从 `since` 重新拉取
+pub const CHANGE_FEED_CAPACITY: usize = 1024;
+
+/// 一次提交中单个键的变更，值为编码前的原始字节
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// 用户键（不含版本号）
+    pub key: Vec<u8>,
+    /// 提交前对其他事务可见的最新值，None 表示此前不存在或已删除
+    pub before: Option<Vec<u8>>,
+    /// 本次提交写入的值，None 表示删除
+    pub after: Option<Vec<u8>>,
+}
+
+/// 一次提交产生的变更事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitEvent {
+    /// 提交序号，按提交顺序从 1 开始递增，进程重启后重新计数
+    pub seq: u64,
+    /// 事务版本号。长事务版本号小但可能提交得晚，顺序以 seq 为准
+    pub version: Version,
+    /// 提交时间（毫秒级 Unix 时间戳）
+    pub commit_time: u64,
+    /// 按键排序的变更
+    pub changes: Vec<Change>,
+}
+
+/// 变更数据捕获（CDC）：按提交顺序发布已提交事务的写入
+///
+/// 事件只保存在内存中：实时订阅通过广播通道接收，轮询通过最近 [`CHANGE_FEED_CAPACITY`]
+/// 个提交的环形缓冲区按序号续读。发布发生在提交持有的引擎写锁内，因此序号顺序与提交顺序一致。
+pub struct ChangeFeed {
+    sender: broadcast::Sender<Arc<CommitEvent>>,
+    state: Mutex<FeedState>,
+}
+
+struct FeedState {
+    // 上一个发布的提交序号
+    last_seq: u64,
+    // 最近的提交，按序号升序
+    recent: VecDeque<Arc<CommitEvent>>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANGE_FEED_CAPACITY).0,
+            state: Mutex::new(FeedState { last_seq: 0, recent: VecDeque::with_capacity(CHANGE_FEED_CAPACITY) }),
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// 发布一次提交，返回分配的提交序号
+    pub(crate) fn publish(&self, version: Version, commit_time: u64, changes: Vec<Change>) -> Result<u64> {
+        let mut state = self.state.lock()?;
+        state.last_seq += 1;
+        let event = Arc::new(CommitEvent { seq: state.last_seq, version, commit_time, changes });
+        if state.recent.len() == CHANGE_FEED_CAPACITY {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(event.clone());
+        // 没有订阅者时发送失败，事件仍保留在环形缓冲区中
+        let _ = self.sender.send(event);
+        Ok(state.last_seq)
+    }
+
+    /// 订阅之后的提交
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<CommitEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// 最近一次提交的序号，还没有提交时为 0
+    pub fn last_seq(&self) -> Result<u64> {
+        Ok(self.state.lock()?.last_seq)
+    }
+
+    /// 序号大于 seq 的提交，只能取到仍在环形缓冲区中的部分
+    pub fn since(&self, seq: u64) -> Result<Vec<Arc<CommitEvent>>> {
+        let state = self.state.lock()?;
+        Ok(state.recent.iter().filter(|event| event.seq > seq).cloned().collect())
+    }
+
+    /// 长轮询：返回序号大于 seq 的提交；暂时没有时最多等待 timeout，超时返回空
+    pub async fn wait_since(&self, seq: u64, timeout: Duration) -> Result<Vec<Arc<CommitEvent>>> {
+        // 先订阅再检查缓冲区，避免两步之间发布的提交被漏掉
+        let mut receiver = self.subscribe();
+        let events = self.since(seq)?;
+        if !events.is_empty() {
+            return Ok(events);
+        }
+        let wait = async {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.seq > seq => return,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        };
+        let _ = tokio::time::timeout(timeout, wait).await;
+        self.since(seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(key: &[u8]) -> Vec<Change> {
+        vec![Change { key: key.to_vec(), before: None, after: Some(b"v".to_vec()) }]
+    }
+
+    #[test]
+    fn test_since_and_capacity() -> Result<()> {
+        let feed = ChangeFeed::default();
+        assert_eq!(feed.publish(1, 0, change(b"a"))?, 1);
+        assert_eq!(feed.publish(3, 0, change(b"b"))?, 2);
+        assert_eq!(feed.since(1)?.iter().map(|e| e.version).collect::<Vec<_>>(), vec![3]);
+
+        for i in 0..CHANGE_FEED_CAPACITY as u64 {
+            feed.publish(10 + i, 0, change(b"c"))?;
+        }
+        let events = feed.since(0)?;
+        assert_eq!(events.len(), CHANGE_FEED_CAPACITY);
+        assert_eq!(events[0].seq, 3);
+        assert_eq!(feed.last_seq()?, CHANGE_FEED_CAPACITY as u64 + 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_since() -> Result<()> {
+        let feed = Arc::new(ChangeFeed::default());
+        assert!(feed.wait_since(0, Duration::from_millis(10)).await?.is_empty());
+
+        let mut receiver = feed.subscribe();
+        let publisher = feed.clone();
+        let waiter = tokio::spawn({
+            let feed = feed.clone();
+            async move { feed.wait_since(0, Duration::from_secs(5)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        publisher.publish(7, 0, change(b"a"))?;
+        let events = waiter.await.unwrap()?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(receiver.recv().await.unwrap().version, 7);
+        Ok(())
+    }
+}
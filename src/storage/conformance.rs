@@ -0,0 +1,491 @@
+//! 存储引擎一致性测试
+//!
+//! 每个 `Engine` 实现都要在自己的测试模块里调用 `test_engine!`，生成同一套用例：
+//! 基本读写、范围扫描、前缀扫描、批量操作、清空、状态统计，以及以 `Memory` 为参照的随机操作对比。
+//! 持久化引擎再加上 `persistent` 参数，额外验证重新打开后数据不变。
+//!
+//! 约定：值不能为空。BitCask 在磁盘上用空值表示墓碑，空值重新打开后会消失，
+//! 上层写入的值总是序列化后的非空字节串。
+
+use crate::db_error::Result;
+use crate::storage::engine::{Engine, WriteBatch};
+use crate::storage::Memory;
+use std::ops::Bound;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// 生成一致性测试用例
+///
+/// `$open` 是 `Fn(&Path) -> Result<E>`，在给定目录下打开（或重新打开）引擎
+macro_rules! test_engine {
+    ($open:expr) => {
+        mod conformance {
+            use super::*;
+            use $crate::storage::conformance as suite;
+
+            #[test]
+            fn point_ops() -> $crate::db_error::Result<()> {
+                suite::point_ops($open)
+            }
+
+            #[test]
+            fn large_values() -> $crate::db_error::Result<()> {
+                suite::large_values($open)
+            }
+
+            #[test]
+            fn scan_ranges() -> $crate::db_error::Result<()> {
+                suite::scan_ranges($open)
+            }
+
+            #[test]
+            fn scan_both_ends() -> $crate::db_error::Result<()> {
+                suite::scan_both_ends($open)
+            }
+
+            #[test]
+            fn scan_prefix() -> $crate::db_error::Result<()> {
+                suite::scan_prefix($open)
+            }
+
+            #[test]
+            fn batch_ops() -> $crate::db_error::Result<()> {
+                suite::batch_ops($open)
+            }
+
+            #[test]
+            fn clear() -> $crate::db_error::Result<()> {
+                suite::clear($open)
+            }
+
+            #[test]
+            fn status_counts() -> $crate::db_error::Result<()> {
+                suite::status_counts($open)
+            }
+
+            #[test]
+            fn random_ops() -> $crate::db_error::Result<()> {
+                for seed in 1..=4 {
+                    suite::random_ops($open, seed, 2000, false)?;
+                }
+                Ok(())
+            }
+        }
+    };
+    ($open:expr, persistent) => {
+        $crate::storage::conformance::test_engine!($open);
+
+        mod persistence {
+            use super::*;
+            use $crate::storage::conformance as suite;
+
+            #[test]
+            fn reopen() -> $crate::db_error::Result<()> {
+                suite::reopen($open)
+            }
+
+            #[test]
+            fn random_ops_with_reopen() -> $crate::db_error::Result<()> {
+                for seed in 1..=4 {
+                    suite::random_ops($open, seed, 2000, true)?;
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use test_engine;
+
+/// 打开一个位于临时目录中的引擎，目录随返回值一起释放
+fn open_temp<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<(TempDir, E)> {
+    let dir = TempDir::new()?;
+    let engine = open(dir.path())?;
+    Ok((dir, engine))
+}
+
+fn collect(iter: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    iter.collect()
+}
+
+fn pairs(items: &[(&[u8], &[u8])]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    items.iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect()
+}
+
+/// 单键读写、覆盖、删除，以及包含 0x00 / 0xff 的二进制键
+pub(crate) fn point_ops<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    assert_eq!(engine.get(b"a")?, None);
+    assert!(!engine.exists(b"a")?);
+    // 删除不存在的键不是错误
+    engine.delete(b"a")?;
+
+    engine.set(b"a", b"1")?;
+    assert_eq!(engine.get(b"a")?, Some(b"1".to_vec()));
+    assert!(engine.exists(b"a")?);
+    engine.set(b"a", b"2")?;
+    assert_eq!(engine.get(b"a")?, Some(b"2".to_vec()));
+
+    engine.delete(b"a")?;
+    assert_eq!(engine.get(b"a")?, None);
+    assert!(!engine.exists(b"a")?);
+    // 删除后可以重新写入
+    engine.set(b"a", b"3")?;
+    assert_eq!(engine.get(b"a")?, Some(b"3".to_vec()));
+
+    for key in [&[0x00][..], &[0x00, 0x00], &[0xff], &[0xff, 0xff, 0x00], b"a\0b"] {
+        engine.set(key, key)?;
+    }
+    for key in [&[0x00][..], &[0x00, 0x00], &[0xff], &[0xff, 0xff, 0x00], b"a\0b"] {
+        assert_eq!(engine.get(key)?, Some(key.to_vec()));
+    }
+    // 键只匹配完整字节串，前缀或扩展都不算
+    assert_eq!(engine.get(&[0xff, 0xff])?, None);
+    assert_eq!(engine.get(b"a\0")?, None);
+    Ok(())
+}
+
+/// 远大于页面 / 数据块的值
+pub(crate) fn large_values<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    engine.set(b"big", &big)?;
+    engine.set(b"small", b"x")?;
+    assert_eq!(engine.get(b"big")?, Some(big.clone()));
+
+    let smaller = big[..5000].to_vec();
+    engine.set(b"big", &smaller)?;
+    assert_eq!(engine.get(b"big")?, Some(smaller.clone()));
+    assert_eq!(collect(engine.scan(..))?, vec![(b"big".to_vec(), smaller), (b"small".to_vec(), b"x".to_vec())]);
+
+    engine.delete(b"big")?;
+    assert_eq!(engine.get(b"big")?, None);
+    assert_eq!(engine.get(b"small")?, Some(b"x".to_vec()));
+    Ok(())
+}
+
+/// 各种边界组合的范围扫描，正向和反向
+pub(crate) fn scan_ranges<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    engine.set(b"b", b"2")?;
+    engine.set(b"a", b"1")?;
+    engine.set(b"d", b"4")?;
+    engine.set(b"c", b"3")?;
+    engine.set(b"e", b"5")?;
+    engine.delete(b"c")?;
+    engine.set(b"ba", b"21")?;
+
+    let all = pairs(&[(b"a", b"1"), (b"b", b"2"), (b"ba", b"21"), (b"d", b"4"), (b"e", b"5")]);
+    assert_eq!(collect(engine.scan(..))?, all);
+    assert_eq!(collect(engine.scan(..).rev())?, all.iter().rev().cloned().collect::<Vec<_>>());
+
+    assert_eq!(collect(engine.scan(b"b".to_vec()..b"d".to_vec()))?, all[1..3]);
+    assert_eq!(collect(engine.scan(b"b".to_vec()..=b"d".to_vec()))?, all[1..4]);
+    assert_eq!(collect(engine.scan(b"bb".to_vec()..))?, all[3..]);
+    assert_eq!(collect(engine.scan(..b"b".to_vec()))?, all[..1]);
+    assert_eq!(collect(engine.scan(..=b"b".to_vec()))?, all[..2]);
+    assert_eq!(collect(engine.scan((Bound::Excluded(b"b".to_vec()), Bound::Included(b"e".to_vec()))))?, all[2..]);
+    assert_eq!(
+        collect(engine.scan((Bound::Excluded(b"a".to_vec()), Bound::Excluded(b"e".to_vec()))).rev())?,
+        all[1..4].iter().rev().cloned().collect::<Vec<_>>()
+    );
+
+    // 空范围
+    assert_eq!(collect(engine.scan(b"c".to_vec()..b"c".to_vec()))?, vec![]);
+    assert_eq!(collect(engine.scan(b"c".to_vec()..=b"c".to_vec()))?, vec![]);
+    assert_eq!(collect(engine.scan(b"f".to_vec()..))?, vec![]);
+    assert_eq!(collect(engine.scan(..b"a".to_vec()).rev())?, vec![]);
+    Ok(())
+}
+
+/// 从两端交替读取同一个迭代器，两端相遇时结束且不重复
+pub(crate) fn scan_both_ends<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    let mut expected = Vec::new();
+    for i in 0..300u32 {
+        let key = format!("key{i:04}").into_bytes();
+        let value = format!("value{i}").repeat(i as usize % 7 + 1).into_bytes();
+        engine.set(&key, &value)?;
+        expected.push((key, value));
+    }
+
+    for take_front in [1, 2, 3] {
+        let mut iter = engine.scan(..);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        loop {
+            let mut done = false;
+            for _ in 0..take_front {
+                match iter.next() {
+                    Some(item) => front.push(item?),
+                    None => done = true,
+                }
+            }
+            match iter.next_back() {
+                Some(item) => back.push(item?),
+                None => done = true,
+            }
+            if done {
+                break;
+            }
+        }
+        // 迭代结束后保持结束
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected, "take_front={take_front}");
+    }
+    Ok(())
+}
+
+/// 前缀扫描，包括以 0xff 结尾、没有后继前缀的情况
+pub(crate) fn scan_prefix<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    for key in [&b"a"[..], b"ab", b"abc", b"abd", b"ac", b"b", &[b'a', 0xff], &[b'a', 0xff, 0x01], &[0xff, 0xff], &[0xff]] {
+        engine.set(key, b"v")?;
+    }
+    let keys = |prefix: &[u8]| -> Result<Vec<Vec<u8>>> {
+        engine.scan_prefix(prefix).map(|item| item.map(|(key, _)| key)).collect()
+    };
+    assert_eq!(keys(b"ab")?, vec![b"ab".to_vec(), b"abc".to_vec(), b"abd".to_vec()]);
+    assert_eq!(keys(b"abc")?, vec![b"abc".to_vec()]);
+    assert_eq!(keys(b"abz")?, Vec::<Vec<u8>>::new());
+    assert_eq!(keys(&[b'a', 0xff])?, vec![vec![b'a', 0xff], vec![b'a', 0xff, 0x01]]);
+    assert_eq!(keys(&[0xff])?, vec![vec![0xff], vec![0xff, 0xff]]);
+    assert_eq!(keys(b"")?.len(), 10);
+
+    let rev: Vec<_> = engine.scan_prefix(b"a").rev().map(|item| item.map(|(key, _)| key)).collect::<Result<_>>()?;
+    assert_eq!(rev.first(), Some(&vec![b'a', 0xff, 0x01]));
+    assert_eq!(rev.last(), Some(&b"a".to_vec()));
+    assert_eq!(rev.len(), 7);
+    Ok(())
+}
+
+/// write_batch 按顺序生效，batch_set / batch_get 与单条操作一致
+pub(crate) fn batch_ops<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    engine.set(b"gone", b"x")?;
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"k1", b"v1").put(b"k2", b"v2").delete(b"gone").put(b"k1", b"v1b").delete(b"k2").put(b"k3", b"v3");
+    engine.write_batch(batch)?;
+    assert_eq!(collect(engine.scan(..))?, pairs(&[(b"k1", b"v1b"), (b"k3", b"v3")]));
+
+    // 空批次什么也不做
+    engine.write_batch(WriteBatch::new())?;
+    assert_eq!(collect(engine.scan(..))?.len(), 2);
+
+    engine.batch_set(vec![(b"k2", b"w2"), (b"k4", b"w4")])?;
+    assert_eq!(
+        engine.batch_get(vec![b"k1", b"k2", b"gone", b"k4"])?,
+        vec![Some(b"v1b".to_vec()), Some(b"w2".to_vec()), None, Some(b"w4".to_vec())]
+    );
+    Ok(())
+}
+
+/// 清空后没有任何数据，并且可以继续写入
+pub(crate) fn clear<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    for i in 0..100u32 {
+        engine.set(&i.to_be_bytes(), b"value")?;
+    }
+    engine.delete(&7u32.to_be_bytes())?;
+    engine.clear()?;
+    assert_eq!(collect(engine.scan(..))?, vec![]);
+    assert_eq!(engine.get(&1u32.to_be_bytes())?, None);
+    assert_eq!(engine.status()?.total_count, 0);
+
+    engine.set(b"after", b"clear")?;
+    assert_eq!(collect(engine.scan(..))?, pairs(&[(b"after", b"clear")]));
+    Ok(())
+}
+
+/// 状态中的键数量和逻辑大小只统计存活数据
+pub(crate) fn status_counts<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let (_dir, mut engine) = open_temp(open)?;
+    engine.set(b"a", b"11")?;
+    engine.set(b"bb", b"2")?;
+    engine.set(b"a", b"111")?;
+    engine.set(b"ccc", b"3")?;
+    engine.delete(b"ccc")?;
+    let status = engine.status()?;
+    assert_eq!(status.total_count, 2);
+    assert_eq!(status.logical_size, (1 + 3) + (2 + 1));
+    assert_eq!(status.live_size + status.garbage_size, status.total_size);
+
+    // 页面塞满较大的值并刷盘后，存活大小不能超过文件大小
+    for i in 0..200u32 {
+        engine.set(&i.to_be_bytes(), &[0xab; 900])?;
+    }
+    engine.flush()?;
+    let status = engine.status()?;
+    assert_eq!(status.total_count, 202);
+    assert_eq!(status.logical_size, (1 + 3) + (2 + 1) + 200 * (4 + 900));
+    assert_eq!(status.live_size + status.garbage_size, status.total_size);
+    Ok(())
+}
+
+/// 刷盘后重新打开，写入、覆盖和删除都保持不变
+pub(crate) fn reopen<E: Engine>(open: impl Fn(&Path) -> Result<E>) -> Result<()> {
+    let dir = TempDir::new()?;
+    let mut engine = open(dir.path())?;
+    engine.set(b"a", b"1")?;
+    engine.set(b"b", b"2")?;
+    engine.set(b"a", b"1b")?;
+    engine.delete(b"b")?;
+    let mut batch = WriteBatch::new();
+    batch.put(b"c", b"3").delete(b"a");
+    engine.write_batch(batch)?;
+    engine.set(b"d", &vec![7; 50_000])?;
+    engine.flush()?;
+    drop(engine);
+
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.get(b"a")?, None);
+    assert_eq!(engine.get(b"b")?, None);
+    assert_eq!(engine.get(b"c")?, Some(b"3".to_vec()));
+    assert_eq!(collect(engine.scan(..))?, vec![(b"c".to_vec(), b"3".to_vec()), (b"d".to_vec(), vec![7; 50_000])]);
+
+    // 重新打开后继续写入，再次打开仍然正确
+    engine.set(b"a", b"again")?;
+    engine.clear()?;
+    engine.set(b"z", b"26")?;
+    engine.flush()?;
+    drop(engine);
+    let engine = open(dir.path())?;
+    assert_eq!(collect(engine.scan(..))?, pairs(&[(b"z", b"26")]));
+    Ok(())
+}
+
+/// 测试用的确定性伪随机数生成器（xorshift64*），失败时可以用种子复现
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// [0, n) 中的随机数
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// 从较小的键空间中取键，保证覆盖和删除足够频繁
+    fn key(&mut self) -> Vec<u8> {
+        let id = self.below(200) as u8;
+        match id % 4 {
+            0 => vec![id],
+            1 => vec![id, 0x00],
+            2 => vec![0xff, id],
+            _ => format!("key{id:03}").into_bytes(),
+        }
+    }
+
+    /// 非空的值，偶尔很大
+    fn value(&mut self) -> Vec<u8> {
+        let len = if self.below(50) == 0 { 2000 + self.below(6000) } else { 1 + self.below(100) };
+        let fill = self.next() as u8;
+        (0..len).map(|i| fill.wrapping_add(i as u8)).collect()
+    }
+
+    fn bound(&mut self) -> Bound<Vec<u8>> {
+        match self.below(3) {
+            0 => Bound::Unbounded,
+            1 => Bound::Included(self.key()),
+            _ => Bound::Excluded(self.key()),
+        }
+    }
+
+    /// 随机范围，起点不大于终点（和 `BTreeMap::range` 的要求一致）
+    fn range(&mut self) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let (mut start, mut end) = (self.bound(), self.bound());
+        if let (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) = (&start, &end) {
+            if s > e {
+                std::mem::swap(&mut start, &mut end);
+            } else if s == e {
+                start = Bound::Included(s.clone());
+            }
+        }
+        (start, end)
+    }
+}
+
+/// 随机执行写入、删除、批量写、读取、扫描和刷盘，每一步都与 `Memory` 的结果比较
+///
+/// `reopen` 为 true 时还会随机关闭并重新打开引擎
+pub(crate) fn random_ops<E: Engine>(
+    open: impl Fn(&Path) -> Result<E>,
+    seed: u64,
+    steps: usize,
+    reopen: bool,
+) -> Result<()> {
+    let dir = TempDir::new()?;
+    let mut engine = open(dir.path())?;
+    let mut model = Memory::default();
+    let mut rng = Rng::new(seed);
+
+    for step in 0..steps {
+        let context = format!("seed={seed} step={step}");
+        match rng.below(100) {
+            0..=39 => {
+                let (key, value) = (rng.key(), rng.value());
+                engine.set(&key, &value)?;
+                model.set(&key, &value)?;
+            }
+            40..=54 => {
+                let key = rng.key();
+                engine.delete(&key)?;
+                model.delete(&key)?;
+            }
+            55..=59 => {
+                let mut batch = WriteBatch::new();
+                for _ in 0..=rng.below(8) {
+                    if rng.below(3) == 0 {
+                        batch.delete(&rng.key());
+                    } else {
+                        batch.put(&rng.key(), &rng.value());
+                    }
+                }
+                engine.write_batch(batch.clone())?;
+                model.write_batch(batch)?;
+            }
+            60..=79 => {
+                let key = rng.key();
+                assert_eq!(engine.get(&key)?, model.get(&key)?, "{context} get {key:?}");
+            }
+            80..=91 => {
+                let range = rng.range();
+                let expected = collect(model.scan(range.clone()))?;
+                if rng.below(2) == 0 {
+                    assert_eq!(collect(engine.scan(range))?, expected, "{context} scan");
+                } else {
+                    let reversed: Vec<_> = expected.into_iter().rev().collect();
+                    assert_eq!(collect(engine.scan(range).rev())?, reversed, "{context} reverse scan");
+                }
+            }
+            92..=95 => {
+                let prefix = rng.key()[..1].to_vec();
+                assert_eq!(collect(engine.scan_prefix(&prefix))?, collect(model.scan_prefix(&prefix))?, "{context} prefix");
+            }
+            96..=97 => engine.flush()?,
+            _ => {
+                if reopen {
+                    engine.flush()?;
+                    drop(engine);
+                    engine = open(dir.path())?;
+                }
+            }
+        }
+    }
+
+    assert_eq!(collect(engine.scan(..))?, collect(model.scan(..))?, "seed={seed} final scan");
+    assert_eq!(engine.status()?.total_count, model.status()?.total_count, "seed={seed} final count");
+    Ok(())
+}
@@ -1,9 +1,13 @@
 use crate::db_error::Result;
+use crate::errinput;
 use crate::utils::key_coder::prefix_range;
+use std::path::Path;
+use std::time::Duration;
 
 /// Engine trait
 /// 定义存储引擎的通用行为
-pub trait Engine: Send {
+/// 只读操作（get / scan / status）只需要 `&self`，上层可以用读写锁让多个读者并行
+pub trait Engine: Send + Sync {
     //定义一个迭代器类型
     type ScanIter<'a>: ScanIter + 'a
     where
@@ -11,6 +15,13 @@ pub trait Engine: Send {
     // 为特定键值Key,设置一个值Value,替代原本已有的值
     fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
 
+    // 写入一个 ttl 之后过期的键值，过期的键与删除的键相同，之后重新写入会清除过期时间
+    // 默认实现返回"不支持"
+    fn set_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        let _ = (key, value, ttl);
+        errinput!("TTL is not supported by this storage engine")
+    }
+
     // 为特定键值Key,获取一个值Value+
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
@@ -21,20 +32,33 @@ pub trait Engine: Send {
     fn flush(&mut self) -> Result<()>;
 
     // 检查键是否存在
-    fn exists(&mut self, key: &[u8]) -> Result<bool> {
+    fn exists(&self, key: &[u8]) -> Result<bool> {
         Ok(self.get(key)?.is_some())
     }
 
     // 批量设置键值对
     fn batch_set(&mut self, pairs: Vec<(&[u8], &[u8])>) -> Result<()> {
+        let mut batch = WriteBatch::new();
         for (key, value) in pairs {
-            self.set(key, &value)?;
+            batch.put(key, value);
+        }
+        self.write_batch(batch)
+    }
+
+    // 原子地写入一批操作：要么全部生效，要么（例如写到一半时崩溃）全部不生效
+    // 默认实现逐条执行，只适用于本身不会写到一半失败的引擎（如内存引擎）
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for op in batch {
+            match op {
+                WriteOp::Put(key, value) => self.set(&key, &value)?,
+                WriteOp::Delete(key) => self.delete(&key)?,
+            }
         }
         Ok(())
     }
 
     // 批量获取键值对
-    fn batch_get(&mut self, keys: Vec<&[u8]>) -> Result<Vec<Option<Vec<u8>>>> {
+    fn batch_get(&self, keys: Vec<&[u8]>) -> Result<Vec<Option<Vec<u8>>>> {
         let mut results = Vec::with_capacity(keys.len());
         for key in keys {
             results.push(self.get(key)?);
@@ -44,15 +68,23 @@ pub trait Engine: Send {
 
     // 扫描指定范围的键值对
     // 由于Btree本身是基于key排序的，所以需要指定开始和结束的键值范围
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_>
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_>
     where
         Self: Sized;
 
     // 清空所有数据
     fn clear(&mut self) -> Result<()>;
 
-    fn status(&mut self) -> Result<EngineStatus>;
-    fn scan_prefix(&mut self, prefix: &[u8]) -> Self::ScanIter<'_>
+    fn status(&self) -> Result<EngineStatus>;
+
+    // 在 dest 目录下生成当前数据的一致副本，副本本身就是一个可以直接打开的数据目录
+    // dest 必须不存在或为空目录；调用期间不会有写入（写入需要 &mut self）
+    fn checkpoint(&self, dest: &Path) -> Result<()> {
+        let _ = dest;
+        errinput!("checkpoint is not supported by this storage engine")
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Self::ScanIter<'_>
     where
         Self: Sized,
     {
@@ -60,6 +92,60 @@ pub trait Engine: Send {
     }
 }
 
+/// 批量写入中的单个操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteOp {
+    /// 写入键值
+    Put(Vec<u8>, Vec<u8>),
+    /// 删除键
+    Delete(Vec<u8>),
+}
+
+/// 一组需要原子写入的操作，按加入顺序执行
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    ops: Vec<WriteOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个写入操作
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(WriteOp::Put(key.to_vec(), value.to_vec()));
+        self
+    }
+
+    /// 追加一个删除操作
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.ops.push(WriteOp::Delete(key.to_vec()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, WriteOp> {
+        self.ops.iter()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = WriteOp;
+    type IntoIter = std::vec::IntoIter<WriteOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
 /// ScanIterator是一个用于遍历存储引擎中键值对的迭代器接口。
 /// 它的设计强调了灵活性和错误处理能力，适合在需要双向遍历和处理潜在错误的场景中使用。
 /// 通过继承DoubleEndedIterator，它为存储引擎的实现提供了一个强大的工具，用于高效地扫描和处理数据。
@@ -84,6 +170,38 @@ pub struct EngineStatus {
     pub live_size: u64,
     /// 垃圾数据占用的磁盘/内存空间
     pub garbage_size: u64,
+    /// 内存索引（如 BitCask 的 KeyDir）占用的估算字节数
+    pub index_memory: u64,
+    /// 每个数据文件的状态，内存引擎为空
+    pub files: Vec<FileStatus>,
+}
+
+/// 单个数据文件的状态，用于决定压缩哪些文件
+#[derive(Debug, Clone)]
+pub struct FileStatus {
+    /// 文件名
+    pub name: String,
+    /// 文件总大小
+    pub total_size: u64,
+    /// 存活数据大小
+    pub live_size: u64,
+    /// 存活键的数量
+    pub live_count: u64,
+}
+
+impl FileStatus {
+    /// 垃圾数据大小
+    pub fn garbage_size(&self) -> u64 {
+        self.total_size.saturating_sub(self.live_size)
+    }
+
+    /// 垃圾数据占文件大小的百分比
+    pub fn garbage_rate(&self) -> f64 {
+        if self.total_size == 0 {
+            return 0.0;
+        }
+        self.garbage_size() as f64 / self.total_size as f64 * 100.0
+    }
 }
 
 impl EngineStatus {
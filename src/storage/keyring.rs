@@ -0,0 +1,198 @@
+//! 静态加密使用的密钥环
+//!
+//! 密钥文件每行一个密钥：`<编号> <64 位十六进制的 256 位密钥>`，空行和 `#` 开头的行忽略，
+//! 最后一行是当前密钥，新写入的条目都用它加密；其余的旧密钥只用于读取。
+//! 轮换密钥时在文件末尾追加新密钥，压缩（见 [`crate::storage::BitCask::rotate_keys`]）之后
+//! 所有存活数据都以新密钥重写，旧密钥即可从文件中删除。
+//!
+//! 每段密文的格式：
+//! ```text
+//! --------|-------|--------------------|
+//!  key_id | nonce | ciphertext + tag   |
+//! --------|-------|--------------------|
+//!  4      | 24    | len + 16           |
+//! --------|-------|--------------------|
+//! ```
+//! 使用 XChaCha20-Poly1305，nonce 随机生成，同一个密钥下加密大量条目也不会重复。
+
+use crate::cfg::get_encryption_keyfile;
+use crate::db_error::Result;
+use crate::{errdata, errinput};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+
+/// 密钥编号的长度
+const KEY_ID_LEN: usize = 4;
+/// nonce 的长度
+const NONCE_LEN: usize = 24;
+/// 认证标签的长度
+const TAG_LEN: usize = 16;
+/// 每段密文比明文多出的字节数
+pub const SEAL_OVERHEAD: usize = KEY_ID_LEN + NONCE_LEN + TAG_LEN;
+
+/// 密钥环：编号 → 密钥，以及当前用于加密的密钥编号
+pub struct Keyring {
+    ciphers: BTreeMap<u32, XChaCha20Poly1305>,
+    current: u32,
+}
+
+/// 不输出密钥本身
+impl Debug for Keyring {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("keys", &self.ciphers.keys().collect::<Vec<_>>())
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// 按配置中的 encryption_keyfile 加载密钥环，未配置时返回 None（不加密）
+    pub fn from_config() -> Result<Option<Self>> {
+        get_encryption_keyfile()
+            .map(|path| Self::load(&path))
+            .transpose()
+    }
+
+    /// 从密钥文件加载
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .or_else(|e| errinput!("failed to read encryption keyfile {}: {e}", path.display()))?;
+        Self::parse(&text)
+            .or_else(|e| errinput!("invalid encryption keyfile {}: {e}", path.display()))
+    }
+
+    /// 解析密钥文件内容
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut ciphers = BTreeMap::new();
+        let mut current = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_no = n + 1;
+            let Some((id, key)) = line.split_once(char::is_whitespace) else {
+                return errinput!("line {line_no}: expected `<id> <hex key>`");
+            };
+            let id: u32 = id
+                .parse()
+                .or_else(|_| errinput!("line {line_no}: invalid key id {id}"))?;
+            let key = hex::decode(key.trim())
+                .or_else(|_| errinput!("line {line_no}: key is not valid hex"))?;
+            if key.len() != 32 {
+                return errinput!("line {line_no}: key must be 32 bytes, got {}", key.len());
+            }
+            let cipher = XChaCha20Poly1305::new_from_slice(&key)
+                .or_else(|_| errinput!("line {line_no}: invalid key"))?;
+            if ciphers.insert(id, cipher).is_some() {
+                return errinput!("line {line_no}: duplicate key id {id}");
+            }
+            current = Some(id);
+        }
+        match current {
+            Some(current) => Ok(Self { ciphers, current }),
+            None => errinput!("no keys found"),
+        }
+    }
+
+    /// 当前用于加密的密钥编号
+    pub fn current_id(&self) -> u32 {
+        self.current
+    }
+
+    /// 用当前密钥加密，`aad` 参与认证但不写入密文
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.ciphers[&self.current]
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .or_else(|_| errdata!("encryption failed"))?;
+        Ok([
+            self.current.to_be_bytes().as_slice(),
+            nonce.as_slice(),
+            &ciphertext,
+        ]
+        .concat())
+    }
+
+    /// 解密 [`Keyring::seal`] 的结果，密钥编号不在密钥环中或认证失败时返回错误
+    pub fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return errdata!("encrypted data too short: {} bytes", sealed.len());
+        }
+        let (id, rest) = sealed.split_at(KEY_ID_LEN);
+        let id = u32::from_be_bytes(id.try_into()?);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let Some(cipher) = self.ciphers.get(&id) else {
+            return errdata!("encryption key {id} not found in keyring");
+        };
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .or_else(|_| errdata!("decryption with key {id} failed: wrong key or corrupted data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: &str =
+        "# old key\n1 0000000000000000000000000000000000000000000000000000000000000001\n\n\
+                        2 0000000000000000000000000000000000000000000000000000000000000002\n";
+
+    #[test]
+    fn test_seal_and_open() -> Result<()> {
+        let keyring = Keyring::parse(KEYS)?;
+        assert_eq!(keyring.current_id(), 2);
+        let sealed = keyring.seal(b"secret", b"key")?;
+        assert_eq!(sealed.len(), 6 + SEAL_OVERHEAD);
+        assert_eq!(&sealed[..4], &2u32.to_be_bytes());
+        assert_eq!(keyring.open(&sealed, b"key")?, b"secret");
+        // nonce 随机，相同明文的密文不同
+        assert_ne!(keyring.seal(b"secret", b"key")?, sealed);
+        // aad 不同或密文被篡改时认证失败
+        assert!(keyring.open(&sealed, b"other").is_err());
+        let mut corrupt = sealed.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(keyring.open(&corrupt, b"key").is_err());
+
+        // 只有旧密钥的密钥环无法解密新密钥加密的数据
+        let old = Keyring::parse(KEYS.lines().take(2).collect::<Vec<_>>().join("\n").as_str())?;
+        assert_eq!(old.current_id(), 1);
+        assert!(old.open(&sealed, b"key").is_err());
+        assert_eq!(keyring.open(&old.seal(b"v", b"")?, b"")?, b"v");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("1 abcd").is_err());
+        assert!(Keyring::parse(
+            "x 0000000000000000000000000000000000000000000000000000000000000001"
+        )
+        .is_err());
+        assert!(Keyring::parse(
+            "1 zz00000000000000000000000000000000000000000000000000000000000001"
+        )
+        .is_err());
+        let duplicate =
+            "1 0000000000000000000000000000000000000000000000000000000000000001\n".repeat(2);
+        assert!(Keyring::parse(&duplicate).is_err());
+    }
+}
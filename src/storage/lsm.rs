@@ -0,0 +1,1286 @@
+use crate::cfg::get_db_base;
+use crate::db_error::Result;
+use crate::errdata;
+use crate::storage::bitcask::{read_exact_at, DirLock};
+use crate::storage::engine::{Engine, EngineStatus, FileStatus, WriteBatch, WriteOp};
+use crate::storage::util::{after_end, before_start, KeyRange, Reader};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+/// 预写日志文件名，记录还没有落盘到 SSTable 的写入
+const WAL_FILE: &str = "wal.log";
+/// 清单文件名，记录每一层包含哪些 SSTable
+const MANIFEST_FILE: &str = "MANIFEST";
+/// SSTable 文件扩展名
+const SST_EXTENSION: &str = "sst";
+/// SSTable 文件尾的魔数，用来识别文件格式
+const SST_MAGIC: u64 = 0x6d69_6e69_6c73_6d31;
+/// SSTable 文件尾长度：索引位置 8 + 布隆过滤器位置 8 + 条目数 8 + 元数据 crc 4 + 魔数 8
+const FOOTER_LEN: u64 = 36;
+/// 条目中表示墓碑的值长度
+const TOMBSTONE: u32 = u32::MAX;
+/// 最多的层数，最后一层不再向下压缩
+const MAX_LEVELS: usize = 7;
+/// 每个键在布隆过滤器中占用的位数，误判率约 1%
+const BLOOM_BITS_PER_KEY: usize = 10;
+
+/// 内存表中的条目，值为 None 表示墓碑
+type Entry = (Vec<u8>, Option<Vec<u8>>);
+
+/// LSM 树的可调参数
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    /// 内存表超过这个大小后落盘为 L0 的 SSTable
+    pub memtable_limit: usize,
+    /// SSTable 数据块的目标大小
+    pub block_size: usize,
+    /// L0 的 SSTable 数量达到这个值时触发压缩
+    pub l0_compaction_trigger: usize,
+    /// L1 的大小上限，之后每层放大 `level_size_multiplier` 倍
+    pub level_base_size: u64,
+    /// 相邻两层大小上限的倍数
+    pub level_size_multiplier: u64,
+    /// 压缩输出的单个 SSTable 的目标大小
+    pub target_file_size: u64,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self {
+            memtable_limit: 4 * 1024 * 1024,
+            block_size: 4 * 1024,
+            l0_compaction_trigger: 4,
+            level_base_size: 10 * 1024 * 1024,
+            level_size_multiplier: 10,
+            target_file_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// LSM 树存储引擎
+/// 成员：
+/// 内存表 - 最新的写入，按键排序，删除记为墓碑
+/// 预写日志 - 内存表的持久化副本，重启时重放
+/// 分层的 SSTable - L0 由内存表直接落盘，文件之间可能重叠；L1 及以下每层内的文件互不重叠
+///
+/// 与 BitCask 不同，内存中只保留内存表和每个 SSTable 的块索引与布隆过滤器，
+/// 键的数量不再受内存限制
+#[derive(Debug)]
+pub struct Lsm {
+    dir: PathBuf,
+    options: LsmOptions,
+    memtable: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// 内存表中键值的估算大小
+    memtable_size: usize,
+    wal: fs::File,
+    /// levels[0] 按落盘顺序从旧到新排列；其余层按最小键排序
+    levels: Vec<Vec<Table>>,
+    /// 每层下一次压缩从哪个键之后开始挑选文件，轮转地压缩整层
+    compact_pointer: Vec<Vec<u8>>,
+    next_file_id: u64,
+    /// 数据目录锁，随实例一起释放
+    _lock: DirLock,
+}
+
+impl Lsm {
+    /// 在配置的存储路径下打开数据库
+    pub fn init_db() -> Result<Self> {
+        Self::init_db_at(Path::new(&get_db_base()))
+    }
+
+    pub fn init_db_at(path: &Path) -> Result<Self> {
+        Self::open_with_options(path, LsmOptions::default())
+    }
+
+    /// 1、获取数据目录锁，目录已被其他实例打开时直接报错，不修改任何文件
+    /// 2、读取清单，打开其中记录的 SSTable，删除清单之外残留的文件
+    /// 3、重放预写日志重建内存表
+    pub fn open_with_options(path: &Path, options: LsmOptions) -> Result<Self> {
+        let lock = DirLock::acquire(path)?;
+        let (next_file_id, manifest) = read_manifest(path)?;
+        let mut levels: Vec<Vec<Table>> = (0..MAX_LEVELS).map(|_| vec![]).collect();
+        for &(level, id) in &manifest {
+            if level >= MAX_LEVELS {
+                return errdata!("manifest references table {id} at invalid level {level}");
+            }
+            levels[level].push(Table::open(&sst_path(path, id), id)?);
+        }
+        for level in levels.iter_mut().skip(1) {
+            level.sort_by(|a, b| a.smallest.cmp(&b.smallest));
+        }
+        // 落盘或压缩中途崩溃时留下的文件不在清单中，可以直接删除
+        for entry in fs::read_dir(path)? {
+            let file_path = entry?.path();
+            let is_sst = file_path.extension().is_some_and(|ext| ext == SST_EXTENSION);
+            let id = file_path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok());
+            if is_sst && !id.is_some_and(|id| manifest.iter().any(|&(_, live)| live == id)) {
+                fs::remove_file(&file_path)?;
+            }
+        }
+
+        let mut wal = fs::OpenOptions::new().read(true).append(true).create(true).open(path.join(WAL_FILE))?;
+        let mut db = Self {
+            dir: path.to_path_buf(),
+            options,
+            memtable: BTreeMap::new(),
+            memtable_size: 0,
+            wal: wal.try_clone()?,
+            levels,
+            compact_pointer: vec![vec![]; MAX_LEVELS],
+            next_file_id,
+            _lock: lock,
+        };
+        let mut bytes = vec![];
+        wal.read_to_end(&mut bytes)?;
+        let valid_len = db.replay_wal(&bytes)?;
+        // 末尾写到一半的记录直接截掉，之后的追加写入不会跟在损坏的数据后面
+        if valid_len < bytes.len() {
+            db.wal.set_len(valid_len as u64)?;
+        }
+        Ok(db)
+    }
+
+    /// 把内存表落盘为 L0 的 SSTable，然后按需压缩
+    pub fn flush_memtable(&mut self) -> Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let entries = std::mem::take(&mut self.memtable).into_iter().map(Ok);
+        let tables = write_tables(&self.dir, &mut self.next_file_id, self.options.block_size, entries, false, u64::MAX)?;
+        self.levels[0].extend(tables);
+        self.write_manifest()?;
+        // 清单写入后内存表的数据已经持久化，可以清空预写日志
+        self.wal.set_len(0)?;
+        self.memtable_size = 0;
+        self.maybe_compact()
+    }
+
+    /// 手动全量压缩：落盘内存表后把每一层依次合并到下一层，
+    /// 最终所有数据位于同一层，被覆盖的旧值和墓碑都被清除
+    pub fn compact(&mut self) -> Result<()> {
+        self.flush_memtable()?;
+        let Some(last) = self.levels.iter().rposition(|level| !level.is_empty()) else {
+            return Ok(());
+        };
+        for level in 0..last.max(1) {
+            let inputs = (0..self.levels[level].len()).collect();
+            self.compact_level(level, inputs)?;
+        }
+        Ok(())
+    }
+
+    /// 每层的 SSTable 数量，调试与测试使用
+    pub fn level_counts(&self) -> Vec<usize> {
+        self.levels.iter().map(|level| level.len()).collect()
+    }
+
+    /// 重放预写日志，返回完整记录覆盖的字节数
+    fn replay_wal(&mut self, bytes: &[u8]) -> Result<usize> {
+        let mut pos = 0;
+        while let Some((ops, len)) = decode_wal_record(&bytes[pos..]) {
+            for op in ops {
+                self.apply(op);
+            }
+            pos += len;
+        }
+        Ok(pos)
+    }
+
+    /// 整批操作编码为一条带 crc 的日志记录，一次追加写入，再应用到内存表
+    fn write_ops(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.wal.write_all(&encode_wal_record(&ops))?;
+        for op in ops {
+            self.apply(op);
+        }
+        if self.memtable_size >= self.options.memtable_limit {
+            self.flush_memtable()?;
+        }
+        Ok(())
+    }
+
+    /// 把一个操作应用到内存表，更新内存表的估算大小
+    fn apply(&mut self, op: WriteOp) {
+        let (key, value) = match op {
+            WriteOp::Put(key, value) => (key, Some(value)),
+            WriteOp::Delete(key) => (key, None),
+        };
+        let (key_len, value_len) = (key.len(), value.as_ref().map_or(0, Vec::len));
+        match self.memtable.insert(key, value) {
+            Some(old) => self.memtable_size -= old.map_or(0, |v| v.len()),
+            None => self.memtable_size += key_len,
+        }
+        self.memtable_size += value_len;
+    }
+
+    /// 按需压缩：L0 文件过多时合并到 L1；其余层超过大小上限时挑一个文件合并到下一层
+    fn maybe_compact(&mut self) -> Result<()> {
+        loop {
+            if self.levels[0].len() >= self.options.l0_compaction_trigger {
+                let inputs = (0..self.levels[0].len()).collect();
+                self.compact_level(0, inputs)?;
+                continue;
+            }
+            let mut max_size = self.options.level_base_size;
+            let mut overflow = None;
+            for level in 1..MAX_LEVELS - 1 {
+                if self.levels[level].iter().map(|t| t.size).sum::<u64>() > max_size {
+                    overflow = Some(level);
+                    break;
+                }
+                max_size = max_size.saturating_mul(self.options.level_size_multiplier);
+            }
+            let Some(level) = overflow else {
+                return Ok(());
+            };
+            // 从上次压缩的位置之后挑选文件，没有时回到层首
+            let pointer = &self.compact_pointer[level];
+            let index = self.levels[level].iter().position(|t| t.smallest > *pointer).unwrap_or(0);
+            self.compact_level(level, vec![index])?;
+        }
+    }
+
+    /// 把 level 层中编号为 inputs 的文件与下一层中键范围重叠的文件合并，结果写入下一层
+    fn compact_level(&mut self, level: usize, inputs: Vec<usize>) -> Result<()> {
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        let output = level + 1;
+        let smallest = inputs.iter().map(|&i| &self.levels[level][i].smallest).min().unwrap().clone();
+        let largest = inputs.iter().map(|&i| &self.levels[level][i].largest).max().unwrap().clone();
+        let overlapping: Vec<usize> = (0..self.levels[output].len())
+            .filter(|&i| {
+                let table = &self.levels[output][i];
+                table.largest >= smallest && table.smallest <= largest
+            })
+            .collect();
+        // 更深的层没有数据时墓碑已经没有需要遮挡的旧值，可以丢弃
+        let bottom = self.levels[output + 1..].iter().all(Vec::is_empty);
+
+        // 新文件优先：L0 从新到旧，之后是下一层（互不重叠，串成一个数据源）
+        let mut sources: Vec<Source> = vec![];
+        for &i in inputs.iter().rev() {
+            sources.push(Source::new(self.levels[level][i].iter(full_range())));
+        }
+        let next_level: Vec<&Table> = overlapping.iter().map(|&i| &self.levels[output][i]).collect();
+        sources.push(Source::new(next_level.into_iter().flat_map(|t| t.iter(full_range()))));
+        let merged = MergeIterator { sources }.map(|item| item.map(|(_, entry)| entry));
+        let (block_size, target) = (self.options.block_size, self.options.target_file_size);
+        let tables = write_tables(&self.dir, &mut self.next_file_id, block_size, merged, bottom, target)?;
+
+        let mut obsolete = vec![];
+        for (from, indexes) in [(level, inputs), (output, overlapping)] {
+            for i in indexes.into_iter().rev() {
+                obsolete.push(self.levels[from].remove(i));
+            }
+        }
+        self.compact_pointer[level] = largest;
+        self.levels[output].extend(tables);
+        self.levels[output].sort_by(|a, b| a.smallest.cmp(&b.smallest));
+        self.write_manifest()?;
+        // 清单已经不再引用旧文件，删除失败也只会在下次打开时被清理
+        for table in obsolete {
+            let _ = fs::remove_file(sst_path(&self.dir, table.id));
+        }
+        Ok(())
+    }
+
+    /// 先写临时文件再重命名，清单的更新是原子的
+    fn write_manifest(&self) -> Result<()> {
+        let mut payload = self.next_file_id.to_be_bytes().to_vec();
+        for (level, tables) in self.levels.iter().enumerate() {
+            for table in tables {
+                payload.extend_from_slice(&(level as u32).to_be_bytes());
+                payload.extend_from_slice(&table.id.to_be_bytes());
+            }
+        }
+        let tmp = self.dir.join(format!("{MANIFEST_FILE}.tmp"));
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&crc32c::crc32c(&payload).to_be_bytes())?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+
+    /// 按优先级从新到旧排列的数据源：内存表、L0 从新到旧、L1 及以下每层一个
+    fn sources(&self, range: KeyRange) -> Vec<Source<'_>> {
+        let mut sources = vec![Source::new(
+            self.memtable.range(range.clone()).map(|(k, v)| Ok((k.clone(), v.clone()))),
+        )];
+        for table in self.levels[0].iter().rev() {
+            sources.push(Source::new(table.iter(range.clone())));
+        }
+        for level in &self.levels[1..] {
+            let bounds = range.clone();
+            let tables = level
+                .iter()
+                .filter(move |t| !before_start(&t.largest, &bounds.0) && !after_end(&t.smallest, &bounds.1));
+            let range = range.clone();
+            sources.push(Source::new(tables.flat_map(move |t| t.iter(range.clone()))));
+        }
+        sources
+    }
+}
+
+impl Engine for Lsm {
+    type ScanIter<'a> = ScanIterator<'a>;
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write_ops(vec![WriteOp::Put(key.to_vec(), value.to_vec())])
+    }
+
+    /// 依次查找内存表、L0（从新到旧）和更深的层，第一个命中的版本就是最新值
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.memtable.get(key) {
+            return Ok(value.clone());
+        }
+        for table in self.levels[0].iter().rev() {
+            if let Some(value) = table.get(key)? {
+                return Ok(value);
+            }
+        }
+        for level in &self.levels[1..] {
+            let index = level.partition_point(|t| t.largest.as_slice() < key);
+            if let Some(table) = level.get(index).filter(|t| t.smallest.as_slice() <= key) {
+                if let Some(value) = table.get(key)? {
+                    return Ok(value);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.write_ops(vec![WriteOp::Delete(key.to_vec())])
+    }
+
+    /// 整批操作写成一条预写日志记录，重放时要么全部生效，要么整条被丢弃
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.write_ops(batch.into_iter().collect())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.wal.flush()?;
+        Ok(())
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        ScanIterator { inner: MergeIterator { sources: self.sources(range) } }
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        let tables: Vec<Table> = self.levels.iter_mut().flat_map(std::mem::take).collect();
+        self.memtable.clear();
+        self.memtable_size = 0;
+        self.compact_pointer = vec![vec![]; MAX_LEVELS];
+        self.write_manifest()?;
+        self.wal.set_len(0)?;
+        for table in tables {
+            fs::remove_file(sst_path(&self.dir, table.id))?;
+        }
+        Ok(())
+    }
+
+    /// 存活数据需要合并所有数据源才能确定，开销与数据量成正比
+    fn status(&self) -> Result<EngineStatus> {
+        let wal_size = self.wal.metadata()?.len();
+        // 数据源与 sources() 的顺序一致：内存表、L0 从新到旧、每层的文件
+        let mut files = vec![FileStatus { name: WAL_FILE.to_string(), total_size: wal_size, live_size: 0, live_count: 0 }];
+        let mut owners = vec![];
+        for table in self.levels[0].iter().rev() {
+            owners.push(files.len());
+            files.push(table.file_status());
+        }
+        for level in &self.levels[1..] {
+            owners.push(files.len());
+            files.extend(level.iter().map(Table::file_status));
+        }
+
+        let (mut logical_size, mut total_count) = (0, 0);
+        for item in (MergeIterator { sources: self.sources(full_range()) }) {
+            let (source, (key, value)) = item?;
+            let Some(value) = value else { continue };
+            logical_size += (key.len() + value.len()) as u64;
+            total_count += 1;
+            // 存活条目计入它所在的文件；L1 及以下一层是一个数据源，再按键定位到文件
+            let file = match source {
+                0 => 0,
+                source if source <= self.levels[0].len() => owners[source - 1],
+                source => {
+                    let level = &self.levels[source - self.levels[0].len()];
+                    owners[source - 1] + level.partition_point(|t| t.largest < key)
+                }
+            };
+            files[file].live_size += entry_len(&key, Some(&value));
+            files[file].live_count += 1;
+        }
+        let total_size: u64 = files.iter().map(|f| f.total_size).sum();
+        let live_size: u64 = files.iter().map(|f| f.live_size.min(f.total_size)).sum();
+        let index_memory = self.memtable_size as u64
+            + self.levels.iter().flatten().map(Table::index_memory).sum::<u64>();
+        Ok(EngineStatus {
+            name: "lsm".to_string(),
+            logical_size,
+            total_count,
+            total_size,
+            live_size,
+            garbage_size: total_size - live_size,
+            index_memory,
+            files,
+        })
+    }
+}
+
+/// LSM 的扫描迭代器：合并所有数据源，跳过墓碑
+pub struct ScanIterator<'a> {
+    inner: MergeIterator<'a>,
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok((_, (key, Some(value)))) => return Some(Ok((key, value))),
+                Ok((_, (_, None))) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for ScanIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back()? {
+                Ok((_, (key, Some(value)))) => return Some(Ok((key, value))),
+                Ok((_, (_, None))) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// 一个有序的数据源，两端各预读一个条目用于比较
+struct Source<'a> {
+    iter: Box<dyn DoubleEndedIterator<Item = Result<Entry>> + Send + 'a>,
+    front: Option<Entry>,
+    back: Option<Entry>,
+}
+
+impl<'a> Source<'a> {
+    fn new(iter: impl DoubleEndedIterator<Item = Result<Entry>> + Send + 'a) -> Self {
+        Self { iter: Box::new(iter), front: None, back: None }
+    }
+
+    /// 预读前端条目；底层迭代器耗尽时接管后端预读的条目
+    fn fill_front(&mut self) -> Result<()> {
+        if self.front.is_none() {
+            self.front = match self.iter.next() {
+                Some(entry) => Some(entry?),
+                None => self.back.take(),
+            };
+        }
+        Ok(())
+    }
+
+    fn fill_back(&mut self) -> Result<()> {
+        if self.back.is_none() {
+            self.back = match self.iter.next_back() {
+                Some(entry) => Some(entry?),
+                None => self.front.take(),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// 多路归并：每次取所有数据源中最小（反向时最大）的键，
+/// 同一个键出现在多个数据源时取编号最小（最新）的那个，其余的跳过
+/// 返回条目所在数据源的编号
+struct MergeIterator<'a> {
+    sources: Vec<Source<'a>>,
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = Result<(usize, Entry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for source in &mut self.sources {
+            if let Err(e) = source.fill_front() {
+                return Some(Err(e));
+            }
+        }
+        let mut winner: Option<(usize, &[u8])> = None;
+        for (i, source) in self.sources.iter().enumerate() {
+            if let Some((key, _)) = &source.front {
+                if winner.is_none_or(|(_, min)| key.as_slice() < min) {
+                    winner = Some((i, key));
+                }
+            }
+        }
+        let (index, key) = winner?;
+        let key = key.to_vec();
+        let entry = self.sources[index].front.take()?;
+        for source in &mut self.sources {
+            if source.front.as_ref().is_some_and(|(k, _)| *k == key) {
+                source.front = None;
+            }
+        }
+        Some(Ok((index, entry)))
+    }
+}
+
+impl<'a> DoubleEndedIterator for MergeIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        for source in &mut self.sources {
+            if let Err(e) = source.fill_back() {
+                return Some(Err(e));
+            }
+        }
+        let mut winner: Option<(usize, &[u8])> = None;
+        for (i, source) in self.sources.iter().enumerate() {
+            if let Some((key, _)) = &source.back {
+                if winner.is_none_or(|(_, max)| key.as_slice() > max) {
+                    winner = Some((i, key));
+                }
+            }
+        }
+        let (index, key) = winner?;
+        let key = key.to_vec();
+        let entry = self.sources[index].back.take()?;
+        for source in &mut self.sources {
+            if source.back.as_ref().is_some_and(|(k, _)| *k == key) {
+                source.back = None;
+            }
+        }
+        Some(Ok((index, entry)))
+    }
+}
+
+/// SSTable 中一个数据块的位置与键范围
+#[derive(Debug)]
+struct BlockHandle {
+    offset: u64,
+    len: u32,
+    crc: u32,
+    first_key: Vec<u8>,
+    last_key: Vec<u8>,
+}
+
+/// 一个已打开的 SSTable，常驻内存的只有块索引和布隆过滤器
+///
+/// 文件格式：
+/// | 数据块... | 块索引 | 布隆过滤器 | 文件尾 |
+/// 数据块由连续的条目组成：klen(4) vlen(4) key value，vlen 为 u32::MAX 表示墓碑
+/// 文件尾：块索引位置(8) 布隆过滤器位置(8) 条目数(8) 元数据crc(4) 魔数(8)
+#[derive(Debug)]
+struct Table {
+    id: u64,
+    file: fs::File,
+    size: u64,
+    index: Vec<BlockHandle>,
+    bloom: Bloom,
+    smallest: Vec<u8>,
+    largest: Vec<u8>,
+}
+
+impl Table {
+    fn open(path: &Path, id: u64) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_LEN {
+            return errdata!("sstable {} is truncated", path.display());
+        }
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        read_exact_at(&file, &mut footer, size - FOOTER_LEN)?;
+        let mut reader = Reader(&footer);
+        let (index_offset, bloom_offset) = (reader.u64()?, reader.u64()?);
+        // 条目数只用于离线检查，打开时不需要
+        let (_entry_count, meta_crc, magic) = (reader.u64()?, reader.u32()?, reader.u64()?);
+        if magic != SST_MAGIC || index_offset > bloom_offset || bloom_offset > size - FOOTER_LEN {
+            return errdata!("sstable {} has an invalid footer", path.display());
+        }
+        let mut meta = vec![0u8; (size - FOOTER_LEN - index_offset) as usize];
+        read_exact_at(&file, &mut meta, index_offset)?;
+        if crc32c::crc32c(&meta) != meta_crc {
+            return errdata!("sstable {} index crc mismatch", path.display());
+        }
+        let (index_bytes, bloom_bytes) = meta.split_at((bloom_offset - index_offset) as usize);
+        let mut reader = Reader(index_bytes);
+        let mut index = vec![];
+        for _ in 0..reader.u32()? {
+            let (offset, len, crc) = (reader.u64()?, reader.u32()?, reader.u32()?);
+            let first_key = reader.bytes()?.to_vec();
+            let last_key = reader.bytes()?.to_vec();
+            index.push(BlockHandle { offset, len, crc, first_key, last_key });
+        }
+        let (Some(first), Some(last)) = (index.first(), index.last()) else {
+            return errdata!("sstable {} has no data blocks", path.display());
+        };
+        let (smallest, largest) = (first.first_key.clone(), last.last_key.clone());
+        let bloom = Bloom::decode(bloom_bytes)?;
+        Ok(Self { id, file, size, index, bloom, smallest, largest })
+    }
+
+    /// 查找键：布隆过滤器判定不存在时不读磁盘
+    /// 返回 Some(None) 表示命中墓碑
+    fn get(&self, key: &[u8]) -> Result<Option<Option<Vec<u8>>>> {
+        if !self.bloom.may_contain(key) {
+            return Ok(None);
+        }
+        let block = self.index.partition_point(|b| b.last_key.as_slice() < key);
+        if self.index.get(block).is_none_or(|b| b.first_key.as_slice() > key) {
+            return Ok(None);
+        }
+        let entries = self.read_block(block)?;
+        Ok(entries
+            .binary_search_by(|(k, _)| k.as_slice().cmp(key))
+            .ok()
+            .map(|i| entries[i].1.clone()))
+    }
+
+    /// 读取并解码一个数据块，校验失败时返回错误
+    fn read_block(&self, block: usize) -> Result<Vec<Entry>> {
+        let handle = &self.index[block];
+        let mut bytes = vec![0u8; handle.len as usize];
+        read_exact_at(&self.file, &mut bytes, handle.offset)?;
+        if crc32c::crc32c(&bytes) != handle.crc {
+            return errdata!("sstable {} block {} crc mismatch", self.id, block);
+        }
+        let mut reader = Reader(&bytes);
+        let mut entries = vec![];
+        while !reader.0.is_empty() {
+            let (klen, vlen) = (reader.u32()? as usize, reader.u32()?);
+            let key = reader.take(klen)?.to_vec();
+            let value = match vlen {
+                TOMBSTONE => None,
+                vlen => Some(reader.take(vlen as usize)?.to_vec()),
+            };
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+
+    /// 范围内的条目，数据块在迭代时按需读取
+    fn iter(&self, range: KeyRange) -> TableIterator<'_> {
+        let next_block = self.index.partition_point(|b| before_start(&b.last_key, &range.0));
+        let end_block = self.index.partition_point(|b| !after_end(&b.first_key, &range.1));
+        TableIterator {
+            table: self,
+            next_block,
+            end_block: end_block.max(next_block),
+            range,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+        }
+    }
+
+    fn file_status(&self) -> FileStatus {
+        FileStatus { name: sst_name(self.id), total_size: self.size, live_size: 0, live_count: 0 }
+    }
+
+    fn index_memory(&self) -> u64 {
+        let keys: usize = self.index.iter().map(|b| b.first_key.len() + b.last_key.len() + 16).sum();
+        (keys + self.bloom.bits.len()) as u64
+    }
+}
+
+/// SSTable 的双向迭代器，两端各自读取数据块，在中间相遇
+struct TableIterator<'a> {
+    table: &'a Table,
+    range: KeyRange,
+    /// 前端下一个要读的数据块
+    next_block: usize,
+    /// 后端已读到的数据块（不含）
+    end_block: usize,
+    front: VecDeque<Entry>,
+    back: VecDeque<Entry>,
+}
+
+impl<'a> TableIterator<'a> {
+    fn load(&self, block: usize) -> Result<VecDeque<Entry>> {
+        let entries = self.table.read_block(block)?;
+        Ok(entries
+            .into_iter()
+            .filter(|(key, _)| !before_start(key, &self.range.0) && !after_end(key, &self.range.1))
+            .collect())
+    }
+}
+
+impl<'a> Iterator for TableIterator<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.front.pop_front() {
+                return Some(Ok(entry));
+            }
+            if self.next_block == self.end_block {
+                return self.back.pop_front().map(Ok);
+            }
+            match self.load(self.next_block) {
+                Ok(entries) => self.front = entries,
+                Err(e) => {
+                    self.next_block = self.end_block;
+                    return Some(Err(e));
+                }
+            }
+            self.next_block += 1;
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for TableIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.back.pop_back() {
+                return Some(Ok(entry));
+            }
+            if self.next_block == self.end_block {
+                return self.front.pop_back().map(Ok);
+            }
+            match self.load(self.end_block - 1) {
+                Ok(entries) => self.back = entries,
+                Err(e) => {
+                    self.end_block = self.next_block;
+                    return Some(Err(e));
+                }
+            }
+            self.end_block -= 1;
+        }
+    }
+}
+
+/// 顺序写入一个 SSTable，键必须严格递增
+struct TableBuilder {
+    id: u64,
+    path: PathBuf,
+    writer: BufWriter<fs::File>,
+    block_size: usize,
+    offset: u64,
+    block: Vec<u8>,
+    block_first_key: Vec<u8>,
+    last_key: Vec<u8>,
+    index: Vec<BlockHandle>,
+    hashes: Vec<u32>,
+}
+
+impl TableBuilder {
+    fn new(path: &Path, id: u64, block_size: usize) -> Result<Self> {
+        Ok(Self {
+            id,
+            path: path.to_path_buf(),
+            writer: BufWriter::new(fs::File::create(path)?),
+            block_size,
+            offset: 0,
+            block: vec![],
+            block_first_key: vec![],
+            last_key: vec![],
+            index: vec![],
+            hashes: vec![],
+        })
+    }
+
+    fn add(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if self.block.is_empty() {
+            self.block_first_key = key.to_vec();
+        }
+        self.block.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        self.block.extend_from_slice(&value.map_or(TOMBSTONE, |v| v.len() as u32).to_be_bytes());
+        self.block.extend_from_slice(key);
+        self.block.extend_from_slice(value.unwrap_or_default());
+        self.last_key = key.to_vec();
+        self.hashes.push(bloom_hash(key));
+        if self.block.len() >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    /// 已写入的字节数
+    fn size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    fn finish_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.writer.write_all(&self.block)?;
+        self.index.push(BlockHandle {
+            offset: self.offset,
+            len: self.block.len() as u32,
+            crc: crc32c::crc32c(&self.block),
+            first_key: std::mem::take(&mut self.block_first_key),
+            last_key: self.last_key.clone(),
+        });
+        self.offset += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// 写入块索引、布隆过滤器和文件尾，同步到磁盘后重新打开用于读取
+    fn finish(mut self) -> Result<Table> {
+        self.finish_block()?;
+        let mut meta = (self.index.len() as u32).to_be_bytes().to_vec();
+        for block in &self.index {
+            meta.extend_from_slice(&block.offset.to_be_bytes());
+            meta.extend_from_slice(&block.len.to_be_bytes());
+            meta.extend_from_slice(&block.crc.to_be_bytes());
+            for key in [&block.first_key, &block.last_key] {
+                meta.extend_from_slice(&(key.len() as u32).to_be_bytes());
+                meta.extend_from_slice(key);
+            }
+        }
+        let index_offset = self.offset;
+        let bloom_offset = index_offset + meta.len() as u64;
+        meta.extend(Bloom::build(&self.hashes).encode());
+        self.writer.write_all(&meta)?;
+        self.writer.write_all(&index_offset.to_be_bytes())?;
+        self.writer.write_all(&bloom_offset.to_be_bytes())?;
+        self.writer.write_all(&(self.hashes.len() as u64).to_be_bytes())?;
+        self.writer.write_all(&crc32c::crc32c(&meta).to_be_bytes())?;
+        self.writer.write_all(&SST_MAGIC.to_be_bytes())?;
+        self.writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Table::open(&self.path, self.id)
+    }
+}
+
+/// 布隆过滤器：用一个哈希值的双重散列模拟 k 个哈希函数
+#[derive(Debug)]
+struct Bloom {
+    bits: Vec<u8>,
+    k: u32,
+}
+
+impl Bloom {
+    fn build(hashes: &[u32]) -> Self {
+        let nbits = (hashes.len() * BLOOM_BITS_PER_KEY).max(64);
+        // k = bits_per_key * ln2 时误判率最低
+        let k = ((BLOOM_BITS_PER_KEY as f64 * 0.69) as u32).clamp(1, 30);
+        let mut bloom = Self { bits: vec![0; nbits.div_ceil(8)], k };
+        for &hash in hashes {
+            for bit in bloom.positions(hash) {
+                bloom.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        bloom
+    }
+
+    fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(bloom_hash(key)).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn positions(&self, hash: u32) -> impl Iterator<Item = usize> {
+        let nbits = self.bits.len() * 8;
+        let delta = hash.rotate_right(17);
+        (0..self.k).map(move |i| hash.wrapping_add(delta.wrapping_mul(i)) as usize % nbits)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.k.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        let k = reader.u32()?;
+        if reader.0.is_empty() {
+            return errdata!("bloom filter is empty");
+        }
+        Ok(Self { bits: reader.0.to_vec(), k })
+    }
+}
+
+fn bloom_hash(key: &[u8]) -> u32 {
+    crc32c::crc32c(key)
+}
+
+/// 把有序的条目写成若干个 SSTable，每个文件达到 target 大小后切分
+fn write_tables(
+    dir: &Path,
+    next_file_id: &mut u64,
+    block_size: usize,
+    entries: impl Iterator<Item = Result<Entry>>,
+    drop_tombstones: bool,
+    target: u64,
+) -> Result<Vec<Table>> {
+    let mut tables = vec![];
+    let mut builder: Option<TableBuilder> = None;
+    for entry in entries {
+        let (key, value) = entry?;
+        if drop_tombstones && value.is_none() {
+            continue;
+        }
+        let current = match builder.as_mut() {
+            Some(builder) => builder,
+            None => {
+                let id = *next_file_id;
+                *next_file_id += 1;
+                builder.insert(TableBuilder::new(&sst_path(dir, id), id, block_size)?)
+            }
+        };
+        current.add(&key, value.as_deref())?;
+        if current.size() >= target {
+            tables.push(builder.take().unwrap().finish()?);
+        }
+    }
+    if let Some(builder) = builder {
+        tables.push(builder.finish()?);
+    }
+    Ok(tables)
+}
+
+/// 预写日志记录：crc(4) len(4) payload
+/// payload 由连续的操作组成：tag(1) klen(4) key [vlen(4) value]，tag 为 1 表示写入，0 表示删除
+fn encode_wal_record(ops: &[WriteOp]) -> Vec<u8> {
+    let mut payload = vec![];
+    for op in ops {
+        let (tag, key, value) = match op {
+            WriteOp::Put(key, value) => (1u8, key, Some(value)),
+            WriteOp::Delete(key) => (0u8, key, None),
+        };
+        payload.push(tag);
+        payload.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        payload.extend_from_slice(key);
+        if let Some(value) = value {
+            payload.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            payload.extend_from_slice(value);
+        }
+    }
+    let mut record = crc32c::crc32c(&payload).to_be_bytes().to_vec();
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend(payload);
+    record
+}
+
+/// 解码开头的一条记录，返回其中的操作和记录长度；记录不完整或校验失败时返回 None
+fn decode_wal_record(bytes: &[u8]) -> Option<(Vec<WriteOp>, usize)> {
+    let mut reader = Reader(bytes);
+    let (crc, len) = (reader.u32().ok()?, reader.u32().ok()? as usize);
+    let payload = reader.take(len).ok()?;
+    if crc32c::crc32c(payload) != crc {
+        return None;
+    }
+    let mut reader = Reader(payload);
+    let mut ops = vec![];
+    while !reader.0.is_empty() {
+        let tag = reader.take(1).ok()?[0];
+        let key = reader.bytes().ok()?.to_vec();
+        ops.push(match tag {
+            1 => WriteOp::Put(key, reader.bytes().ok()?.to_vec()),
+            _ => WriteOp::Delete(key),
+        });
+    }
+    Some((ops, 8 + len))
+}
+
+/// 条目在数据块中占用的字节数
+fn entry_len(key: &[u8], value: Option<&[u8]>) -> u64 {
+    (8 + key.len() + value.map_or(0, <[u8]>::len)) as u64
+}
+
+/// 读取清单，返回下一个文件编号和 (层, 文件编号) 列表；清单不存在时为空库
+fn read_manifest(dir: &Path) -> Result<(u64, Vec<(usize, u64)>)> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok((1, vec![]));
+    }
+    let bytes = fs::read(&path)?;
+    let mut reader = Reader(&bytes);
+    let crc = reader.u32()?;
+    if crc32c::crc32c(reader.0) != crc {
+        return errdata!("manifest {} crc mismatch", path.display());
+    }
+    let next_file_id = reader.u64()?;
+    let mut tables = vec![];
+    while !reader.0.is_empty() {
+        tables.push((reader.u32()? as usize, reader.u64()?));
+    }
+    Ok((next_file_id, tables))
+}
+
+fn sst_name(id: u64) -> String {
+    format!("{id:06}.{SST_EXTENSION}")
+}
+
+fn sst_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(sst_name(id))
+}
+
+fn full_range() -> KeyRange {
+    (Bound::Unbounded, Bound::Unbounded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mvcc::MVCC;
+    use tempfile::TempDir;
+
+    /// 很小的内存表和数据块，少量写入就能触发落盘与压缩
+    fn small_options() -> LsmOptions {
+        LsmOptions {
+            memtable_limit: 512,
+            block_size: 64,
+            l0_compaction_trigger: 2,
+            level_base_size: 1024,
+            level_size_multiplier: 2,
+            target_file_size: 256,
+        }
+    }
+
+    // 比 small_options 稍大：随机测试里的大值不至于每次写入都触发压缩，但仍然会多次落盘和压缩
+    crate::storage::conformance::test_engine!(
+        |path: &Path| {
+            let options = LsmOptions {
+                memtable_limit: 8 * 1024,
+                block_size: 512,
+                level_base_size: 32 * 1024,
+                target_file_size: 8 * 1024,
+                ..small_options()
+            };
+            Lsm::open_with_options(path, options)
+        },
+        persistent
+    );
+
+    fn collect(iter: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        iter.collect::<Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn test_crud() {
+        let dir = TempDir::new().unwrap();
+        let mut db = Lsm::init_db_at(dir.path()).unwrap();
+
+        db.set(b"foo", b"bar").unwrap();
+        assert_eq!(db.get(b"foo").unwrap().unwrap(), b"bar");
+        db.set(b"foo", b"baz").unwrap();
+        assert_eq!(db.get(b"foo").unwrap().unwrap(), b"baz");
+        db.delete(b"foo").unwrap();
+        assert!(db.get(b"foo").unwrap().is_none());
+        assert!(!db.exists(b"foo").unwrap());
+
+        // 空值与删除不同，落盘后仍然存在
+        db.set(b"empty", b"").unwrap();
+        db.flush_memtable().unwrap();
+        assert_eq!(db.get(b"empty").unwrap(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_scan_range() {
+        let dir = TempDir::new().unwrap();
+        let mut db = Lsm::init_db_at(dir.path()).unwrap();
+        db.set(b"a", b"1").unwrap();
+        db.set(b"b", b"2").unwrap();
+        db.flush_memtable().unwrap();
+        db.set(b"c", b"3").unwrap();
+        db.set(b"d", b"4").unwrap();
+        db.delete(b"a").unwrap();
+
+        let pair = |k: &[u8], v: &[u8]| (k.to_vec(), v.to_vec());
+        assert_eq!(collect(db.scan(b"b".to_vec()..b"d".to_vec())), vec![pair(b"b", b"2"), pair(b"c", b"3")]);
+        assert_eq!(collect(db.scan(b"b".to_vec()..b"d".to_vec()).rev()), vec![pair(b"c", b"3"), pair(b"b", b"2")]);
+        assert_eq!(collect(db.scan(..)).len(), 3);
+
+        // 两端交替读取在中间相遇，不重复也不遗漏
+        {
+            let mut iter = db.scan(..);
+            assert_eq!(iter.next().unwrap().unwrap(), pair(b"b", b"2"));
+            assert_eq!(iter.next_back().unwrap().unwrap(), pair(b"d", b"4"));
+            assert_eq!(iter.next().unwrap().unwrap(), pair(b"c", b"3"));
+            assert!(iter.next_back().is_none());
+        }
+
+        db.set(b"prefix:a", b"1").unwrap();
+        db.set(b"prefix:b", b"2").unwrap();
+        assert_eq!(collect(db.scan_prefix(b"prefix")).len(), 2);
+    }
+
+    #[test]
+    fn test_reopen_replays_wal() {
+        let dir = TempDir::new().unwrap();
+        {
+            let mut db = Lsm::init_db_at(dir.path()).unwrap();
+            db.set(b"flushed", b"old").unwrap();
+            db.flush_memtable().unwrap();
+            db.set(b"flushed", b"new").unwrap();
+            db.set(b"key", b"v1").unwrap();
+            db.delete(b"key").unwrap();
+            db.set(b"other", b"v").unwrap();
+            db.flush().unwrap();
+        }
+        let db = Lsm::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"flushed").unwrap().unwrap(), b"new");
+        assert!(db.get(b"key").unwrap().is_none());
+        assert_eq!(db.get(b"other").unwrap().unwrap(), b"v");
+        assert_eq!(db.level_counts()[0], 1);
+    }
+
+    #[test]
+    fn test_write_batch_and_torn_record() {
+        let dir = TempDir::new().unwrap();
+        {
+            let mut db = Lsm::init_db_at(dir.path()).unwrap();
+            db.set(b"a", b"before").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"a", b"after").put(b"b", b"new").delete(b"a").put(b"c", b"3");
+            db.write_batch(batch).unwrap();
+            assert!(db.get(b"a").unwrap().is_none());
+            assert_eq!(db.batch_get(vec![b"b", b"c"]).unwrap(), vec![Some(b"new".to_vec()), Some(b"3".to_vec())]);
+        }
+        let path = dir.path().join(WAL_FILE);
+        let full = fs::read(&path).unwrap();
+
+        // 批量写入的记录写到一半时崩溃：整批丢弃，之前的数据保持不变
+        fs::write(&path, &full[..full.len() - 3]).unwrap();
+        {
+            let mut db = Lsm::init_db_at(dir.path()).unwrap();
+            assert_eq!(db.get(b"a").unwrap().unwrap(), b"before");
+            assert!(db.get(b"b").unwrap().is_none());
+            // 残缺的记录已被截掉，之后的写入可以正常重放
+            db.set(b"d", b"4").unwrap();
+        }
+        let db = Lsm::init_db_at(dir.path()).unwrap();
+        assert_eq!(db.get(b"a").unwrap().unwrap(), b"before");
+        assert_eq!(db.get(b"d").unwrap().unwrap(), b"4");
+    }
+
+    #[test]
+    fn test_flush_and_compaction() {
+        let dir = TempDir::new().unwrap();
+        let mut model = BTreeMap::new();
+        {
+            let mut db = Lsm::open_with_options(dir.path(), small_options()).unwrap();
+            for i in 0..2000u32 {
+                let key = format!("key_{:04}", (i * 7919) % 500).into_bytes();
+                if i % 5 == 0 {
+                    db.delete(&key).unwrap();
+                    model.remove(&key);
+                } else {
+                    let value = format!("value_{i}").into_bytes();
+                    db.set(&key, &value).unwrap();
+                    model.insert(key, value);
+                }
+            }
+            let counts = db.level_counts();
+            assert!(counts[0] < 2, "{counts:?}");
+            assert!(counts[2..].iter().sum::<usize>() > 0, "{counts:?}");
+            let expected: Vec<_> = model.clone().into_iter().collect();
+            assert_eq!(collect(db.scan(..)), expected);
+            assert_eq!(collect(db.scan(..).rev()), expected.iter().rev().cloned().collect::<Vec<_>>());
+        }
+
+        // 重新打开后清单恢复出相同的分层，数据不变
+        let mut db = Lsm::open_with_options(dir.path(), small_options()).unwrap();
+        for i in 0..500u32 {
+            let key = format!("key_{i:04}").into_bytes();
+            assert_eq!(db.get(&key).unwrap(), model.get(&key).cloned(), "{i}");
+        }
+        let start = b"key_0100".to_vec();
+        let end = b"key_0300".to_vec();
+        let expected: Vec<_> = model.range(start.clone()..=end.clone()).map(|(k, v)| (k.clone(), v.clone())).collect();
+        assert_eq!(collect(db.scan(start..=end)), expected);
+
+        // 全量压缩后只剩一层，墓碑被清除
+        db.compact().unwrap();
+        assert_eq!(db.level_counts().iter().filter(|&&n| n > 0).count(), 1);
+        let status = db.status().unwrap();
+        assert_eq!(status.total_count, model.len() as u64);
+        assert_eq!(status.live_size + status.garbage_size, status.total_size);
+        assert_eq!(collect(db.scan(..)).len(), model.len());
+
+        // 目录被占用时第二个实例直接报错，不删除正在运行的实例写入中的文件
+        fs::write(sst_path(dir.path(), 999_999), b"garbage").unwrap();
+        let err = Lsm::open_with_options(dir.path(), small_options()).err().unwrap().to_string();
+        assert!(err.contains("is in use by pid"), "{err}");
+        assert!(sst_path(dir.path(), 999_999).exists());
+
+        // 清单之外残留的文件在打开时被删除
+        drop(db);
+        fs::write(sst_path(dir.path(), 999_999), b"garbage").unwrap();
+        let db = Lsm::open_with_options(dir.path(), small_options()).unwrap();
+        assert!(!sst_path(dir.path(), 999_999).exists());
+        assert_eq!(collect(db.scan(..)).len(), model.len());
+    }
+
+    #[test]
+    fn test_status_and_clear() {
+        let dir = TempDir::new().unwrap();
+        let mut db = Lsm::init_db_at(dir.path()).unwrap();
+        db.set(b"k1", b"v1").unwrap();
+        db.set(b"k2", b"v2").unwrap();
+        db.flush_memtable().unwrap();
+        db.set(b"k2", b"v22").unwrap();
+        db.set(b"k3", b"v3").unwrap();
+        db.delete(b"k3").unwrap();
+
+        let status = db.status().unwrap();
+        assert_eq!(status.name, "lsm");
+        assert_eq!(status.total_count, 2);
+        assert_eq!(status.logical_size, 2 + 2 + 2 + 3);
+        assert_eq!(status.files.len(), 2);
+        // SSTable 中 k2 的旧值已被内存表覆盖，只有 k1 存活
+        assert_eq!(status.files[1].live_count, 1);
+        assert_eq!(status.files[1].live_size, entry_len(b"k1", Some(b"v1")));
+        assert!(status.index_memory > 0);
+        let disk: u64 = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.file_name().unwrap() != MANIFEST_FILE && p.file_name().unwrap() != "LOCK")
+            .map(|p| fs::metadata(p).unwrap().len())
+            .sum();
+        assert_eq!(status.total_size, disk);
+
+        db.clear().unwrap();
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert!(collect(db.scan(..)).is_empty());
+        assert_eq!(db.status().unwrap().total_size, 0);
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let keys: Vec<Vec<u8>> = (0..1000).map(|i| format!("key{i}").into_bytes()).collect();
+        let hashes: Vec<u32> = keys.iter().map(|k| bloom_hash(k)).collect();
+        let bloom = Bloom::decode(&Bloom::build(&hashes).encode()).unwrap();
+        assert!(keys.iter().all(|k| bloom.may_contain(k)));
+        let false_positives = (0..1000).filter(|i| bloom.may_contain(format!("missing{i}").as_bytes())).count();
+        assert!(false_positives < 50, "{false_positives}");
+    }
+
+    #[test]
+    fn test_mvcc_on_lsm() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        let mvcc = MVCC::new(Lsm::open_with_options(dir.path(), small_options())?)?;
+        let txn = mvcc.begin()?;
+        txn.set(b"a", Some(b"1"))?;
+        txn.set(b"b", Some(b"2"))?;
+        txn.commit()?;
+
+        let reader = mvcc.begin()?;
+        let writer = mvcc.begin()?;
+        writer.set(b"a", Some(b"3"))?;
+        writer.delete(b"b")?;
+        writer.commit()?;
+        assert_eq!(reader.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(reader.scan(..)?.collect::<Result<Vec<_>>>()?.len(), 2);
+        reader.commit()?;
+
+        let txn = mvcc.begin()?;
+        assert_eq!(txn.get(b"a")?, Some(b"3".to_vec()));
+        assert_eq!(txn.get(b"b")?, None);
+        Ok(())
+    }
+}
@@ -28,7 +28,7 @@ impl Engine for Memory {
         Ok(())
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIter<'_> {
         ScanIterator(self.0.range(range))
     }
 
@@ -37,7 +37,7 @@ impl Engine for Memory {
         Ok(())
     }
 
-    fn status(&mut self) -> Result<EngineStatus> {
+    fn status(&self) -> Result<EngineStatus> {
         Ok(EngineStatus {
             name: "memory".to_string(),
             logical_size: self.0.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>() as u64,
@@ -45,6 +45,8 @@ impl Engine for Memory {
             total_size: self.0.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>() as u64,
             live_size: self.0.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>() as u64,
             garbage_size: 0,
+            index_memory: 0,
+            files: vec![],
         })
     }
 }
@@ -63,3 +65,10 @@ impl<'a> DoubleEndedIterator for ScanIterator<'a> {
         self.0.next_back().map(|(k, v)| Ok((k.clone(), v.clone())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::storage::conformance::test_engine!(|_: &std::path::Path| Ok(Memory::default()));
+}
@@ -4,7 +4,23 @@ pub use bitcask::*;
 pub mod memory;
 pub use memory::*;
 
+pub mod btree;
+pub use btree::BTree;
+
+pub mod lsm;
+pub use lsm::{Lsm, LsmOptions};
+
 pub mod mvcc;
 pub use mvcc::*;
 
+pub mod cdc;
 pub mod engine;
+pub mod keyring;
+pub use keyring::Keyring;
+pub(crate) mod util;
+
+#[cfg(test)]
+mod conformance;
+
+pub mod any;
+pub use any::AnyEngine;
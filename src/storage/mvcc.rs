@@ -1,13 +1,14 @@
-use crate::db_error::Result;
-use crate::errdata;
+use crate::db_error::{Error, Result};
+use crate::{errdata, errinput};
+use crate::storage::cdc::{Change, ChangeFeed};
 use crate::storage::engine;
-use crate::storage::engine::Engine;
-use crate::utils::{bin_coder, key_coder, Key as KeyTrait, Value};
+use crate::storage::engine::{Engine, WriteBatch};
+use crate::storage::util::KeyRange;
+use crate::utils::{bin_coder, get_timestamp_millis, key_coder, Key as KeyTrait, Value};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::ops::{Bound, RangeBounds};
-use std::sync::MutexGuard;
 /// 数据事务模块
 /// 主要分为以下几个功能子模块
 /// 1、事务管理
@@ -19,51 +20,405 @@ use std::sync::MutexGuard;
 /// 7、数据压缩
 use std::{
     borrow::Cow,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
 };
 
 #[allow(dead_code)]
 pub struct MVCC<E: Engine> {
-    // 引擎 增加原子指针和互斥锁，目的是实现线程安全的引擎
-    engine: Arc<Mutex<E>>,
+    // 引擎 增加原子指针和读写锁：读操作（get / scan）共享读锁并行执行，
+    // 写操作（begin / write / commit / rollback / gc）持有写锁，只在追加写入时互斥
+    engine: Arc<RwLock<E>>,
+    // 已提交写入的变更订阅
+    changes: Arc<ChangeFeed>,
+    // 本进程中尚未结束的只读事务，回收时不能删除它们仍需要的版本
+    readers: Arc<Readers>,
+    // 只读实例：引擎可能正被另一个进程读写，只允许开启只读事务
+    read_only: bool,
 }
 
 impl<E: Engine> MVCC<E> {
-    pub fn new(engine: E) -> Self {
+    /// 创建 MVCC 实例
+    /// 引擎由当前实例独占，此时引擎中残留的活跃事务都来自上一个进程（崩溃或未提交就退出），
+    /// 它们不可能再提交，因此在这里统一回滚，避免其版本永远被视为活跃而引发写冲突。
+    pub fn new(engine: E) -> Result<Self> {
+        let engine = Arc::new(RwLock::new(engine));
+        {
+            let mut session = engine.write()?;
+            // 推进进程纪元，本进程开启的事务都以新纪元登记
+            let epoch = match session.get(&Key::Epoch.encode()?)? {
+                Some(ref v) => Epoch::decode(v)? + 1,
+                None => 1,
+            };
+            session.set(&Key::Epoch.encode()?, &epoch.encode()?)?;
+            for txn in Self::scan_active_transactions(&session)? {
+                if txn.epoch < epoch {
+                    tracing::warn!("回滚上一个进程遗留的事务 {:?}", txn);
+                    Transaction::rollback_version(&mut *session, txn.version)?;
+                }
+            }
+        }
+        Ok(Self { engine, changes: Arc::new(ChangeFeed::default()), readers: Arc::default(), read_only: false })
+    }
+
+    /// 以只读方式创建 MVCC 实例，用于检查另一个进程正在读写的数据库
+    /// 引擎中的活跃事务可能属于仍在运行的写入方，因此不推进纪元、也不回滚它们，
+    /// 只读事务把它们视为活跃，看不到其中的写入。开启读写事务、写入无版本键和回收都返回 [`Error::ReadOnly`]。
+    pub fn new_read_only(engine: E) -> Self {
         Self {
-            engine: Arc::new(Mutex::new(engine)),
+            engine: Arc::new(RwLock::new(engine)),
+            changes: Arc::new(ChangeFeed::default()),
+            readers: Arc::default(),
+            read_only: true,
         }
     }
 
+    /// 是否以只读方式创建
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// 列出所有活跃（未提交）的读写事务
+    pub fn active_transactions(&self) -> Result<Vec<ActiveTransaction>> {
+        Self::scan_active_transactions(&*self.engine.read()?)
+    }
+
+    fn scan_active_transactions(session: &E) -> Result<Vec<ActiveTransaction>> {
+        let mut active = Vec::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::Active.encode()?);
+        while let Some((key, value)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                // 旧版本写入的活跃标记没有纪元，视为最早的纪元
+                Key::Active(version) => active.push(ActiveTransaction {
+                    version,
+                    epoch: Epoch::decode(&value).unwrap_or(0),
+                    writes: 0,
+                }),
+                key => return errdata!("require Key::Active got {key:?}"),
+            }
+        }
+        drop(scan);
+        for txn in active.iter_mut() {
+            txn.writes = session
+                .scan_prefix(&KeyPrefix::ActiveWrite(txn.version).encode()?)
+                .count();
+        }
+        Ok(active)
+    }
+
     /// 开启一个读写事务
     pub fn begin(&self) -> Result<Transaction<E>> {
-        Transaction::begin(self.engine.clone())
+        self.check_writable()?;
+        Ok(Transaction::begin(self.engine.clone())?.with_change_feed(self.changes.clone()))
+    }
+
+    /// 以指定的隔离级别开启一个读写事务
+    pub fn begin_with_isolation(&self, isolation: IsolationLevel) -> Result<Transaction<E>> {
+        self.check_writable()?;
+        Ok(Transaction::begin_with_isolation(self.engine.clone(), isolation)?.with_change_feed(self.changes.clone()))
     }
 
     /// 开启最近事务版本的一个只读事务
     pub fn begin_readonly(&self) -> Result<Transaction<E>> {
-        Transaction::begin_readonly(self.engine.clone(), None)
+        Transaction::begin_readonly(self.engine.clone(), &self.readers, None)
     }
 
     /// 开启指定版本的只读事务
     pub fn begin_readonly_version(&self, version: Version) -> Result<Transaction<E>> {
-        Transaction::begin_readonly(Arc::clone(&self.engine), Some(version))
+        Transaction::begin_readonly(Arc::clone(&self.engine), &self.readers, Some(version))
+    }
+
+    /// 开启按时间回溯的只读事务：只能看到提交时间不晚于 timestamp（毫秒级 Unix 时间戳）的事务写入
+    pub fn begin_readonly_at(&self, timestamp: u64) -> Result<Transaction<E>> {
+        Transaction::begin_readonly_at(Arc::clone(&self.engine), &self.readers, timestamp)
+    }
+
+    /// 解析时间戳对应的版本：提交时间不晚于 timestamp 的最新版本，不存在时返回 None
+    pub fn version_at(&self, timestamp: u64) -> Result<Option<Version>> {
+        Self::latest_commit_at(&*self.engine.read()?, timestamp)
+    }
+
+    /// 从最新的版本向前扫描提交时间，只读取 timestamp 之后提交的版本
+    fn latest_commit_at(session: &E, timestamp: u64) -> Result<Option<Version>> {
+        let mut scan = session.scan_prefix(&KeyPrefix::CommitTime.encode()?).rev();
+        while let Some((key, value)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::CommitTime(version) if u64::decode(&value)? <= timestamp => return Ok(Some(version)),
+                Key::CommitTime(_) => {}
+                key => return errdata!("require Key::CommitTime got {key:?}"),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 回收水位线以下的版本全部提交的时间，见 [`MVCC::prune_commit_times`]；没有回收过时为 0
+    fn commit_horizon(session: &E, watermark: Version) -> Result<u64> {
+        let from = Key::CommitTime(0).encode()?;
+        let to = Key::CommitTime(watermark).encode()?;
+        match session.scan(from..to).next_back().transpose()? {
+            Some((_, value)) => u64::decode(&value),
+            None => Ok(0),
+        }
+    }
+
+    /// 水位线以下的提交时间只保留版本最新的一条，值改为其中最晚的提交时间。
+    /// 这些版本对所有事务都已可见，按时间回溯时只需要知道它们最晚在什么时候全部提交，
+    /// 早于这个时间的回溯查询无法再还原，直接报错。
+    fn prune_commit_times(session: &E, watermark: Version, batch: &mut WriteBatch) -> Result<()> {
+        let from = Key::CommitTime(0).encode()?;
+        let to = Key::CommitTime(watermark).encode()?;
+        let (mut newest, mut horizon, mut pruned) = (None, 0, 0);
+        let mut scan = session.scan(from..to);
+        while let Some((key, value)) = scan.next().transpose()? {
+            horizon = horizon.max(u64::decode(&value)?);
+            if let Some(older) = newest.replace(key) {
+                batch.delete(&older);
+                pruned += 1;
+            }
+        }
+        if let Some(key) = newest.filter(|_| pruned > 0) {
+            batch.put(&key, &horizon.encode()?);
+        }
+        Ok(())
     }
 
     /// 事务状态恢复
     pub fn resume(&self, transaction_state: TransactionState) -> Result<Transaction<E>> {
-        Transaction::resume(self.engine.clone(), transaction_state)
+        Ok(Transaction::resume(self.engine.clone(), &self.readers, transaction_state)?
+            .with_change_feed(self.changes.clone()))
+    }
+
+    /// 已提交写入的变更订阅（CDC）
+    pub fn changes(&self) -> &Arc<ChangeFeed> {
+        &self.changes
     }
 
     /// 获取无版本标记key的值
     pub fn get_unversioned(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        self.engine.lock()?.get(key)
+        self.engine.read()?.get(key)
+    }
+
+    /// 按前缀扫描无版本标记的键值对
+    pub fn scan_unversioned(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.engine.read()?.scan_prefix(prefix).collect()
     }
 
     /// 设置无版本标记的键值对
     pub fn set_unversioned(&self, key: &Vec<u8>, value: &[u8]) -> Result<()> {
-        self.engine.lock()?.set(key, value)
+        self.check_writable()?;
+        self.engine.write()?.set(key, value)
+    }
+
+    /// 在 dest 下生成数据库的一致副本（见 [`Engine::checkpoint`]）
+    /// 只持有读锁：备份期间查询照常执行，写入等待备份完成；副本中未提交的事务在打开时回滚
+    pub fn checkpoint(&self, dest: &std::path::Path) -> Result<()> {
+        self.engine.read()?.checkpoint(dest)
+    }
+
+    /// 计算回收水位线：所有活跃事务及其快照中最旧的版本、本进程中只读事务仍需要的最旧版本，
+    /// 没有活跃事务时为下一个版本号。低于水位线的版本都已提交，且对所有活跃事务和之后开启的事务可见。
+    pub fn watermark(&self) -> Result<Version> {
+        self.compute_watermark(&*self.engine.read()?)
     }
+
+    fn compute_watermark(&self, session: &E) -> Result<Version> {
+        let mut watermark = match session.get(&Key::NextVersion.encode()?)? {
+            Some(ref v) => Version::decode(v)?,
+            None => 1,
+        };
+        if let Some(oldest) = self.readers.oldest()? {
+            watermark = watermark.min(oldest);
+        }
+        for version in Transaction::scan_active(session)? {
+            watermark = watermark.min(version);
+            if let Some(ref snapshot) = session.get(&Key::Snapshot(version).encode()?)? {
+                if let Some(oldest) = BTreeSet::<Version>::decode(snapshot)?.first() {
+                    watermark = watermark.min(*oldest);
+                }
+            }
+        }
+        Ok(watermark)
+    }
+
+    /// 上一次回收的水位线，低于它的版本可能已经被删除
+    fn gc_watermark(session: &E) -> Result<Version> {
+        match session.get(&Key::GcWatermark.encode()?)? {
+            Some(ref v) => Version::decode(v),
+            None => Ok(0),
+        }
+    }
+
+    /// 垃圾回收（VACUUM）
+    /// 1、计算回收水位线并持久化
+    /// 2、对每个键，低于水位线的版本只保留最新的一个；若它是墓碑，则一并删除
+    /// 3、删除低于水位线的快照
+    ///
+    /// 只读事务不写入引擎，由本进程内存中的登记约束水位线。水位线在写锁下计算并写入，
+    /// 只读事务在读锁下检查它再登记，因此之后开启的只读事务若需要更旧的版本会直接报错，
+    /// 不会读到回收了一半的数据。水位线以下的提交时间在同一个批次中清理。
+    pub fn gc(&self) -> Result<GcReport> {
+        self.gc_in_batches(GC_BATCH_SIZE)
+    }
+
+    /// 垃圾回收，每批最多扫描 batch_size 个版本键后释放锁
+    fn gc_in_batches(&self, batch_size: usize) -> Result<GcReport> {
+        self.check_writable()?;
+        let watermark = {
+            let mut session = self.engine.write()?;
+            let watermark = self.compute_watermark(&session)?.max(Self::gc_watermark(&session)?);
+            let mut batch = WriteBatch::new();
+            batch.put(&Key::GcWatermark.encode()?, &watermark.encode()?);
+            Self::prune_commit_times(&session, watermark, &mut batch)?;
+            session.write_batch(batch)?;
+            watermark
+        };
+        let mut report = GcReport { watermark, ..Default::default() };
+        // 分批扫描所有版本键，每批处理完整的若干个键，批次之间释放锁
+        let mut start = Bound::Included(Key::Version(vec![].into(), 0).encode()?);
+        let end = Bound::Excluded(KeyPrefix::Unversioned.encode()?);
+        loop {
+            let mut session = self.engine.write()?;
+            let mut batch = WriteBatch::new();
+            let mut resume = None;
+            let mut scan = session.scan((start, end.clone()));
+            let mut current: Option<Vec<u8>> = None;
+            // 当前键低于水位线的版本：（版本键, 是否为墓碑）
+            let mut below = Vec::new();
+            let mut scanned = 0;
+            while let Some((raw, value)) = scan.next().transpose()? {
+                let (key, version) = match Key::decode(&raw)? {
+                    Key::Version(key, version) => (key.into_owned(), version),
+                    key => return errdata!("require Key::Version got {key:?}"),
+                };
+                if current.as_ref() != Some(&key) {
+                    Self::collect_garbage(&mut below, &mut batch, &mut report);
+                    if scanned >= batch_size {
+                        resume = Some(Key::Version(key.into(), 0).encode()?);
+                        break;
+                    }
+                    current = Some(key);
+                }
+                scanned += 1;
+                if version < watermark {
+                    let tombstone = bin_coder::decode::<Option<Vec<u8>>>(&value)?.is_none();
+                    below.push((raw, tombstone));
+                }
+            }
+            drop(scan);
+            Self::collect_garbage(&mut below, &mut batch, &mut report);
+            session.write_batch(batch)?;
+            match resume {
+                Some(key) => start = Bound::Included(key),
+                None => break,
+            }
+        }
+        // 低于水位线的版本都已结束，它们的快照不再被使用
+        let mut session = self.engine.write()?;
+        let mut batch = WriteBatch::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::Snapshot.encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::Snapshot(version) if version < watermark => {
+                    batch.delete(&key);
+                    report.snapshots_removed += 1;
+                }
+                Key::Snapshot(_) => {}
+                key => return errdata!("require Key::Snapshot got {key:?}"),
+            }
+        }
+        drop(scan);
+        session.write_batch(batch)?;
+        Ok(report)
+    }
+
+    /// 处理一个键低于水位线的版本（按版本号升序）
+    fn collect_garbage(below: &mut Vec<(Vec<u8>, bool)>, batch: &mut WriteBatch, report: &mut GcReport) {
+        let newest = below.len().saturating_sub(1);
+        for (i, (key, tombstone)) in below.drain(..).enumerate() {
+            // 最新的非墓碑版本仍然对所有事务可见
+            if i == newest && !tombstone {
+                continue;
+            }
+            batch.delete(&key);
+            match tombstone {
+                true => report.tombstones_removed += 1,
+                false => report.versions_removed += 1,
+            }
+        }
+    }
+}
+
+/// 进程纪元：每次 [`MVCC::new`] 打开引擎时加一，用于识别上一个进程遗留的活跃事务
+pub type Epoch = u64;
+
+/// 活跃事务的概要信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveTransaction {
+    /// 事务版本号
+    pub version: Version,
+    /// 开启该事务的进程纪元
+    pub epoch: Epoch,
+    /// 已写入的键数量
+    pub writes: usize,
+}
+
+/// 写标记的值。BitCask 把空值当作墓碑，重启后会丢弃，因此标记必须带一个非空的值
+const ACTIVE_WRITE_MARKER: &[u8] = &[1];
+
+/// 垃圾回收每批扫描的版本键数量
+const GC_BATCH_SIZE: usize = 1024;
+
+/// 本进程中尚未结束的只读事务：版本号 → 事务数量
+/// 版本号是事务仍需要读取的最旧版本，即观察版本与其活跃集合中的最小值
+#[derive(Default)]
+struct Readers(Mutex<BTreeMap<Version, usize>>);
+
+impl Readers {
+    fn register(self: &Arc<Self>, version: Version) -> Result<ReaderGuard> {
+        *self.0.lock()?.entry(version).or_default() += 1;
+        Ok(ReaderGuard { readers: self.clone(), version })
+    }
+
+    fn oldest(&self) -> Result<Option<Version>> {
+        Ok(self.0.lock()?.keys().next().copied())
+    }
+}
+
+/// 只读事务的登记，事务结束（提交、回滚或丢弃）时注销
+struct ReaderGuard {
+    readers: Arc<Readers>,
+    version: Version,
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        let Ok(mut readers) = self.readers.0.lock() else { return };
+        if let Some(count) = readers.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&self.version);
+            }
+        }
+    }
+}
+
+/// 垃圾回收结果
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    /// 回收水位线
+    pub watermark: Version,
+    /// 删除的旧版本数量
+    pub versions_removed: u64,
+    /// 删除的墓碑数量
+    pub tombstones_removed: u64,
+    /// 删除的快照数量
+    pub snapshots_removed: u64,
 }
 
 /// 一个事务的版本号是逻辑上的时间戳
@@ -104,6 +459,12 @@ pub enum Key<'a> {
         #[serde(borrow)]
         Cow<'a, [u8]>,
     ),
+    /// 当前进程纪元
+    Epoch,
+    /// 版本的提交时间（毫秒级 Unix 时间戳），用于按时间回溯
+    CommitTime(Version),
+    /// 上一次垃圾回收的水位线
+    GcWatermark,
 }
 
 impl<'a> KeyTrait<'a> for Key<'a> {}
@@ -120,6 +481,9 @@ pub enum KeyPrefix<'a> {
         Cow<'a, [u8]>,
     ),
     Unversioned,
+    Epoch,
+    CommitTime,
+    GcWatermark,
 }
 
 impl<'a> KeyTrait<'a> for KeyPrefix<'a> {}
@@ -127,9 +491,41 @@ impl<'a> KeyTrait<'a> for KeyPrefix<'a> {}
 /// 事务结构体
 pub struct Transaction<E: Engine> {
     // 存储引擎
-    engine: Arc<Mutex<E>>,
+    engine: Arc<RwLock<E>>,
     // 事务状态
     state: TransactionState,
+    // 可串行化事务读过的范围（Key::Version 编码后的引擎范围），提交时校验
+    reads: Mutex<Vec<KeyRange>>,
+    // 保存点与撤销日志
+    savepoints: Mutex<Savepoints>,
+    // 提交时发布变更的订阅，只读事务没有
+    feed: Option<Arc<ChangeFeed>>,
+    // 只读事务在回收水位线中的登记，读写事务没有
+    reader: Mutex<Option<ReaderGuard>>,
+}
+
+/// 事务内的保存点
+/// 每次写入分配一个递增的序号；存在保存点时，写入前先把该键在本事务中的旧值记入撤销日志。
+/// 回滚到保存点时，按序号倒序撤销所有晚于该保存点的写入。
+/// 撤销日志只保存在内存中：进程崩溃时整个事务都会被回滚，不需要持久化。
+#[derive(Default)]
+struct Savepoints {
+    // 下一次写入的序号
+    next_seq: u64,
+    // （名称, 创建时的写入序号），按创建顺序排列
+    stack: Vec<(String, u64)>,
+    // （写入序号, 键, 写入前本事务中该键的版本值；None 表示此前未写过）
+    undo: Vec<(u64, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+/// 事务隔离级别
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// 快照隔离：只检测写写冲突，允许写偏斜（write skew）
+    #[default]
+    Snapshot,
+    /// 可串行化：额外记录读集合，提交时若读过的键已被并发事务写入则中止
+    Serializable,
 }
 
 /// 事务状态结构体
@@ -141,6 +537,8 @@ pub struct TransactionState {
     readonly: bool,
     // 未提交的活跃事务
     active: BTreeSet<Version>,
+    // 隔离级别
+    isolation: IsolationLevel,
 }
 
 impl TransactionState {
@@ -168,10 +566,15 @@ impl TransactionState {
 }
 
 impl<E: Engine> Transaction<E> {
-    /// 开启事务
-    pub fn begin(engine: Arc<Mutex<E>>) -> Result<Transaction<E>> {
+    /// 开启事务（快照隔离）
+    pub fn begin(engine: Arc<RwLock<E>>) -> Result<Transaction<E>> {
+        Self::begin_with_isolation(engine, IsolationLevel::Snapshot)
+    }
+
+    /// 以指定隔离级别开启事务
+    pub fn begin_with_isolation(engine: Arc<RwLock<E>>, isolation: IsolationLevel) -> Result<Transaction<E>> {
         // 获取存储引擎
-        let mut session = engine.lock()?;
+        let mut session = engine.write()?;
         // 从存储引擎获取下一个版本号
         // 如果获取失败，则初始化一个版本号为1，并将下一个版本号写入存储引擎
         // 如果获取成功，则直接将下一个版本号写入存储引擎
@@ -180,16 +583,23 @@ impl<E: Engine> Transaction<E> {
             None => 1u64,
         };
         let next_version = version + 1;
-        // 将下一个版本号写入存储引擎
-        session.set(&Key::NextVersion.encode()?, &next_version.encode()?)?;
+        let epoch = match session.get(&Key::Epoch.encode()?)? {
+            Some(ref v) => Epoch::decode(v)?,
+            None => 0,
+        };
         // 扫描所有活跃事务
-        let active = Self::scan_active(&mut session)?;
+        let active = Self::scan_active(&session)?;
+        // 版本号、快照和活跃标记在同一个批次中原子写入
+        let mut batch = WriteBatch::new();
+        // 将下一个版本号写入存储引擎
+        batch.put(&Key::NextVersion.encode()?, &next_version.encode()?);
         // 如果活跃事务集合不为空，则保存快照
         if !active.is_empty() {
-            session.set(&Key::Snapshot(version).encode()?, &active.encode()?)?;
+            batch.put(&Key::Snapshot(version).encode()?, &active.encode()?);
         }
-        // 标记当前版本为活跃事务
-        session.set(&Key::Active(version).encode()?, &vec![])?;
+        // 标记当前版本为活跃事务，值为开启它的进程纪元
+        batch.put(&Key::Active(version).encode()?, &epoch.encode()?);
+        session.write_batch(batch)?;
         // 删除锁
         drop(session);
         // 返回事务对象
@@ -199,19 +609,26 @@ impl<E: Engine> Transaction<E> {
                 version,
                 readonly: false,
                 active,
+                isolation,
             },
+            reads: Mutex::new(Vec::new()),
+            savepoints: Mutex::new(Savepoints::default()),
+            feed: None,
+            reader: Mutex::new(None),
         })
     }
 
     ///开启只读事务
     /// 开始一个新的只读事务。如果指定了版本参数，事务将会看到该版本开始时的数据状态（会忽略该版本中的写入操作）。
     /// 换句话说，它看到的状态与该版本的读写事务开始时看到的状态相同
-    pub fn begin_readonly(
-        engine: Arc<Mutex<E>>,
+    fn begin_readonly(
+        engine: Arc<RwLock<E>>,
+        readers: &Arc<Readers>,
         target: Option<Version>,
     ) -> Result<Transaction<E>> {
         // 1、开启一个只读事务
-        let mut session = engine.lock()?;
+        let session = engine.read()?;
+        let watermark = MVCC::gc_watermark(&*session)?;
         // 2、获取当前最新的版本号，但只读事务不消耗版本号
         // 只读事务使用一个观察版本号，确保它能看到所有已提交的数据，但看不到未来版本的写入
         // 使用当前next_version作为观察点，能看到所有 < next_version 的已提交数据
@@ -223,6 +640,10 @@ impl<E: Engine> Transaction<E> {
         // 3、如果存在目标版本号，则返回该版本号的快照
         let active_snapshot = match target {
             Some(target) => {
+                // 低于回收水位线的快照和版本可能已经被删除
+                if target < watermark {
+                    return Self::vacuumed(target, watermark);
+                }
                 // 获取指定版本号的快照
                 match session.get(&Key::Snapshot(target).encode()?)? {
                     Some(ref v) => BTreeSet::<Version>::decode(v)?,
@@ -231,22 +652,88 @@ impl<E: Engine> Transaction<E> {
             }
             None => {
                 // 获取当前活跃事务集合
-                Self::scan_active(&mut session)?
+                Self::scan_active(&session)?
             }
         };
-        // 4、删除锁
+        // 指定版本时只能看到该版本开启前已提交的数据
+        let state = TransactionState {
+            version: target.unwrap_or(readonly_version),
+            readonly: true,
+            active: active_snapshot,
+            isolation: IsolationLevel::Snapshot,
+        };
+        // 4、持有读锁时登记，回收不会删除本事务需要的版本
+        let reader = Self::register_reader(&*session, readers, &state)?;
         drop(session);
         // 5、返回事务对象
         Ok(Self {
             engine,
-            state: TransactionState {
-                version: readonly_version,
-                readonly: true,
-                active: active_snapshot,
-            },
+            state,
+            reads: Mutex::new(Vec::new()),
+            savepoints: Mutex::new(Savepoints::default()),
+            feed: None,
+            reader: Mutex::new(Some(reader)),
         })
     }
 
+    /// 开启按时间回溯的只读事务
+    /// 可见版本为提交时间不晚于 timestamp 的版本：观察版本号取其中最大的版本加一，
+    /// 更小的版本中提交时间晚于 timestamp 的、以及当前仍活跃的都放进活跃集合。
+    /// 没有提交时间记录的旧版本（记录提交时间之前写入的数据，或已回滚而不留数据的版本）视为已提交。
+    ///
+    /// 比最大版本小、却在它之后提交的版本，在它开启时一定还没有提交，都在它的快照中，
+    /// 因此只需要检查快照中的版本，不必读取全部提交时间。
+    fn begin_readonly_at(engine: Arc<RwLock<E>>, readers: &Arc<Readers>, timestamp: u64) -> Result<Transaction<E>> {
+        let session = engine.read()?;
+        let horizon = MVCC::commit_horizon(&*session, MVCC::gc_watermark(&*session)?)?;
+        if timestamp < horizon {
+            return errinput!("timestamp {timestamp} is no longer available: VACUUM removed versions committed up to {horizon}");
+        }
+        let (version, mut active) = match MVCC::latest_commit_at(&*session, timestamp)? {
+            Some(latest) => {
+                let mut active = BTreeSet::new();
+                if let Some(ref snapshot) = session.get(&Key::Snapshot(latest).encode()?)? {
+                    for v in BTreeSet::<Version>::decode(snapshot)? {
+                        if let Some(ref commit_time) = session.get(&Key::CommitTime(v).encode()?)? {
+                            if u64::decode(commit_time)? > timestamp {
+                                active.insert(v);
+                            }
+                        }
+                    }
+                }
+                (latest + 1, active)
+            }
+            None => (1, BTreeSet::new()),
+        };
+        active.extend(Self::scan_active(&*session)?.into_iter().filter(|v| *v < version));
+        let state = TransactionState { version, readonly: true, active, isolation: IsolationLevel::Snapshot };
+        let reader = Self::register_reader(&*session, readers, &state)?;
+        drop(session);
+        Ok(Self {
+            engine,
+            state,
+            reads: Mutex::new(Vec::new()),
+            savepoints: Mutex::new(Savepoints::default()),
+            feed: None,
+            reader: Mutex::new(Some(reader)),
+        })
+    }
+
+    /// 登记只读事务仍需要的最旧版本：观察版本与活跃集合中的最小值
+    /// 低于上一次回收水位线时，需要的旧版本可能已经被删除，直接报错
+    fn register_reader(session: &E, readers: &Arc<Readers>, state: &TransactionState) -> Result<ReaderGuard> {
+        let oldest = state.active.first().map_or(state.version, |v| state.version.min(*v));
+        let watermark = MVCC::gc_watermark(session)?;
+        if oldest < watermark {
+            return Self::vacuumed(oldest, watermark);
+        }
+        readers.register(oldest)
+    }
+
+    fn vacuumed<T>(version: Version, watermark: Version) -> Result<T> {
+        errinput!("version {version} is no longer available: VACUUM removed versions below {watermark}")
+    }
+
     /// 数据写入操作（状态检测=>乐观并发事务）
     /// 这个函数在事务的特定版本号下为一个键写入新的值或标记删除。
     /// 它接收两个参数：要写入的键（key）和可选的值（value）。
@@ -257,7 +744,7 @@ impl<E: Engine> Transaction<E> {
             return errdata!("readonly transaction");
         }
         // 2、获取session
-        let mut session = self.engine.lock()?;
+        let mut session = self.engine.write()?;
         // 3、写冲突检测
         // 构建扫描范围
         // 从当前下一个版本开始，到u64::MAX结束，扫描所有版本号
@@ -287,7 +774,7 @@ impl<E: Engine> Transaction<E> {
         // 表示这个 key 在当前事务版本（self.state.version）中有写入行为。
         // - 记录事务写操作：这里创建了一个特殊类型的记录 ActiveWrite(version, key)，用来跟踪当前事务（由 self.state.version 标识）修改了哪个键。
         // - 目的是支持事务回滚：如果事务需要回滚，系统需要知道该事务修改了哪些键，以便撤销这些修改。通过扫描所有 ActiveWrite(version, ...) 记录，系统可以找出所有需要删除的版本。
-        // - 值为空向量 vec![]：因为只需要记录"这个键被这个事务修改过"这一事实，不需要存储实际值（实际值会存储在 Key::Version 记录中）。
+        // - 值为固定标记 ACTIVE_WRITE_MARKER：只需要记录"这个键被这个事务修改过"这一事实，不需要存储实际值（实际值会存储在 Key::Version 记录中）。
         // 为什么需要这个记录：
         // 在 rollback() 方法中，系统会扫描所有 ActiveWrite(version, key) 记录，找出当前事务写入的所有键
         // 然后删除对应的 Version(key, version) 记录以及 ActiveWrite 记录本身
@@ -295,20 +782,26 @@ impl<E: Engine> Transaction<E> {
         // 与活跃事务集的关系：
         // 活跃事务集(active set)是通过 Active(version) 记录来跟踪的，不是 ActiveWrite 记录
         // ActiveWrite 只记录事务内部的写操作，与其他事务的版本无关
-        session.set(
-            &Key::ActiveWrite(self.state.version, key.into()).encode()?,
-            &vec![],
-        )?;
+        // 写标记与新版本在同一个批次中原子写入，崩溃时不会只留下其中之一
+        // 存在保存点时记录写入前的值，供回滚到保存点时恢复
+        let version_key = Key::Version(key.into(), self.state.version).encode()?;
+        let mut savepoints = self.savepoints.lock()?;
+        let seq = savepoints.next_seq;
+        savepoints.next_seq += 1;
+        if !savepoints.stack.is_empty() {
+            let prior = session.get(&version_key)?;
+            savepoints.undo.push((seq, key.to_vec(), prior));
+        }
+        let mut batch = WriteBatch::new();
+        batch.put(&Key::ActiveWrite(self.state.version, key.into()).encode()?, ACTIVE_WRITE_MARKER);
         // 写入key
-        session.set(
-            &Key::Version(key.into(), self.state.version).encode()?,
-            &bin_coder::encode(value)?,
-        )?;
-        Ok(())
+        batch.put(&version_key, &bin_coder::encode(value)?);
+        session.write_batch(batch)
+
     }
 
     /// 扫描活跃事务
-    fn scan_active(session: &mut MutexGuard<E>) -> Result<BTreeSet<Version>> {
+    fn scan_active(session: &E) -> Result<BTreeSet<Version>> {
         // 初始化活跃事务集合
         let mut active = BTreeSet::new();
         // 扫描所有活跃事务
@@ -329,6 +822,11 @@ impl<E: Engine> Transaction<E> {
         self.state.version
     }
 
+    /// 获取事务隔离级别
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.state.isolation
+    }
+
     /// 查询当前事务是否为只读类型
     pub fn is_readonly(&self) -> bool {
         self.state.readonly
@@ -351,7 +849,11 @@ impl<E: Engine> Transaction<E> {
 
     /// 读取键
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let mut session = self.engine.lock()?;
+        self.record_read((
+            Bound::Included(Key::Version(key.into(), 0).encode()?),
+            Bound::Included(Key::Version(key.into(), u64::MAX).encode()?),
+        ))?;
+        let session = self.engine.read()?;
         let from = Key::Version(key.into(), 0).encode()?;
         let to = Key::Version(key.into(), self.get_version()).encode()?;
         // 按照版本号倒序，目的是获取最新的有效值
@@ -369,136 +871,336 @@ impl<E: Engine> Transaction<E> {
         Ok(None)
     }
 
+    /// 获取键对当前事务可见的所有版本，按版本号升序排列
+    /// 值为 None 表示该版本删除了这个键
+    /// VACUUM 会回收水位线以下的旧版本，因此历史只能追溯到最近一次回收时仍需保留的版本
+    pub fn history(&self, key: &[u8]) -> Result<Vec<(Version, Option<Vec<u8>>)>> {
+        let from = Key::Version(key.into(), 0).encode()?;
+        let to = Key::Version(key.into(), u64::MAX).encode()?;
+        self.record_read((Bound::Included(from.clone()), Bound::Included(to.clone())))?;
+        let session = self.engine.read()?;
+        let mut history = Vec::new();
+        let mut scan = session.scan(from..=to);
+        while let Some((key_version, val)) = scan.next().transpose()? {
+            match Key::decode(&key_version)? {
+                Key::Version(_, version) => {
+                    if self.state.is_visible(version) {
+                        history.push((version, bin_coder::decode(&val)?));
+                    }
+                }
+                key => return errdata!("require Key::Version got {key:?}"),
+            }
+        }
+        Ok(history)
+    }
+
     /// 事务提交
     pub fn commit(self) -> Result<()> {
         //1、只读事务不用处理直接返回
         if self.state.readonly {
             return Ok(());
         }
-        //2、删除当前版本下所有写事务标记键
-        let mut session = self.engine.lock()?;
+        let mut session = self.engine.write()?;
+        //2、可串行化事务校验读集合，失败则回滚
+        if let Err(err) = self.validate_reads(&session) {
+            Self::rollback_version(&mut *session, self.state.version)?;
+            return Err(err);
+        }
+        //3、删除当前版本下所有写事务标记键
         let remove: Vec<_> = session
             .scan_prefix(&KeyPrefix::ActiveWrite(self.state.version).encode()?)
             .map_ok(|(k, _)| k)
             .try_collect()?;
+        let changes = match self.feed {
+            Some(_) => self.collect_changes(&*session, &remove)?,
+            None => Vec::new(),
+        };
+        let commit_time = get_timestamp_millis();
 
+        let mut batch = WriteBatch::new();
         for key in remove {
-            session.delete(&key)?
+            batch.delete(&key);
+        }
+        //4、删除当前版本所有的活跃事务键，与写标记一起原子删除
+        batch.delete(&Key::Active(self.state.version).encode()?);
+        //5、记录提交时间，供按时间回溯时解析版本
+        batch.put(&Key::CommitTime(self.state.version).encode()?, &commit_time.encode()?);
+        session.write_batch(batch)?;
+        //6、仍持有写锁时发布变更，保证发布顺序与提交顺序一致
+        if let Some(feed) = &self.feed {
+            if !changes.is_empty() {
+                feed.publish(self.state.version, commit_time, changes)?;
+            }
         }
-        //3、删除当前版本所有的活跃事务键
-        session.delete(&Key::Active(self.state.version).encode()?)
+        Ok(())
+    }
+
+    /// 关联变更订阅，提交时发布写入
+    fn with_change_feed(mut self, feed: Arc<ChangeFeed>) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    /// 收集本事务写入的键在提交前后的值
+    /// 写冲突检测保证不存在对本事务不可见的已提交版本，因此提交前的值就是本事务可见的最新旧版本
+    fn collect_changes(&self, session: &E, active_writes: &[Vec<u8>]) -> Result<Vec<Change>> {
+        let mut changes = Vec::with_capacity(active_writes.len());
+        for active_write in active_writes {
+            let key = match Key::decode(active_write)? {
+                Key::ActiveWrite(_, key) => key.into_owned(),
+                key => return errdata!("require Key::ActiveWrite got {key:?}"),
+            };
+            let after = match session.get(&Key::Version(key.as_slice().into(), self.state.version).encode()?)? {
+                Some(value) => bin_coder::decode(&value)?,
+                None => continue,
+            };
+            let from = Key::Version(key.as_slice().into(), 0).encode()?;
+            let to = Key::Version(key.as_slice().into(), self.state.version).encode()?;
+            let mut before = None;
+            let mut scan = session.scan(from..to).rev();
+            while let Some((key_version, value)) = scan.next().transpose()? {
+                match Key::decode(&key_version)? {
+                    Key::Version(_, version) if self.state.is_visible(version) => {
+                        before = bin_coder::decode(&value)?;
+                        break;
+                    }
+                    Key::Version(..) => {}
+                    key => return errdata!("require Key::Version got {key:?}"),
+                }
+            }
+            changes.push(Change { key, before, after });
+        }
+        Ok(changes)
+    }
+
+    /// 记录可串行化读写事务读过的范围
+    fn record_read(&self, range: KeyRange) -> Result<()> {
+        if self.state.isolation == IsolationLevel::Serializable && !self.state.readonly {
+            self.reads.lock()?.push(range);
+        }
+        Ok(())
+    }
+
+    /// 校验读集合：读过的范围内若存在对本事务不可见、且已经提交的版本，说明有先于本事务提交的
+    /// 并发事务写入了本事务读过的数据，即存在指向先提交者的读写反依赖（rw-antidependency）。
+    /// 仍未提交的写入者会在本事务之后提交，对应的依赖边与提交顺序一致，由它提交时自行校验。
+    /// 这样所有可串行化事务之间的依赖边都从先提交者指向后提交者，依赖图无环。
+    fn validate_reads(&self, session: &E) -> Result<()> {
+        let reads = self.reads.lock()?;
+        if reads.is_empty() {
+            return Ok(());
+        }
+        let active = Self::scan_active(session)?;
+        for range in reads.iter() {
+            let mut scan = session.scan(range.clone());
+            while let Some((key, _)) = scan.next().transpose()? {
+                match Key::decode(&key)? {
+                    Key::Version(_, version) if !self.state.is_visible(version) && !active.contains(&version) => {
+                        return errdata!("serialization failure: read conflicts with concurrent transaction {version}");
+                    }
+                    Key::Version(..) => {}
+                    key => return errdata!("require Key::Version got {key:?}"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 创建保存点，同名保存点可以重复创建，回滚和释放时使用最近的一个
+    pub fn savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.lock()?;
+        let seq = savepoints.next_seq;
+        savepoints.stack.push((name.to_string(), seq));
+        Ok(())
+    }
+
+    /// 撤销保存点之后的所有写入，保存点本身保留，可以再次回滚到它
+    /// 之后创建的保存点被丢弃
+    pub fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.lock()?;
+        let Some(index) = savepoints.stack.iter().rposition(|(n, _)| n == name) else {
+            return errinput!("savepoint {name} does not exist");
+        };
+        let seq = savepoints.stack[index].1;
+        savepoints.stack.truncate(index + 1);
+        let keep = savepoints.undo.partition_point(|(s, _, _)| *s < seq);
+        let undo = savepoints.undo.split_off(keep);
+        if undo.is_empty() {
+            return Ok(());
+        }
+        // 倒序撤销：同一个键被多次写入时，最终恢复为最早一次写入前的值
+        let mut session = self.engine.write()?;
+        let mut batch = WriteBatch::new();
+        for (_, key, prior) in undo.into_iter().rev() {
+            let version_key = Key::Version(key.as_slice().into(), self.state.version).encode()?;
+            match prior {
+                Some(value) => batch.put(&version_key, &value),
+                None => {
+                    batch.delete(&Key::ActiveWrite(self.state.version, key.into()).encode()?);
+                    batch.delete(&version_key)
+                }
+            };
+        }
+        session.write_batch(batch)
+    }
+
+    /// 释放保存点及之后创建的保存点，已经做出的写入保留
+    pub fn release_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.lock()?;
+        let Some(index) = savepoints.stack.iter().rposition(|(n, _)| n == name) else {
+            return errinput!("savepoint {name} does not exist");
+        };
+        savepoints.stack.truncate(index);
+        // 撤销日志仍可能被更早的保存点用到，没有保存点时才清空
+        if savepoints.stack.is_empty() {
+            savepoints.undo.clear();
+        }
+        Ok(())
     }
 
     /// 事务回滚
     pub fn rollback(&self) -> Result<()> {
-        //1、只读事务不需要处理
+        //1、只读事务只需要注销登记
         if self.state.readonly {
+            self.reader.lock()?.take();
             return Ok(());
         }
-        //2、扫描当前版本所有具备【写事务】标记的key
-        let mut session = self.engine.lock()?;
+        Self::rollback_version(&mut *self.engine.write()?, self.state.version)
+    }
+
+    /// 回滚指定版本的读写事务
+    fn rollback_version(session: &mut E, version: Version) -> Result<()> {
+        //1、扫描该版本所有具备【写事务】标记的key
         let mut rollback = Vec::<Vec<u8>>::new();
-        let mut scan = session.scan_prefix(&KeyPrefix::ActiveWrite(self.state.version).encode()?);
+        let mut scan = session.scan_prefix(&KeyPrefix::ActiveWrite(version).encode()?);
         while let Some((key, _)) = scan.next().transpose()? {
             match Key::decode(&key)? {
                 Key::ActiveWrite(_, key) => {
-                    rollback.push(Key::Version(key, self.get_version()).encode()?);
+                    rollback.push(Key::Version(key, version).encode()?);
                 }
                 key => return errdata!("require Key::ActiveWrite got {key:?}"),
             }
             rollback.push(key);
         }
         drop(scan);
-        //3、删除当前版本所有有【写事务】标记的key
+        //2、删除该版本所有有【写事务】标记的key
+        let mut batch = WriteBatch::new();
         for key in rollback {
-            session.delete(&key)?;
+            batch.delete(&key);
         }
-        //4、删除当前版本所有的活跃事务
-        session.delete(&Key::Active(self.state.version).encode()?)
+        //3、删除该版本的活跃事务标记，整个回滚原子生效
+        batch.delete(&Key::Active(version).encode()?);
+        session.write_batch(batch)
     }
 
     /// 恢复指定事务的状态
-    fn resume(engine: Arc<Mutex<E>>, s: TransactionState) -> Result<Self> {
-        // 检验合法性，如果事务不是只读事务且没有活跃事务存在则报错
-        if !s.readonly
-            && engine
-            .lock()?
-            .get(&Key::Active(s.version).encode()?)?
-            .is_none()
-        {
-            return Err(errdata!("no active key"));
-        }
-        Ok(Self { engine, state: s })
+    fn resume(engine: Arc<RwLock<E>>, readers: &Arc<Readers>, s: TransactionState) -> Result<Self> {
+        // 检验合法性，如果事务不是只读事务且没有活跃事务存在则报错；只读事务重新登记
+        let session = engine.read()?;
+        let reader = match s.readonly {
+            true => Some(Self::register_reader(&*session, readers, &s)?),
+            false if session.get(&Key::Active(s.version).encode()?)?.is_none() => return Err(errdata!("no active key")),
+            false => None,
+        };
+        drop(session);
+        Ok(Self {
+            engine,
+            state: s,
+            reads: Mutex::new(Vec::new()),
+            savepoints: Mutex::new(Savepoints::default()),
+            feed: None,
+            reader: Mutex::new(reader),
+        })
     }
 
     /// 范围扫描
-    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> ScanIterator<E> {
-        let msg = "范围编码错误";
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<ScanIterator<E>> {
         let start_bound = match range.start_bound() {
-            Bound::Excluded(start) => Bound::Excluded(Key::Version(start.into(), u64::MAX).encode().expect(msg)),
-            Bound::Included(start) => Bound::Included(Key::Version(start.into(), 0).encode().expect(msg)),
-            Bound::Unbounded => Bound::Included(Key::Version(vec![].into(), 0).encode().expect(msg)),
+            Bound::Excluded(start) => Bound::Excluded(Key::Version(start.into(), u64::MAX).encode()?),
+            Bound::Included(start) => Bound::Included(Key::Version(start.into(), 0).encode()?),
+            Bound::Unbounded => Bound::Included(Key::Version(vec![].into(), 0).encode()?),
         };
         let end_bound = match range.end_bound() {
-            Bound::Excluded(end) => Bound::Excluded(Key::Version(end.into(), 0).encode().expect(msg)),
-            Bound::Included(end) => Bound::Included(Key::Version(end.into(), u64::MAX).encode().expect(msg)),
-            Bound::Unbounded => Bound::Excluded(KeyPrefix::Unversioned.encode().expect(msg)),
+            Bound::Excluded(end) => Bound::Excluded(Key::Version(end.into(), 0).encode()?),
+            Bound::Included(end) => Bound::Included(Key::Version(end.into(), u64::MAX).encode()?),
+            Bound::Unbounded => Bound::Excluded(KeyPrefix::Unversioned.encode()?),
         };
-        ScanIterator::new(
-            self.engine.clone(),
-            self.state.clone(),
-            (start_bound, end_bound),
-        )
+        let range = (start_bound, end_bound);
+        self.record_read(range.clone())?;
+        Ok(ScanIterator::new(self.engine.clone(), self.state.clone(), range))
     }
 
     /// 前缀扫描
-    pub fn scan_prefix(&self, prefix: &[u8]) -> ScanIterator<E> {
-        let mut prefix = KeyPrefix::Version(prefix.into()).encode().expect("序列化失败");
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<ScanIterator<E>> {
+        let mut prefix = KeyPrefix::Version(prefix.into()).encode()?;
         //因为字节编码默认会给末尾增加【0x00 0x00】，只有去掉末尾的【0x00 0x00】前缀扫描才会更准确
         prefix.truncate(prefix.len() - 2);
         let range = key_coder::prefix_range(&prefix);
-        ScanIterator::new(self.engine.clone(), self.state().clone(), range)
+        self.record_read(range.clone())?;
+        Ok(ScanIterator::new(self.engine.clone(), self.state().clone(), range))
     }
 }
 
 /// 对事务可见的最新活跃键值对的迭代器。
 ///
-/// （单线程的）引擎通过互斥锁共享，如果在迭代器的生命周期内一直持有互斥锁可能会导致死锁
+/// 引擎通过读写锁共享，如果在迭代器的生命周期内一直持有读锁，排队的写者会阻塞后续读者，可能导致死锁
 /// （例如，当本地 SQL 引擎在进行连接操作时同时从两个表中获取数据）。因此，我们每次获取
-/// 并缓冲一批行数据，并在获取批次之间释放互斥锁。
+/// 并缓冲一批行数据，并在获取批次之间释放读锁。
+///
+/// 支持双向迭代：正向和反向各自维护一个缓冲区，从未扫描范围的两端分别拉取批次。
+/// 未扫描范围耗尽后，一端的缓冲区用完就继续消费另一端缓冲区中剩下的数据。
 ///
-/// 这个迭代器没有实现 DoubleEndedIterator（反向扫描），因为 SQL 层目前不需要这个功能。
+/// 通过 `with_limit` 限制返回条数后，每批最多只拉取还需要的条数，
+/// 因此 `rev().take(n)` 这类"最新 N 条"查询不会读遍整个范围。
 #[derive(Clone)]
 pub struct ScanIterator<E: Engine> {
     // 存储引擎
-    engine: Arc<Mutex<E>>,
+    engine: Arc<RwLock<E>>,
     // 事务状态
     txn: TransactionState,
-    // 缓冲区 存放已经是“最新且对当前事务可见”的键值对
+    // 正向缓冲区 存放已经是“最新且对当前事务可见”的键值对，按键升序排列
     buffer: VecDeque<(Vec<u8>, Vec<u8>)>,
+    // 反向缓冲区，按键降序排列
+    buffer_back: VecDeque<(Vec<u8>, Vec<u8>)>,
     // 未扫描进缓冲区的范围
-    remainder: Option<(Bound<Vec<u8>>, Bound<Vec<u8>>)>,
+    remainder: Option<KeyRange>,
+    // 还可以返回的条数，None 表示不限制
+    limit: Option<usize>,
 }
 
 impl<E: Engine> ScanIterator<E> {
     /// 每次锁缓冲区拉取的有效键值对存放数量
     const BUFFER_SIZE: usize = if cfg!(test) { 2 } else { 32 };
-    pub fn new(engine: Arc<Mutex<E>>,
+    pub fn new(engine: Arc<RwLock<E>>,
                txn: TransactionState,
-               range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+               range: KeyRange,
     ) -> Self {
         let buffer = VecDeque::with_capacity(Self::BUFFER_SIZE);
         Self {
             engine,
             txn,
             buffer,
+            buffer_back: VecDeque::new(),
             remainder: Some(range),
+            limit: None,
         }
     }
 
+    /// 最多返回 limit 条（两端合计），批次大小随之缩小
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // 本批次最多拉取的条数
+    fn batch_size(&self) -> usize {
+        self.limit.map_or(Self::BUFFER_SIZE, |limit| limit.clamp(1, Self::BUFFER_SIZE))
+    }
+
     pub fn fill_buffer(&mut self) -> Result<()> {
         //1、校验是否缓存池是否还有空间
-        if self.buffer.len() >= Self::BUFFER_SIZE {
+        let batch_size = self.batch_size();
+        if self.buffer.len() >= batch_size {
             return Ok(());
         }
         //2、校验还有没扫描完的范围
@@ -506,7 +1208,7 @@ impl<E: Engine> ScanIterator<E> {
 
         let range_end = range.1.clone();
 
-        let mut engine = self.engine.lock()?;
+        let engine = self.engine.read()?;
 
         let mut iter = VersionIterator::new(&self.txn, engine.scan(range)).peekable();
         while let Some((key, _, value)) = iter.next().transpose()? {
@@ -523,7 +1225,7 @@ impl<E: Engine> ScanIterator<E> {
 
             // 当 buffer 装满时，我们要保存还没扫描的剩余范围，方便下次续扫。
             // 由于 peek() 已经偷偷缓存了一条数据，所以在保存剩余范围前，需要先用 next() 把它消费掉并用来生成新的扫描起点。
-            if self.buffer.len() == Self::BUFFER_SIZE {
+            if self.buffer.len() == batch_size {
                 if let Some((next, version, _)) = iter.next().transpose()? {
                     let range_start = Bound::Included(Key::Version(next.into(), version).encode()?);
                     self.remainder = Some((range_start, range_end));
@@ -533,20 +1235,89 @@ impl<E: Engine> ScanIterator<E> {
         }
         Ok(())
     }
+
+    /// 从未扫描范围的末尾反向拉取一批
+    /// 反向遍历时同一个键先遇到的是最新的可见版本，之后遇到的同键旧版本直接跳过
+    pub fn fill_buffer_back(&mut self) -> Result<()> {
+        let batch_size = self.batch_size();
+        if self.buffer_back.len() >= batch_size {
+            return Ok(());
+        }
+        let Some(range) = self.remainder.take() else { return Ok(()) };
+
+        let range_start = range.0.clone();
+
+        let engine = self.engine.read()?;
+
+        let mut iter = VersionIterator::new(&self.txn, engine.scan(range)).rev();
+        let mut last_key: Option<Vec<u8>> = None;
+        while let Some((key, _, value)) = iter.next().transpose()? {
+            if last_key.as_ref() == Some(&key) {
+                continue;
+            }
+            last_key = Some(key.clone());
+
+            let Some(value) = bin_coder::decode(&value)? else { continue };
+            self.buffer_back.push_back((key.clone(), value));
+
+            // 剩余范围截止到当前键的最小版本（不含），当前键的旧版本也一并排除
+            if self.buffer_back.len() == batch_size {
+                let range_end = Bound::Excluded(Key::Version(key.into(), 0).encode()?);
+                self.remainder = Some((range_start, range_end));
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    // 记录一条已返回的数据
+    fn consume(&mut self, item: Option<(Vec<u8>, Vec<u8>)>) -> Option<(Vec<u8>, Vec<u8>)> {
+        if let (Some(limit), Some(_)) = (self.limit.as_mut(), &item) {
+            *limit -= 1;
+        }
+        item
+    }
+
+    fn try_next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.limit == Some(0) {
+            return Ok(None);
+        }
+        if self.buffer.is_empty() {
+            self.fill_buffer()?;
+        }
+        // 范围已经扫描完，继续消费反向缓冲区中剩余的数据
+        let item = self.buffer.pop_front().or_else(|| self.buffer_back.pop_back());
+        Ok(self.consume(item))
+    }
+
+    fn try_next_back(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.limit == Some(0) {
+            return Ok(None);
+        }
+        if self.buffer_back.is_empty() {
+            self.fill_buffer_back()?;
+        }
+        let item = self.buffer_back.pop_front().or_else(|| self.buffer.pop_back());
+        Ok(self.consume(item))
+    }
 }
 
 impl<E: Engine> Iterator for ScanIterator<E> {
     type Item = Result<(Vec<u8>, Vec<u8>)>;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.buffer.is_empty() {
-            if let Err(error) = self.fill_buffer() {
-                return Some(Err(error));
-            }
-        }
-        self.buffer.pop_front().map(Ok)
+        self.try_next().transpose()
+    }
+}
+
+impl<E: Engine> DoubleEndedIterator for ScanIterator<E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next_back().transpose()
     }
 }
 
+// 键 / 版本 / 值 三元组
+type VersionEntry = (Vec<u8>, Version, Vec<u8>);
+
 struct VersionIterator<'a, I: engine::ScanIter> {
     // 当前正在扫描的事务
     txn: &'a TransactionState,
@@ -561,26 +1332,49 @@ impl<'a, I: engine::ScanIter> VersionIterator<'a, I> {
     }
 
     //可失败的 next() 方法。返回下一个对当前事务可见的键 / 版本 / 值 三元组。
-    fn try_next(&mut self) -> Result<Option<(Vec<u8>, Version, Vec<u8>)>> {
+    fn try_next(&mut self) -> Result<Option<VersionEntry>> {
         while let Some((key, value)) = self.inner.next().transpose()? {
-            let Key::Version(key, version) = Key::decode(&key)? else {
-                return errdata!("require Key::Version got {key:?}");
-            };
-            if !self.txn.is_visible(version) {
-                continue;
+            if let Some(entry) = self.visible(key, value)? {
+                return Ok(Some(entry));
             }
-            return Ok(Some((key.into_owned(), version, value)));
         }
         Ok(None)
     }
+
+    // 可失败的 next_back() 方法，从末尾返回对当前事务可见的三元组
+    fn try_next_back(&mut self) -> Result<Option<VersionEntry>> {
+        while let Some((key, value)) = self.inner.next_back().transpose()? {
+            if let Some(entry) = self.visible(key, value)? {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    // 解码版本键，对当前事务不可见时返回 None
+    fn visible(&self, key: Vec<u8>, value: Vec<u8>) -> Result<Option<VersionEntry>> {
+        let Key::Version(key, version) = Key::decode(&key)? else {
+            return errdata!("require Key::Version got {key:?}");
+        };
+        if !self.txn.is_visible(version) {
+            return Ok(None);
+        }
+        Ok(Some((key.into_owned(), version, value)))
+    }
 }
 impl<'a, I: engine::ScanIter> Iterator for VersionIterator<'a, I> {
-    type Item = Result<(Vec<u8>, Version, Vec<u8>)>;
+    type Item = Result<VersionEntry>;
     fn next(&mut self) -> Option<Self::Item> {
         self.try_next().transpose()
     }
 }
 
+impl<'a, I: engine::ScanIter> DoubleEndedIterator for VersionIterator<'a, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next_back().transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -598,7 +1392,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let txn = mvcc.begin()?;
         let key1 = "mvcc_key_1".as_bytes();
         let value1 = "mvcc_value_1".as_bytes();
@@ -617,7 +1411,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let read_txn = mvcc.begin_readonly()?;
         let txn = mvcc.begin()?;
         let key = "mvcc_set_key".as_bytes();
@@ -637,7 +1431,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let read_txn = mvcc.begin_readonly()?;
         let txn = mvcc.begin()?;
         let key = "mvcc_set_key_1".as_bytes();
@@ -664,7 +1458,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         // 先写入数据
         let txn = mvcc.begin()?;
         let key1 = "mvcc_key_1".as_bytes();
@@ -683,7 +1477,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let _ = mvcc.begin()?;
         let _ = mvcc.begin_readonly()?;
         Ok(())
@@ -694,7 +1488,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let txn = mvcc.begin()?;
         let key1 = "mvcc_rollback_key".as_bytes();
         let original_value = "mvcc_rollback_val".as_bytes();
@@ -724,14 +1518,14 @@ mod tests {
         let value = b"recover_val";
         {
             let db = BitCask::init_db_at(dir.path())?;
-            let mvcc = MVCC::new(db);
+            let mvcc = MVCC::new(db)?;
             let txn = mvcc.begin()?;
             txn.set(key, Some(value))?;
             txn.commit()?;
         }
         // 重新打开数据库
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let read_txn = mvcc.begin_readonly()?;
         assert_eq!(read_txn.get(key)?, Some(value.to_vec()));
         Ok(())
@@ -742,7 +1536,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let key = b"conflict_key";
         let value1 = b"v1";
         let value2 = b"v2";
@@ -761,7 +1555,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let key = b"del_key";
         let value = b"del_val";
 
@@ -787,7 +1581,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         setup(&dir);
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let key1 = b"scan_a";
         let key2 = b"scan_b";
         let value = b"v";
@@ -796,21 +1590,156 @@ mod tests {
         txn.set(key1, Some(value))?;
         txn.set(key2, Some(value))?;
 
-        let results: Vec<_> = txn.scan(..).collect::<Result<Vec<_>>>()?;
+        let results: Vec<_> = txn.scan(..)?.collect::<Result<Vec<_>>>()?;
         assert_eq!(results.len(), 2);
 
         let read_txn = mvcc.begin_readonly()?;
-        let results: Vec<_> = read_txn.scan(..).collect::<Result<Vec<_>>>()?;
+        let results: Vec<_> = read_txn.scan(..)?.collect::<Result<Vec<_>>>()?;
         assert_eq!(results.len(), 0);
 
         txn.commit()?;
 
         let read_txn = mvcc.begin_readonly()?;
-        let results: Vec<_> = read_txn.scan(..).collect::<Result<Vec<_>>>()?;
+        let results: Vec<_> = read_txn.scan(..)?.collect::<Result<Vec<_>>>()?;
         assert_eq!(results.len(), 2);
         Ok(())
     }
 
+    #[test]
+    fn test_mvcc_scan_reverse_and_limit() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        // 每个键写入多个版本，并删除其中一个，覆盖跳过旧版本和墓碑的逻辑
+        for round in 0..3u8 {
+            let txn = mvcc.begin()?;
+            for key in [b"k1", b"k2", b"k3", b"k4", b"k5"] {
+                txn.set(key, Some(&[round]))?;
+            }
+            txn.commit()?;
+        }
+        let txn = mvcc.begin()?;
+        txn.delete(b"k3")?;
+        txn.commit()?;
+        // 未提交的写入对读事务不可见
+        let writer = mvcc.begin()?;
+        writer.set(b"k6", Some(b"x"))?;
+        writer.set(b"k5", Some(b"x"))?;
+
+        let read_txn = mvcc.begin_readonly()?;
+        let keys = |items: Vec<(Vec<u8>, Vec<u8>)>| items.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        let expected: Vec<Vec<u8>> = [b"k1", b"k2", b"k4", b"k5"].iter().map(|k| k.to_vec()).collect();
+
+        let reversed = read_txn.scan(..)?.rev().collect::<Result<Vec<_>>>()?;
+        assert!(reversed.iter().all(|(_, v)| v == &[2]));
+        let mut reversed = keys(reversed);
+        reversed.reverse();
+        assert_eq!(reversed, expected);
+
+        // 两端交替消费，每条数据只返回一次
+        let mut scan = read_txn.scan(..)?;
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        loop {
+            match scan.next().transpose()? {
+                Some((k, _)) => front.push(k),
+                None => break,
+            }
+            match scan.next_back().transpose()? {
+                Some((k, _)) => back.push(k),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected);
+
+        // 限制条数
+        let latest = keys(read_txn.scan_prefix(b"k")?.with_limit(2).rev().collect::<Result<Vec<_>>>()?);
+        assert_eq!(latest, vec![b"k5".to_vec(), b"k4".to_vec()]);
+        let first = keys(read_txn.scan(..)?.with_limit(3).collect::<Result<Vec<_>>>()?);
+        assert_eq!(first, expected[..3].to_vec());
+        assert_eq!(read_txn.scan(..)?.with_limit(0).count(), 0);
+
+        // 写事务反向扫描能看到自己的写入
+        let own = keys(writer.scan(..)?.rev().collect::<Result<Vec<_>>>()?);
+        assert_eq!(own[0], b"k6".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_travel() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        // 每次提交后记录时间并等待，保证前后提交的时间戳不同
+        let checkpoint = || {
+            let now = get_timestamp_millis();
+            std::thread::sleep(std::time::Duration::from_millis(3));
+            now
+        };
+        let before = checkpoint();
+
+        let txn = mvcc.begin()?;
+        txn.set(b"a", Some(b"1"))?;
+        let v1 = txn.get_version();
+        txn.commit()?;
+        let after_v1 = checkpoint();
+
+        // 版本号较小的长事务晚于 v3 提交
+        let long = mvcc.begin()?;
+        long.set(b"b", Some(b"1"))?;
+        let txn = mvcc.begin()?;
+        txn.set(b"a", Some(b"2"))?;
+        let v3 = txn.get_version();
+        txn.commit()?;
+        let after_v3 = checkpoint();
+        long.commit()?;
+
+        let read = |txn: Transaction<BitCask>| -> Result<_> { Ok((txn.get(b"a")?, txn.get(b"b")?)) };
+        assert_eq!(read(mvcc.begin_readonly_at(before)?)?, (None, None));
+        assert_eq!(read(mvcc.begin_readonly_at(after_v1)?)?, (Some(b"1".to_vec()), None));
+        assert_eq!(read(mvcc.begin_readonly_at(after_v3)?)?, (Some(b"2".to_vec()), None));
+        assert_eq!(
+            read(mvcc.begin_readonly_at(get_timestamp_millis())?)?,
+            (Some(b"2".to_vec()), Some(b"1".to_vec()))
+        );
+        assert_eq!(mvcc.version_at(before)?, None);
+        assert_eq!(mvcc.version_at(after_v1)?, Some(v1));
+        assert_eq!(mvcc.version_at(after_v3)?, Some(v3));
+
+        // 按版本回溯只能看到该版本开启前已提交的数据
+        assert_eq!(read(mvcc.begin_readonly_version(v3)?)?, (Some(b"1".to_vec()), None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let key = b"history_key";
+        let txn = mvcc.begin()?;
+        txn.set(key, Some(b"v1"))?;
+        let v1 = txn.get_version();
+        txn.commit()?;
+        let txn = mvcc.begin()?;
+        txn.delete(key)?;
+        let v2 = txn.get_version();
+        txn.commit()?;
+        let reader = mvcc.begin_readonly()?;
+        let txn = mvcc.begin()?;
+        txn.set(key, Some(b"v3"))?;
+        let v3 = txn.get_version();
+
+        // 写事务能看到自己未提交的版本，并发的读事务看不到
+        assert_eq!(txn.history(key)?, vec![(v1, Some(b"v1".to_vec())), (v2, None), (v3, Some(b"v3".to_vec()))]);
+        assert_eq!(reader.history(key)?, vec![(v1, Some(b"v1".to_vec())), (v2, None)]);
+        assert!(reader.history(b"missing")?.is_empty());
+        txn.commit()?;
+        Ok(())
+    }
+
     #[test]
     fn test_mvcc_rollback_and_recover() -> Result<()> {
         let dir = TempDir::new().unwrap();
@@ -820,7 +1749,7 @@ mod tests {
         let new_val = b"new";
         {
             let db = BitCask::init_db_at(dir.path())?;
-            let mvcc = MVCC::new(db);
+            let mvcc = MVCC::new(db)?;
             let txn = mvcc.begin()?;
             txn.set(key, Some(original))?;
             txn.commit()?;
@@ -830,9 +1759,379 @@ mod tests {
             txn.rollback()?;
         }
         let db = BitCask::init_db_at(dir.path())?;
-        let mvcc = MVCC::new(db);
+        let mvcc = MVCC::new(db)?;
         let read_txn = mvcc.begin_readonly()?;
         assert_eq!(read_txn.get(key)?, Some(original.to_vec()));
         Ok(())
     }
+    #[test]
+    fn test_savepoints() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let txn = mvcc.begin()?;
+        txn.set(b"a", Some(b"a1"))?;
+        txn.savepoint("sp1")?;
+        txn.set(b"a", Some(b"a2"))?;
+        txn.set(b"b", Some(b"b1"))?;
+        txn.savepoint("sp2")?;
+        txn.set(b"a", Some(b"a3"))?;
+        txn.delete(b"b")?;
+
+        // 回滚到内层保存点
+        txn.rollback_to_savepoint("sp2")?;
+        assert_eq!(txn.get(b"a")?, Some(b"a2".to_vec()));
+        assert_eq!(txn.get(b"b")?, Some(b"b1".to_vec()));
+
+        // 回滚到外层保存点：保存点之后才写入的键被彻底移除，内层保存点被丢弃
+        txn.rollback_to_savepoint("sp1")?;
+        assert_eq!(txn.get(b"a")?, Some(b"a1".to_vec()));
+        assert_eq!(txn.get(b"b")?, None);
+        assert_eq!(count_versions(&mvcc, b"b")?, 0);
+        assert!(txn.rollback_to_savepoint("sp2").is_err());
+
+        // 保存点在回滚后保留，可以再次回滚
+        txn.set(b"c", Some(b"c1"))?;
+        txn.rollback_to_savepoint("sp1")?;
+        assert_eq!(txn.get(b"c")?, None);
+
+        // 释放后写入保留，保存点不再可用
+        txn.set(b"a", Some(b"a4"))?;
+        txn.release_savepoint("sp1")?;
+        assert!(txn.rollback_to_savepoint("sp1").is_err());
+        assert!(txn.release_savepoint("missing").is_err());
+        assert_eq!(mvcc.active_transactions()?[0].writes, 1);
+        txn.commit()?;
+
+        let txn = mvcc.begin_readonly()?;
+        assert_eq!(txn.get(b"a")?, Some(b"a4".to_vec()));
+        assert_eq!(txn.get(b"b")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_orphaned_transactions_rolled_back_on_open() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let key = b"orphan_key";
+        {
+            let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+            let txn = mvcc.begin()?;
+            txn.set(key, Some(b"committed"))?;
+            txn.commit()?;
+
+            // 未提交也未回滚就退出，模拟进程崩溃
+            let txn = mvcc.begin()?;
+            txn.set(key, Some(b"orphan"))?;
+            txn.set(b"orphan_only", Some(b"orphan"))?;
+            let active = mvcc.active_transactions()?;
+            assert_eq!(active.len(), 1);
+            assert_eq!(active[0].version, txn.get_version());
+            assert_eq!(active[0].epoch, 1);
+            assert_eq!(active[0].writes, 2);
+        }
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        assert!(mvcc.active_transactions()?.is_empty());
+        assert_eq!(count_versions(&mvcc, b"orphan_only")?, 0);
+
+        // 遗留事务的写入被撤销，新事务可以正常写入同一个键
+        let txn = mvcc.begin()?;
+        assert_eq!(txn.get(key)?, Some(b"committed".to_vec()));
+        assert!(txn.state().active.is_empty());
+        txn.set(key, Some(b"new"))?;
+        assert_eq!(mvcc.active_transactions()?[0].epoch, 2);
+        txn.commit()?;
+        assert_eq!(mvcc.begin_readonly()?.get(key)?, Some(b"new".to_vec()));
+        Ok(())
+    }
+
+    /// 经典写偏斜：两个事务各自读取两个键，再分别写入其中一个
+    fn write_skew<E: Engine>(mvcc: &MVCC<E>, isolation: IsolationLevel) -> Result<(Result<()>, Result<()>)> {
+        let setup = mvcc.begin()?;
+        setup.set(b"x", Some(b"1"))?;
+        setup.set(b"y", Some(b"1"))?;
+        setup.commit()?;
+
+        let t1 = mvcc.begin_with_isolation(isolation)?;
+        let t2 = mvcc.begin_with_isolation(isolation)?;
+        assert_eq!(t1.get(b"x")?, Some(b"1".to_vec()));
+        assert_eq!(t1.get(b"y")?, Some(b"1".to_vec()));
+        assert_eq!(t2.get(b"x")?, Some(b"1".to_vec()));
+        assert_eq!(t2.get(b"y")?, Some(b"1".to_vec()));
+        t1.set(b"x", Some(b"0"))?;
+        t2.set(b"y", Some(b"0"))?;
+        Ok((t1.commit(), t2.commit()))
+    }
+
+    #[test]
+    fn test_snapshot_allows_write_skew() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let (r1, r2) = write_skew(&mvcc, IsolationLevel::Snapshot)?;
+        assert!(r1.is_ok() && r2.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_prevents_write_skew() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let (r1, r2) = write_skew(&mvcc, IsolationLevel::Serializable)?;
+        assert!(r1.is_ok());
+        assert!(r2.unwrap_err().to_string().contains("serialization failure"));
+        // 失败的事务已被回滚
+        assert!(mvcc.active_transactions()?.is_empty());
+        let read = mvcc.begin_readonly()?;
+        assert_eq!(read.get(b"x")?, Some(b"0".to_vec()));
+        assert_eq!(read.get(b"y")?, Some(b"1".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_scan_conflict() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let t1 = mvcc.begin_with_isolation(IsolationLevel::Serializable)?;
+        let t2 = mvcc.begin_with_isolation(IsolationLevel::Serializable)?;
+        // t1 扫描前缀后，t2 在该范围内插入新键（幻读）
+        assert_eq!(t1.scan_prefix(b"row")?.count(), 0);
+        t2.set(b"row1", Some(b"v"))?;
+        t2.commit()?;
+        t1.set(b"other", Some(b"v"))?;
+        assert!(t1.commit().is_err());
+
+        // 不相交的读写互不影响
+        let t3 = mvcc.begin_with_isolation(IsolationLevel::Serializable)?;
+        let t4 = mvcc.begin_with_isolation(IsolationLevel::Serializable)?;
+        t3.get(b"a")?;
+        t4.get(b"b")?;
+        t3.set(b"a", Some(b"3"))?;
+        t4.set(b"b", Some(b"4"))?;
+        t3.commit()?;
+        t4.commit()?;
+
+        // 读集合的锁异常时扫描返回错误，不会 panic
+        let t5 = mvcc.begin_with_isolation(IsolationLevel::Serializable)?;
+        let _ = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = t5.reads.lock().unwrap();
+                    panic!("poison the read set");
+                })
+                .join()
+        });
+        assert!(t5.scan(..).is_err());
+        assert!(t5.scan_prefix(b"row").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_readers_see_consistent_snapshots() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let txn = mvcc.begin()?;
+        txn.set(b"acct_a", Some(&100u64.to_be_bytes()))?;
+        txn.set(b"acct_b", Some(&0u64.to_be_bytes()))?;
+        txn.commit()?;
+
+        let read = |txn: &Transaction<BitCask>, key: &[u8]| -> Result<u64> {
+            Ok(u64::from_be_bytes(txn.get(key)?.unwrap().try_into().unwrap()))
+        };
+        std::thread::scope(|scope| -> Result<()> {
+            // 写者不断在两个账户之间转账，总额保持不变
+            let writer = scope.spawn(|| -> Result<()> {
+                for _ in 0..50 {
+                    let txn = mvcc.begin()?;
+                    let (a, b) = (read(&txn, b"acct_a")?, read(&txn, b"acct_b")?);
+                    txn.set(b"acct_a", Some(&(a - 1).to_be_bytes()))?;
+                    txn.set(b"acct_b", Some(&(b + 1).to_be_bytes()))?;
+                    txn.commit()?;
+                }
+                Ok(())
+            });
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| -> Result<()> {
+                        for _ in 0..50 {
+                            let txn = mvcc.begin_readonly()?;
+                            assert_eq!(read(&txn, b"acct_a")? + read(&txn, b"acct_b")?, 100);
+                            let total: u64 = txn
+                                .scan_prefix(b"acct_")?
+                                .map(|r| r.map(|(_, v)| u64::from_be_bytes(v.try_into().unwrap())))
+                                .sum::<Result<u64>>()?;
+                            assert_eq!(total, 100);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            writer.join().unwrap()?;
+            for reader in readers {
+                reader.join().unwrap()?;
+            }
+            Ok(())
+        })?;
+        let txn = mvcc.begin_readonly()?;
+        assert_eq!(read(&txn, b"acct_a")?, 50);
+        Ok(())
+    }
+
+    /// 统计引擎中指定键的版本数量
+    fn count_versions<E: Engine>(mvcc: &MVCC<E>, key: &[u8]) -> Result<usize> {
+        let engine = mvcc.engine.read()?;
+        let from = Key::Version(key.into(), 0).encode()?;
+        let to = Key::Version(key.into(), u64::MAX).encode()?;
+        Ok(engine.scan(from..=to).count())
+    }
+
+    #[test]
+    fn test_gc_removes_obsolete_versions() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        for value in [b"v1", b"v2", b"v3"] {
+            let txn = mvcc.begin()?;
+            txn.set(b"a", Some(value))?;
+            txn.set(b"b", Some(value))?;
+            txn.commit()?;
+        }
+        let txn = mvcc.begin()?;
+        txn.delete(b"b")?;
+        txn.commit()?;
+
+        // 每批只扫描两个版本键，a 的三个版本处理完后在 b 处分批
+        let report = mvcc.gc_in_batches(2)?;
+        assert_eq!(report.watermark, 5);
+        assert_eq!(report.versions_removed, 2 + 3);
+        assert_eq!(report.tombstones_removed, 1);
+        assert_eq!(count_versions(&mvcc, b"a")?, 1);
+        assert_eq!(count_versions(&mvcc, b"b")?, 0);
+
+        let read_txn = mvcc.begin_readonly()?;
+        assert_eq!(read_txn.get(b"a")?, Some(b"v3".to_vec()));
+        assert_eq!(read_txn.get(b"b")?, None);
+        assert_eq!(read_txn.scan(..)?.collect::<Result<Vec<_>>>()?.len(), 1);
+
+        // 再次回收没有可删除的数据
+        let report = mvcc.gc()?;
+        assert_eq!((report.versions_removed, report.tombstones_removed), (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_keeps_versions_visible_to_active_transactions() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let txn = mvcc.begin()?;
+        txn.set(b"k", Some(b"v1"))?;
+        txn.commit()?;
+
+        // t4 开始时 t3 尚未提交，t4 的快照中包含 t3
+        let t3 = mvcc.begin()?;
+        let t4 = mvcc.begin()?;
+        let t3_version = t3.get_version();
+        t3.set(b"k", Some(b"v3"))?;
+        t3.commit()?;
+        let txn = mvcc.begin()?;
+        txn.set(b"k", Some(b"v5"))?;
+        txn.commit()?;
+
+        // 水位线受 t4 快照中最旧的活跃版本 t3 约束
+        assert_eq!(mvcc.watermark()?, t3_version);
+        let report = mvcc.gc()?;
+        assert_eq!(report.versions_removed, 0);
+        assert_eq!(t4.get(b"k")?, Some(b"v1".to_vec()));
+        t4.commit()?;
+
+        // t4 结束后旧版本和快照都可以回收
+        let report = mvcc.gc()?;
+        assert_eq!(report.versions_removed, 2);
+        assert_eq!(report.snapshots_removed, 2);
+        assert_eq!(mvcc.begin_readonly()?.get(b"k")?, Some(b"v5".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_keeps_versions_visible_to_readonly_transactions() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let txn = mvcc.begin()?;
+        let v1 = txn.get_version();
+        txn.set(b"k", Some(b"v1"))?;
+        txn.commit()?;
+        let after_v1 = get_timestamp_millis();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        let reader = mvcc.begin_readonly()?;
+        let reader_at = mvcc.begin_readonly_at(after_v1)?;
+        assert_eq!(reader.get(b"k")?, Some(b"v1".to_vec()));
+        for value in [b"v2", b"v3"] {
+            let txn = mvcc.begin()?;
+            txn.set(b"k", Some(value))?;
+            txn.commit()?;
+        }
+
+        // 运行中的只读事务约束水位线，回收前后读到的值相同
+        let report = mvcc.gc()?;
+        assert_eq!(report.versions_removed, 0);
+        assert_eq!(reader.get(b"k")?, Some(b"v1".to_vec()));
+        assert_eq!(reader_at.get(b"k")?, Some(b"v1".to_vec()));
+        reader.commit()?;
+        reader_at.rollback()?;
+        drop(reader_at);
+
+        let report = mvcc.gc()?;
+        assert_eq!(report.versions_removed, 2);
+        assert_eq!(mvcc.begin_readonly()?.get(b"k")?, Some(b"v3".to_vec()));
+        // 回收过的版本不能再被回溯查询
+        let err = mvcc.begin_readonly_version(v1).err().unwrap().to_string();
+        assert!(err.contains("VACUUM removed versions below"), "{err}");
+        assert!(mvcc.begin_readonly_at(after_v1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_prunes_commit_times() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        setup(&dir);
+        let mvcc = MVCC::new(BitCask::init_db_at(dir.path())?)?;
+        let before = get_timestamp_millis();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let mut versions = Vec::new();
+        for value in [b"v1", b"v2", b"v3"] {
+            let txn = mvcc.begin()?;
+            txn.set(b"k", Some(value))?;
+            versions.push(txn.get_version());
+            txn.commit()?;
+        }
+        let after = get_timestamp_millis();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let count_commit_times = || -> Result<usize> {
+            Ok(mvcc.engine.read()?.scan_prefix(&KeyPrefix::CommitTime.encode()?).count())
+        };
+        assert_eq!(count_commit_times()?, 3);
+
+        // 水位线以下只保留最新的一条提交时间
+        mvcc.gc()?;
+        assert_eq!(count_commit_times()?, 1);
+        assert_eq!(mvcc.version_at(after)?, Some(versions[2]));
+        assert_eq!(mvcc.begin_readonly_at(after)?.get(b"k")?, Some(b"v3".to_vec()));
+        let err = mvcc.begin_readonly_at(before).err().unwrap().to_string();
+        assert!(err.contains("VACUUM removed versions committed up to"), "{err}");
+
+        // 之后的提交照常记录，回溯查询只读取水位线之上的提交时间
+        let txn = mvcc.begin()?;
+        txn.set(b"k", Some(b"v4"))?;
+        txn.commit()?;
+        assert_eq!(count_commit_times()?, 2);
+        assert_eq!(mvcc.begin_readonly_at(after)?.get(b"k")?, Some(b"v3".to_vec()));
+        assert_eq!(mvcc.begin_readonly_at(get_timestamp_millis())?.get(b"k")?, Some(b"v4".to_vec()));
+        Ok(())
+    }
 }
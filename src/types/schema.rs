@@ -12,7 +12,9 @@ pub struct Table {
     /// 主键字段的索引
     pub primary_key: usize,
     /// 列集合,至少一个
-    pub columns: Vec<Column>
+    pub columns: Vec<Column>,
+    /// 行的存活时间（秒），由 `EXPIRE AFTER` 指定；行在最后一次写入之后超过该时间即视为已删除
+    pub expire_after: Option<u64>,
 }
 
 impl crate::utils::Value for Table {}
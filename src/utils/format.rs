@@ -1,4 +1,7 @@
+use crate::storage::mvcc::{Key, Version};
+use crate::utils::{bin_coder, format_timestamp_millis, Key as KeyTrait};
 use itertools::Itertools;
+use std::collections::BTreeSet;
 
 /// 格式化打印输出
 pub trait Formatter {
@@ -45,9 +48,53 @@ impl Formatter for Raw {
     }
 }
 
+/// MVCC 键值格式化：按 `mvcc::Key` 解码键，无法解码的键（如目录键）回退为原始字节
+pub struct MVCC;
+
+impl Formatter for MVCC {
+    fn key(key: &[u8]) -> String {
+        let Ok(decoded) = Key::decode(key) else {
+            return Raw::key(key);
+        };
+        match decoded {
+            Key::NextVersion => "NextVersion".to_string(),
+            Key::Active(version) => format!("Active({version})"),
+            Key::Snapshot(version) => format!("Snapshot({version})"),
+            Key::ActiveWrite(version, key) => format!("ActiveWrite({version}, {})", Raw::bytes(&key)),
+            Key::Version(key, version) => format!("Version({}, {version})", Raw::bytes(&key)),
+            Key::Unversioned(key) => format!("Unversioned({})", Raw::bytes(&key)),
+            Key::Epoch => "Epoch".to_string(),
+            Key::CommitTime(version) => format!("CommitTime({version})"),
+            Key::GcWatermark => "GcWatermark".to_string(),
+        }
+    }
+
+    fn value(key: &[u8], value: &[u8]) -> String {
+        match Key::decode(key) {
+            Ok(Key::NextVersion | Key::Epoch | Key::Active(_) | Key::GcWatermark) => bin_coder::decode::<Version>(value)
+                .map(|v| v.to_string())
+                .unwrap_or_else(|_| Raw::bytes(value)),
+            Ok(Key::CommitTime(_)) => bin_coder::decode::<u64>(value)
+                .map(format_timestamp_millis)
+                .unwrap_or_else(|_| Raw::bytes(value)),
+            Ok(Key::Snapshot(_)) => bin_coder::decode::<BTreeSet<Version>>(value)
+                .map(|active| format!("{active:?}"))
+                .unwrap_or_else(|_| Raw::bytes(value)),
+            Ok(Key::Version(..)) => match bin_coder::decode::<Option<Vec<u8>>>(value) {
+                Ok(Some(v)) => Raw::bytes(&v),
+                Ok(None) => "None".to_string(),
+                Err(_) => Raw::bytes(value),
+            },
+            _ => Raw::bytes(value),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::Raw;
+    use super::{Formatter, MVCC};
+    use crate::storage::mvcc::Key;
+    use crate::utils::{bin_coder, Key as KeyTrait, Raw};
 
     #[test]
     fn test_format() {
@@ -62,4 +109,17 @@ mod tests {
         println!("{}", output_1);
         assert_eq!(output, "\"\\x00\\x7fABC\"");
     }
+
+    #[test]
+    fn test_mvcc_format() {
+        let key = Key::Version(b"foo".as_slice().into(), 7).encode().unwrap();
+        let value = bin_coder::encode(Some(b"bar".as_slice())).unwrap();
+        assert_eq!(MVCC::key_value(&key, &value), "Version(\"foo\", 7) → \"bar\"");
+
+        let tombstone = bin_coder::encode(None::<&[u8]>).unwrap();
+        assert_eq!(MVCC::key_value(&key, &tombstone), "Version(\"foo\", 7) → None");
+
+        // 非 MVCC 编码的键回退为原始字节
+        assert_eq!(MVCC::key(b"__catalog__\x00t"), "\"__catalog__\\x00t\"");
+    }
 }
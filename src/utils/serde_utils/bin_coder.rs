@@ -16,6 +16,10 @@ pub fn encode<T: Serialize>(value: T) -> Result<Vec<u8>> {
 pub fn decode<'de, T: Deserialize<'de>>(value: &'de [u8]) -> Result<T> {
     Ok(bincode::serde::borrow_decode_from_slice(value, CONFIG)?.0)
 }
+/// 反序列化值的前缀，同时返回读取的字节数，剩余的字节留给调用方解析
+pub fn decode_prefix<'de, T: Deserialize<'de>>(value: &'de [u8]) -> Result<(T, usize)> {
+    Ok(bincode::serde::borrow_decode_from_slice(value, CONFIG)?)
+}
 
 /// 用于可写入文件的序列化方法
 /// 实现了Write特征的一个目标：代表一个“可写入”的目标，比如 File、Vec<u8>、TcpStream 等。
@@ -427,7 +427,7 @@ impl<'de> KeyDecoder<'de> {
     /// 取出指定长度的字节返回
     pub fn take_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
         if self.input.len() < len {
-            panic!("invalid length");
+            return errdata!("invalid length: need {len} bytes, got {}", self.input.len());
         }
         let bytes = &self.input[..len];
         self.input = &self.input[len..];
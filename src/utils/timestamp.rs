@@ -1,3 +1,6 @@
+use crate::db_error::Result;
+use crate::errinput;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_timestamp() -> u32 {
@@ -16,6 +19,46 @@ pub fn get_timestamp_to_vec() -> Vec<u8> {
     (since_the_epoch.as_secs() as u32).to_be_bytes().to_vec()
 }
 
+/// 毫秒级 Unix 时间戳
+pub fn get_timestamp_millis() -> u64 {
+    let start = SystemTime::now();
+    let since_the_epoch = start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    since_the_epoch.as_millis() as u64
+}
+
+/// 解析 ISO 8601 时间为毫秒级 Unix 时间戳
+/// 支持 RFC 3339（`2024-05-01T12:00:00+08:00`），以及不带时区的
+/// `2024-05-01 12:00:00[.fff]`、`2024-05-01T12:00:00` 和 `2024-05-01`，不带时区时按 UTC 处理
+pub fn parse_timestamp_millis(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let datetime = if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+        datetime.with_timezone(&Utc)
+    } else if let Some(naive) = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+    {
+        naive.and_utc()
+    } else if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc()
+    } else {
+        return errinput!("invalid timestamp {s}, expected ISO 8601 such as 2024-05-01T12:00:00Z");
+    };
+    match u64::try_from(datetime.timestamp_millis()) {
+        Ok(millis) => Ok(millis),
+        Err(_) => errinput!("timestamp {s} is before 1970-01-01"),
+    }
+}
+
+/// 毫秒级 Unix 时间戳格式化为 RFC 3339（UTC）
+pub fn format_timestamp_millis(millis: u64) -> String {
+    match DateTime::<Utc>::from_timestamp_millis(millis as i64) {
+        Some(datetime) => datetime.to_rfc3339_opts(SecondsFormat::Millis, true),
+        None => millis.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -29,4 +72,16 @@ mod tests {
     fn test_timestamp_to_be() {
         println!("{:?}", get_timestamp_to_vec());
     }
+
+    #[test]
+    fn test_parse_timestamp_millis() -> Result<()> {
+        assert_eq!(parse_timestamp_millis("1970-01-01T00:00:01Z")?, 1000);
+        assert_eq!(parse_timestamp_millis("1970-01-01T08:00:01.5+08:00")?, 1500);
+        assert_eq!(parse_timestamp_millis("1970-01-01 00:01:00")?, 60_000);
+        assert_eq!(parse_timestamp_millis("1970-01-02")?, 86_400_000);
+        assert!(parse_timestamp_millis("yesterday").is_err());
+        assert!(parse_timestamp_millis("1969-12-31").is_err());
+        assert_eq!(format_timestamp_millis(1500), "1970-01-01T00:00:01.500Z");
+        Ok(())
+    }
 }
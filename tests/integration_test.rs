@@ -1,11 +1,14 @@
-use mini_db::{BitCask, Database};
+use mini_db::cfg::EngineKind;
+use mini_db::db_error::Error;
+use mini_db::{AnyEngine, BTree, BitCask, Database, Lsm};
+use mini_db::sql::execution::Format;
 use mini_db::types::Value;
 
 #[tokio::test]
 async fn test_sql_crud() {
     let dir = tempfile::tempdir().unwrap();
     let engine = BitCask::init_db_at(dir.path()).unwrap();
-    let db = Database::new(engine);
+    let db = Database::new(engine).unwrap();
 
     // CREATE TABLE
     let result = db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
@@ -44,7 +47,7 @@ async fn test_sql_crud() {
 async fn test_sql_order_by_and_group_by() {
     let dir = tempfile::tempdir().unwrap();
     let engine = BitCask::init_db_at(dir.path()).unwrap();
-    let db = Database::new(engine);
+    let db = Database::new(engine).unwrap();
 
     db.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, category STRING, amount INTEGER)").await.unwrap();
     db.execute("INSERT INTO items VALUES (3, 'b', 30), (1, 'a', 10), (2, 'a', 20)").await.unwrap();
@@ -66,15 +69,265 @@ async fn test_sql_persistence() {
     let dir = tempfile::tempdir().unwrap();
     {
         let engine = BitCask::init_db_at(dir.path()).unwrap();
-        let db = Database::new(engine);
+        let db = Database::new(engine).unwrap();
         db.execute("CREATE TABLE persist (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
         db.execute("INSERT INTO persist VALUES (1, 'alice')").await.unwrap();
     }
 
     // Reopen and query
     let engine = BitCask::init_db_at(dir.path()).unwrap();
-    let db = Database::new(engine);
+    let db = Database::new(engine).unwrap();
     let result = db.execute("SELECT * FROM persist").await.unwrap();
     assert_eq!(result.rows.len(), 1);
     assert_eq!(result.rows[0][1], Value::String("alice".into()));
 }
+
+#[tokio::test]
+async fn test_sql_uncommitted_transaction_is_discarded() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let engine = BitCask::init_db_at(dir.path()).unwrap();
+        let db = Database::new(engine).unwrap();
+        db.execute("CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance INTEGER)").await.unwrap();
+        db.execute("INSERT INTO accounts VALUES (1, 100)").await.unwrap();
+
+        // 连接在事务提交前断开，模拟进程退出
+        let mut conn = db.connect();
+        conn.execute("BEGIN ISOLATION LEVEL SERIALIZABLE").await.unwrap();
+        conn.execute("UPDATE accounts SET balance = 0 WHERE id = 1").await.unwrap();
+        assert!(conn.in_transaction());
+    }
+
+    let engine = BitCask::init_db_at(dir.path()).unwrap();
+    let db = Database::new(engine).unwrap();
+    let result = db.execute("SELECT * FROM accounts").await.unwrap();
+    assert_eq!(result.rows[0][1], Value::Integer(100));
+    db.execute("UPDATE accounts SET balance = 50 WHERE id = 1").await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_sql_concurrent_queries() {
+    let dir = tempfile::tempdir().unwrap();
+    let engine = BitCask::init_db_at(dir.path()).unwrap();
+    let db = std::sync::Arc::new(Database::new(engine).unwrap());
+    db.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
+    db.execute("INSERT INTO items VALUES (1, 'a'), (2, 'b')").await.unwrap();
+
+    let mut tasks = Vec::new();
+    for i in 0..8 {
+        let db = db.clone();
+        tasks.push(tokio::spawn(async move {
+            if i % 4 == 0 {
+                let sql = format!("INSERT INTO items VALUES ({}, 'x')", 100 + i);
+                db.execute(&sql).await.unwrap();
+            }
+            for _ in 0..10 {
+                let result = db.execute("SELECT * FROM items").await.unwrap();
+                assert!(result.rows.len() >= 2);
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let result = db.execute("SELECT * FROM items").await.unwrap();
+    assert_eq!(result.rows.len(), 4);
+}
+
+#[tokio::test]
+async fn test_change_feed() {
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let engine = BitCask::init_db_at(dir.path()).unwrap();
+    let db = Database::new(engine).unwrap();
+    db.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, status STRING)").await.unwrap();
+
+    let mut stream = Box::pin(db.change_stream());
+    db.execute("INSERT INTO orders VALUES (1, 'new')").await.unwrap();
+    db.execute("UPDATE orders SET status = 'paid' WHERE id = 1").await.unwrap();
+
+    let insert = stream.next().await.unwrap().unwrap();
+    assert_eq!(insert.changes.len(), 1);
+    assert_eq!(insert.changes[0].table, "orders");
+    assert_eq!(insert.changes[0].primary_key, Value::Integer(1));
+    assert_eq!(insert.changes[0].before, None);
+    let update = stream.next().await.unwrap().unwrap();
+    assert_eq!(update.changes[0].after.as_ref().unwrap()[1], Value::String("paid".into()));
+
+    // 长轮询按序号续读，没有新变更时等待超时后返回空
+    let changes = db.changes_since(insert.seq, Duration::from_millis(10)).await.unwrap();
+    assert_eq!(changes, vec![update.clone()]);
+    assert!(db.changes_since(update.seq, Duration::from_millis(10)).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_sql_on_lsm_engine() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let db = Database::new(Lsm::init_db_at(dir.path()).unwrap()).unwrap();
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
+        db.execute("INSERT INTO users VALUES (1, 'alice'), (2, 'bob'), (3, 'carol')").await.unwrap();
+        db.execute("DELETE FROM users WHERE id = 2").await.unwrap();
+    }
+    // 重新打开后从预写日志恢复
+    let db = Database::new(Lsm::init_db_at(dir.path()).unwrap()).unwrap();
+    let result = db.execute("SELECT * FROM users ORDER BY id DESC").await.unwrap();
+    assert_eq!(result.rows.len(), 2);
+    assert_eq!(result.rows[0][0], Value::Integer(3));
+    assert_eq!(result.rows[1][1], Value::String("alice".into()));
+}
+
+#[tokio::test]
+async fn test_sql_on_btree_engine() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let db = Database::new(BTree::init_db_at(dir.path()).unwrap()).unwrap();
+        db.execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, body STRING)").await.unwrap();
+        for i in 0..50 {
+            db.execute(&format!("INSERT INTO notes VALUES ({i}, '{}')", "n".repeat(200))).await.unwrap();
+        }
+        db.execute("DELETE FROM notes WHERE id < 10").await.unwrap();
+    }
+    let db = Database::new(BTree::init_db_at(dir.path()).unwrap()).unwrap();
+    let result = db.execute("SELECT id FROM notes ORDER BY id DESC LIMIT 3").await.unwrap();
+    assert_eq!(result.rows.len(), 3);
+    assert_eq!(result.rows[0][0], Value::Integer(49));
+    let result = db.execute("SELECT COUNT(*) FROM notes").await.unwrap();
+    assert_eq!(result.rows[0][0], Value::Integer(40));
+}
+
+#[tokio::test]
+async fn test_sql_on_any_engine_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::new(AnyEngine::open(EngineKind::Memory, dir.path()).unwrap()).unwrap();
+    db.execute("CREATE TABLE kv (k INTEGER PRIMARY KEY, v STRING)").await.unwrap();
+    db.execute("INSERT INTO kv VALUES (1, 'a'), (2, 'b')").await.unwrap();
+    let result = db.execute("SELECT v FROM kv WHERE k = 2").await.unwrap();
+    assert_eq!(result.rows[0][0], Value::String("b".into()));
+    // 内存引擎不落盘
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_backup_to_while_transaction_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let backup = tempfile::tempdir().unwrap();
+    let backup_path = backup.path().join("b1");
+    let db = Database::new(BitCask::init_db_at(dir.path()).unwrap()).unwrap();
+    db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
+    db.execute("INSERT INTO users VALUES (1, 'alice')").await.unwrap();
+
+    // 备份时仍未提交的写入不会出现在恢复后的数据中
+    let mut conn = db.connect();
+    conn.execute("BEGIN").await.unwrap();
+    conn.execute("INSERT INTO users VALUES (2, 'bob')").await.unwrap();
+    db.execute(&format!("BACKUP TO '{}'", backup_path.display())).await.unwrap();
+    conn.execute("COMMIT").await.unwrap();
+    db.execute("INSERT INTO users VALUES (3, 'carol')").await.unwrap();
+
+    // 不写入已存在的目录
+    let err = db.execute(&format!("BACKUP TO '{}'", backup.path().display())).await.unwrap_err().to_string();
+    assert!(err.contains("already exists"), "{err}");
+
+    let restored = Database::new(BitCask::init_db_at(&backup_path).unwrap()).unwrap();
+    let result = restored.execute("SELECT id FROM users").await.unwrap();
+    assert_eq!(result.rows, vec![vec![Value::Integer(1)]]);
+    // 恢复后的数据库可以继续写入
+    restored.execute("INSERT INTO users VALUES (2, 'bob')").await.unwrap();
+    let result = restored.execute("SELECT COUNT(*) FROM users").await.unwrap();
+    assert_eq!(result.rows[0][0], Value::Integer(2));
+}
+
+#[tokio::test]
+async fn test_export_import_across_engines() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let src = Database::new(BitCask::init_db_at(&dir.path().join("bitcask")).unwrap()).unwrap();
+    src.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING, score FLOAT DEFAULT 0.5)").await.unwrap();
+    src.execute("INSERT INTO users VALUES (1, 'alice', 1.5), (2, 'o''brien, \"jr\"', NULL), (3, '', -0.0)").await.unwrap();
+    src.execute("CREATE TABLE empty (k STRING PRIMARY KEY)").await.unwrap();
+    let expected = src.execute("SELECT * FROM users").await.unwrap().rows;
+
+    for (i, format) in [Format::Sql, Format::Csv, Format::Json].into_iter().enumerate() {
+        let dest = out.path().join(format!("export.{}", format.extension()));
+        let report = src.export(&dest, format).await.unwrap();
+        assert_eq!((report.tables, report.rows), (2, 3));
+
+        let dst = Database::new(Lsm::init_db_at(&dir.path().join(format!("lsm{i}"))).unwrap()).unwrap();
+        let report = dst.import(&dest, format).await.unwrap();
+        assert_eq!((report.tables, report.rows), (2, 3), "{format}");
+        assert_eq!(dst.execute("SELECT * FROM users").await.unwrap().rows, expected, "{format}");
+        assert!(dst.execute("SELECT * FROM empty").await.unwrap().rows.is_empty());
+    }
+
+    // COPY 单表导出再导入到新表
+    let file = out.path().join("users.csv");
+    let result = src.execute(&format!("COPY users TO '{}'", file.display())).await.unwrap();
+    assert_eq!(result.rows[0][0], Value::Integer(3));
+    src.execute("CREATE TABLE users2 (id INTEGER PRIMARY KEY, name STRING, score FLOAT DEFAULT 0.5)").await.unwrap();
+    let result = src.execute(&format!("COPY users2 FROM '{}' (FORMAT csv)", file.display())).await.unwrap();
+    assert_eq!(result.rows[0][0], Value::Integer(3));
+    assert_eq!(src.execute("SELECT * FROM users2").await.unwrap().rows, expected);
+    // 不覆盖已存在的文件
+    let err = src.execute(&format!("COPY users TO '{}'", file.display())).await.unwrap_err().to_string();
+    assert!(err.contains("already exists"), "{err}");
+    assert!(src.export(out.path().join("export.sql"), Format::Sql).await.is_err());
+
+    // 显式事务中的 COPY FROM 随事务回滚
+    src.execute("CREATE TABLE users3 (id INTEGER PRIMARY KEY, name STRING, score FLOAT)").await.unwrap();
+    let mut conn = src.connect();
+    conn.execute("BEGIN").await.unwrap();
+    conn.execute(&format!("COPY users3 FROM '{}'", file.display())).await.unwrap();
+    conn.execute("ROLLBACK").await.unwrap();
+    assert!(src.execute("SELECT * FROM users3").await.unwrap().rows.is_empty());
+}
+
+#[tokio::test]
+async fn test_read_only_database_while_writer_holds_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::new(BitCask::init_db_at(dir.path()).unwrap()).unwrap();
+    db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
+    db.execute("INSERT INTO users VALUES (1, 'alice'), (2, 'bob')").await.unwrap();
+    // 写入方还有一个未提交的事务
+    let mut conn = db.connect();
+    conn.execute("BEGIN").await.unwrap();
+    conn.execute("INSERT INTO users VALUES (3, 'carol')").await.unwrap();
+
+    // 写入方持有目录锁，只能以只读方式打开
+    assert!(BitCask::init_db_at(dir.path()).is_err());
+    let reader = Database::new_read_only(BitCask::open_read_only(dir.path()).unwrap());
+    let result = reader.execute("SELECT * FROM users").await.unwrap();
+    assert_eq!(result.rows.len(), 2);
+    assert!(matches!(reader.execute("INSERT INTO users VALUES (4, 'dave')").await, Err(Error::ReadOnly)));
+    assert!(reader.execute("CREATE TABLE other (id INTEGER PRIMARY KEY)").await.is_err());
+    assert!(reader.vacuum().await.is_err());
+
+    // 只读实例不回滚写入方的事务
+    conn.execute("COMMIT").await.unwrap();
+    let result = db.execute("SELECT * FROM users").await.unwrap();
+    assert_eq!(result.rows.len(), 3);
+}
+
+#[tokio::test]
+async fn test_execute_remote_rejects_file_statements() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let db = Database::new(BitCask::init_db_at(dir.path()).unwrap()).unwrap();
+    db.execute_remote("CREATE TABLE users (id INTEGER PRIMARY KEY, name STRING)").await.unwrap();
+    db.execute_remote("INSERT INTO users VALUES (1, 'alice')").await.unwrap();
+    assert_eq!(db.execute_remote("SELECT * FROM users").await.unwrap().rows.len(), 1);
+
+    let file = out.path().join("users.csv");
+    for sql in [
+        format!("COPY users TO '{}'", file.display()),
+        format!("COPY users FROM '{}'", file.display()),
+        format!("BACKUP TO '{}'", out.path().join("backup").display()),
+    ] {
+        let err = db.execute_remote(&sql).await.unwrap_err().to_string();
+        assert!(err.contains("not allowed over the network"), "{err}");
+    }
+    assert!(!file.exists());
+    assert!(!out.path().join("backup").exists());
+}